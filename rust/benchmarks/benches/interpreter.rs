@@ -1,9 +1,14 @@
 /// USAGE:
 /// cargo bench --package benchmarks --profile profiling [--features <feature1,feature2,...>]
-use std::time::Duration;
+use std::{
+    hint::black_box,
+    thread,
+    time::{Duration, Instant},
+};
 
 use benchmarks::RunArgs;
 use criterion::{Criterion, criterion_group, criterion_main};
+use evmrs::{HashCache, Opcode};
 
 fn criterion_benchmark(c: &mut Criterion) {
     let (mut args, expected) = RunArgs::static_overhead(1);
@@ -106,6 +111,88 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("analysis/push32", |b| {
         b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
     });
+    let (mut args, expected) = RunArgs::prologue_only(0x6000);
+    c.bench_function("analysis/prologue_only", |b| {
+        b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
+    });
+    let (mut args, expected) = RunArgs::recursive_call(1);
+    c.bench_function("call/1", |b| {
+        b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
+    });
+    let (mut args, expected) = RunArgs::recursive_call(10);
+    c.bench_function("call/10", |b| {
+        b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
+    });
+    let (mut args, expected) = RunArgs::recursive_call(100);
+    c.bench_function("call/100", |b| {
+        b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
+    });
+    // stack heavy opcodes, e.g. to compare with and without feature stack-precheck
+    for (name, opcode) in [
+        ("add", Opcode::Add),
+        ("dup1", Opcode::Dup1),
+        ("swap1", Opcode::Swap1),
+    ] {
+        let (mut args, expected) = RunArgs::opcode_sled(opcode, 0x6000);
+        c.bench_function(&format!("sled/{name}"), |b| {
+            b.iter(|| assert_eq!(benchmarks::run(&mut args), expected))
+        });
+    }
+}
+
+/// Hashes `iters` 32 byte inputs on each of `threads` threads which share `hash_cache`. The
+/// inputs repeat so that the cache gets hits.
+fn hash_contended(hash_cache: &HashCache, threads: usize, iters: u64) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for i in 0..iters {
+                    let mut data = [0; 32];
+                    data[..8].copy_from_slice(&(i % 64).to_le_bytes());
+                    black_box(hash_cache.hash(black_box(&data)));
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Compares cached and direct hashing of 32 byte inputs under contention. This only measures a
+/// difference if feature hash-cache is enabled.
+fn hash_cache_benchmark(c: &mut Criterion) {
+    const THREADS: usize = 4;
+    let cached = HashCache::new_with_policy(1024, 0);
+    c.bench_function("hash_cache/32/cached", |b| {
+        b.iter_custom(|iters| hash_contended(&cached, THREADS, iters))
+    });
+    let direct = HashCache::new_with_policy(1024, usize::MAX);
+    c.bench_function("hash_cache/32/direct", |b| {
+        b.iter_custom(|iters| hash_contended(&direct, THREADS, iters))
+    });
+}
+
+/// Compares hashing 1000 repeated 64 byte inputs as one batch and one by one.
+fn hash_many_benchmark(c: &mut Criterion) {
+    let inputs: Vec<[u8; 64]> = (0..1000u64)
+        .map(|i| {
+            let mut data = [0; 64];
+            data[..8].copy_from_slice(&(i % 64).to_le_bytes());
+            data
+        })
+        .collect();
+    let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+    let hash_cache = HashCache::default();
+    c.bench_function("hash_cache/64/batched", |b| {
+        b.iter(|| black_box(hash_cache.hash_many(black_box(&inputs))))
+    });
+    c.bench_function("hash_cache/64/individual", |b| {
+        b.iter(|| {
+            for input in black_box(&inputs) {
+                black_box(hash_cache.hash(input));
+            }
+        })
+    });
 }
 
 criterion_group!(
@@ -114,6 +201,6 @@ criterion_group!(
         .warm_up_time(Duration::from_secs(5))
         .measurement_time(Duration::from_secs(20))
         .sample_size(100);
-    targets = criterion_benchmark
+    targets = criterion_benchmark, hash_cache_benchmark, hash_many_benchmark
 );
 criterion_main!(benches);
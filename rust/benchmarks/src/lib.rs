@@ -1,11 +1,13 @@
+use std::{borrow::Cow, ffi, ptr};
+
 use driver::{self, Instance, get_tx_context_zeroed, host_interface::null_ptr_host_interface};
 use evmrs::{
-    MockExecutionMessage, Opcode,
+    CodeByteType, MockExecutionMessage, Opcode, OpcodeHistogramObserver, code_byte_type,
     evmc_vm::{
-        Revision, StatusCode, Uint256,
-        ffi::{evmc_host_interface, evmc_message},
+        AccessStatus, Address, ExecutionContext, ExecutionMessage, Revision, StatusCode, Uint256,
+        ffi::{evmc_host_interface, evmc_message, evmc_result, evmc_vm as evmc_vm_t},
     },
-    u256,
+    run_with_opcode_histogram, u256,
 };
 use sha3::{Digest, Keccak256};
 
@@ -14,6 +16,17 @@ pub struct RunArgs {
     host: evmc_host_interface,
     revision: Revision,
     message: evmc_message,
+    code: Cow<'static, [u8]>,
+    /// The host context for benchmarks which make calls. All other benchmarks use a null context.
+    call_context: Option<Box<CallContext>>,
+}
+
+/// The host context of [`RunArgs::recursive_call`]. The host executes the code of the benchmark
+/// again for every CALL, passing the input of the call through unchanged.
+struct CallContext {
+    vm: *mut evmc_vm_t,
+    host: evmc_host_interface,
+    revision: Revision,
     code: &'static [u8],
 }
 
@@ -401,7 +414,161 @@ impl RunArgs {
         Self::analysis(size, &SHORT_CODE, &LONG_CODE)
     }
 
-    fn new(code: &'static [u8], size: u32, func: Option<[u8; 4]>) -> Self {
+    /// Create arguments for a contract which returns the size right away and is followed by `size`
+    /// bytes of PUSH1 JUMPDEST pairs which are never executed. This measures how much of the
+    /// analysis of large contracts can be avoided, e.g. with feature lazy-analysis. Note that the
+    /// eager analysis is only done once if feature code-analysis-cache is enabled.
+    pub fn prologue_only(size: usize) -> (Self, u32) {
+        fn prologue_only_ref(input: u32) -> u32 {
+            input
+        }
+
+        let mut code = vec![
+            Opcode::Push1 as u8,
+            4,
+            Opcode::CallDataLoad as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+        code.extend([Opcode::Push1 as u8, Opcode::JumpDest as u8].repeat(size / 2));
+
+        let size = u32::try_from(size).expect("code size must fit into u32");
+        (Self::new(code, size, None), prologue_only_ref(size))
+    }
+
+    /// Create arguments for a contract which executes `size` bytes of `opcode`, so that the
+    /// throughput of any opcode can be measured. The immediates of PUSH opcodes are zero. Each
+    /// opcode is preceded by a PUSH0 for each of its inputs and followed by a POP for each of its
+    /// outputs, to keep the stack balanced. The remaining bytes are filled with JUMPDESTs. Only
+    /// opcodes which neither access the host nor halt or jump are supported.
+    pub fn opcode_sled(opcode: Opcode, size: usize) -> (Self, u32) {
+        fn opcode_sled_ref(input: u32) -> u32 {
+            input
+        }
+
+        let (inputs, outputs) = sled_stack_io(opcode);
+        let (_, immediates) = code_byte_type(opcode as u8);
+        let mut unit = vec![Opcode::Push0 as u8; inputs];
+        unit.push(opcode as u8);
+        unit.resize(unit.len() + immediates, 0);
+        unit.resize(unit.len() + outputs, Opcode::Pop as u8);
+
+        let mut code = unit.repeat(size / unit.len());
+        code.resize(size, Opcode::JumpDest as u8);
+        // return the size, which is stored in the call data
+        code.extend_from_slice(&[
+            Opcode::Push1 as u8,
+            4,
+            Opcode::CallDataLoad as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ]);
+
+        let size = u32::try_from(size).expect("sled size must fit into u32");
+        (Self::new(code, size, None), opcode_sled_ref(size))
+    }
+
+    /// Create arguments for a contract which calls itself recursively until the depth given in
+    /// the call data is reached. Each frame returns the result of its nested call plus one, so the
+    /// outermost frame returns `depth`. This measures the overhead of setting up call frames.
+    pub fn recursive_call(depth: u32) -> (Self, u32) {
+        fn recursive_call_ref(input: u32) -> u32 {
+            input
+        }
+
+        const RETURN_DEPTH: u8 = 37;
+        const CODE: [u8; 44] = [
+            Opcode::Push1 as u8,
+            4,
+            Opcode::CallDataLoad as u8, // load the remaining depth from call data at offset 4
+            Opcode::Dup1 as u8,
+            Opcode::IsZero as u8,
+            Opcode::Push1 as u8,
+            RETURN_DEPTH,
+            Opcode::JumpI as u8, // return 0 if the remaining depth is 0
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Swap1 as u8,
+            Opcode::Sub as u8,
+            Opcode::Push1 as u8,
+            4,
+            Opcode::MStore as u8, // store the remaining depth - 1 as input of the nested call
+            Opcode::Push1 as u8,
+            32,                  // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push1 as u8,
+            36,                  // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push0 as u8, // value
+            Opcode::Address as u8,
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+            Opcode::Pop as u8,
+            Opcode::Push0 as u8,
+            Opcode::MLoad as u8, // load the result of the nested call
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Add as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8, // return 32 bytes at offset 0
+            Opcode::JumpDest as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8, // store the remaining depth, which is 0
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8, // return 32 bytes at offset 0
+        ];
+
+        let mut args = Self::new(&CODE, depth, None);
+        // The caller adds the gas left of a nested call to its own gas before it deducts the gas
+        // passed to the call, which overflows with the default gas of i64::MAX.
+        args.message.gas = 100_000_000;
+        args.host.access_account = Some(access_account_warm);
+        args.host.get_balance = Some(get_balance_zero);
+        args.host.call = Some(call_recursively);
+        args.call_context = Some(Box::new(CallContext {
+            vm: &mut *args.instance,
+            host: args.host,
+            revision: args.revision,
+            code: &CODE,
+        }));
+        (args, recursive_call_ref(depth))
+    }
+
+    /// Runs the benchmark once and returns how often each opcode was executed and how much gas it
+    /// used. The histogram is empty if feature tail-call is enabled.
+    pub fn opcode_histogram(&self) -> OpcodeHistogramObserver {
+        let message = ExecutionMessage::from(&self.message);
+        // A null pointer is allowed as context if the benchmark does not make calls, because the
+        // host interface does not require a valid pointer then, see `run`.
+        let call_context = self
+            .call_context
+            .as_ref()
+            .map_or(ptr::null_mut(), |call_context| {
+                ptr::from_ref::<CallContext>(call_context).cast_mut().cast()
+            });
+        let mut context = ExecutionContext::new(&self.host, call_context);
+        let (result, histogram) =
+            run_with_opcode_histogram(self.revision, &message, &mut context, &self.code);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        histogram
+    }
+
+    fn new(code: impl Into<Cow<'static, [u8]>>, size: u32, func: Option<[u8; 4]>) -> Self {
+        let code = code.into();
         let instance = Instance::default();
         let mut host = null_ptr_host_interface();
         host.get_tx_context = Some(get_tx_context_zeroed);
@@ -413,7 +580,7 @@ impl RunArgs {
         input[32..].copy_from_slice(&size.to_be_bytes());
 
         let mut hasher = Keccak256::new();
-        hasher.update(code);
+        hasher.update(&code);
         let mut code_hash = [0; 32];
         hasher.finalize_into((&mut code_hash).into());
 
@@ -429,6 +596,7 @@ impl RunArgs {
             revision: Revision::EVMC_CANCUN,
             message: message.to_evmc_message(),
             code,
+            call_context: None,
         }
     }
 }
@@ -452,14 +620,210 @@ impl Drop for RunArgs {
 }
 
 pub fn run(args: &mut RunArgs) -> u32 {
-    // SAFETY:
-    // `host` and `message` are valid pointers since they are created from references. `context`
-    // is a null pointer but this is allowed by the evmc interface as long as the host
-    // interface does not require a valid pointer, which is not the case here.
-    let result =
-        args.instance
-            .run_with_null_context(&args.host, args.revision, &args.message, args.code);
+    let result = match &mut args.call_context {
+        // SAFETY:
+        // `host` and `message` are valid pointers since they are created from references.
+        // `context` is a null pointer but this is allowed by the evmc interface as long as the
+        // host interface does not require a valid pointer, which is not the case here.
+        None => args.instance.run_with_null_context(
+            &args.host,
+            args.revision,
+            &args.message,
+            &args.code,
+        ),
+        Some(call_context) => args.instance.run(
+            &args.host,
+            call_context.as_mut(),
+            args.revision,
+            &args.message,
+            &args.code,
+        ),
+    };
     assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
     assert_eq!(result.output.len(), 32);
     u32::from_be_bytes(result.output[28..32].try_into().unwrap())
 }
+
+extern "C" fn access_account_warm(
+    _context: *mut ffi::c_void,
+    _addr: *const Address,
+) -> AccessStatus {
+    AccessStatus::EVMC_ACCESS_WARM
+}
+
+extern "C" fn get_balance_zero(_context: *mut ffi::c_void, _addr: *const Address) -> Uint256 {
+    Uint256 { bytes: [0; 32] }
+}
+
+/// Executes the code of the benchmark again with `message`.
+///
+/// # Safety
+/// `context` must point to the [`CallContext`] of a [`RunArgs`] which is alive, and `message`
+/// must be valid. Both are guaranteed if this is only called by the interpreter during `run`.
+unsafe extern "C" fn call_recursively(
+    context: *mut ffi::c_void,
+    message: *const evmc_message,
+) -> evmc_result {
+    // SAFETY:
+    // Guaranteed by the caller.
+    let call_context = unsafe { &*context.cast::<CallContext>() };
+    // SAFETY:
+    // `vm` points to the instance of the `RunArgs` which owns the context, so it is valid. All
+    // other pointers are created from references or are valid according to the caller.
+    unsafe {
+        let execute = (*call_context.vm).execute.unwrap();
+        execute(
+            call_context.vm,
+            &call_context.host,
+            context.cast(),
+            call_context.revision,
+            message,
+            call_context.code.as_ptr(),
+            call_context.code.len(),
+        )
+    }
+}
+
+/// Prints the `n` most frequently executed opcodes and the `n` opcodes which used the most gas.
+pub fn print_histogram(histogram: &OpcodeHistogramObserver, n: usize) {
+    println!("top opcodes by count:");
+    for stats in histogram.top_by_count(n) {
+        println!("  {:?}: {} (gas {})", stats.opcode, stats.count, stats.gas);
+    }
+    println!("top opcodes by gas:");
+    for stats in histogram.top_by_gas(n) {
+        println!(
+            "  {:?}: {} (count {})",
+            stats.opcode, stats.gas, stats.count
+        );
+    }
+}
+
+/// Returns the number of stack inputs and outputs of `opcode` for [`RunArgs::opcode_sled`].
+///
+/// # Panics
+/// Panics if the opcode accesses the host, halts or jumps, because it cannot be executed in a sled.
+fn sled_stack_io(opcode: Opcode) -> (usize, usize) {
+    let op = opcode as u8;
+    match opcode {
+        Opcode::JumpDest => (0, 0),
+        Opcode::Pop => (1, 0),
+        Opcode::Address
+        | Opcode::Origin
+        | Opcode::Caller
+        | Opcode::CallValue
+        | Opcode::CallDataSize
+        | Opcode::CodeSize
+        | Opcode::GasPrice
+        | Opcode::ReturnDataSize
+        | Opcode::Coinbase
+        | Opcode::Timestamp
+        | Opcode::Number
+        | Opcode::PrevRandao
+        | Opcode::GasLimit
+        | Opcode::ChainId
+        | Opcode::BaseFee
+        | Opcode::BlobBaseFee
+        | Opcode::Pc
+        | Opcode::MSize
+        | Opcode::Gas
+        | Opcode::Push0 => (0, 1),
+        _ if (Opcode::Push1 as u8..=Opcode::Push32 as u8).contains(&op) => (0, 1),
+        Opcode::IsZero | Opcode::Not | Opcode::CallDataLoad | Opcode::MLoad => (1, 1),
+        Opcode::Add
+        | Opcode::Mul
+        | Opcode::Sub
+        | Opcode::Div
+        | Opcode::SDiv
+        | Opcode::Mod
+        | Opcode::SMod
+        | Opcode::Exp
+        | Opcode::SignExtend
+        | Opcode::Lt
+        | Opcode::Gt
+        | Opcode::SLt
+        | Opcode::SGt
+        | Opcode::Eq
+        | Opcode::And
+        | Opcode::Or
+        | Opcode::Xor
+        | Opcode::Byte
+        | Opcode::Shl
+        | Opcode::Shr
+        | Opcode::Sar
+        | Opcode::Sha3 => (2, 1),
+        Opcode::MStore | Opcode::MStore8 => (2, 0),
+        Opcode::AddMod | Opcode::MulMod => (3, 1),
+        Opcode::CallDataCopy | Opcode::CodeCopy | Opcode::MCopy => (3, 0),
+        _ if (Opcode::Dup1 as u8..=Opcode::Dup16 as u8).contains(&op) => {
+            let n = (op - Opcode::Dup1 as u8 + 1) as usize;
+            (n, n + 1)
+        }
+        _ if (Opcode::Swap1 as u8..=Opcode::Swap16 as u8).contains(&op) => {
+            let n = (op - Opcode::Swap1 as u8 + 2) as usize;
+            (n, n)
+        }
+        _ => panic!("{opcode:?} cannot be executed in an opcode sled"),
+    }
+}
+
+/// Parses an opcode from its name, ignoring case (e.g. `add` or `PUSH1`).
+pub fn parse_opcode(name: &str) -> Result<Opcode, String> {
+    (0..=u8::MAX)
+        .filter(|byte| code_byte_type(*byte).0 != CodeByteType::DataOrInvalid)
+        .map(|byte| {
+            // SAFETY:
+            // The code analysis determined that this byte is a valid Opcode.
+            unsafe { std::mem::transmute::<u8, Opcode>(byte) }
+        })
+        .find(|opcode| format!("{opcode:?}").eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown opcode {name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use evmrs::Opcode;
+
+    use crate::{RunArgs, parse_opcode};
+
+    #[test]
+    fn opcode_sled_of_add_runs() {
+        let (mut args, expected) = RunArgs::opcode_sled(Opcode::Add, 0x101);
+        // 64 x PUSH0, PUSH0, ADD, POP and one JUMPDEST, followed by the return sequence
+        assert_eq!(args.code.len(), 0x101 + 9);
+        assert_eq!(
+            args.code[..3],
+            [Opcode::Push0 as u8, Opcode::Push0 as u8, Opcode::Add as u8]
+        );
+        assert_eq!(args.code[0x100], Opcode::JumpDest as u8);
+        assert_eq!(expected, 0x101);
+        assert_eq!(crate::run(&mut args), expected);
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn opcode_histogram_of_sha3_includes_keccak256() {
+        let (args, _) = RunArgs::sha3(1);
+        let histogram = args.opcode_histogram();
+        let sha3 = histogram.get(Opcode::Sha3).unwrap();
+        assert!(sha3.count >= 1);
+        assert!(sha3.gas > 0);
+    }
+
+    #[test]
+    fn recursive_call_returns_depth() {
+        for depth in [0, 1, 10] {
+            let (mut args, expected) = RunArgs::recursive_call(depth);
+            assert_eq!(expected, depth);
+            assert_eq!(crate::run(&mut args), expected);
+        }
+    }
+
+    #[test]
+    fn parse_opcode_ignores_case() {
+        assert_eq!(parse_opcode("add"), Ok(Opcode::Add));
+        assert_eq!(parse_opcode("PUSH32"), Ok(Opcode::Push32));
+        assert!(parse_opcode("foo").is_err());
+    }
+}
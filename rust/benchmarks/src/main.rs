@@ -1,13 +1,27 @@
 use benchmarks::RunArgs;
 use clap::{Parser, ValueEnum};
+use evmrs::Opcode;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     runs: u64,
     benchmark: Benchmark,
+    /// The opcode executed by the opcode-sled benchmark, e.g. `add` or `push1`.
+    #[arg(long, value_parser = benchmarks::parse_opcode, required_if_eq("benchmark", "opcode-sled"))]
+    opcode: Option<Opcode>,
+    /// The size of the sled in bytes for the opcode-sled benchmark.
+    #[arg(long, default_value_t = 0x6000)]
+    size: usize,
+    /// Instead of running the benchmarks `runs` times, run each of them once and print the
+    /// opcodes executed most frequently and the opcodes which used the most gas.
+    #[arg(long)]
+    histogram: bool,
 }
 
+/// The number of opcodes printed per ranking with `--histogram`.
+const HISTOGRAM_LEN: usize = 10;
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 enum Benchmark {
     StaticOverhead,
@@ -35,6 +49,10 @@ enum Benchmark {
     AnalysisStop,
     AnalysisPush1,
     AnalysisPush32,
+    Call1,
+    Call10,
+    Call100,
+    OpcodeSled,
     All,
     AllShort,
 }
@@ -68,6 +86,11 @@ fn main() {
         Benchmark::AnalysisStop => vec![|| RunArgs::stop_analysis(0x6000)],
         Benchmark::AnalysisPush1 => vec![|| RunArgs::push1_analysis(0x6000)],
         Benchmark::AnalysisPush32 => vec![|| RunArgs::push32_analysis(0x6000)],
+        Benchmark::Call1 => vec![|| RunArgs::recursive_call(1)],
+        Benchmark::Call10 => vec![|| RunArgs::recursive_call(10)],
+        Benchmark::Call100 => vec![|| RunArgs::recursive_call(100)],
+        // the sled depends on the arguments and is therefore created below
+        Benchmark::OpcodeSled => Vec::new(),
         Benchmark::All => vec![
             || RunArgs::static_overhead(1),
             || RunArgs::inc(1),
@@ -79,6 +102,7 @@ fn main() {
             || RunArgs::stop_analysis(0x6000),
             || RunArgs::push1_analysis(0x6000),
             || RunArgs::push32_analysis(0x6000),
+            || RunArgs::recursive_call(100),
         ],
         Benchmark::AllShort => vec![
             || RunArgs::static_overhead(1),
@@ -91,11 +115,26 @@ fn main() {
             || RunArgs::stop_analysis(100),
             || RunArgs::push1_analysis(100),
             || RunArgs::push32_analysis(100),
+            || RunArgs::recursive_call(1),
         ],
     };
 
-    for bench_fn in benches {
-        let (mut run_args, expected) = bench_fn();
+    let sled = args
+        .opcode
+        .filter(|_| matches!(args.benchmark, Benchmark::OpcodeSled))
+        .map(|opcode| RunArgs::opcode_sled(opcode, args.size));
+
+    for (index, (mut run_args, expected)) in benches
+        .into_iter()
+        .map(|bench_fn| bench_fn())
+        .chain(sled)
+        .enumerate()
+    {
+        if args.histogram {
+            println!("{:?} #{index}", args.benchmark);
+            benchmarks::print_histogram(&run_args.opcode_histogram(), HISTOGRAM_LEN);
+            continue;
+        }
         for _ in 0..args.runs {
             assert_eq!(benchmarks::run(&mut run_args), expected);
         }
@@ -1,5 +1,6 @@
 use std::{
     ffi,
+    mem::MaybeUninit,
     ops::{Deref, DerefMut},
     ptr,
 };
@@ -7,16 +8,41 @@ use std::{
 use evmrs::evmc_vm::{
     Address, ExecutionResult, Revision, StepResult, Uint256,
     ffi::{
-        evmc_host_interface, evmc_message, evmc_step_status_code, evmc_tx_context,
-        evmc_vm as evmc_vm_t, evmc_vm_steppable,
+        evmc_host_context, evmc_host_interface, evmc_message, evmc_result, evmc_revision,
+        evmc_step_status_code, evmc_tx_context, evmc_vm as evmc_vm_t, evmc_vm_steppable,
     },
 };
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
 pub mod host_interface;
 
 unsafe extern "C" {
     safe fn evmc_create_evmrs() -> *mut evmc_vm_t;
     safe fn evmc_create_steppable_evmrs() -> *mut evmc_vm_steppable;
+    #[allow(clippy::too_many_arguments)]
+    fn evmrs_execute_batch(
+        instance: *mut evmc_vm_t,
+        host: *const evmc_host_interface,
+        context: *mut evmc_host_context,
+        revision: evmc_revision,
+        messages: *const evmc_message,
+        codes: *const *const u8,
+        code_sizes: *const usize,
+        count: usize,
+        results: *mut evmc_result,
+    );
+    #[allow(clippy::too_many_arguments)]
+    fn evmrs_estimate_gas(
+        instance: *mut evmc_vm_t,
+        host: *const evmc_host_interface,
+        context: *mut evmc_host_context,
+        revision: evmc_revision,
+        message: *const evmc_message,
+        code: *const u8,
+        code_size: usize,
+        result: *mut evmc_result,
+    ) -> u64;
 }
 
 pub const ZERO: Uint256 = Uint256 { bytes: [0; 32] };
@@ -161,6 +187,83 @@ impl Instance {
             )
         }
     }
+
+    /// Run the interpreter for all pairs of messages and codes with a single call to
+    /// `evmrs_execute_batch`, using a null pointer as context.
+    pub fn run_batch_with_null_context(
+        &mut self,
+        host: &evmc_host_interface,
+        revision: Revision,
+        messages: &[evmc_message],
+        codes: &[&[u8]],
+    ) -> Vec<ExecutionResult> {
+        assert_eq!(messages.len(), codes.len());
+        let code_ptrs: Vec<_> = codes
+            .iter()
+            .map(|code| {
+                if code.is_empty() {
+                    ptr::null()
+                } else {
+                    code.as_ptr()
+                }
+            })
+            .collect();
+        let code_sizes: Vec<_> = codes.iter().map(|code| code.len()).collect();
+        let mut results = Vec::with_capacity(messages.len());
+        // SAFETY:
+        // All arrays have `messages.len()` elements and `results` has enough capacity for all
+        // results. `context` is allowed to be null.
+        unsafe {
+            evmrs_execute_batch(
+                &mut *self.0,
+                host,
+                ptr::null_mut(),
+                revision,
+                messages.as_ptr(),
+                code_ptrs.as_ptr(),
+                code_sizes.as_ptr(),
+                messages.len(),
+                results.as_mut_ptr(),
+            );
+            results.set_len(messages.len());
+        }
+        // Converting the results releases them.
+        results.into_iter().map(ExecutionResult::from).collect()
+    }
+
+    /// Run the interpreter as a dry run with `evmrs_estimate_gas` and return the result together
+    /// with the gas used.
+    pub fn estimate_gas<T>(
+        &mut self,
+        host: &evmc_host_interface,
+        context: &mut T,
+        revision: Revision,
+        message: &evmc_message,
+        code: &[u8],
+    ) -> (ExecutionResult, u64) {
+        let mut result = MaybeUninit::uninit();
+        // SAFETY:
+        // All pointer are valid since they are created from references. `result` is initialized by
+        // `evmrs_estimate_gas` because it is not null.
+        unsafe {
+            let gas_used = evmrs_estimate_gas(
+                &mut *self.0,
+                host,
+                context as *mut T as *mut evmc_host_context,
+                revision,
+                message,
+                if code.is_empty() {
+                    ptr::null()
+                } else {
+                    code.as_ptr()
+                },
+                code.len(),
+                result.as_mut_ptr(),
+            );
+            // Converting the result releases it.
+            (result.assume_init().into(), gas_used)
+        }
+    }
 }
 
 pub struct SteppableInstance(&'static mut evmc_vm_steppable);
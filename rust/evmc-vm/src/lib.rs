@@ -11,7 +11,7 @@
 mod container;
 mod types;
 
-use std::{ptr, slice};
+use std::{fmt, ops::Range, ptr, slice};
 
 pub use container::{EvmcContainer, SteppableEvmcContainer};
 pub use evmc_sys as ffi;
@@ -71,6 +71,158 @@ pub struct ExecutionResult {
     pub create_address: Option<Address>,
 }
 
+impl ExecutionResult {
+    /// Returns the address of the created account if the result belongs to a message of the given
+    /// kind. Only CREATE and CREATE2 messages create accounts, so for all other kinds `None` is
+    /// returned, even if `create_address` is set.
+    pub fn created_address_for(&self, kind: MessageKind) -> Option<Address> {
+        match kind {
+            MessageKind::EVMC_CREATE | MessageKind::EVMC_CREATE2 => self.create_address,
+            _ => None,
+        }
+    }
+}
+
+/// Returns the name of the status code, as used by EVMC's `evmc_status_code_to_string`.
+fn status_code_name(status_code: StatusCode) -> &'static str {
+    match status_code {
+        StatusCode::EVMC_SUCCESS => "success",
+        StatusCode::EVMC_FAILURE => "failure",
+        StatusCode::EVMC_REVERT => "revert",
+        StatusCode::EVMC_OUT_OF_GAS => "out of gas",
+        StatusCode::EVMC_INVALID_INSTRUCTION => "invalid instruction",
+        StatusCode::EVMC_UNDEFINED_INSTRUCTION => "undefined instruction",
+        StatusCode::EVMC_STACK_OVERFLOW => "stack overflow",
+        StatusCode::EVMC_STACK_UNDERFLOW => "stack underflow",
+        StatusCode::EVMC_BAD_JUMP_DESTINATION => "bad jump destination",
+        StatusCode::EVMC_INVALID_MEMORY_ACCESS => "invalid memory access",
+        StatusCode::EVMC_CALL_DEPTH_EXCEEDED => "call depth exceeded",
+        StatusCode::EVMC_STATIC_MODE_VIOLATION => "static mode violation",
+        StatusCode::EVMC_PRECOMPILE_FAILURE => "precompile failure",
+        StatusCode::EVMC_CONTRACT_VALIDATION_FAILURE => "contract validation failure",
+        StatusCode::EVMC_ARGUMENT_OUT_OF_RANGE => "argument out of range",
+        StatusCode::EVMC_WASM_UNREACHABLE_INSTRUCTION => "wasm unreachable instruction",
+        StatusCode::EVMC_WASM_TRAP => "wasm trap",
+        StatusCode::EVMC_INSUFFICIENT_BALANCE => "insufficient balance",
+        StatusCode::EVMC_INTERNAL_ERROR => "internal error",
+        StatusCode::EVMC_REJECTED => "rejected",
+        StatusCode::EVMC_OUT_OF_MEMORY => "out of memory",
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for byte in bytes {
+        write!(f, "{byte:02x}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ExecutionResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "status: {}, gas_left: {}, gas_refund: {}, output: ",
+            status_code_name(self.status_code),
+            self.gas_left,
+            self.gas_refund
+        )?;
+        write_hex(f, &self.output)?;
+        write!(f, ", create_address: ")?;
+        match &self.create_address {
+            Some(addr) => write_hex(f, &addr.bytes),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// A field in which two [`ExecutionResult`]s differ, see [`diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultDiff {
+    StatusCode {
+        a: StatusCode,
+        b: StatusCode,
+    },
+    GasLeft {
+        a: i64,
+        b: i64,
+    },
+    GasRefund {
+        a: i64,
+        b: i64,
+    },
+    OutputLen {
+        a: usize,
+        b: usize,
+    },
+    /// A maximal range of differing bytes within the length of the shorter output, together with
+    /// the bytes of both outputs in this range.
+    Output {
+        range: Range<usize>,
+        a: Box<[u8]>,
+        b: Box<[u8]>,
+    },
+    CreateAddress {
+        a: Option<Address>,
+        b: Option<Address>,
+    },
+}
+
+/// Returns all fields in which `a` and `b` differ, in the order in which they are declared in
+/// [`ExecutionResult`]. The result is empty if and only if `a` and `b` are equal.
+pub fn diff(a: &ExecutionResult, b: &ExecutionResult) -> Vec<ResultDiff> {
+    let mut diffs = Vec::new();
+    if a.status_code != b.status_code {
+        diffs.push(ResultDiff::StatusCode {
+            a: a.status_code,
+            b: b.status_code,
+        });
+    }
+    if a.gas_left != b.gas_left {
+        diffs.push(ResultDiff::GasLeft {
+            a: a.gas_left,
+            b: b.gas_left,
+        });
+    }
+    if a.gas_refund != b.gas_refund {
+        diffs.push(ResultDiff::GasRefund {
+            a: a.gas_refund,
+            b: b.gas_refund,
+        });
+    }
+    if a.output.len() != b.output.len() {
+        diffs.push(ResultDiff::OutputLen {
+            a: a.output.len(),
+            b: b.output.len(),
+        });
+    }
+    let mut start = None;
+    let common_len = a.output.len().min(b.output.len());
+    for idx in 0..=common_len {
+        let differs = idx < common_len && a.output[idx] != b.output[idx];
+        match (start, differs) {
+            (None, true) => start = Some(idx),
+            (Some(range_start), false) => {
+                let range = range_start..idx;
+                diffs.push(ResultDiff::Output {
+                    a: a.output[range.clone()].into(),
+                    b: b.output[range.clone()].into(),
+                    range,
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if a.create_address != b.create_address {
+        diffs.push(ResultDiff::CreateAddress {
+            a: a.create_address,
+            b: b.create_address,
+        });
+    }
+    diffs
+}
+
 #[derive(Debug)]
 pub struct StepResult {
     pub step_status_code: StepStatusCode,
@@ -102,6 +254,124 @@ pub struct ExecutionMessage<'a> {
     pub code_hash: Option<Uint256>,
 }
 
+/// Error returned by [`ExecutionMessageBuilder::build`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum BuildMessageError {
+    /// A CREATE2 salt was set for a message which is not of kind `EVMC_CREATE2`.
+    SaltWithoutCreate2,
+}
+
+/// Builder for [`ExecutionMessage`]. All fields which are not set default to the values of a call
+/// with the maximal gas, i.e. kind `EVMC_CALL`, zero addresses and value, and no input or code.
+#[derive(Debug)]
+pub struct ExecutionMessageBuilder<'a> {
+    message: ExecutionMessage<'a>,
+    create2_salt: Option<Uint256>,
+}
+
+impl Default for ExecutionMessageBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ExecutionMessageBuilder<'a> {
+    pub fn new() -> Self {
+        const ZERO_ADDRESS: Address = Address { bytes: [0; 20] };
+        const ZERO: Uint256 = Uint256 { bytes: [0; 32] };
+        Self {
+            message: ExecutionMessage {
+                kind: MessageKind::EVMC_CALL,
+                flags: 0,
+                depth: 0,
+                gas: i64::MAX,
+                recipient: ZERO_ADDRESS,
+                sender: ZERO_ADDRESS,
+                input: &[],
+                value: ZERO,
+                create2_salt: ZERO,
+                code_address: ZERO_ADDRESS,
+                code: &[],
+                code_hash: None,
+            },
+            create2_salt: None,
+        }
+    }
+
+    pub fn kind(mut self, kind: MessageKind) -> Self {
+        self.message.kind = kind;
+        self
+    }
+
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.message.flags = flags;
+        self
+    }
+
+    pub fn depth(mut self, depth: i32) -> Self {
+        self.message.depth = depth;
+        self
+    }
+
+    pub fn gas(mut self, gas: i64) -> Self {
+        self.message.gas = gas;
+        self
+    }
+
+    pub fn recipient(mut self, recipient: Address) -> Self {
+        self.message.recipient = recipient;
+        self
+    }
+
+    pub fn sender(mut self, sender: Address) -> Self {
+        self.message.sender = sender;
+        self
+    }
+
+    pub fn input(mut self, input: &'a [u8]) -> Self {
+        self.message.input = input;
+        self
+    }
+
+    pub fn value(mut self, value: Uint256) -> Self {
+        self.message.value = value;
+        self
+    }
+
+    /// Sets the salt of a CREATE2 message. [`Self::build`] fails if the kind is not
+    /// `EVMC_CREATE2`.
+    pub fn create2_salt(mut self, create2_salt: Uint256) -> Self {
+        self.create2_salt = Some(create2_salt);
+        self
+    }
+
+    pub fn code_address(mut self, code_address: Address) -> Self {
+        self.message.code_address = code_address;
+        self
+    }
+
+    pub fn code(mut self, code: &'a [u8]) -> Self {
+        self.message.code = code;
+        self
+    }
+
+    pub fn code_hash(mut self, code_hash: Uint256) -> Self {
+        self.message.code_hash = Some(code_hash);
+        self
+    }
+
+    pub fn build(self) -> Result<ExecutionMessage<'a>, BuildMessageError> {
+        let mut message = self.message;
+        if let Some(create2_salt) = self.create2_salt {
+            if message.kind != MessageKind::EVMC_CREATE2 {
+                return Err(BuildMessageError::SaltWithoutCreate2);
+            }
+            message.create2_salt = create2_salt;
+        }
+        Ok(message)
+    }
+}
+
 /// EVMC transaction context structure.
 #[derive(Debug, Copy, Clone, Hash, PartialEq)]
 pub struct ExecutionTxContext<'a> {
@@ -230,6 +500,7 @@ impl<'a> ExecutionContext<'a> {
 
     /// Call to another account.
     pub fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        let kind = message.kind;
         // There is no need to make any kind of copies here, because the caller
         // won't go out of scope and ensures these pointers remain valid.
         let (input_data, input_size) = slice_into_raw_parts(message.input);
@@ -252,7 +523,11 @@ impl<'a> ExecutionContext<'a> {
             code_size,
             code_hash: ptr::null(),
         };
-        unsafe { self.host.call.unwrap()(self.context, &message).into() }
+        let mut result: ExecutionResult =
+            unsafe { self.host.call.unwrap()(self.context, &message).into() };
+        // The create address is only meaningful for CREATE and CREATE2 messages.
+        result.create_address = result.created_address_for(kind);
+        result
     }
 
     /// Get block hash of an account.
@@ -296,6 +571,8 @@ impl<'a> ExecutionContext<'a> {
     }
 }
 
+/// Takes ownership of the result and releases it. The `padding` field is reserved and ignored. The
+/// zero address encodes a missing `create_address`, no account can be created at this address.
 impl From<ffi::evmc_result> for ExecutionResult {
     fn from(result: ffi::evmc_result) -> Self {
         let ret = Self {
@@ -307,8 +584,10 @@ impl From<ffi::evmc_result> for ExecutionResult {
             output: Box::from(unsafe {
                 slice_from_raw_parts(result.output_data as *mut u8, result.output_size)
             }),
-            // Consider it is always valid.
-            create_address: Some(result.create_address),
+            // The message kind is not known here, so consider any nonzero address valid. Use
+            // `ExecutionResult::created_address_for` to only get it for create messages.
+            create_address: (result.create_address != Address::default())
+                .then_some(result.create_address),
         };
 
         // Release allocated ffi struct.
@@ -362,6 +641,8 @@ unsafe fn slice_from_raw_parts<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
     }
 }
 
+/// The `padding` field is always zeroed and carries no data. A missing `create_address` is encoded
+/// as the zero address, which converts back to `None`.
 impl From<ExecutionResult> for ffi::evmc_result {
     fn from(value: ExecutionResult) -> Self {
         let (output_data, output_size) = boxed_slice_into_raw_parts(value.output);
@@ -551,7 +832,144 @@ mod tests {
         assert_eq!(r.gas_left, 1337);
         assert_eq!(r.gas_refund, 21);
         assert_eq!(r.output.len(), 4);
+        assert_eq!(r.create_address, None);
+    }
+
+    #[test]
+    fn diff_pinpoints_gas_left_and_output_byte() {
+        let a = ExecutionResult {
+            status_code: StatusCode::EVMC_SUCCESS,
+            gas_left: 100,
+            gas_refund: 0,
+            output: Box::from([1, 2, 3, 4]),
+            create_address: None,
+        };
+        let b = ExecutionResult {
+            status_code: StatusCode::EVMC_SUCCESS,
+            gas_left: 90,
+            gas_refund: 0,
+            output: Box::from([1, 2, 0xff, 4]),
+            create_address: None,
+        };
+        assert!(diff(&a, &a).is_empty());
+        assert_eq!(
+            diff(&a, &b),
+            [
+                ResultDiff::GasLeft { a: 100, b: 90 },
+                ResultDiff::Output {
+                    range: 2..3,
+                    a: Box::from([3]),
+                    b: Box::from([0xff]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn result_display() {
+        let r = ExecutionResult {
+            status_code: StatusCode::EVMC_SUCCESS,
+            gas_left: 1337,
+            gas_refund: 21,
+            output: Box::from([0xde, 0xad, 0xbe, 0xef]),
+            create_address: None,
+        };
+        assert_eq!(
+            r.to_string(),
+            "status: success, gas_left: 1337, gas_refund: 21, output: 0xdeadbeef, create_address: none"
+        );
+
+        let r = ExecutionResult {
+            status_code: StatusCode::EVMC_OUT_OF_GAS,
+            create_address: Some(Address { bytes: [0xaa; 20] }),
+            ..r
+        };
+        assert_eq!(
+            r.to_string(),
+            format!(
+                "status: out of gas, gas_left: 1337, gas_refund: 21, output: 0xdeadbeef, \
+                create_address: 0x{}",
+                "aa".repeat(20)
+            )
+        );
+    }
+
+    #[test]
+    fn created_address_for_create_kinds_only() {
+        let create_address = Address { bytes: [0xaa; 20] };
+        let r = ExecutionResult {
+            status_code: StatusCode::EVMC_SUCCESS,
+            gas_left: 0,
+            gas_refund: 0,
+            output: Box::default(),
+            create_address: Some(create_address),
+        };
+
+        assert_eq!(
+            r.created_address_for(MessageKind::EVMC_CREATE),
+            Some(create_address)
+        );
+        assert_eq!(
+            r.created_address_for(MessageKind::EVMC_CREATE2),
+            Some(create_address)
+        );
+        assert_eq!(r.created_address_for(MessageKind::EVMC_CALL), None);
+        assert_eq!(r.created_address_for(MessageKind::EVMC_CALLCODE), None);
+        assert_eq!(r.created_address_for(MessageKind::EVMC_DELEGATECALL), None);
+    }
+
+    #[test]
+    fn call_result_from_ffi_has_no_create_address() {
+        let f = ffi::evmc_result {
+            status_code: StatusCode::EVMC_SUCCESS,
+            gas_left: 0,
+            gas_refund: 0,
+            output_data: std::ptr::null(),
+            output_size: 0,
+            release: None,
+            create_address: Address { bytes: [0xaa; 20] },
+            padding: [0u8; 4],
+        };
+
+        let r: ExecutionResult = f.into();
+
+        // The conversion itself does not know the message kind.
         assert!(r.create_address.is_some());
+        assert_eq!(r.created_address_for(MessageKind::EVMC_CALL), None);
+    }
+
+    #[test]
+    fn result_round_trip_through_ffi() {
+        let create_address = Address { bytes: [0xaa; 20] };
+        let r = ExecutionResult {
+            status_code: StatusCode::EVMC_REVERT,
+            gas_left: 1337,
+            gas_refund: -21,
+            output: Box::from([0xde, 0xad, 0xbe, 0xef]),
+            create_address: Some(create_address),
+        };
+
+        let mut f: ffi::evmc_result = r.into();
+        assert_eq!(f.padding, [0u8; 4]);
+        // padding must never be interpreted as data
+        f.padding = [0xff; 4];
+        let r: ExecutionResult = f.into();
+
+        assert_eq!(r.status_code, StatusCode::EVMC_REVERT);
+        assert_eq!(r.gas_left, 1337);
+        assert_eq!(r.gas_refund, -21);
+        assert_eq!(*r.output, [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(r.create_address, Some(create_address));
+
+        let r = ExecutionResult {
+            output: Box::default(),
+            create_address: None,
+            ..r
+        };
+        let r: ExecutionResult = ffi::evmc_result::from(r).into();
+        assert!(r.output.is_empty());
+        // a missing create address is encoded as the zero address and decoded as missing again
+        assert_eq!(r.create_address, None);
     }
 
     #[test]
@@ -864,7 +1282,7 @@ mod tests {
         assert_eq!(b.status_code, StatusCode::EVMC_SUCCESS);
         assert_eq!(b.gas_left, 2);
         assert_eq!(b.output, Box::default());
-        assert_eq!(b.create_address, Some(Address::default()));
+        assert_eq!(b.create_address, None);
     }
 
     #[test]
@@ -897,6 +1315,49 @@ mod tests {
         assert_eq!(b.status_code, StatusCode::EVMC_SUCCESS);
         assert_eq!(b.gas_left, 2);
         assert_eq!(b.output, Box::from(data));
-        assert_eq!(b.create_address, Some(Address::default()));
+        assert_eq!(b.create_address, None);
+    }
+
+    #[test]
+    fn message_builder_uses_defaults_of_a_call() {
+        let message = ExecutionMessageBuilder::new().build().unwrap();
+        assert_eq!(message.kind, MessageKind::EVMC_CALL);
+        assert_eq!(message.gas, i64::MAX);
+        assert_eq!(message.recipient, Address { bytes: [0; 20] });
+        assert!(message.input.is_empty());
+        assert!(message.code_hash.is_none());
+
+        let input = [1, 2, 3];
+        let message = ExecutionMessageBuilder::new()
+            .gas(100)
+            .sender(Address { bytes: [1; 20] })
+            .input(&input)
+            .build()
+            .unwrap();
+        assert_eq!(message.gas, 100);
+        assert_eq!(message.sender, Address { bytes: [1; 20] });
+        assert_eq!(message.input, input);
+    }
+
+    #[test]
+    fn message_builder_only_accepts_salt_for_create2() {
+        let salt = Uint256 { bytes: [1; 32] };
+        let message = ExecutionMessageBuilder::new()
+            .kind(MessageKind::EVMC_CREATE2)
+            .create2_salt(salt)
+            .build()
+            .unwrap();
+        assert_eq!(message.create2_salt, salt);
+
+        for kind in [MessageKind::EVMC_CALL, MessageKind::EVMC_CREATE] {
+            assert_eq!(
+                ExecutionMessageBuilder::new()
+                    .kind(kind)
+                    .create2_salt(salt)
+                    .build()
+                    .unwrap_err(),
+                BuildMessageError::SaltWithoutCreate2
+            );
+        }
     }
 }
@@ -0,0 +1,159 @@
+//! Minimal decoding of ABI encoded return values, intended for asserting on the output of an
+//! execution without depending on a full ABI implementation.
+use evmc_vm::Address;
+
+use crate::types::u256;
+
+const WORD_SIZE: usize = 32;
+
+/// The types which can be decoded by [`decode_return`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiType {
+    Uint256,
+    Address,
+    Bool,
+    Bytes,
+    String,
+}
+
+/// A decoded value of the corresponding [`AbiType`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiValue {
+    Uint256(u256),
+    Address(Address),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    String(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The output ends before a word or the data of a dynamic value.
+    OutputTooShort,
+    /// The offset or length of a dynamic value does not fit into the output.
+    OffsetOutOfRange,
+    /// An address has non-zero bytes in its 12 padding bytes.
+    InvalidAddress,
+    /// A bool is neither 0 nor 1.
+    InvalidBool,
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+}
+
+/// Decodes `output` as the ABI encoded tuple of `types`. Static types are decoded in place from
+/// the head, dynamic types (`bytes` and `string`) are decoded from the offset stored in the head.
+/// Trailing bytes are ignored.
+pub fn decode_return(output: &[u8], types: &[AbiType]) -> Result<Vec<AbiValue>, DecodeError> {
+    types
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| {
+            let head = read_word(output, index * WORD_SIZE)?;
+            match ty {
+                AbiType::Uint256 => Ok(AbiValue::Uint256(u256::from_be_bytes(head))),
+                AbiType::Address => {
+                    if head[..WORD_SIZE - 20].iter().any(|byte| *byte != 0) {
+                        return Err(DecodeError::InvalidAddress);
+                    }
+                    Ok(AbiValue::Address(u256::from_be_bytes(head).into()))
+                }
+                AbiType::Bool => match u256::from_be_bytes(head) {
+                    value if value == u256::ZERO => Ok(AbiValue::Bool(false)),
+                    value if value == u256::ONE => Ok(AbiValue::Bool(true)),
+                    _ => Err(DecodeError::InvalidBool),
+                },
+                AbiType::Bytes => {
+                    read_dynamic(output, head).map(|bytes| AbiValue::Bytes(bytes.to_vec()))
+                }
+                AbiType::String => {
+                    let bytes = read_dynamic(output, head)?;
+                    let string =
+                        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)?;
+                    Ok(AbiValue::String(string))
+                }
+            }
+        })
+        .collect()
+}
+
+fn read_word(output: &[u8], offset: usize) -> Result<[u8; WORD_SIZE], DecodeError> {
+    output
+        .get(offset..)
+        .and_then(|tail| tail.first_chunk())
+        .copied()
+        .ok_or(DecodeError::OutputTooShort)
+}
+
+fn to_usize(word: [u8; WORD_SIZE]) -> Result<usize, DecodeError> {
+    u64::try_from(u256::from_be_bytes(word))
+        .ok()
+        .and_then(|value| usize::try_from(value).ok())
+        .ok_or(DecodeError::OffsetOutOfRange)
+}
+
+/// Reads the length prefixed data at the offset stored in `head`.
+fn read_dynamic(output: &[u8], head: [u8; WORD_SIZE]) -> Result<&[u8], DecodeError> {
+    let offset = to_usize(head)?;
+    let len = to_usize(read_word(output, offset)?)?;
+    let start = offset + WORD_SIZE;
+    let end = start
+        .checked_add(len)
+        .ok_or(DecodeError::OffsetOutOfRange)?;
+    output.get(start..end).ok_or(DecodeError::OutputTooShort)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        abi::{AbiType, AbiValue, DecodeError, decode_return},
+        types::u256,
+    };
+
+    fn word(value: u64) -> [u8; 32] {
+        u256::from(value).to_be_bytes()
+    }
+
+    #[test]
+    fn decode_return_decodes_static_types() {
+        let output = [word(42), word(1)].concat();
+        assert_eq!(
+            decode_return(&output, &[AbiType::Uint256, AbiType::Bool]),
+            Ok(vec![
+                AbiValue::Uint256(u256::from(42u8)),
+                AbiValue::Bool(true)
+            ])
+        );
+
+        let output = [word(42), word(2)].concat();
+        assert_eq!(
+            decode_return(&output, &[AbiType::Uint256, AbiType::Bool]),
+            Err(DecodeError::InvalidBool)
+        );
+        assert_eq!(
+            decode_return(&output[..63], &[AbiType::Uint256, AbiType::Bool]),
+            Err(DecodeError::OutputTooShort)
+        );
+    }
+
+    #[test]
+    fn decode_return_decodes_dynamic_string() {
+        let mut data = [0; 32];
+        data[..5].copy_from_slice(b"hello");
+        let output = [word(32), word(5), data].concat();
+        assert_eq!(
+            decode_return(&output, &[AbiType::String]),
+            Ok(vec![AbiValue::String("hello".to_owned())])
+        );
+
+        let output = [word(32), word(33), data].concat();
+        assert_eq!(
+            decode_return(&output, &[AbiType::String]),
+            Err(DecodeError::OutputTooShort)
+        );
+        let output = [word(u64::MAX), word(5), data].concat();
+        assert_eq!(
+            decode_return(&output, &[AbiType::Bytes]),
+            Err(DecodeError::OutputTooShort)
+        );
+    }
+}
@@ -1,37 +1,283 @@
-use std::process;
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    process,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use evmc_vm::{
     EvmcVm, ExecutionContext, ExecutionMessage, ExecutionResult, Revision, SetOptionError,
     StatusCode as EvmcStatusCode, StepResult, StepStatusCode as EvmcStepStatusCode,
-    SteppableEvmcVm, Uint256, ffi::evmc_capabilities,
+    SteppableEvmcVm, Uint256,
 };
 
+#[cfg(feature = "precompiles")]
+use crate::precompiles::execute_precompile;
+#[cfg(feature = "blake2b")]
+use crate::types::hash_cache::Blake2bHasher;
 use crate::{
-    ffi::EVMC_CAPABILITY,
-    interpreter::Interpreter,
+    interpreter::{ExecutionSnapshot, Interpreter, load_delegated_code},
     types::{
-        CodeAnalysisCache, LoggingObserver, Memory, NoOpObserver, ObserverType, Stack,
-        hash_cache::HashCache, u256,
+        CodeAnalysisCache, Eip3155Json, GasBreakdownObserver, JsonTraceObserver, LoggingObserver,
+        MemoryBudget, NoOpObserver, ObserverType, Stack,
+        hash_cache::{HashCache, HashCachePolicy, Hasher, KeccakHasher},
+        u256,
     },
 };
 
+/// A hash cache which is shared by all instances which enabled the option "shared-hash-cache" and
+/// use Keccak-256.
+static SHARED_HASH_CACHE: LazyLock<Arc<HashCache>> = LazyLock::new(Arc::default);
+
+/// Like [`SHARED_HASH_CACHE`], but for instances which use BLAKE2b.
+#[cfg(feature = "blake2b")]
+static SHARED_BLAKE2B_HASH_CACHE: LazyLock<Arc<HashCache>> =
+    LazyLock::new(|| Arc::new(HashCache::default().with_hasher::<Blake2bHasher>()));
+
+thread_local! {
+    /// The deadline of the outermost execution which is currently running on this thread, see
+    /// [`DeadlineScope::enter`].
+    static CURRENT_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Keeps the deadline of an outermost execution available to its nested executions, see the
+/// option "execution-timeout-ms".
+struct DeadlineScope {
+    deadline: Option<Instant>,
+    /// The deadline to restore when the scope ends, if this scope replaced it.
+    previous: Option<Option<Instant>>,
+}
+
+impl DeadlineScope {
+    /// Returns the deadline of an execution at `depth`. The outermost execution computes the
+    /// deadline from `timeout`, which stays available to nested executions until the returned
+    /// scope is dropped. Nested calls are executed by the host on the same thread as their caller,
+    /// so nested executions use the deadline of the outermost execution instead, which limits the
+    /// time of the whole call tree. If there is no `timeout`, the thread state is not touched.
+    fn enter(timeout: Option<Duration>, depth: i32) -> Self {
+        let Some(timeout) = timeout else {
+            return Self {
+                deadline: None,
+                previous: None,
+            };
+        };
+        if depth > 0
+            && let Some(deadline) = CURRENT_DEADLINE.get()
+        {
+            return Self {
+                deadline: Some(deadline),
+                previous: None,
+            };
+        }
+        let deadline = Instant::now() + timeout;
+        let previous = CURRENT_DEADLINE.replace(Some(deadline));
+        Self {
+            deadline: Some(deadline),
+            previous: Some(previous),
+        }
+    }
+}
+
+impl Drop for DeadlineScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CURRENT_DEADLINE.set(previous);
+        }
+    }
+}
+
+/// Resets the cancellation flag of an instance when its outermost execution ends, see
+/// [`EvmRs::cancellation`].
+struct CancellationScope<'a>(Option<&'a AtomicBool>);
+
+impl<'a> CancellationScope<'a> {
+    /// Nested executions belong to the same cancellable execution as their caller, so only the
+    /// outermost execution resets the flag.
+    fn enter(cancellation: &'a AtomicBool, depth: i32) -> Self {
+        Self((depth == 0).then_some(cancellation))
+    }
+}
+
+impl Drop for CancellationScope<'_> {
+    fn drop(&mut self) {
+        if let Some(cancellation) = self.0 {
+            cancellation.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The hash function used by SHA3, see the option "hash-function".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashFunction {
+    Keccak256,
+    #[cfg(feature = "blake2b")]
+    Blake2b,
+}
+
+impl HashFunction {
+    fn hash_fn(self) -> fn(&[u8]) -> u256 {
+        match self {
+            Self::Keccak256 => KeccakHasher::hash,
+            #[cfg(feature = "blake2b")]
+            Self::Blake2b => Blake2bHasher::hash,
+        }
+    }
+
+    /// The hash cache shared by all instances which use this hash function. Each hash function
+    /// needs its own cache, because the cached hashes depend on it.
+    fn shared_hash_cache(self) -> &'static Arc<HashCache> {
+        match self {
+            Self::Keccak256 => &SHARED_HASH_CACHE,
+            #[cfg(feature = "blake2b")]
+            Self::Blake2b => &SHARED_BLAKE2B_HASH_CACHE,
+        }
+    }
+}
+
+/// Where the logging and tracing observers, the gas breakdown and panics caught at the FFI boundary
+/// write to, see the option "trace-output".
+#[derive(Debug)]
+enum TraceOutput {
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+impl TraceOutput {
+    fn writer(&self) -> Box<dyn Write + '_> {
+        match self {
+            Self::Stdout => Box::new(io::stdout()),
+            Self::Stderr => Box::new(io::stderr()),
+            // &File implements Write, so nested executions can share the file
+            Self::File(file) => Box::new(file),
+        }
+    }
+}
+
 pub struct EvmRs {
     observer_type: ObserverType,
-    hash_cache: HashCache,
+    trace_output: TraceOutput,
+    max_total_memory_bytes: u64,
+    max_output_bytes: u64,
+    /// The maximum number of values on the stack, see [`Stack::with_limit`].
+    max_stack_size: usize,
+    cancellation: Arc<AtomicBool>,
+    /// The wall-clock time after which an execution is aborted with `EVMC_INTERNAL_ERROR`.
+    execution_timeout: Option<Duration>,
+    hash_cache: Arc<HashCache>,
+    /// The size of the hash cache of this instance, which is kept while the shared hash cache is
+    /// used, see the options "hash-cache-size" and "shared-hash-cache".
+    hash_cache_size: usize,
+    /// The policy for the lookups of this instance in whichever hash cache is in use, see the
+    /// options "hash-cache-min-cacheable-len" and "hash-cache-enabled". It is not stored in the
+    /// hash cache, so that it does not affect other instances sharing the cache.
+    hash_cache_policy: HashCachePolicy,
+    /// Whether the hash cache is shared with all other instances using the same hash function,
+    /// see the option "shared-hash-cache".
+    shared_hash_cache: bool,
+    hash_function: HashFunction,
     code_analysis_cache_steppable: CodeAnalysisCache<true>,
     code_analysis_cache_non_steppable: CodeAnalysisCache<false>,
 }
 
-impl EvmcVm for EvmRs {
-    fn init() -> Self {
+impl EvmRs {
+    /// Creates an instance which uses `hash_cache` instead of its own hash cache. This allows
+    /// sharing the cache between multiple instances.
+    pub fn with_hash_cache(hash_cache: Arc<HashCache>) -> Self {
         EvmRs {
             observer_type: ObserverType::NoOp,
-            hash_cache: HashCache::default(),
+            trace_output: TraceOutput::Stdout,
+            max_total_memory_bytes: u64::MAX,
+            max_output_bytes: u64::MAX,
+            max_stack_size: Stack::CAPACITY,
+            cancellation: Arc::new(AtomicBool::new(false)),
+            execution_timeout: None,
+            hash_cache,
+            hash_cache_size: HashCache::DEFAULT_CACHE_SIZE,
+            hash_cache_policy: HashCachePolicy::default(),
+            shared_hash_cache: false,
+            hash_function: HashFunction::Keccak256,
             code_analysis_cache_steppable: CodeAnalysisCache::default(),
             code_analysis_cache_non_steppable: CodeAnalysisCache::default(),
         }
     }
 
+    /// Replaces the hash cache with the shared or a new cache of the configured size, depending on
+    /// the configured sharing and hash function.
+    fn update_hash_cache(&mut self) {
+        self.hash_cache = if self.shared_hash_cache {
+            Arc::clone(self.hash_function.shared_hash_cache())
+        } else {
+            Arc::new(
+                HashCache::new(self.hash_cache_size).with_hash_fn(self.hash_function.hash_fn()),
+            )
+        };
+    }
+
+    /// Returns the flag which cancels the running executions of this instance when set. Executions
+    /// abort with `EVMC_INTERNAL_ERROR`. The flag is reset when an outermost execution ends, so a
+    /// cancellation issued before an execution starts aborts it, but does not affect later
+    /// executions.
+    pub fn cancellation(&self) -> &Arc<AtomicBool> {
+        &self.cancellation
+    }
+
+    /// Returns the writer for diagnostic messages of this instance, see the option "trace-output".
+    pub(crate) fn log_writer(&self) -> Box<dyn Write + '_> {
+        self.trace_output.writer()
+    }
+
+    /// Writes the hit and miss counters of all caches enabled at compile time to `writer`.
+    #[allow(unused_variables, clippy::unused_self)]
+    pub fn write_cache_stats(&self, writer: &mut impl Write) -> io::Result<()> {
+        #[cfg(feature = "code-analysis-cache")]
+        {
+            writeln!(
+                writer,
+                "code analysis cache (non-steppable): {}",
+                self.code_analysis_cache_non_steppable.stats()
+            )?;
+            writeln!(
+                writer,
+                "code analysis cache (steppable): {}",
+                self.code_analysis_cache_steppable.stats()
+            )?;
+        }
+        #[cfg(feature = "hash-cache")]
+        writeln!(writer, "hash cache: {}", self.hash_cache.stats())?;
+        Ok(())
+    }
+
+    /// Executes `code` as a dry run with the caches of this instance, see
+    /// [`Interpreter::estimate`], and returns the result together with the gas used.
+    pub fn estimate_gas(
+        &self,
+        revision: Revision,
+        code: &[u8],
+        message: &ExecutionMessage,
+        context: &mut ExecutionContext,
+    ) -> (ExecutionResult, u64) {
+        Interpreter::estimate(
+            revision,
+            message,
+            context,
+            code,
+            &self.code_analysis_cache_non_steppable,
+            &self.hash_cache,
+        )
+    }
+}
+
+impl EvmcVm for EvmRs {
+    fn init() -> Self {
+        Self::with_hash_cache(Arc::default())
+    }
+
     fn execute<'a>(
         &self,
         revision: Revision,
@@ -39,26 +285,81 @@ impl EvmcVm for EvmRs {
         message: &'a ExecutionMessage,
         context: Option<&'a mut ExecutionContext<'a>>,
     ) -> ExecutionResult {
-        assert_ne!(
-            EVMC_CAPABILITY,
-            evmc_capabilities::EVMC_CAPABILITY_PRECOMPILES
-        );
+        #[cfg(feature = "precompiles")]
+        if let Some(result) =
+            execute_precompile(revision, &message.code_address, message.input, message.gas)
+        {
+            return result;
+        }
         let Some(context) = context else {
-            // Since EVMC_CAPABILITY_PRECOMPILES is not supported context must be set.
-            // If this is not the case it violates the EVMC spec and is an irrecoverable error.
+            // The context may only be missing when executing precompiles, but evmrs does not
+            // advertise EVMC_CAPABILITY_PRECOMPILES, so this violates the EVMC spec and is an
+            // irrecoverable error.
             process::abort();
         };
-        let interpreter = Interpreter::new(
-            revision,
-            message,
-            context,
-            code,
-            &self.code_analysis_cache_non_steppable,
-            &self.hash_cache,
-        );
+        let _cancellation = CancellationScope::enter(&self.cancellation, message.depth);
+        let deadline = DeadlineScope::enter(self.execution_timeout, message.depth);
+        let memory_budget = MemoryBudget::enter(self.max_total_memory_bytes, message.depth);
+        let delegation = load_delegated_code(revision, code, context);
+        let interpreter = if let Some((_, code)) = &delegation {
+            match Interpreter::new_delegated(
+                revision,
+                message,
+                context,
+                code,
+                &self.code_analysis_cache_non_steppable,
+                &self.hash_cache,
+            ) {
+                Ok(interpreter) => interpreter,
+                Err(err) => return err.into(),
+            }
+        } else {
+            match Interpreter::new(
+                revision,
+                message,
+                context,
+                code,
+                &self.code_analysis_cache_non_steppable,
+                &self.hash_cache,
+            ) {
+                Ok(interpreter) => interpreter,
+                Err(err) => return err.into(),
+            }
+        };
+        let mut interpreter = interpreter
+            .with_cancellation(&self.cancellation)
+            .with_max_output_len(self.max_output_bytes)
+            .with_hash_cache_policy(self.hash_cache_policy);
+        if self.max_stack_size != Stack::CAPACITY {
+            interpreter = interpreter.with_stack_limit(self.max_stack_size);
+        }
+        if let Some(budget) = memory_budget.budget() {
+            interpreter = interpreter.with_memory_budget(budget);
+        }
+        if let Some(deadline) = deadline.deadline {
+            interpreter = interpreter.with_deadline(deadline);
+        }
         match self.observer_type {
             ObserverType::NoOp => interpreter.run(&mut NoOpObserver()),
-            ObserverType::Logging => interpreter.run(&mut LoggingObserver::new(std::io::stdout())),
+            ObserverType::Logging => {
+                interpreter.run(&mut LoggingObserver::new(self.trace_output.writer()))
+            }
+            ObserverType::GasBreakdown => {
+                let mut observer = GasBreakdownObserver::new();
+                let result = interpreter.run(&mut observer);
+                // the gas of nested frames is included in the calls of their caller
+                if message.depth == 0 {
+                    writeln!(self.trace_output.writer(), "{}", observer.breakdown()).unwrap();
+                }
+                result
+            }
+            ObserverType::Json => {
+                let mut observer =
+                    JsonTraceObserver::new(Eip3155Json::new(self.trace_output.writer()));
+                let result = interpreter.run(&mut observer);
+                observer.finish(&result);
+                result
+            }
         }
     }
 
@@ -66,6 +367,26 @@ impl EvmcVm for EvmRs {
         match (key, value) {
             ("logging", "true") => self.observer_type = ObserverType::Logging,
             ("logging", "false") => self.observer_type = ObserverType::NoOp,
+            ("gas-breakdown", "true") => self.observer_type = ObserverType::GasBreakdown,
+            ("gas-breakdown", "false") => {
+                if matches!(self.observer_type, ObserverType::GasBreakdown) {
+                    self.observer_type = ObserverType::NoOp;
+                }
+            }
+            ("tracing", "json") => self.observer_type = ObserverType::Json,
+            ("tracing", "false") => {
+                if matches!(self.observer_type, ObserverType::Json) {
+                    self.observer_type = ObserverType::NoOp;
+                }
+            }
+            ("trace-output", "stdout") => self.trace_output = TraceOutput::Stdout,
+            ("trace-output", "stderr") => self.trace_output = TraceOutput::Stderr,
+            ("trace-output", path) => {
+                let Ok(file) = OpenOptions::new().create(true).append(true).open(path) else {
+                    return Err(SetOptionError::InvalidValue);
+                };
+                self.trace_output = TraceOutput::File(file);
+            }
             ("code-analysis-cache-size", size) => {
                 if let Ok(size) = size.parse::<usize>() {
                     self.code_analysis_cache_steppable = CodeAnalysisCache::new(size);
@@ -76,11 +397,68 @@ impl EvmcVm for EvmRs {
             }
             ("hash-cache-size", size) => {
                 if let Ok(size) = size.parse::<usize>() {
-                    self.hash_cache = HashCache::new(size);
+                    self.hash_cache_size = size;
+                    self.update_hash_cache();
+                } else {
+                    return Err(SetOptionError::InvalidValue);
+                }
+            }
+            ("hash-cache-min-cacheable-len", len) => {
+                if let Ok(len) = len.parse::<usize>() {
+                    self.hash_cache_policy.min_cacheable_len = len;
+                } else {
+                    return Err(SetOptionError::InvalidValue);
+                }
+            }
+            ("hash-cache-enabled", "true" | "false") => {
+                self.hash_cache_policy.enabled = value == "true";
+            }
+            ("shared-hash-cache", "true" | "false") => {
+                self.shared_hash_cache = value == "true";
+                self.update_hash_cache();
+            }
+            ("hash-function", hash_function) => {
+                self.hash_function = match hash_function {
+                    "keccak256" => HashFunction::Keccak256,
+                    #[cfg(feature = "blake2b")]
+                    "blake2b" => HashFunction::Blake2b,
+                    _ => return Err(SetOptionError::InvalidValue),
+                };
+                self.update_hash_cache();
+            }
+            ("dump-cache-stats", "stderr") => {
+                return self
+                    .write_cache_stats(&mut io::stderr())
+                    .map_err(|_| SetOptionError::InvalidValue);
+            }
+            ("shared-hash-cache" | "dump-cache-stats", _) => {
+                return Err(SetOptionError::InvalidValue);
+            }
+            ("max-total-memory-bytes", limit) => {
+                if let Ok(limit) = limit.parse::<u64>() {
+                    self.max_total_memory_bytes = limit;
+                } else {
+                    return Err(SetOptionError::InvalidValue);
+                }
+            }
+            ("execution-timeout-ms", timeout) => {
+                if let Ok(timeout) = timeout.parse::<u64>() {
+                    self.execution_timeout = Some(Duration::from_millis(timeout));
                 } else {
                     return Err(SetOptionError::InvalidValue);
                 }
             }
+            ("max-output-bytes", limit) => {
+                if let Ok(limit) = limit.parse::<u64>() {
+                    self.max_output_bytes = limit;
+                } else {
+                    return Err(SetOptionError::InvalidValue);
+                }
+            }
+            ("max-stack-size", size) => match size.parse::<usize>() {
+                Ok(size) if size > 0 => self.max_stack_size = size,
+                _ => return Err(SetOptionError::InvalidValue),
+            },
             _ => (),
         }
         Ok(())
@@ -114,7 +492,7 @@ impl SteppableEvmcVm for EvmRs {
                 },
                 revision,
                 pc,
-                gas_left: gas_refund,
+                gas_left: message.gas,
                 gas_refund,
                 output: Box::default(),
                 stack: stack.to_owned(),
@@ -122,43 +500,118 @@ impl SteppableEvmcVm for EvmRs {
                 last_call_return_data: Box::from(last_call_return_data),
             };
         }
-        assert_ne!(
-            EVMC_CAPABILITY,
-            evmc_capabilities::EVMC_CAPABILITY_PRECOMPILES
-        );
         let Some(context) = context else {
-            // Since EVMC_CAPABILITY_PRECOMPILES is not supported context must be set.
+            // Precompiles are never executed step by step, so the context must be set.
             // If this is not the case it violates the EVMC spec and is an irrecoverable error.
             process::abort();
         };
-        let stack = Stack::new(&stack.iter().map(|i| u256::from(*i)).collect::<Vec<_>>());
-        let memory = Memory::new(memory);
-        let interpreter = Interpreter::new_steppable(
-            revision,
-            message,
-            context,
-            code,
+        let _cancellation = CancellationScope::enter(&self.cancellation, message.depth);
+        let deadline = DeadlineScope::enter(self.execution_timeout, message.depth);
+        let memory_budget = MemoryBudget::enter(self.max_total_memory_bytes, message.depth);
+        let snapshot = ExecutionSnapshot::new(
             pc as usize,
+            message.gas,
             gas_refund,
             stack,
+            self.max_stack_size,
             memory,
-            Box::from(last_call_return_data),
+            last_call_return_data,
+        );
+        let interpreter = match Interpreter::resume(
+            revision,
+            message,
+            context,
+            code,
+            snapshot,
             Some(steps),
             &self.code_analysis_cache_steppable,
             &self.hash_cache,
-        );
+        ) {
+            Ok(interpreter) => interpreter,
+            Err(err) => return err.into(),
+        };
+        let mut interpreter = interpreter
+            .with_cancellation(&self.cancellation)
+            .with_max_output_len(self.max_output_bytes)
+            .with_hash_cache_policy(self.hash_cache_policy);
+        if let Some(budget) = memory_budget.budget() {
+            interpreter = interpreter.with_memory_budget(budget);
+        }
+        if let Some(deadline) = deadline.deadline {
+            interpreter = interpreter.with_deadline(deadline);
+        }
         match self.observer_type {
             ObserverType::NoOp => interpreter.run(&mut NoOpObserver()),
-            ObserverType::Logging => interpreter.run(&mut LoggingObserver::new(std::io::stdout())),
+            ObserverType::Logging => {
+                interpreter.run(&mut LoggingObserver::new(self.trace_output.writer()))
+            }
+            ObserverType::GasBreakdown => {
+                let mut observer = GasBreakdownObserver::new();
+                let result: StepResult = interpreter.run(&mut observer);
+                // the gas of nested frames is included in the calls of their caller
+                if message.depth == 0 {
+                    writeln!(self.trace_output.writer(), "{}", observer.breakdown()).unwrap();
+                }
+                result
+            }
+            ObserverType::Json => {
+                let mut observer =
+                    JsonTraceObserver::new(Eip3155Json::new(self.trace_output.writer()));
+                let result: StepResult = interpreter.run(&mut observer);
+                // the summary is only written once the execution halted
+                if result.step_status_code != EvmcStepStatusCode::EVMC_STEP_RUNNING {
+                    observer.finish(&ExecutionResult {
+                        status_code: result.status_code,
+                        gas_left: result.gas_left,
+                        gas_refund: result.gas_refund,
+                        output: result.output.clone(),
+                        create_address: None,
+                    });
+                }
+                result
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use evmc_vm::EvmcVm;
+    use std::{
+        cell::Cell,
+        process,
+        rc::Rc,
+        sync::{Arc, atomic::Ordering},
+        thread,
+        time::Duration,
+    };
 
-    use crate::evmrs::EvmRs;
+    use evmc_vm::{
+        Address, EvmcVm, Revision, SetOptionError, StatusCode, StepStatusCode, SteppableEvmcVm,
+    };
+
+    #[cfg(feature = "blake2b")]
+    use crate::types::hash_cache::{Blake2bHasher, Hasher, KeccakHasher};
+    use crate::{
+        evmrs::{EvmRs, TraceOutput},
+        types::{
+            Account, MockExecutionMessage, ObserverType, Opcode, ReferenceHost,
+            hash_cache::{HashCache, HashCachePolicy},
+            u256,
+        },
+        utils::constants::GAS_COLD_ACCOUNT_ACCESS,
+    };
+
+    /// Returns the EIP-7702 delegation designator of `target`.
+    fn delegation_designator(target: Address) -> Vec<u8> {
+        let mut designator = vec![0xef, 0x01, 0x00];
+        designator.extend_from_slice(&target.bytes);
+        designator
+    }
+
+    /// Hashes `data` with the hash cache and the hash cache policy of `evm`, like SHA3 does.
+    fn hash(evm: &EvmRs, data: &[u8]) -> u256 {
+        evm.hash_cache.hash_with_policy(data, evm.hash_cache_policy)
+    }
 
     #[test]
     fn set_option_with_cache_sizes_correctly_handles_input() {
@@ -189,4 +642,545 @@ mod tests {
         #[cfg(feature = "hash-cache")]
         assert_eq!(evm.hash_cache.capacity(), 100);
     }
+
+    #[test]
+    fn write_cache_stats_lists_enabled_caches() {
+        let mut evm = EvmRs::init();
+        assert!(evm.set_option("dump-cache-stats", "stderr").is_ok());
+        assert!(matches!(
+            evm.set_option("dump-cache-stats", "stdout"),
+            Err(SetOptionError::InvalidValue)
+        ));
+
+        let mut stats = Vec::new();
+        evm.write_cache_stats(&mut stats).unwrap();
+        let stats = String::from_utf8(stats).unwrap();
+        assert_eq!(
+            stats.contains("code analysis cache"),
+            cfg!(feature = "code-analysis-cache")
+        );
+        assert_eq!(stats.contains("hash cache"), cfg!(feature = "hash-cache"));
+    }
+
+    #[test]
+    fn set_option_with_hash_cache_policy_correctly_handles_input() {
+        let mut evm = EvmRs::init();
+
+        assert!(evm.set_option("hash-cache-min-cacheable-len", "64").is_ok());
+        assert!(
+            evm.set_option("hash-cache-min-cacheable-len", "invalid")
+                .is_err()
+        );
+        hash(&evm, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm.hash_cache.entries(), 0);
+
+        assert!(evm.set_option("hash-cache-min-cacheable-len", "0").is_ok());
+        assert!(evm.set_option("hash-cache-enabled", "false").is_ok());
+        hash(&evm, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm.hash_cache.entries(), 0);
+
+        assert!(evm.set_option("hash-cache-enabled", "true").is_ok());
+        hash(&evm, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm.hash_cache.entries(), 1);
+
+        // the policy is kept when the cache is replaced
+        assert!(evm.set_option("hash-cache-enabled", "false").is_ok());
+        assert!(evm.set_option("hash-cache-size", "100").is_ok());
+        hash(&evm, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm.hash_cache.entries(), 0);
+    }
+
+    #[test]
+    fn hash_cache_policy_does_not_affect_other_instances_sharing_the_cache() {
+        let hash_cache = Arc::new(HashCache::default());
+        let mut evm1 = EvmRs::with_hash_cache(Arc::clone(&hash_cache));
+        let evm2 = EvmRs::with_hash_cache(Arc::clone(&hash_cache));
+        assert!(evm1.set_option("hash-cache-enabled", "false").is_ok());
+        assert_eq!(hash_cache.policy(), HashCachePolicy::default());
+
+        hash(&evm1, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(hash_cache.entries(), 0);
+        hash(&evm2, &[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(hash_cache.entries(), 1);
+    }
+
+    #[test]
+    fn set_option_with_tracing_correctly_handles_input() {
+        let mut evm = EvmRs::init();
+
+        assert!(evm.set_option("tracing", "json").is_ok());
+        assert!(matches!(evm.observer_type, ObserverType::Json));
+
+        assert!(evm.set_option("tracing", "false").is_ok());
+        assert!(matches!(evm.observer_type, ObserverType::NoOp));
+
+        assert!(evm.set_option("logging", "true").is_ok());
+        assert!(evm.set_option("tracing", "false").is_ok());
+        assert!(matches!(evm.observer_type, ObserverType::Logging));
+    }
+
+    #[test]
+    fn set_option_with_gas_breakdown_writes_to_trace_output() {
+        let path = std::env::temp_dir().join(format!("evmrs-gas-breakdown-{}.log", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut evm = EvmRs::init();
+        assert!(evm.set_option("logging", "true").is_ok());
+        assert!(evm.set_option("gas-breakdown", "false").is_ok());
+        assert!(matches!(evm.observer_type, ObserverType::Logging));
+        assert!(evm.set_option("gas-breakdown", "true").is_ok());
+        assert!(
+            evm.set_option("trace-output", path.to_str().unwrap())
+                .is_ok()
+        );
+
+        let code = [Opcode::Push0 as u8, Opcode::Stop as u8];
+        for depth in [0, 1] {
+            let message = MockExecutionMessage {
+                depth,
+                ..Default::default()
+            };
+            let message = message.into();
+            let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+            let mut context = host.as_execution_context();
+            let result = evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context));
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        }
+        let breakdown = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // only the outermost frame is reported
+        assert_eq!(breakdown.lines().count(), 1);
+        assert!(breakdown.starts_with("computation: "));
+
+        assert!(evm.set_option("gas-breakdown", "false").is_ok());
+        assert!(matches!(evm.observer_type, ObserverType::NoOp));
+    }
+
+    #[test]
+    fn set_option_with_trace_output_routes_json_trace_to_file() {
+        let path = std::env::temp_dir().join(format!("evmrs-json-trace-{}.log", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut evm = EvmRs::init();
+        assert!(evm.set_option("tracing", "json").is_ok());
+        assert!(
+            evm.set_option("trace-output", path.to_str().unwrap())
+                .is_ok()
+        );
+
+        let message = MockExecutionMessage::default().into();
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut context = host.as_execution_context();
+        let code = [Opcode::Push0 as u8, Opcode::Stop as u8];
+        let result = evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // observers are not called if feature tail-call is enabled
+        if cfg!(not(feature = "tail-call")) {
+            assert!(trace.starts_with("{\"pc\":0,"));
+        }
+    }
+
+    #[test]
+    fn set_option_with_trace_output_routes_logging_to_file() {
+        let path = std::env::temp_dir().join(format!("evmrs-trace-{}.log", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut evm = EvmRs::init();
+        assert!(evm.set_option("logging", "true").is_ok());
+        assert!(
+            evm.set_option("trace-output", path.to_str().unwrap())
+                .is_ok()
+        );
+
+        let message = MockExecutionMessage::default().into();
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut context = host.as_execution_context();
+        let code = [Opcode::Push0 as u8, Opcode::Stop as u8];
+        let result = evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // observers are not called if feature tail-call is enabled
+        if cfg!(not(feature = "tail-call")) {
+            assert!(trace.starts_with("Push0, "));
+        }
+
+        assert!(evm.set_option("trace-output", "stderr").is_ok());
+        assert!(matches!(evm.trace_output, TraceOutput::Stderr));
+        assert!(matches!(
+            evm.set_option("trace-output", "/nonexistent/dir/trace.log"),
+            Err(SetOptionError::InvalidValue)
+        ));
+        assert!(matches!(evm.trace_output, TraceOutput::Stderr));
+    }
+
+    #[test]
+    fn set_option_with_max_total_memory_bytes_correctly_handles_input() {
+        let mut evm = EvmRs::init();
+        assert_eq!(evm.max_total_memory_bytes, u64::MAX);
+
+        assert!(evm.set_option("max-total-memory-bytes", "1024").is_ok());
+        assert_eq!(evm.max_total_memory_bytes, 1024);
+
+        assert!(evm.set_option("max-total-memory-bytes", "invalid").is_err());
+        assert_eq!(evm.max_total_memory_bytes, 1024);
+    }
+
+    #[test]
+    fn set_option_with_max_output_bytes_correctly_handles_input() {
+        let mut evm = EvmRs::init();
+        assert_eq!(evm.max_output_bytes, u64::MAX);
+
+        assert!(evm.set_option("max-output-bytes", "1024").is_ok());
+        assert_eq!(evm.max_output_bytes, 1024);
+
+        assert!(evm.set_option("max-output-bytes", "invalid").is_err());
+        assert_eq!(evm.max_output_bytes, 1024);
+    }
+
+    #[test]
+    fn max_stack_size_limits_number_of_pushes() {
+        let mut evm = EvmRs::init();
+        let message = MockExecutionMessage::default().into();
+        let execute = |evm: &EvmRs, pushes| {
+            let code = vec![Opcode::Push0 as u8; pushes];
+            let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+            let mut context = host.as_execution_context();
+            evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context))
+                .status_code
+        };
+        assert_eq!(execute(&evm, 1024), StatusCode::EVMC_SUCCESS);
+        assert_eq!(execute(&evm, 1025), StatusCode::EVMC_STACK_OVERFLOW);
+
+        assert!(evm.set_option("max-stack-size", "2048").is_ok());
+        assert_eq!(execute(&evm, 2048), StatusCode::EVMC_SUCCESS);
+        assert_eq!(execute(&evm, 2049), StatusCode::EVMC_STACK_OVERFLOW);
+
+        assert!(evm.set_option("max-stack-size", "0").is_err());
+        assert!(evm.set_option("max-stack-size", "invalid").is_err());
+        assert_eq!(evm.max_stack_size, 2048);
+    }
+
+    #[test]
+    fn instances_with_shared_hash_cache_see_each_others_inserts() {
+        let hash_cache = Arc::new(HashCache::default());
+        let evm1 = EvmRs::with_hash_cache(Arc::clone(&hash_cache));
+        let evm2 = EvmRs::with_hash_cache(Arc::clone(&hash_cache));
+        assert!(Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+
+        evm1.hash_cache.hash(&[1; 32]);
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm2.hash_cache.entries(), 1);
+    }
+
+    #[test]
+    fn set_option_with_execution_timeout_correctly_handles_input() {
+        let mut evm = EvmRs::init();
+        assert_eq!(evm.execution_timeout, None);
+
+        assert!(evm.set_option("execution-timeout-ms", "10").is_ok());
+        assert_eq!(evm.execution_timeout, Some(Duration::from_millis(10)));
+
+        assert!(evm.set_option("execution-timeout-ms", "invalid").is_err());
+        assert_eq!(evm.execution_timeout, Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn execution_timeout_limits_the_whole_call_tree() {
+        let mut evm = EvmRs::init();
+        assert!(evm.set_option("execution-timeout-ms", "20").is_ok());
+        let evm = Rc::new(evm);
+
+        // The callee alone finishes well within the timeout, but only starts after the timeout of
+        // the call tree passed. It is long enough for the deadline to be checked.
+        let mut callee = vec![Opcode::JumpDest as u8; 2048];
+        callee.push(Opcode::Stop as u8);
+        let callee_status = Rc::new(Cell::new(None));
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.call_router_mut()
+            .route(Address::from(u256::from(0xaau8)), {
+                let evm = Rc::clone(&evm);
+                let callee_status = Rc::clone(&callee_status);
+                move |message| {
+                    thread::sleep(Duration::from_millis(40));
+                    let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+                    let mut context = host.as_execution_context();
+                    let result =
+                        evm.execute(Revision::EVMC_CANCUN, &callee, message, Some(&mut context));
+                    callee_status.set(Some(result.status_code));
+                    result
+                }
+            });
+
+        let code = [
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push1 as u8,
+            0xaa,
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+            Opcode::Stop as u8,
+        ];
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut context = host.as_execution_context();
+        let result = evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(callee_status.get(), Some(StatusCode::EVMC_INTERNAL_ERROR));
+    }
+
+    #[cfg(feature = "blake2b")]
+    #[test]
+    fn sha3_uses_hash_function_selected_by_option() {
+        let mut evm = EvmRs::init();
+        for (option, hash_fn) in [
+            ("blake2b", Blake2bHasher::hash as fn(&[u8]) -> u256),
+            ("keccak256", KeccakHasher::hash),
+        ] {
+            assert!(evm.set_option("hash-function", option).is_ok());
+            // inputs of 32 and 64 bytes take the cached path
+            for len in [0, 10, 32, 64] {
+                let message = MockExecutionMessage::default().into();
+                let code = [
+                    Opcode::Push1 as u8,
+                    len,
+                    Opcode::Push0 as u8,
+                    Opcode::Sha3 as u8,
+                    Opcode::Push0 as u8,
+                    Opcode::MStore as u8,
+                    Opcode::Push1 as u8,
+                    32,
+                    Opcode::Push0 as u8,
+                    Opcode::Return as u8,
+                ];
+                // hash twice to also hit the cache
+                for _ in 0..2 {
+                    let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+                    let mut context = host.as_execution_context();
+                    let result =
+                        evm.execute(Revision::EVMC_CANCUN, &code, &message, Some(&mut context));
+                    assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+                    let expected = hash_fn(&vec![0; len as usize]).to_be_bytes();
+                    assert_eq!(*result.output, expected);
+                }
+            }
+        }
+        assert_ne!(
+            Blake2bHasher::hash(&[]),
+            KeccakHasher::hash(&[]),
+            "backends must differ for this test to be meaningful"
+        );
+    }
+
+    #[cfg(feature = "blake2b")]
+    #[test]
+    fn set_option_with_hash_function_keeps_hash_cache_settings() {
+        let mut evm1 = EvmRs::init();
+        let mut evm2 = EvmRs::init();
+        assert!(evm1.set_option("hash-cache-size", "100").is_ok());
+        assert!(evm1.set_option("hash-cache-enabled", "false").is_ok());
+        assert!(evm1.set_option("hash-function", "blake2b").is_ok());
+        assert_eq!(hash(&evm1, &[1; 32]), Blake2bHasher::hash(&[1; 32]));
+        #[cfg(feature = "hash-cache")]
+        {
+            assert_eq!(evm1.hash_cache.capacity(), 100);
+            assert_eq!(evm1.hash_cache.entries(), 0);
+        }
+
+        // the shared cache of the selected hash function is used
+        assert!(evm1.set_option("hash-cache-enabled", "true").is_ok());
+        assert!(evm1.set_option("shared-hash-cache", "true").is_ok());
+        assert_eq!(hash(&evm1, &[1; 32]), Blake2bHasher::hash(&[1; 32]));
+        assert!(evm2.set_option("shared-hash-cache", "true").is_ok());
+        assert!(!Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+        assert_eq!(hash(&evm2, &[1; 32]), KeccakHasher::hash(&[1; 32]));
+        assert!(evm2.set_option("hash-function", "blake2b").is_ok());
+        assert!(Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+
+        assert!(matches!(
+            evm1.set_option("hash-function", "invalid"),
+            Err(SetOptionError::InvalidValue)
+        ));
+        assert!(Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+    }
+
+    #[test]
+    fn cancellation_before_execute_aborts_it_and_is_reset_afterwards() {
+        let evm = EvmRs::init();
+        evm.cancellation().store(true, Ordering::Relaxed);
+        let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+        let mut context = host.as_execution_context();
+        let message = MockExecutionMessage {
+            gas: 1_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        // JUMPDEST PUSH0 JUMP, which runs long enough for the flag to be checked
+        let code = [
+            Opcode::JumpDest as u8,
+            Opcode::Push0 as u8,
+            Opcode::Jump as u8,
+        ];
+        let result = evm.execute(Revision::EVMC_PRAGUE, &code, &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_INTERNAL_ERROR);
+        assert!(!evm.cancellation().load(Ordering::Relaxed));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+        let mut context = host.as_execution_context();
+        let result = evm.execute(Revision::EVMC_PRAGUE, &code, &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_OUT_OF_GAS);
+        assert!(!evm.cancellation().load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn execute_with_empty_code_succeeds_with_all_gas_left() {
+        let evm = EvmRs::init();
+        let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+        let mut context = host.as_execution_context();
+        let message = MockExecutionMessage {
+            gas: 12_345,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result = evm.execute(Revision::EVMC_PRAGUE, &[], &message, Some(&mut context));
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.gas_left, 12_345);
+        assert_eq!(result.gas_refund, 0);
+        assert!(result.output.is_empty());
+    }
+
+    #[test]
+    fn execute_delegated_code_in_storage_context_of_delegating_account() {
+        let delegating = Address::from(u256::from(0xaau8));
+        let target = Address::from(u256::from(0xbbu8));
+        let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+        host.set_account(
+            target,
+            Account {
+                // SSTORE(1, 1)
+                code: vec![
+                    Opcode::Push1 as u8,
+                    1,
+                    Opcode::Push1 as u8,
+                    1,
+                    Opcode::SStore as u8,
+                ],
+                ..Default::default()
+            },
+        );
+        let designator = delegation_designator(target);
+        host.set_account(
+            delegating,
+            Account {
+                code: designator.clone(),
+                ..Default::default()
+            },
+        );
+
+        let evm = EvmRs::init();
+        let message = MockExecutionMessage {
+            recipient: delegating,
+            code_address: delegating,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result = evm.execute(
+            Revision::EVMC_PRAGUE,
+            &designator,
+            &message,
+            Some(&mut host.as_execution_context()),
+        );
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(
+            host.account(&delegating).unwrap().storage.get(&u256::ONE),
+            Some(&u256::ONE)
+        );
+        assert!(host.account(&target).unwrap().storage.is_empty());
+    }
+
+    #[test]
+    fn execute_delegation_to_empty_account_keeps_all_gas() {
+        let target = Address::from(u256::from(0xbbu8));
+        let designator = delegation_designator(target);
+        let evm = EvmRs::init();
+        // the access to the delegation target is charged by the caller
+        for gas in [0, 10, GAS_COLD_ACCOUNT_ACCESS as i64] {
+            let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+            let message = MockExecutionMessage {
+                gas,
+                ..Default::default()
+            };
+            let message = message.into();
+            let result = evm.execute(
+                Revision::EVMC_PRAGUE,
+                &designator,
+                &message,
+                Some(&mut host.as_execution_context()),
+            );
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.gas_left, gas);
+        }
+    }
+
+    #[test]
+    fn step_n_with_non_running_status_returns_gas_of_message() {
+        let evm = EvmRs::init();
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result = evm.step_n(
+            Revision::EVMC_CANCUN,
+            &[],
+            &message,
+            None,
+            StepStatusCode::EVMC_STEP_STOPPED,
+            0,
+            7,
+            &mut [],
+            &mut [],
+            &mut [],
+            1,
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.gas_left, 100);
+        assert_eq!(result.gas_refund, 7);
+    }
+
+    #[test]
+    fn set_option_with_shared_hash_cache_correctly_handles_input() {
+        let mut evm1 = EvmRs::init();
+        let mut evm2 = EvmRs::init();
+        assert!(!Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+
+        assert!(evm1.set_option("shared-hash-cache", "true").is_ok());
+        assert!(evm2.set_option("shared-hash-cache", "true").is_ok());
+        assert!(Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+
+        assert!(matches!(
+            evm2.set_option("shared-hash-cache", "invalid"),
+            Err(SetOptionError::InvalidValue)
+        ));
+        assert!(Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+
+        assert!(evm2.set_option("hash-cache-size", "100").is_ok());
+        assert!(evm2.set_option("shared-hash-cache", "true").is_ok());
+        assert!(evm2.set_option("shared-hash-cache", "false").is_ok());
+        assert!(!Arc::ptr_eq(&evm1.hash_cache, &evm2.hash_cache));
+        #[cfg(feature = "hash-cache")]
+        assert_eq!(evm2.hash_cache.capacity(), 100);
+    }
 }
@@ -1,6 +1,14 @@
 use std::{
+    cell::Cell,
     ffi::{CStr, c_char},
-    panic,
+    io::Write,
+    mem::MaybeUninit,
+    panic::{self, UnwindSafe},
+    slice,
+    sync::{
+        Arc, Once,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
 use evmc_vm::{
@@ -19,15 +27,20 @@ use crate::{
     },
 };
 
+/// The number of instances which were created with [`evmc_create_evmrs`] and not destroyed yet.
+pub(super) static LIVE_INSTANCES: AtomicU64 = AtomicU64::new(0);
+
 static EVM_RS_NAME: &CStr = c"evmrs";
 static EVM_RS_VERSION: &CStr = c"0.1.0";
 
-/// Evmrs is currently only capable of executing EVM1 bytecode and not EWASM or precompiled
-/// contracts.
-pub const EVMC_CAPABILITY: evmc_capabilities = evmc_capabilities::EVMC_CAPABILITY_EVM1;
+/// Evmrs executes EVM1 bytecode but not EWASM. It does not advertise
+/// `EVMC_CAPABILITY_PRECOMPILES` because it only implements some precompiled contracts, see
+/// [`crate::precompiles`].
+pub const EVMC_CAPABILITY: evmc_capabilities_flagset =
+    evmc_capabilities::EVMC_CAPABILITY_EVM1 as evmc_capabilities_flagset;
 
 extern "C" fn __evmc_get_capabilities(_instance: *mut evmc_vm_t) -> evmc_capabilities_flagset {
-    EVMC_CAPABILITY as evmc_capabilities_flagset
+    EVMC_CAPABILITY
 }
 
 extern "C" fn __evmc_set_option(
@@ -89,11 +102,56 @@ pub(super) extern "C" fn evmc_create_evmrs() -> *mut evmc_vm_t {
     };
 
     let container = EvmcContainer::<EvmRs>::new(new_instance);
+    LIVE_INSTANCES.fetch_add(1, Ordering::Relaxed);
 
     // Release ownership to EVMC.
     EvmcContainer::into_ffi_pointer(container)
 }
 
+/// Returns the number of instances which were created and not destroyed yet, including the
+/// instances wrapped by steppable instances. This allows hosts to detect leaked instances.
+#[unsafe(no_mangle)]
+pub(super) extern "C" fn evmrs_live_instance_count() -> u64 {
+    LIVE_INSTANCES.load(Ordering::Relaxed)
+}
+
+/// Returns a handle to the cancellation flag of an instance created with [`evmc_create_evmrs`].
+/// The handle stays valid until the instance is destroyed and can be passed to
+/// [`evmrs_set_cancelled`] from any thread.
+#[unsafe(no_mangle)]
+pub(super) extern "C" fn evmrs_cancellation_handle(instance: *mut evmc_vm_t) -> *const AtomicBool {
+    let token = LifetimeToken;
+
+    assert!(!instance.is_null());
+
+    // SAFETY:
+    // `instance` is not null. The caller must make sure that `instance` points to a valid
+    // `EvmcContainer::<EvmRs>` (which is the case it it was created with evmc_create_evmrs).
+    let container = unsafe { ref_from_ptr_scoped(instance as *const EvmcContainer<EvmRs>, &token) };
+    Arc::as_ptr(container.cancellation())
+}
+
+/// Set or reset the cancellation flag behind `handle`. While the flag is set, the running
+/// executions of the corresponding instance abort promptly with `EVMC_INTERNAL_ERROR`. A flag set
+/// while no execution is running aborts the next one. The flag is reset when the outermost
+/// execution ends.
+///
+/// # Safety
+/// `handle` must have been returned by [`evmrs_cancellation_handle`] and the corresponding instance
+/// must not have been destroyed yet.
+#[unsafe(no_mangle)]
+pub(super) unsafe extern "C" fn evmrs_set_cancelled(handle: *const AtomicBool, cancelled: bool) {
+    let token = LifetimeToken;
+
+    assert!(!handle.is_null());
+
+    // SAFETY:
+    // `handle` is not null. The caller must make sure that it points to the cancellation flag of a
+    // live instance.
+    let cancellation = unsafe { ref_from_ptr_scoped(handle, &token) };
+    cancellation.store(cancelled, Ordering::Relaxed);
+}
+
 extern "C" fn __evmc_destroy(instance: *mut evmc_vm_t) {
     if !instance.is_null() {
         // Acquire ownership from EVMC. This will deallocate it at the end of the scope.
@@ -103,6 +161,7 @@ extern "C" fn __evmc_destroy(instance: *mut evmc_vm_t) {
         unsafe {
             EvmcContainer::<EvmRs>::from_ffi_pointer(instance);
         }
+        LIVE_INSTANCES.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -118,7 +177,7 @@ extern "C" fn __evmc_execute(
     let token = LifetimeToken;
 
     if instance.is_null()
-        || (host.is_null() && EVMC_CAPABILITY != evmc_capabilities::EVMC_CAPABILITY_PRECOMPILES)
+        || host.is_null()
         || message.is_null()
         || (code.is_null() && code_size != 0)
     {
@@ -146,7 +205,7 @@ extern "C" fn __evmc_execute(
     let container =
         unsafe { ref_mut_from_ptr_scoped(instance as *mut EvmcContainer<EvmRs>, &token) };
 
-    panic::catch_unwind(|| {
+    catch_panic(&mut container.log_writer(), || {
         let mut execution_context = if host.is_null() {
             None
         } else {
@@ -164,32 +223,232 @@ extern "C" fn __evmc_execute(
             execution_context.as_mut(),
         )
     })
-    .unwrap_or(ExecutionResult {
-        status_code: evmc_status_code::EVMC_INTERNAL_ERROR,
-        gas_left: 0,
-        gas_refund: 0,
-        output: Box::default(),
-        create_address: None,
-    })
     .into()
 }
 
+thread_local! {
+    /// The location of the last panic on this thread, recorded by the hook installed by
+    /// [`install_panic_location_hook`].
+    static PANIC_LOCATION: Cell<Option<String>> = const { Cell::new(None) };
+}
+
+/// Installs a panic hook which records the location of each panic and then delegates to the
+/// previously installed hook, so that the hooks of other crates keep working.
+fn install_panic_location_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            PANIC_LOCATION.set(info.location().map(ToString::to_string));
+            previous(info);
+        }));
+    });
+}
+
+/// Runs `f` and turns a panic into an `EVMC_INTERNAL_ERROR` result. The panic message and location
+/// are written to `log`, so that the reason of the failure is not lost.
+fn catch_panic(
+    log: &mut impl Write,
+    f: impl FnOnce() -> ExecutionResult + UnwindSafe,
+) -> ExecutionResult {
+    install_panic_location_hook();
+    PANIC_LOCATION.set(None);
+
+    panic::catch_unwind(f).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("<non-string payload>");
+        let location = PANIC_LOCATION
+            .take()
+            .unwrap_or_else(|| "<unknown location>".to_owned());
+        // Logging is best effort and must not panic itself.
+        let _ = writeln!(log, "evmrs panicked at {location}: {message}");
+
+        ExecutionResult {
+            status_code: evmc_status_code::EVMC_INTERNAL_ERROR,
+            gas_left: 0,
+            gas_refund: 0,
+            output: Box::default(),
+            create_address: None,
+        }
+    })
+}
+
+/// Execute `count` messages with the same instance, host and revision in a single call. The i-th
+/// message is executed with the i-th code and its result is written to the i-th entry of
+/// `results`. All executions share the caches of the instance.
+///
+/// The results are owned by the caller, which must release each of them with its `release`
+/// function, exactly as if it was returned by `execute`.
+///
+/// # Safety
+/// `instance`, `host` and `context` must be valid as for `execute`. `messages`, `codes` and
+/// `code_sizes` must point to `count` valid elements each and every code must be valid for its
+/// size. `results` must point to writable memory for `count` results, which is not required to be
+/// initialized.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub(super) unsafe extern "C" fn evmrs_execute_batch(
+    instance: *mut evmc_vm_t,
+    host: *const evmc_host_interface,
+    context: *mut evmc_host_context,
+    revision: evmc_revision,
+    messages: *const evmc_message,
+    codes: *const *const u8,
+    code_sizes: *const usize,
+    count: usize,
+    results: *mut evmc_result,
+) {
+    if count == 0 {
+        return;
+    }
+    if messages.is_null() || codes.is_null() || code_sizes.is_null() || results.is_null() {
+        // These are irrecoverable errors that violate the API contract.
+        std::process::abort();
+    }
+
+    let token = LifetimeToken;
+    // SAFETY:
+    // The caller must make sure that `messages` has `count` elements.
+    let messages = unsafe { slice_from_raw_parts_scoped(messages, count, &token) };
+    // SAFETY:
+    // The caller must make sure that `codes` has `count` elements.
+    let codes = unsafe { slice_from_raw_parts_scoped(codes, count, &token) };
+    // SAFETY:
+    // The caller must make sure that `code_sizes` has `count` elements.
+    let code_sizes = unsafe { slice_from_raw_parts_scoped(code_sizes, count, &token) };
+    // SAFETY:
+    // The caller must make sure that `results` is writable for `count` results. They may be
+    // uninitialized, which `MaybeUninit` allows.
+    let results =
+        unsafe { slice::from_raw_parts_mut(results.cast::<MaybeUninit<evmc_result>>(), count) };
+
+    for (((message, code), code_size), result) in
+        messages.iter().zip(codes).zip(code_sizes).zip(results)
+    {
+        result.write(__evmc_execute(
+            instance, host, context, revision, message, *code, *code_size,
+        ));
+    }
+}
+
+/// Executes `code` as a dry run, see [`crate::interpreter::Interpreter::estimate`], and returns the
+/// gas used. Storage writes, selfdestructs and logs of the executed frame are discarded, while the
+/// status code and the output are the same as in a real run. If `result` is not null, the result
+/// is written to it and must be released by the caller like a result returned by `execute`.
+///
+/// Nested calls are executed by the host and apply their effects there, so the host must provide
+/// a context which can be snapshotted before and reverted after the dry run. See
+/// [`crate::types::DryRunContext`] for the cases which are not supported.
+///
+/// # Safety
+/// `instance`, `host`, `context`, `message` and `code` must be valid as for `execute`, except that
+/// `host` must not be null. `result` must be null or point to writable memory for a result, which
+/// is not required to be initialized.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub(super) unsafe extern "C" fn evmrs_estimate_gas(
+    instance: *mut evmc_vm_t,
+    host: *const evmc_host_interface,
+    context: *mut evmc_host_context,
+    revision: evmc_revision,
+    message: *const evmc_message,
+    code: *const u8,
+    code_size: usize,
+    result: *mut evmc_result,
+) -> u64 {
+    let token = LifetimeToken;
+
+    if instance.is_null()
+        || host.is_null()
+        || message.is_null()
+        || (code.is_null() && code_size != 0)
+    {
+        // These are irrecoverable errors that violate the API contract.
+        std::process::abort();
+    }
+
+    // SAFETY:
+    // `message` is not null. The caller must make sure it points to a valid `ExecutionMessage`.
+    let execution_message = ExecutionMessage::from(unsafe { ref_from_ptr_scoped(message, &token) });
+
+    let code_ref = if code.is_null() {
+        &[]
+    } else {
+        // SAFETY:
+        // `code` is not null and `code_size > 0`. The caller must make sure that the size is
+        // valid.
+        unsafe { slice_from_raw_parts_scoped(code, code_size, &token) }
+    };
+
+    // SAFETY:
+    // `instance` is not null. The caller must make sure that `instance` points to a valid
+    // `EvmcContainer::<EvmRs>` (which is the case it it was created with evmc_create_evmrs).
+    let container = unsafe { ref_from_ptr_scoped(instance as *const EvmcContainer<EvmRs>, &token) };
+
+    // SAFETY:
+    // `host` is not null. The caller must make sure that it points to a valid
+    // `evmc_host_interface`.
+    let host = unsafe { ref_from_ptr_scoped(host, &token) };
+
+    let execution_result = catch_panic(&mut container.log_writer(), || {
+        let mut execution_context = ExecutionContext::new(host, context);
+        let (execution_result, _) = container.estimate_gas(
+            revision,
+            code_ref,
+            &execution_message,
+            &mut execution_context,
+        );
+        execution_result
+    });
+    // A panic is reported with no gas left, so it counts as having used all gas.
+    let gas_used = execution_message
+        .gas
+        .saturating_sub(execution_result.gas_left)
+        .max(0) as u64;
+    if !result.is_null() {
+        // SAFETY:
+        // `result` is not null. The caller must make sure that it points to writable memory for a
+        // result.
+        unsafe { result.write(execution_result.into()) };
+    }
+    gas_used
+}
+
 #[cfg(test)]
-mod tests {
-    use evmc_vm::ffi::{evmc_capabilities_flagset, evmc_set_option_result};
+pub(super) mod tests {
+    use std::{
+        panic::AssertUnwindSafe,
+        sync::{Mutex, PoisonError, atomic::Ordering},
+    };
+
+    use evmc_vm::{EvmcVm, Revision, StatusCode, ffi::evmc_set_option_result};
 
-    use crate::ffi::{
-        EVMC_CAPABILITY,
-        evmc_vm::{__evmc_destroy, __evmc_get_capabilities, __evmc_set_option, evmc_create_evmrs},
+    use crate::{
+        ffi::evmc_vm::{
+            __evmc_destroy, __evmc_get_capabilities, __evmc_set_option, EVMC_CAPABILITY, EvmRs,
+            catch_panic, evmc_create_evmrs, evmrs_cancellation_handle, evmrs_live_instance_count,
+            evmrs_set_cancelled,
+        },
+        interpreter::Interpreter,
+        types::{
+            CodeAnalysisCache, MockExecutionContextTrait, MockExecutionMessage, NoOpObserver,
+            Opcode, hash_cache::HashCache,
+        },
     };
 
+    /// Serializes all tests which create instances, so that the live instance count is exact.
+    pub(in crate::ffi) static INSTANCES_LOCK: Mutex<()> = Mutex::new(());
+
     #[test]
     fn create_set_option_destroy() {
+        let _lock = INSTANCES_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
         let vm = evmc_create_evmrs();
-        assert_eq!(
-            __evmc_get_capabilities(vm),
-            EVMC_CAPABILITY as evmc_capabilities_flagset
-        );
+        assert_eq!(__evmc_get_capabilities(vm), EVMC_CAPABILITY);
         assert_eq!(
             __evmc_set_option(vm, std::ptr::null(), std::ptr::null()),
             evmc_set_option_result::EVMC_SET_OPTION_INVALID_NAME,
@@ -212,4 +471,100 @@ mod tests {
         );
         __evmc_destroy(vm);
     }
+
+    #[test]
+    fn cancellation_handle_sets_and_resets_flag() {
+        let _lock = INSTANCES_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let vm = evmc_create_evmrs();
+        let handle = evmrs_cancellation_handle(vm);
+        // SAFETY:
+        // `handle` was returned by evmrs_cancellation_handle and `vm` is not destroyed yet.
+        let cancellation = unsafe { &*handle };
+        assert!(!cancellation.load(Ordering::Relaxed));
+
+        // SAFETY:
+        // `handle` was returned by evmrs_cancellation_handle and `vm` is not destroyed yet.
+        unsafe { evmrs_set_cancelled(handle, true) };
+        assert!(cancellation.load(Ordering::Relaxed));
+
+        // SAFETY:
+        // `handle` was returned by evmrs_cancellation_handle and `vm` is not destroyed yet.
+        unsafe { evmrs_set_cancelled(handle, false) };
+        assert!(!cancellation.load(Ordering::Relaxed));
+
+        __evmc_destroy(vm);
+    }
+
+    #[test]
+    fn live_instance_count_returns_to_zero_after_destroy() {
+        let _lock = INSTANCES_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(evmrs_live_instance_count(), 0);
+
+        let vms: Vec<_> = (1..=3)
+            .map(|count| {
+                let vm = evmc_create_evmrs();
+                assert_eq!(evmrs_live_instance_count(), count);
+                vm
+            })
+            .collect();
+        for vm in vms {
+            __evmc_destroy(vm);
+        }
+
+        assert_eq!(evmrs_live_instance_count(), 0);
+    }
+
+    #[test]
+    fn catch_panic_logs_message_and_returns_internal_error() {
+        // The mock has no expectations, so the host access of BALANCE panics.
+        let code = [Opcode::Push0 as u8, Opcode::Balance as u8];
+        let message = MockExecutionMessage::default().into();
+        let mut context = MockExecutionContextTrait::new();
+
+        let mut log = Vec::new();
+        let result = catch_panic(
+            &mut log,
+            AssertUnwindSafe(|| {
+                Interpreter::new(
+                    Revision::EVMC_CANCUN,
+                    &message,
+                    &mut context,
+                    &code,
+                    &CodeAnalysisCache::default(),
+                    &HashCache::default(),
+                )
+                .unwrap()
+                .run(&mut NoOpObserver())
+            }),
+        );
+
+        assert_eq!(result.status_code, StatusCode::EVMC_INTERNAL_ERROR);
+        let log = String::from_utf8(log).unwrap();
+        assert!(log.starts_with("evmrs panicked at "), "{log}");
+        assert!(log.contains(".rs:"), "{log}");
+        assert!(log.contains("No matching expectation found"), "{log}");
+    }
+
+    #[test]
+    fn caught_panics_are_written_to_trace_output() {
+        let path = std::env::temp_dir().join(format!("evmrs-panic-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut evm = EvmRs::init();
+        assert!(
+            evm.set_option("trace-output", path.to_str().unwrap())
+                .is_ok()
+        );
+
+        let result = catch_panic(&mut evm.log_writer(), || panic!("boom"));
+
+        assert_eq!(result.status_code, StatusCode::EVMC_INTERNAL_ERROR);
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(log.starts_with("evmrs panicked at "), "{log}");
+        assert!(log.ends_with(": boom\n"), "{log}");
+    }
 }
@@ -2,8 +2,6 @@ mod evmc_vm;
 mod steppable_evmc_vm;
 use std::slice;
 
-pub use evmc_vm::EVMC_CAPABILITY;
-
 /// This type is indented to be used to enforce the correct lifetime for references created from
 /// pointers obtained via FFI. It is assumed that the lifetime of the pointer is bound by the
 /// lifetime of the token.
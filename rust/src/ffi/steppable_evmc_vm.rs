@@ -1,20 +1,19 @@
-use std::{ffi::c_void, panic, slice};
+use std::{ffi::c_void, panic, slice, sync::atomic::Ordering};
 
 use ::evmc_vm::{
     ExecutionContext, ExecutionMessage, StatusCode, StepResult, StepStatusCode,
     SteppableEvmcContainer, SteppableEvmcVm,
     ffi::{
-        evmc_bytes32, evmc_capabilities, evmc_host_interface, evmc_message, evmc_revision,
-        evmc_step_result, evmc_step_status_code, evmc_vm_steppable,
+        evmc_bytes32, evmc_host_interface, evmc_message, evmc_revision, evmc_step_result,
+        evmc_step_status_code, evmc_vm_steppable,
     },
 };
 
 use crate::{
     evmrs::EvmRs,
     ffi::{
-        LifetimeToken,
-        evmc_vm::{self, EVMC_CAPABILITY},
-        ref_from_ptr_scoped, ref_mut_from_ptr_scoped, slice_from_raw_parts_scoped,
+        LifetimeToken, evmc_vm, ref_from_ptr_scoped, ref_mut_from_ptr_scoped,
+        slice_from_raw_parts_scoped,
     },
 };
 
@@ -40,6 +39,8 @@ extern "C" fn __evmc_steppable_destroy(instance: *mut evmc_vm_steppable) {
         unsafe {
             SteppableEvmcContainer::<EvmRs>::from_ffi_pointer(instance);
         }
+        // The wrapped instance is destroyed without `__evmc_destroy`.
+        evmc_vm::LIVE_INSTANCES.fetch_sub(1, Ordering::Relaxed);
     }
 }
 
@@ -65,7 +66,7 @@ extern "C" fn __evmc_step_n(
     let token = LifetimeToken;
 
     if instance.is_null()
-        || (host.is_null() && EVMC_CAPABILITY != evmc_capabilities::EVMC_CAPABILITY_PRECOMPILES)
+        || host.is_null()
         || message.is_null()
         || (code.is_null() && code_size > 0)
         || (stack.is_null() && stack_size > 0)
@@ -166,11 +167,21 @@ extern "C" fn __evmc_step_n(
 
 #[cfg(test)]
 mod tests {
-    use crate::ffi::steppable_evmc_vm::{__evmc_steppable_destroy, evmc_create_steppable_evmrs};
+    use std::sync::PoisonError;
+
+    use crate::ffi::{
+        evmc_vm::{evmrs_live_instance_count, tests::INSTANCES_LOCK},
+        steppable_evmc_vm::{__evmc_steppable_destroy, evmc_create_steppable_evmrs},
+    };
 
     #[test]
     fn create_destroy() {
+        let _lock = INSTANCES_LOCK
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
         let vm = evmc_create_steppable_evmrs();
+        assert_eq!(evmrs_live_instance_count(), 1);
         __evmc_steppable_destroy(vm);
+        assert_eq!(evmrs_live_instance_count(), 0);
     }
 }
@@ -1,16 +1,44 @@
-use std::cmp::min;
+#[cfg(debug_assertions)]
+use std::io;
+use std::{
+    cmp::min,
+    collections::BTreeSet,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
 
 use evmc_vm::{
-    AccessStatus, ExecutionMessage, ExecutionResult, MessageFlags, MessageKind, Revision,
+    AccessStatus, Address, ExecutionMessage, ExecutionResult, MessageFlags, MessageKind, Revision,
     StatusCode, StepResult, StorageStatus, Uint256,
 };
 
+#[cfg(feature = "stack-precheck")]
+use crate::types::OPCODE_INFO;
+#[cfg(not(feature = "tail-call"))]
+use crate::types::opcode_at;
+#[cfg(debug_assertions)]
+use crate::types::{CodeByteType, Opcode, code_byte_type};
 use crate::{
     types::{
-        CodeAnalysisCache, CodeReader, ExecStatus, ExecutionContextTrait, FailStatus,
-        GetOpcodeError, Memory, Observer, Stack, hash_cache::HashCache, u256,
+        CodeAnalysisCache, CodeReader, DryRunContext, ExecStatus, ExecutionContextTrait,
+        FailStatus, FormattingObserver, GetOpcodeError, Memory, MemoryBudget, NoOpObserver,
+        Observer, OpcodeHistogramObserver, Stack, TraceFormat,
+        hash_cache::{HashCache, HashCachePolicy},
+        u256,
+    },
+    utils::{
+        Gas, GasRefund, SliceExt, check_min_revision, check_not_read_only,
+        constants::{
+            GAS_BASE, GAS_BLOCKHASH, GAS_CALL_STIPEND, GAS_COLD_ACCOUNT_ACCESS, GAS_COLD_SLOAD,
+            GAS_CREATE, GAS_EXP, GAS_EXP_BYTE, GAS_EXP_BYTE_FRONTIER, GAS_HIGH, GAS_INITCODE_WORD,
+            GAS_JUMPDEST, GAS_LOG, GAS_LOG_DATA, GAS_LOG_TOPIC, GAS_LOW, GAS_MID, GAS_NEW_ACCOUNT,
+            GAS_SELFDESTRUCT, GAS_SHA3, GAS_VERYLOW, GAS_WARM_ACCESS,
+        },
+        delegation_designator, word_size,
     },
-    utils::{Gas, GasRefund, SliceExt, check_min_revision, check_not_read_only, word_size},
 };
 
 type OpResult = Result<(), FailStatus>;
@@ -308,6 +336,145 @@ pub const fn get_jumptable<const STEPPABLE: bool>() -> &'static [OpFn<STEPPABLE>
     }
 }
 
+/// Writes one line for each entry of the jumptable, consisting of the index, the name of the
+/// opcode (`-` for undefined opcodes) and the address of the function.
+#[cfg(debug_assertions)]
+pub fn dump_jumptable<const STEPPABLE: bool>(mut writer: impl io::Write) -> io::Result<()> {
+    for (idx, func) in get_jumptable::<STEPPABLE>().iter().enumerate() {
+        let byte = idx as u8;
+        #[cfg(feature = "fn-ptr-conversion-dispatch")]
+        let is_opcode = code_byte_type(byte).0 != CodeByteType::DataOrInvalid
+            || byte == Opcode::NoOp as u8
+            || byte == Opcode::SkipNoOps as u8;
+        #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
+        let is_opcode = code_byte_type(byte).0 != CodeByteType::DataOrInvalid;
+        let name = if is_opcode {
+            // SAFETY:
+            // All bytes which are not data or invalid according to the code analysis are variants
+            // of Opcode.
+            format!("{:?}", unsafe { std::mem::transmute::<u8, Opcode>(byte) })
+        } else {
+            "-".to_owned()
+        };
+        writeln!(writer, "{idx:#04x} {name} {:p}", *func as *const ())?;
+    }
+    Ok(())
+}
+
+/// Resolves an EIP-7702 delegation designator (`0xef0100 || address`).
+///
+/// If `code` is a delegation designator and `revision` is Prague or later, the code of the
+/// delegation target is loaded from the host and returned together with the target address.
+/// Otherwise `None` is returned and `code` must be executed as is.
+pub fn load_delegated_code(
+    revision: Revision,
+    code: &[u8],
+    context: &mut dyn ExecutionContextTrait,
+) -> Option<(Address, Box<[u8]>)> {
+    if revision < Revision::EVMC_PRAGUE {
+        return None;
+    }
+    let target = delegation_designator(code)?;
+    let mut delegated_code = vec![0; context.get_code_size(&target)];
+    let len = context.copy_code(&target, 0, &mut delegated_code);
+    delegated_code.truncate(len);
+    Some((target, delegated_code.into_boxed_slice()))
+}
+
+/// Executes `code` with an [`OpcodeHistogramObserver`] and returns the result together with the
+/// observer. This is meant for analyzing workloads, not for production use. Observers are not
+/// called if feature tail-call is enabled, so the histogram is empty in that case.
+pub fn run_with_opcode_histogram(
+    revision: Revision,
+    message: &ExecutionMessage,
+    context: &mut dyn ExecutionContextTrait,
+    code: &[u8],
+) -> (ExecutionResult, OpcodeHistogramObserver) {
+    let mut observer = OpcodeHistogramObserver::new();
+    let result = match Interpreter::new(
+        revision,
+        message,
+        context,
+        code,
+        &CodeAnalysisCache::default(),
+        &HashCache::default(),
+    ) {
+        Ok(interpreter) => interpreter.run(&mut observer),
+        Err(err) => err.into(),
+    };
+    (result, observer)
+}
+
+/// Executes `code` and writes its trace in the given [`TraceFormat`], which is returned together
+/// with the result. Like [`run_with_opcode_histogram`], the trace is empty if feature tail-call is
+/// enabled.
+pub fn run_with_trace_format<F: TraceFormat>(
+    revision: Revision,
+    message: &ExecutionMessage,
+    context: &mut dyn ExecutionContextTrait,
+    code: &[u8],
+    format: F,
+) -> (ExecutionResult, F) {
+    let mut observer = FormattingObserver::new(format);
+    let result = match Interpreter::new(
+        revision,
+        message,
+        context,
+        code,
+        &CodeAnalysisCache::default(),
+        &HashCache::default(),
+    ) {
+        Ok(interpreter) => interpreter.run(&mut observer),
+        Err(err) => err.into(),
+    };
+    let format = observer.finish(&result);
+    (result, format)
+}
+
+/// Executes `code` with `observer`, which only observes this frame and not the nested frames
+/// executed by the host. Like [`run_with_opcode_histogram`], the observer is not called if feature
+/// tail-call is enabled.
+pub fn run_with_observer<O: Observer<false>>(
+    revision: Revision,
+    message: &ExecutionMessage,
+    context: &mut dyn ExecutionContextTrait,
+    code: &[u8],
+    observer: &mut O,
+) -> ExecutionResult {
+    match Interpreter::new(
+        revision,
+        message,
+        context,
+        code,
+        &CodeAnalysisCache::default(),
+        &HashCache::default(),
+    ) {
+        Ok(interpreter) => interpreter.run(observer),
+        Err(err) => err.into(),
+    }
+}
+
+/// Creates the code reader of an interpreter. With feature `verify-code-hash` the code must match
+/// `code_hash`, which is hashed with `hash_cache`.
+#[allow(unused_variables)]
+fn new_code_reader<'a, const STEPPABLE: bool>(
+    code: &'a [u8],
+    code_hash: Option<u256>,
+    pc: usize,
+    revision: Revision,
+    kind: MessageKind,
+    code_analysis_cache: &CodeAnalysisCache<STEPPABLE>,
+    hash_cache: &HashCache,
+) -> Result<CodeReader<'a, STEPPABLE>, FailStatus> {
+    #[cfg(feature = "verify-code-hash")]
+    CodeReader::<STEPPABLE>::verify_code_hash(code, code_hash, hash_cache)?;
+    CodeReader::new(code, code_hash, pc, revision, kind, code_analysis_cache)
+}
+
+/// The number of instructions after which the cancellation flag and the deadline of an
+/// interpreter are checked.
+pub const CANCELLATION_CHECK_INTERVAL: u32 = 1024;
+
 pub struct Interpreter<'a, const STEPPABLE: bool> {
     pub exec_status: ExecStatus,
     pub message: &'a ExecutionMessage<'a>,
@@ -322,9 +489,22 @@ pub struct Interpreter<'a, const STEPPABLE: bool> {
     pub last_call_return_data: Box<[u8]>,
     pub steps: Option<i32>,
     pub hash_cache: &'a HashCache,
+    /// The policy for looking up inputs of SHA3 in `hash_cache`.
+    pub hash_cache_policy: HashCachePolicy,
+    pub cancellation: Option<&'a AtomicBool>,
+    /// Abort the execution once this point in time has passed.
+    pub deadline: Option<Instant>,
+    /// Number of instructions until `cancellation` and `deadline` are checked the next time.
+    cancellation_countdown: u32,
+    /// The maximum length of the output of RETURN and REVERT.
+    pub max_output_len: u64,
+    /// Storage keys of the current account which are known to be warm. Accesses to them do not
+    /// need to be reported to the host again, because they stay warm for the rest of this frame.
+    warm_storage_keys: BTreeSet<u256>,
 }
 
 impl<'a> Interpreter<'a, false> {
+    /// Fails if the code can not be executed at all, see [`CodeReader::new`].
     pub fn new(
         revision: Revision,
         message: &'a ExecutionMessage,
@@ -332,18 +512,65 @@ impl<'a> Interpreter<'a, false> {
         code: &'a [u8],
         code_analysis_cache: &'a CodeAnalysisCache<false>,
         hash_cache: &'a HashCache,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, FailStatus> {
+        let code_reader = new_code_reader(
+            code,
+            message.code_hash.map(u256::from),
+            0,
+            revision,
+            message.kind,
+            code_analysis_cache,
+            hash_cache,
+        )?;
+        Ok(Self {
+            exec_status: ExecStatus::Running,
+            message,
+            context,
+            revision,
+            code_reader,
+            gas_left: Gas::new(message.gas),
+            gas_refund: GasRefund::new(0),
+            output: Box::default(),
+            stack: Stack::new(&[]),
+            memory: Memory::new(&[]),
+            last_call_return_data: Box::default(),
+            steps: None,
+            hash_cache,
+            hash_cache_policy: hash_cache.policy(),
+            cancellation: None,
+            deadline: None,
+            cancellation_countdown: CANCELLATION_CHECK_INTERVAL,
+            max_output_len: u64::MAX,
+            warm_storage_keys: BTreeSet::new(),
+        })
+    }
+
+    /// Creates an interpreter for code that was loaded via an EIP-7702 delegation designator
+    /// (see [`load_delegated_code`]). The access cost for the delegation target is charged by the
+    /// caller together with the access cost of the called account, so it is not charged here
+    /// again. The code hash of the message belongs to the designator and not to the delegated
+    /// code, so it is not used as key for the code analysis cache.
+    pub fn new_delegated(
+        revision: Revision,
+        message: &'a ExecutionMessage,
+        context: &'a mut dyn ExecutionContextTrait,
+        code: &'a [u8],
+        code_analysis_cache: &'a CodeAnalysisCache<false>,
+        hash_cache: &'a HashCache,
+    ) -> Result<Self, FailStatus> {
+        Ok(Self {
             exec_status: ExecStatus::Running,
             message,
             context,
             revision,
             code_reader: CodeReader::new(
                 code,
-                message.code_hash.map(u256::from),
+                None,
                 0,
+                revision,
+                message.kind,
                 code_analysis_cache,
-            ),
+            )?,
             gas_left: Gas::new(message.gas),
             gas_refund: GasRefund::new(0),
             output: Box::default(),
@@ -352,7 +579,42 @@ impl<'a> Interpreter<'a, false> {
             last_call_return_data: Box::default(),
             steps: None,
             hash_cache,
-        }
+            hash_cache_policy: hash_cache.policy(),
+            cancellation: None,
+            deadline: None,
+            cancellation_countdown: CANCELLATION_CHECK_INTERVAL,
+            max_output_len: u64::MAX,
+            warm_storage_keys: BTreeSet::new(),
+        })
+    }
+
+    /// Executes `code` as a dry run and returns the result together with the gas used. Storage
+    /// writes, selfdestructs and logs are discarded by a [`DryRunContext`], but output, status
+    /// code and gas are the same as in a real run, so estimating gas by searching for the lowest
+    /// limit which does not revert works. See [`DryRunContext`] for the cases in which this does
+    /// not hold and why the host must be able to revert the effects of the dry run afterwards.
+    pub fn estimate(
+        revision: Revision,
+        message: &ExecutionMessage,
+        context: &mut dyn ExecutionContextTrait,
+        code: &[u8],
+        code_analysis_cache: &CodeAnalysisCache<false>,
+        hash_cache: &HashCache,
+    ) -> (ExecutionResult, u64) {
+        let mut context = DryRunContext::new(context);
+        let result: ExecutionResult = match Interpreter::new(
+            revision,
+            message,
+            &mut context,
+            code,
+            code_analysis_cache,
+            hash_cache,
+        ) {
+            Ok(interpreter) => interpreter.run(&mut NoOpObserver()),
+            Err(err) => err.into(),
+        };
+        let gas_used = message.gas.saturating_sub(result.gas_left).max(0) as u64;
+        (result, gas_used)
     }
 }
 
@@ -371,31 +633,210 @@ impl<'a> Interpreter<'a, true> {
         steps: Option<i32>,
         code_analysis_cache: &'a CodeAnalysisCache<true>,
         hash_cache: &'a HashCache,
-    ) -> Self {
-        Self {
+    ) -> Result<Self, FailStatus> {
+        let snapshot = ExecutionSnapshot {
             exec_status: ExecStatus::Running,
-            message,
-            context,
-            revision,
-            code_reader: CodeReader::new(
-                code,
-                message.code_hash.map(u256::from),
-                pc,
-                code_analysis_cache,
-            ),
-            gas_left: Gas::new(message.gas),
-            gas_refund: GasRefund::new(gas_refund),
+            pc,
+            gas_left: message.gas,
+            gas_refund,
             output: Box::default(),
             stack,
             memory,
             last_call_return_data,
+        };
+        Self::resume(
+            revision,
+            message,
+            context,
+            code,
+            snapshot,
             steps,
+            code_analysis_cache,
             hash_cache,
-        }
+        )
+    }
+
+    /// Creates an interpreter which continues the execution captured in `snapshot`. The gas left
+    /// is taken from the snapshot and not from `message`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resume(
+        revision: Revision,
+        message: &'a ExecutionMessage,
+        context: &'a mut dyn ExecutionContextTrait,
+        code: &'a [u8],
+        snapshot: ExecutionSnapshot,
+        steps: Option<i32>,
+        code_analysis_cache: &'a CodeAnalysisCache<true>,
+        hash_cache: &'a HashCache,
+    ) -> Result<Self, FailStatus> {
+        let code_reader = new_code_reader(
+            code,
+            message.code_hash.map(u256::from),
+            snapshot.pc,
+            revision,
+            message.kind,
+            code_analysis_cache,
+            hash_cache,
+        )?;
+        Ok(Self {
+            exec_status: snapshot.exec_status,
+            message,
+            context,
+            revision,
+            code_reader,
+            gas_left: Gas::new(snapshot.gas_left),
+            gas_refund: GasRefund::new(snapshot.gas_refund),
+            output: snapshot.output,
+            stack: snapshot.stack,
+            memory: snapshot.memory,
+            last_call_return_data: snapshot.last_call_return_data,
+            steps,
+            hash_cache,
+            hash_cache_policy: hash_cache.policy(),
+            cancellation: None,
+            deadline: None,
+            cancellation_countdown: CANCELLATION_CHECK_INTERVAL,
+            max_output_len: u64::MAX,
+            warm_storage_keys: BTreeSet::new(),
+        })
     }
 }
 
-impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
+impl<'a, const STEPPABLE: bool> Interpreter<'a, STEPPABLE> {
+    /// Abort the execution with [`FailStatus::InternalError`] once `cancellation` is set. The flag
+    /// is only checked every [`CANCELLATION_CHECK_INTERVAL`] instructions, so it can be set from
+    /// another thread without slowing down the execution.
+    pub fn with_cancellation(mut self, cancellation: &'a AtomicBool) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Abort the execution with [`FailStatus::InternalError`] once `deadline` has passed. Like the
+    /// cancellation flag, the time is only checked every [`CANCELLATION_CHECK_INTERVAL`]
+    /// instructions, so that reading the clock does not dominate the runtime.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Fail RETURN and REVERT with [`FailStatus::OutOfMemory`] if their output is longer than
+    /// `max_output_len`. The check happens before the memory is expanded.
+    pub fn with_max_output_len(mut self, max_output_len: u64) -> Self {
+        self.max_output_len = max_output_len;
+        self
+    }
+
+    /// Starts the execution with `return_data` as the return data of the last call, as if a call
+    /// had returned it before the first instruction. This allows testing RETURNDATASIZE and
+    /// RETURNDATACOPY without performing a call.
+    pub fn with_return_data(mut self, return_data: Box<[u8]>) -> Self {
+        self.last_call_return_data = return_data;
+        self
+    }
+
+    /// Executes the opcodes with the functions of `jumptable` instead of the default jump table.
+    /// This analyzes the code again without using the code analysis cache. The entries for
+    /// JUMPDEST and the internal no-op opcodes are ignored.
+    ///
+    /// The functions must uphold the same invariants as the default handlers: they must consume
+    /// gas, check the stack before accessing it, leave the pc on the next opcode (or jump with
+    /// [`CodeReader::try_jump`]) and return through `return_from_op`. Violating them can lead to
+    /// undefined behavior with the `unsafe-stack` and `unsafe-hints` features.
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    pub fn with_jumptable(mut self, jumptable: &[OpFn<STEPPABLE>; 256]) -> Self {
+        self.code_reader.set_jumptable(jumptable);
+        self
+    }
+
+    /// Limit the stack to `limit` values instead of [`Stack::CAPACITY`]. Values beyond the limit
+    /// are dropped.
+    pub fn with_stack_limit(mut self, limit: usize) -> Self {
+        self.stack = Stack::with_limit(self.stack.as_slice(), limit);
+        self
+    }
+
+    /// Look up the inputs of SHA3 in the hash cache according to `policy` instead of the policy
+    /// of the hash cache.
+    pub fn with_hash_cache_policy(mut self, policy: HashCachePolicy) -> Self {
+        self.hash_cache_policy = policy;
+        self
+    }
+
+    /// Charge the memory of this frame to `budget`, which is shared with the other frames of the
+    /// call tree.
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        self.memory.set_budget(budget);
+        self
+    }
+
+    /// Captures the state of the execution, so that it can be continued later with
+    /// [`Interpreter::resume`].
+    pub fn snapshot(self) -> ExecutionSnapshot {
+        ExecutionSnapshot {
+            exec_status: self.exec_status,
+            pc: self.code_reader.pc(),
+            gas_left: self.gas_left.as_u64() as i64,
+            gas_refund: self.gas_refund.as_i64(),
+            output: self.output,
+            stack: self.stack,
+            memory: self.memory,
+            last_call_return_data: self.last_call_return_data,
+        }
+    }
+
+    /// Marks `key` in the storage of the current account as accessed and returns whether it was
+    /// cold before. The host is only asked if the key is not yet known to be warm.
+    fn access_storage(&mut self, key: &Uint256) -> AccessStatus {
+        if !self.warm_storage_keys.insert(u256::from(*key)) {
+            return AccessStatus::EVMC_ACCESS_WARM;
+        }
+        self.context.access_storage(&self.message.recipient, key)
+    }
+
+    #[inline(always)]
+    fn check_cancellation(&mut self) -> OpResult {
+        if self.cancellation.is_none() && self.deadline.is_none() {
+            return Ok(());
+        }
+        self.cancellation_countdown -= 1;
+        if self.cancellation_countdown == 0 {
+            self.cancellation_countdown = CANCELLATION_CHECK_INTERVAL;
+            if self
+                .cancellation
+                .is_some_and(|cancellation| cancellation.load(Ordering::Relaxed))
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(FailStatus::InternalError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the stack holds enough items for the current opcode and has enough space for
+    /// its results, so that the opcode handlers do not have to check the stack bounds themselves.
+    /// Opcodes which are not defined in the current revision are left to their handlers, which
+    /// fail with [`FailStatus::UndefinedInstruction`].
+    #[cfg(feature = "stack-precheck")]
+    #[inline(always)]
+    fn check_stack_requirements(&self) -> OpResult {
+        let info = OPCODE_INFO[self.code_reader.opcode() as usize];
+        if self.revision < info.min_revision {
+            return Ok(());
+        }
+        let len = self.stack.len();
+        if len < info.stack_in as usize {
+            return Err(FailStatus::StackUnderflow);
+        }
+        if info.stack_out > info.stack_in
+            && len + (info.stack_out - info.stack_in) as usize > self.stack.limit()
+        {
+            return Err(FailStatus::StackOverflow);
+        }
+        Ok(())
+    }
+
     /// R is expected to be [ExecutionResult] or [StepResult].
     #[cfg(not(feature = "tail-call"))]
     pub fn run<O, R>(mut self, observer: &mut O) -> R
@@ -425,11 +866,26 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
                     return FailStatus::InvalidInstruction.into();
                 }
             };
-            observer.pre_op(&self);
+            if let Err(err) = self.check_cancellation() {
+                return err.into();
+            }
+            if !observer.should_continue(&self) {
+                break;
+            }
+            observer.pre_op(&self, self.gas_left.as_u64(), self.gas_refund.as_i64());
+            let pc = self.code_reader.pc();
+            let gas_before = self.gas_left.as_u64();
+            #[cfg(feature = "stack-precheck")]
+            if let Err(err) = self.check_stack_requirements() {
+                return err.into();
+            }
             if let Err(err) = self.run_op(op) {
                 return err.into();
             }
-            observer.post_op(&self);
+            let gas_left = self.gas_left.as_u64();
+            let gas_cost = gas_before.saturating_sub(gas_left);
+            observer.gas_consumed(opcode_at(&self, pc), gas_cost, pc);
+            observer.post_op(&self, gas_left as i64, gas_cost);
         }
 
         self.into()
@@ -468,6 +924,9 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
                 return Err(FailStatus::InvalidInstruction);
             }
         };
+        self.check_cancellation()?;
+        #[cfg(feature = "stack-precheck")]
+        self.check_stack_requirements()?;
         self.run_op(op)
     }
 
@@ -512,23 +971,23 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn add(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value2, value1]) = self.stack.pop_with_location()?;
-        push_location.push(value1 + value2);
+        push_location.push(value1.overflowing_add(value2).0);
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn mul(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [fac2, fac1]) = self.stack.pop_with_location()?;
-        push_location.push(fac1 * fac2);
+        push_location.push(fac1.overflowing_mul(fac2).0);
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn sub(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value2, value1]) = self.stack.pop_with_location()?;
         push_location.push(value1 - value2);
         self.code_reader.next();
@@ -536,7 +995,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn div(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [denominator, value]) = self.stack.pop_with_location()?;
         push_location.push(value / denominator);
         self.code_reader.next();
@@ -544,7 +1003,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn s_div(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [denominator, value]) = self.stack.pop_with_location()?;
         push_location.push(value.sdiv(denominator));
         self.code_reader.next();
@@ -552,7 +1011,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn mod_(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [denominator, value]) = self.stack.pop_with_location()?;
         push_location.push(value % denominator);
         self.code_reader.next();
@@ -560,7 +1019,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn s_mod(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [denominator, value]) = self.stack.pop_with_location()?;
         push_location.push(value.srem(denominator));
         self.code_reader.next();
@@ -568,7 +1027,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn add_mod(&mut self) -> OpResult {
-        self.gas_left.consume(8)?;
+        self.gas_left.consume(GAS_MID)?;
         let (push_location, [denominator, value2, value1]) = self.stack.pop_with_location()?;
         push_location.push(u256::addmod(value1, value2, denominator));
         self.code_reader.next();
@@ -576,7 +1035,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn mul_mod(&mut self) -> OpResult {
-        self.gas_left.consume(8)?;
+        self.gas_left.consume(GAS_MID)?;
         let (push_location, [denominator, fac2, fac1]) = self.stack.pop_with_location()?;
         push_location.push(u256::mulmod(fac1, fac2, denominator));
         self.code_reader.next();
@@ -584,24 +1043,30 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn exp(&mut self) -> OpResult {
-        self.gas_left.consume(10)?;
+        self.gas_left.consume(GAS_EXP)?;
         let (push_location, [exp, value]) = self.stack.pop_with_location()?;
-        self.gas_left.consume(exp.bits().div_ceil(8) as u64 * 50)?; // * does not overflow
+        let gas_exp_byte = if self.revision >= Revision::EVMC_SPURIOUS_DRAGON {
+            GAS_EXP_BYTE
+        } else {
+            GAS_EXP_BYTE_FRONTIER
+        };
+        self.gas_left
+            .consume(exp.byte_len() as u64 * gas_exp_byte)?; // * does not overflow
         push_location.push(value.pow(exp));
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn sign_extend(&mut self) -> OpResult {
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let (push_location, [value, size]) = self.stack.pop_with_location()?;
-        push_location.push(u256::signextend(size, value));
+        push_location.push(u256::sign_extend(size, value));
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn lt(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs < rhs);
         self.code_reader.next();
@@ -609,7 +1074,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn gt(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs > rhs);
         self.code_reader.next();
@@ -617,7 +1082,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn s_lt(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs.slt(&rhs));
         self.code_reader.next();
@@ -625,7 +1090,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn s_gt(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs.sgt(&rhs));
         self.code_reader.next();
@@ -633,7 +1098,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn eq(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs == rhs);
         self.code_reader.next();
@@ -641,7 +1106,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn is_zero(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value]) = self.stack.pop_with_location()?;
         push_location.push(value == u256::ZERO);
         self.code_reader.next();
@@ -649,7 +1114,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn and(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs & rhs);
         self.code_reader.next();
@@ -657,7 +1122,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn or(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs | rhs);
         self.code_reader.next();
@@ -665,7 +1130,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn xor(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [rhs, lhs]) = self.stack.pop_with_location()?;
         push_location.push(lhs ^ rhs);
         self.code_reader.next();
@@ -673,7 +1138,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn not(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value]) = self.stack.pop_with_location()?;
         push_location.push(!value);
         self.code_reader.next();
@@ -681,7 +1146,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn byte(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value, offset]) = self.stack.pop_with_location()?;
         push_location.push(value.byte(offset));
         self.code_reader.next();
@@ -689,7 +1154,8 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn shl(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        check_min_revision(Revision::EVMC_CONSTANTINOPLE, self.revision)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value, shift]) = self.stack.pop_with_location()?;
         push_location.push(value << shift);
         self.code_reader.next();
@@ -697,7 +1163,8 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn shr(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        check_min_revision(Revision::EVMC_CONSTANTINOPLE, self.revision)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value, shift]) = self.stack.pop_with_location()?;
         push_location.push(value >> shift);
         self.code_reader.next();
@@ -705,28 +1172,32 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn sar(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        check_min_revision(Revision::EVMC_CONSTANTINOPLE, self.revision)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [value, shift]) = self.stack.pop_with_location()?;
-        push_location.push(value.sar(shift));
+        push_location.push(value.arithmetic_shr(shift));
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn sha3(&mut self) -> OpResult {
-        self.gas_left.consume(30)?;
+        self.gas_left.consume(GAS_SHA3)?;
         let (push_location, [len, offset]) = self.stack.pop_with_location()?;
 
         let len = u64::try_from(len).map_err(|_| FailStatus::OutOfGas)?;
-        self.gas_left.consume(6 * word_size(len)?)?; // * does not overflow
+        self.gas_left.consume_hash_cost(len)?;
 
         let data = self.memory.get_mut_slice(offset, len, &mut self.gas_left)?;
-        push_location.push(self.hash_cache.hash(data));
+        push_location.push(
+            self.hash_cache
+                .hash_with_policy(data, self.hash_cache_policy),
+        );
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn address(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.message.recipient)?;
         self.code_reader.next();
         self.return_from_op()
@@ -746,28 +1217,28 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn origin(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.context.get_tx_context().tx_origin)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn caller(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.message.sender)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn call_value(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.message.value)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn call_data_load(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [offset]) = self.stack.pop_with_location()?;
         let (offset, overflow) = offset.into_u64_with_overflow();
         let offset = offset as usize;
@@ -785,7 +1256,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn call_data_size(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         let call_data_len = self.message.input.len();
         self.stack.push(call_data_len)?;
         self.code_reader.next();
@@ -794,14 +1265,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn push0(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_SHANGHAI, self.revision)?;
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(u256::ZERO)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn call_data_copy(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [len, offset, dest_offset] = self.stack.pop()?;
 
         if len != u256::ZERO {
@@ -818,14 +1289,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn code_size(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.code_reader.len())?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn code_copy(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [len, offset, dest_offset] = self.stack.pop()?;
 
         if len != u256::ZERO {
@@ -842,7 +1313,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn gas_price(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().tx_gas_price)?;
         self.code_reader.next();
@@ -891,14 +1362,16 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn return_data_size(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        check_min_revision(Revision::EVMC_BYZANTIUM, self.revision)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.last_call_return_data.len())?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn return_data_copy(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        check_min_revision(Revision::EVMC_BYZANTIUM, self.revision)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [len, offset, dest_offset] = self.stack.pop()?;
 
         let src = &self.last_call_return_data;
@@ -921,6 +1394,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn ext_code_hash(&mut self) -> OpResult {
+        check_min_revision(Revision::EVMC_CONSTANTINOPLE, self.revision)?;
         if self.revision < Revision::EVMC_BERLIN {
             self.gas_left.consume(700)?;
         }
@@ -934,7 +1408,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn block_hash(&mut self) -> OpResult {
-        self.gas_left.consume(20)?;
+        self.gas_left.consume(GAS_BLOCKHASH)?;
         let (push_location, [block_number]) = self.stack.pop_with_location()?;
         push_location.push(
             u64::try_from(block_number)
@@ -946,7 +1420,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn coinbase(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_coinbase)?;
         self.code_reader.next();
@@ -954,7 +1428,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn timestamp(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_timestamp as u64)?;
         self.code_reader.next();
@@ -962,7 +1436,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn number(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_number as u64)?;
         self.code_reader.next();
@@ -970,7 +1444,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn prev_randao(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_prev_randao)?;
         self.code_reader.next();
@@ -978,7 +1452,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn gas_limit(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_gas_limit as u64)?;
         self.code_reader.next();
@@ -986,7 +1460,8 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn chain_id(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        check_min_revision(Revision::EVMC_ISTANBUL, self.revision)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.context.get_tx_context().chain_id)?;
         self.code_reader.next();
         self.return_from_op()
@@ -994,7 +1469,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn self_balance(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_ISTANBUL, self.revision)?;
-        self.gas_left.consume(5)?;
+        self.gas_left.consume(GAS_LOW)?;
         let addr = self.message.recipient;
         if u256::from(addr) == u256::ZERO {
             self.stack.push(u256::ZERO)?;
@@ -1007,7 +1482,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn base_fee(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_LONDON, self.revision)?;
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().block_base_fee)?;
         self.code_reader.next();
@@ -1016,15 +1491,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn blob_hash(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_CANCUN, self.revision)?;
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [idx]) = self.stack.pop_with_location()?;
-        let (idx, idx_overflow) = idx.into_u64_with_overflow();
-        let idx = idx as usize;
+        // an index which saturates is out of range for any number of blob hashes
+        let idx = idx.into_usize_saturating();
         let hashes = self.context.get_tx_context().blob_hashes;
-        if !idx_overflow && idx < hashes.len() {
-            push_location.push(hashes[idx]);
-        } else {
-            push_location.push(u256::ZERO);
+        match hashes.get(idx) {
+            Some(hash) => push_location.push(*hash),
+            None => push_location.push(u256::ZERO),
         }
         self.code_reader.next();
         self.return_from_op()
@@ -1032,7 +1506,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn blob_base_fee(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_CANCUN, self.revision)?;
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack
             .push(self.context.get_tx_context().blob_base_fee)?;
         self.code_reader.next();
@@ -1040,14 +1514,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn pop(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         let [_] = self.stack.pop()?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn m_load(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let (push_location, [offset]) = self.stack.pop_with_location()?;
 
         push_location.push(self.memory.get_word(offset, &mut self.gas_left)?);
@@ -1056,7 +1530,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn m_store(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [value, offset] = self.stack.pop()?;
 
         let dest = self.memory.get_mut_slice(offset, 32, &mut self.gas_left)?;
@@ -1074,11 +1548,11 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn m_store8(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [value, offset] = self.stack.pop()?;
 
         let dest = self.memory.get_mut_byte(offset, &mut self.gas_left)?;
-        *dest = value.least_significant_byte();
+        *dest = value.as_u8();
         self.code_reader.next();
         self.return_from_op()
     }
@@ -1087,24 +1561,27 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
         if self.revision < Revision::EVMC_BERLIN {
             self.gas_left.consume(800)?;
         }
-        let (push_location, [key]) = self.stack.pop_with_location()?;
+        let [key] = self.stack.pop()?;
         let key = key.into();
-        let addr = &self.message.recipient;
         if self.revision >= Revision::EVMC_BERLIN {
-            if self.context.access_storage(addr, &key) == AccessStatus::EVMC_ACCESS_COLD {
-                self.gas_left.consume(2_100)?;
+            if self.access_storage(&key) == AccessStatus::EVMC_ACCESS_COLD {
+                self.gas_left.consume(GAS_COLD_SLOAD)?;
             } else {
-                self.gas_left.consume(100)?;
+                self.gas_left.consume(GAS_WARM_ACCESS)?;
             }
         }
-        let value = self.context.get_storage(addr, &key);
-        push_location.push(value);
+        let value = self.context.get_storage(&self.message.recipient, &key);
+        self.stack.push(value)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn jump(&mut self) -> OpResult {
-        self.gas_left.consume(if STEPPABLE { 8 } else { 8 + 1 })?;
+        self.gas_left.consume(if STEPPABLE {
+            GAS_MID
+        } else {
+            GAS_MID + GAS_JUMPDEST
+        })?;
         let [dest] = self.stack.pop()?;
         self.code_reader.try_jump(dest)?;
         if !STEPPABLE {
@@ -1114,14 +1591,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn jump_i(&mut self) -> OpResult {
-        self.gas_left.consume(10)?;
+        self.gas_left.consume(GAS_HIGH)?;
         let [cond, dest] = self.stack.pop()?;
         if cond == u256::ZERO {
             self.code_reader.next();
         } else {
             self.code_reader.try_jump(dest)?;
             if !STEPPABLE {
-                self.gas_left.consume(1)?;
+                self.gas_left.consume(GAS_JUMPDEST)?;
                 self.code_reader.next();
             }
         }
@@ -1129,35 +1606,35 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn pc(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.code_reader.pc())?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn m_size(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.memory.len())?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn gas(&mut self) -> OpResult {
-        self.gas_left.consume(2)?;
+        self.gas_left.consume(GAS_BASE)?;
         self.stack.push(self.gas_left.as_u64())?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn jump_dest(&mut self) -> OpResult {
-        self.gas_left.consume(1)?;
+        self.gas_left.consume(GAS_JUMPDEST)?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn t_load(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_CANCUN, self.revision)?;
-        self.gas_left.consume(100)?;
+        self.gas_left.consume(GAS_WARM_ACCESS)?;
         let (push_location, [key]) = self.stack.pop_with_location()?;
         let value = self
             .context
@@ -1170,7 +1647,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     fn t_store(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_CANCUN, self.revision)?;
         check_not_read_only(self.message)?;
-        self.gas_left.consume(100)?;
+        self.gas_left.consume(GAS_WARM_ACCESS)?;
         let [value, key] = self.stack.pop()?;
         self.context
             .set_transient_storage(&self.message.recipient, &key.into(), &value.into());
@@ -1180,7 +1657,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn m_copy(&mut self) -> OpResult {
         check_min_revision(Revision::EVMC_CANCUN, self.revision)?;
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         let [len, offset, dest_offset] = self.stack.pop()?;
         if len != u256::ZERO {
             self.memory
@@ -1193,6 +1670,9 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     fn return_(&mut self) -> OpResult {
         let [len, offset] = self.stack.pop()?;
         let len = u64::try_from(len).map_err(|_| FailStatus::OutOfGas)?;
+        if len > self.max_output_len {
+            return Err(FailStatus::OutOfMemory);
+        }
         let data = self.memory.get_mut_slice(offset, len, &mut self.gas_left)?;
         self.output = Box::from(&*data);
         self.exec_status = ExecStatus::Returned;
@@ -1200,8 +1680,12 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn revert(&mut self) -> OpResult {
+        check_min_revision(Revision::EVMC_BYZANTIUM, self.revision)?;
         let [len, offset] = self.stack.pop()?;
         let len = u64::try_from(len).map_err(|_| FailStatus::OutOfGas)?;
+        if len > self.max_output_len {
+            return Err(FailStatus::OutOfMemory);
+        }
         let data = self.memory.get_mut_slice(offset, len, &mut self.gas_left)?;
         self.output = Box::from(&*data);
         self.exec_status = ExecStatus::Revert;
@@ -1215,20 +1699,20 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn self_destruct(&mut self) -> OpResult {
         check_not_read_only(self.message)?;
-        self.gas_left.consume(5_000)?;
+        self.gas_left.consume(GAS_SELFDESTRUCT)?;
         let [addr] = self.stack.pop()?;
         let addr = addr.into();
 
         if self.revision >= Revision::EVMC_BERLIN
             && self.context.access_account(&addr) == AccessStatus::EVMC_ACCESS_COLD
         {
-            self.gas_left.consume(2_600)?;
+            self.gas_left.consume(GAS_COLD_ACCOUNT_ACCESS)?;
         }
 
         if u256::from(self.context.get_balance(&self.message.recipient)) > u256::ZERO
             && !self.context.account_exists(&addr)
         {
-            self.gas_left.consume(25_000)?;
+            self.gas_left.consume(GAS_NEW_ACCOUNT)?;
         }
 
         let destructed = self.context.selfdestruct(&self.message.recipient, &addr);
@@ -1243,7 +1727,8 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     fn sstore(&mut self) -> OpResult {
         check_not_read_only(self.message)?;
 
-        if self.revision >= Revision::EVMC_ISTANBUL && self.gas_left <= 2_300 {
+        // EIP-2200: SSTORE must not be executable with only the call stipend left.
+        if self.revision >= Revision::EVMC_ISTANBUL && self.gas_left <= GAS_CALL_STIPEND {
             return Err(FailStatus::OutOfGas);
         }
         let [value, key] = self.stack.pop()?;
@@ -1281,7 +1766,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
             StorageStatus::EVMC_STORAGE_MODIFIED_RESTORED => (dyn_gas_1, refund_1),
         };
         if self.revision >= Revision::EVMC_BERLIN
-            && self.context.access_storage(addr, &key) == AccessStatus::EVMC_ACCESS_COLD
+            && self.access_storage(&key) == AccessStatus::EVMC_ACCESS_COLD
         {
             dyn_gas += 2_100;
         }
@@ -1292,7 +1777,7 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn push<const N: usize>(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
         self.code_reader.next();
         #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
@@ -1303,14 +1788,14 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn dup<const N: usize>(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         self.stack.dup::<N>()?;
         self.code_reader.next();
         self.return_from_op()
     }
 
     fn swap<const N: usize>(&mut self) -> OpResult {
-        self.gas_left.consume(3)?;
+        self.gas_left.consume(GAS_VERYLOW)?;
         self.stack.swap_with_top::<N>()?;
         self.code_reader.next();
         self.return_from_op()
@@ -1318,12 +1803,12 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
 
     fn log<const N: usize>(&mut self) -> OpResult {
         check_not_read_only(self.message)?;
-        self.gas_left.consume(375)?;
+        self.gas_left.consume(GAS_LOG)?;
         let [len, offset] = self.stack.pop()?;
         let topics: [u256; N] = self.stack.pop()?;
         let (len, len_overflow) = len.into_u64_with_overflow();
-        let (len8, len8_overflow) = len.overflowing_mul(8);
-        let (cost, cost_overflow) = (375 * N as u64).overflowing_add(len8);
+        let (len8, len8_overflow) = len.overflowing_mul(GAS_LOG_DATA);
+        let (cost, cost_overflow) = (GAS_LOG_TOPIC * N as u64).overflowing_add(len8);
         if len_overflow || len8_overflow || cost_overflow {
             return Err(FailStatus::OutOfGas);
         }
@@ -1345,11 +1830,12 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn create2(&mut self) -> OpResult {
+        check_min_revision(Revision::EVMC_CONSTANTINOPLE, self.revision)?;
         self.create_or_create2::<true>()
     }
 
     fn create_or_create2<const CREATE2: bool>(&mut self) -> OpResult {
-        self.gas_left.consume(32_000)?;
+        self.gas_left.consume(GAS_CREATE)?;
         check_not_read_only(self.message)?;
         let [len, offset, value] = self.stack.pop()?;
         let salt = if CREATE2 {
@@ -1366,12 +1852,11 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
             if len > MAX_INIT_CODE_LEN {
                 return Err(FailStatus::OutOfGas);
             }
-            let init_code_cost = 2 * init_code_word_size; // does not overflow
+            let init_code_cost = GAS_INITCODE_WORD * init_code_word_size; // does not overflow
             self.gas_left.consume(init_code_cost)?;
         }
         if CREATE2 {
-            let hash_cost = 6 * init_code_word_size; // does not overflow
-            self.gas_left.consume(hash_cost)?;
+            self.gas_left.consume_hash_cost(len)?;
         }
 
         let init_code = self.memory.get_mut_slice(offset, len, &mut self.gas_left)?;
@@ -1470,7 +1955,11 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
         let mut endowment = gas.into_u64_saturating();
         endowment = min(endowment, limit); // cap gas at all but one 64th of gas left
 
-        let stipend: u64 = if value == u256::ZERO { 0 } else { 2_300 };
+        let stipend: u64 = if value == u256::ZERO {
+            0
+        } else {
+            GAS_CALL_STIPEND
+        };
         self.gas_left.add(stipend as i64)?;
 
         if value > u256::from(self.context.get_balance(&self.message.recipient)) {
@@ -1533,10 +2022,12 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 
     fn static_call(&mut self) -> OpResult {
+        check_min_revision(Revision::EVMC_BYZANTIUM, self.revision)?;
         self.static_or_delegate_call::<false>()
     }
 
     fn delegate_call(&mut self) -> OpResult {
+        check_min_revision(Revision::EVMC_HOMESTEAD, self.revision)?;
         self.static_or_delegate_call::<true>()
     }
 
@@ -1620,30 +2111,124 @@ impl<const STEPPABLE: bool> Interpreter<'_, STEPPABLE> {
     }
 }
 
-impl<const STEPPABLE: bool> From<Interpreter<'_, STEPPABLE>> for StepResult {
-    fn from(value: Interpreter<STEPPABLE>) -> Self {
-        let stack = value
+/// The state of a steppable execution between two runs of an interpreter. In contrast to
+/// [`StepResult`] it keeps the stack and the memory in the representation used by the
+/// interpreter, so that a debugger can hold it across many steps without converting them from and
+/// to [`Uint256`] slices each time.
+#[derive(Debug)]
+pub struct ExecutionSnapshot {
+    exec_status: ExecStatus,
+    pc: usize,
+    gas_left: i64,
+    gas_refund: i64,
+    output: Box<[u8]>,
+    stack: Stack,
+    memory: Memory,
+    last_call_return_data: Box<[u8]>,
+}
+
+impl ExecutionSnapshot {
+    /// Creates the snapshot of a running execution from the raw state passed to `step_n`. The
+    /// stack holds at most `stack_limit` values.
+    pub fn new(
+        pc: usize,
+        gas_left: i64,
+        gas_refund: i64,
+        stack: &[Uint256],
+        stack_limit: usize,
+        memory: &[u8],
+        last_call_return_data: &[u8],
+    ) -> Self {
+        let stack: Vec<_> = stack.iter().copied().map(u256::from).collect();
+        Self {
+            exec_status: ExecStatus::Running,
+            pc,
+            gas_left,
+            gas_refund,
+            output: Box::default(),
+            stack: Stack::with_limit(&stack, stack_limit),
+            memory: Memory::new(memory),
+            last_call_return_data: Box::from(last_call_return_data),
+        }
+    }
+
+    pub fn exec_status(&self) -> ExecStatus {
+        self.exec_status
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn gas_left(&self) -> i64 {
+        self.gas_left
+    }
+
+    pub fn gas_refund(&self) -> i64 {
+        self.gas_refund
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn stack(&self) -> &[u256] {
+        self.stack.as_slice()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    pub fn last_call_return_data(&self) -> &[u8] {
+        &self.last_call_return_data
+    }
+
+    pub fn into_step_result(self, revision: Revision) -> StepResult {
+        let stack = self
             .stack
             .as_slice()
             .iter()
             .copied()
             .map(Into::into)
             .collect();
-        Self {
-            step_status_code: value.exec_status.into(),
+        StepResult {
+            step_status_code: self.exec_status.into(),
             status_code: StatusCode::EVMC_SUCCESS,
-            revision: value.revision,
-            pc: value.code_reader.pc() as u64,
-            gas_left: value.gas_left.as_u64() as i64,
-            gas_refund: value.gas_refund.as_i64(),
-            output: value.output,
+            revision,
+            pc: self.pc as u64,
+            gas_left: self.gas_left,
+            gas_refund: self.gas_refund,
+            output: self.output,
             stack,
-            memory: value.memory.as_slice().to_vec(),
-            last_call_return_data: value.last_call_return_data,
+            memory: self.memory.as_slice().to_vec(),
+            last_call_return_data: self.last_call_return_data,
         }
     }
 }
 
+/// Allows running an interpreter to a snapshot, with failures as error.
+impl<const STEPPABLE: bool> From<Interpreter<'_, STEPPABLE>>
+    for Result<ExecutionSnapshot, FailStatus>
+{
+    fn from(value: Interpreter<STEPPABLE>) -> Self {
+        Ok(value.snapshot())
+    }
+}
+
+impl From<FailStatus> for Result<ExecutionSnapshot, FailStatus> {
+    fn from(fail_status: FailStatus) -> Self {
+        Err(fail_status)
+    }
+}
+
+impl<const STEPPABLE: bool> From<Interpreter<'_, STEPPABLE>> for StepResult {
+    fn from(value: Interpreter<STEPPABLE>) -> Self {
+        let revision = value.revision;
+        value.snapshot().into_step_result(revision)
+    }
+}
+
 impl<const STEPPABLE: bool> From<Interpreter<'_, STEPPABLE>> for ExecutionResult {
     fn from(value: Interpreter<STEPPABLE>) -> Self {
         Self {
@@ -1658,20 +2243,69 @@ impl<const STEPPABLE: bool> From<Interpreter<'_, STEPPABLE>> for ExecutionResult
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
     use evmc_vm::{
-        Address, ExecutionResult, MessageKind, Revision, StatusCode, StepResult, StepStatusCode,
-        Uint256,
+        AccessStatus, Address, ExecutionMessage, ExecutionResult, ExecutionTxContext, MessageFlags,
+        MessageKind, Revision, StatusCode, StepResult, StepStatusCode, StorageStatus, Uint256,
     };
     use mockall::predicate;
+    use sha3::{Digest, Keccak256};
 
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    use crate::interpreter::get_jumptable;
     use crate::{
-        interpreter::Interpreter,
+        interpreter::{ExecutionSnapshot, Interpreter, load_delegated_code},
         types::{
-            CodeAnalysisCache, Memory, MockExecutionContextTrait, MockExecutionMessage,
-            NoOpObserver, Opcode, Stack, hash_cache::HashCache, u256,
+            Account, CodeAnalysisCache, CodeByteType, ExecStatus, ExecutionContextTrait,
+            FailStatus, Memory, MemoryBudget, MockExecutionContextTrait, MockExecutionMessage,
+            NoOpObserver, OPCODE_INFO, Opcode, ReferenceHost, Stack, code_byte_type,
+            hash_cache::HashCache, opcode_availability, u256,
+        },
+        utils::constants::{
+            GAS_BASE, GAS_CALL_STIPEND, GAS_CALL_VALUE, GAS_COLD_ACCOUNT_ACCESS, GAS_EXP,
+            GAS_EXP_BYTE, GAS_EXP_BYTE_FRONTIER, GAS_JUMPDEST, GAS_LOW, GAS_MID, GAS_NEW_ACCOUNT,
+            GAS_SHA3, GAS_VERYLOW, GAS_WARM_ACCESS,
         },
     };
 
+    /// Runs `code` with a steppable interpreter which starts at the first instruction with
+    /// `stack` and `memory`, until the code stops or fails.
+    fn run_steppable(
+        revision: Revision,
+        message: &ExecutionMessage,
+        context: &mut dyn ExecutionContextTrait,
+        code: &[u8],
+        stack: &[u256],
+        memory: &[u8],
+    ) -> StepResult {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        Interpreter::new_steppable(
+            revision,
+            message,
+            context,
+            code,
+            0,
+            0,
+            Stack::new(stack),
+            Memory::new(memory),
+            Box::default(),
+            None,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver())
+    }
+
     #[test]
     fn empty_code() {
         let code_analysis_cache = CodeAnalysisCache::default();
@@ -1685,7 +2319,8 @@ mod tests {
             &[],
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
         assert_eq!(result.pc, 0);
@@ -1714,7 +2349,8 @@ mod tests {
             None,
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
         assert_eq!(result.pc, 1);
@@ -1724,6 +2360,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn single_stepping_snapshots_match_single_execution() {
+        // count down from 142 in a loop of 7 instructions, then write to memory
+        let code = [
+            Opcode::Push1 as u8,
+            142,
+            Opcode::JumpDest as u8,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Swap1 as u8,
+            Opcode::Sub as u8,
+            Opcode::Dup1 as u8,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::JumpI as u8,
+            Opcode::Pop as u8,
+            Opcode::Push1 as u8,
+            0xff,
+            Opcode::Push1 as u8,
+            0x20,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            0x2a,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+
+        let mut snapshot = ExecutionSnapshot::new(
+            0,
+            MockExecutionMessage::DEFAULT_INIT_GAS as i64,
+            0,
+            &[],
+            Stack::CAPACITY,
+            &[],
+            &[],
+        );
+        let mut steps = 0;
+        while snapshot.exec_status() == ExecStatus::Running {
+            let result: Result<ExecutionSnapshot, FailStatus> = Interpreter::resume(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                &code,
+                snapshot,
+                Some(1),
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            snapshot = result.unwrap();
+            steps += 1;
+        }
+        // 1000 instructions and a final step which reaches the end of the code
+        assert_eq!(steps, 1001);
+        assert_eq!(snapshot.stack(), [u256::from(0x2au8)]);
+        assert_eq!(snapshot.memory()[0x3f], 0xff);
+
+        let result: StepResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        let stepped = snapshot.into_step_result(Revision::EVMC_CANCUN);
+        assert_eq!(stepped.step_status_code, result.step_status_code);
+        assert_eq!(stepped.pc, result.pc);
+        assert_eq!(stepped.gas_left, result.gas_left);
+        assert_eq!(stepped.gas_refund, result.gas_refund);
+        assert_eq!(stepped.stack, result.stack);
+        assert_eq!(stepped.memory, result.memory);
+    }
+
     // when features "fn-ptr-conversion-dispatch" is enabled this in undefined behavior
     #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
     #[test]
@@ -1746,17 +2461,125 @@ mod tests {
             &code_analysis_cache,
             &hash_cache,
         )
+        .unwrap()
         .run(&mut NoOpObserver());
         assert_eq!(result.status_code, StatusCode::EVMC_INVALID_INSTRUCTION);
     }
 
     #[test]
-    fn zero_steps() {
+    fn base_costs_match_gas_constants() {
         let code_analysis_cache = CodeAnalysisCache::default();
         let hash_cache = HashCache::default();
-        let mut context = MockExecutionContextTrait::new();
-        let message = MockExecutionMessage::default().into();
-        let interpreter = Interpreter::new_steppable(
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        // all inputs are zero, so there are no dynamic costs
+        for (opcode, inputs, cost) in [
+            (Opcode::Add, 2, GAS_VERYLOW),
+            (Opcode::Mul, 2, GAS_LOW),
+            (Opcode::AddMod, 3, GAS_MID),
+            (Opcode::Address, 0, GAS_BASE),
+            (Opcode::Exp, 2, GAS_EXP),
+            (Opcode::Sha3, 2, GAS_SHA3),
+            (Opcode::JumpDest, 0, GAS_JUMPDEST),
+        ] {
+            let mut context = MockExecutionContextTrait::new();
+            let result: ExecutionResult = Interpreter::new_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                &[opcode as u8],
+                0,
+                0,
+                Stack::new(&vec![u256::ZERO; inputs]),
+                Memory::new(&[]),
+                Box::default(),
+                None,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.gas_left, 1_000 - cost as i64, "{opcode:?}");
+        }
+    }
+
+    #[test]
+    fn jump_dest_costs_one_gas_in_all_dispatch_paths() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let code_analysis_cache_steppable = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        assert_eq!(GAS_JUMPDEST, 1);
+        let jump_dest_stop = [Opcode::JumpDest as u8, Opcode::Stop as u8];
+        // the JUMPDEST is reached via a jump, which may charge it together with the JUMP
+        let jump_to_jump_dest = [
+            Opcode::Push1 as u8,
+            3,
+            Opcode::Jump as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Stop as u8,
+        ];
+        for (code, cost) in [
+            (jump_dest_stop.as_slice(), GAS_JUMPDEST),
+            (
+                jump_to_jump_dest.as_slice(),
+                GAS_VERYLOW + GAS_MID + GAS_JUMPDEST,
+            ),
+        ] {
+            let mut context = MockExecutionContextTrait::new();
+            let result: ExecutionResult = Interpreter::new(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                code,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.gas_left, 100 - cost as i64, "{code:02x?}");
+
+            let result: ExecutionResult = Interpreter::new_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                code,
+                0,
+                0,
+                Stack::new(&[]),
+                Memory::new(&[]),
+                Box::default(),
+                None,
+                &code_analysis_cache_steppable,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(
+                result.gas_left,
+                100 - cost as i64,
+                "{code:02x?} (steppable)"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_steps() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let interpreter = Interpreter::new_steppable(
             Revision::EVMC_ISTANBUL,
             &message,
             &mut context,
@@ -1769,7 +2592,8 @@ mod tests {
             Some(0),
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_RUNNING);
         assert_eq!(result.pc, 0);
@@ -1798,7 +2622,8 @@ mod tests {
             Some(1),
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_RUNNING);
         assert_eq!(result.stack.as_slice(), [u256::from(3u8).into()]);
@@ -1821,7 +2646,8 @@ mod tests {
             &[Opcode::Add as u8],
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         interpreter.stack = Stack::new(&[1u8.into(), 2u8.into()]);
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
@@ -1845,7 +2671,8 @@ mod tests {
             &[Opcode::Add as u8, Opcode::Add as u8],
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         interpreter.stack = Stack::new(&[1u8.into(), 2u8.into(), 3u8.into()]);
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
@@ -1874,7 +2701,8 @@ mod tests {
             &[Opcode::JumpDest as u8; 10_000_000],
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
     }
@@ -1896,7 +2724,8 @@ mod tests {
             &[Opcode::Add as u8],
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         interpreter.stack = Stack::new(&[1u8.into(), 2u8.into()]);
         let result: ExecutionResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.status_code, StatusCode::EVMC_OUT_OF_GAS);
@@ -1983,7 +2812,8 @@ mod tests {
             None,
             &code_analysis_cache,
             &hash_cache,
-        );
+        )
+        .unwrap();
         let result: StepResult = interpreter.run(&mut NoOpObserver());
         assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
         assert_eq!(result.pc, 1);
@@ -1997,4 +2827,1800 @@ mod tests {
             ret_data.as_slice()
         );
     }
+
+    #[test]
+    fn call_with_insufficient_balance_fails_without_calling_host() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let addr = u256::from(0xaau8);
+        let value = u256::from(2u8);
+        let message = MockExecutionMessage {
+            recipient: u256::from(0xbbu8).into(),
+            ..Default::default()
+        };
+
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_account_exists()
+            .with(predicate::eq(Address::from(addr)))
+            .return_const(true);
+        context
+            .expect_get_balance()
+            .times(1)
+            .with(predicate::eq(Address::from(message.recipient)))
+            .return_const(Uint256::from(u256::ONE));
+        context.expect_call().times(0);
+
+        let message = message.into();
+        let stack = [
+            u256::ZERO, // ret len
+            u256::ZERO, // ret offset
+            u256::ZERO, // args len
+            u256::ZERO, // args offset
+            value,
+            addr,
+            u256::from(1000u64), // gas
+        ];
+        let result: StepResult = Interpreter::new_steppable(
+            Revision::EVMC_ISTANBUL,
+            &message,
+            &mut context,
+            &[Opcode::Call as u8],
+            0,
+            0,
+            Stack::new(&stack),
+            Memory::new(&[]),
+            Box::from([1, 2, 3].as_slice()),
+            None,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.stack, [Uint256::from(u256::ZERO)]);
+        assert!(result.last_call_return_data.is_empty());
+        // the forwarded gas is kept, the base and value costs are charged and the stipend is not
+        // consumed
+        assert_eq!(
+            result.gas_left,
+            MockExecutionMessage::DEFAULT_INIT_GAS as i64 - 700 - 9000 + 2300
+        );
+    }
+
+    #[test]
+    fn call_with_value_and_zero_gas_forwards_only_stipend() {
+        let addr = u256::from(0xaau8);
+        let message = MockExecutionMessage {
+            recipient: u256::from(0xbbu8).into(),
+            ..Default::default()
+        };
+
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_account_exists()
+            .with(predicate::eq(Address::from(addr)))
+            .return_const(true);
+        context
+            .expect_get_balance()
+            .times(1)
+            .with(predicate::eq(Address::from(message.recipient)))
+            .return_const(Uint256::from(u256::from(10u8)));
+        context
+            .expect_call()
+            .times(1)
+            .withf(|call_message| {
+                call_message.gas == GAS_CALL_STIPEND as i64
+                    && call_message.value == Uint256::from(u256::ONE)
+            })
+            .returning(|_| ExecutionResult {
+                status_code: StatusCode::EVMC_SUCCESS,
+                gas_left: 1000,
+                gas_refund: 0,
+                output: Box::default(),
+                create_address: None,
+            });
+
+        let message = message.into();
+        let stack = [
+            u256::ZERO, // ret len
+            u256::ZERO, // ret offset
+            u256::ZERO, // args len
+            u256::ZERO, // args offset
+            u256::ONE,  // value
+            addr,
+            u256::ZERO, // gas
+        ];
+        let result = run_steppable(
+            Revision::EVMC_ISTANBUL,
+            &message,
+            &mut context,
+            &[Opcode::Call as u8],
+            &stack,
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.stack, [Uint256::from(u256::ONE)]);
+        // The stipend is not drawn from the gas of the caller, but the gas the callee did not use
+        // is returned to it.
+        assert_eq!(
+            result.gas_left,
+            MockExecutionMessage::DEFAULT_INIT_GAS as i64 - 700 - 9000 + 1000
+        );
+    }
+
+    #[test]
+    fn callee_with_only_stipend_runs_out_of_gas_on_sstore() {
+        let contract_addr = Address::from(u256::from(0xbbu8));
+        let callee_addr = Address::from(u256::from(0x42u8));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.set_account(
+            contract_addr,
+            Account {
+                balance: u256::from(10u8),
+                ..Default::default()
+            },
+        );
+        // SSTORE fails if no more than the stipend is left (EIP-2200)
+        host.set_account(
+            callee_addr,
+            Account {
+                code: vec![
+                    Opcode::Push1 as u8,
+                    1,
+                    Opcode::Push0 as u8,
+                    Opcode::SStore as u8,
+                ],
+                ..Default::default()
+            },
+        );
+
+        let code = [
+            Opcode::Push0 as u8, // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push0 as u8, // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push1 as u8,
+            1, // value
+            Opcode::Push1 as u8,
+            0x42,                // addr
+            Opcode::Push0 as u8, // gas
+            Opcode::Call as u8,
+            // return the success flag of the call
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            recipient: contract_addr,
+            code_address: contract_addr,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.output.as_ref(), [0; 32]);
+        // The pushes, the cold access, the value transfer and the MSTORE including its memory
+        // expansion. The whole stipend was used up by the callee but not drawn from the caller.
+        assert_eq!(
+            result.gas_left as u64,
+            100_000 - 7 * 2 - 3 * 3 - GAS_COLD_ACCOUNT_ACCESS - GAS_CALL_VALUE - 2 * 3
+        );
+        assert!(host.account(&callee_addr).unwrap().storage.is_empty());
+    }
+
+    #[test]
+    fn delegated_code_is_executed() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+
+        let target = Address::from(u256::from(0xaau8));
+        let mut designator = vec![0xef, 0x01, 0x00];
+        designator.extend_from_slice(&target.bytes);
+        let delegated_code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Add as u8,
+        ];
+
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_code_size()
+            .times(1)
+            .with(predicate::eq(target))
+            .return_const(delegated_code.len());
+        context
+            .expect_copy_code()
+            .times(1)
+            .withf(move |addr, offset, _| *addr == target && *offset == 0)
+            .returning(move |_, _, buffer| {
+                buffer.copy_from_slice(&delegated_code);
+                delegated_code.len()
+            });
+        context.expect_access_account().never();
+
+        let message = MockExecutionMessage::default().into();
+        let (delegation_target, code) =
+            load_delegated_code(Revision::EVMC_PRAGUE, &designator, &mut context).unwrap();
+        assert_eq!(delegation_target, target);
+        assert_eq!(code.as_ref(), delegated_code.as_slice());
+
+        let interpreter = Interpreter::new_delegated(
+            Revision::EVMC_PRAGUE,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap();
+        let result: StepResult = interpreter.run(&mut NoOpObserver());
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.stack.as_slice(), [u256::from(3u8).into()]);
+        assert_eq!(
+            result.gas_left,
+            MockExecutionMessage::DEFAULT_INIT_GAS as i64 - 3 * 3
+        );
+    }
+
+    #[test]
+    fn delegation_designator_is_not_resolved_before_prague() {
+        let mut designator = vec![0xef, 0x01, 0x00];
+        designator.extend_from_slice(&[0; 20]);
+
+        let mut context = MockExecutionContextTrait::new();
+        assert!(load_delegated_code(Revision::EVMC_CANCUN, &designator, &mut context).is_none());
+        assert!(
+            load_delegated_code(Revision::EVMC_PRAGUE, &designator[..22], &mut context).is_none()
+        );
+    }
+
+    #[test]
+    fn create2_collision_is_left_to_host() {
+        let sender = Address::from(u256::from(0xaau8));
+        let salt = u256::from(0x42u8);
+
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_balance()
+            .times(1)
+            .return_const(Uint256::from(u256::ZERO));
+        context.expect_get_code_size().never();
+        // the host detects the collision, bumps the nonce of the sender and fails the creation
+        context
+            .expect_call()
+            .times(1)
+            .withf(move |call_message| {
+                call_message.kind == MessageKind::EVMC_CREATE2
+                    && call_message.sender == sender
+                    && call_message.create2_salt == Uint256::from(salt)
+            })
+            .returning(|_| ExecutionResult {
+                status_code: StatusCode::EVMC_FAILURE,
+                gas_left: 0,
+                gas_refund: 0,
+                output: Box::default(),
+                create_address: None,
+            });
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            recipient: sender,
+            ..Default::default()
+        };
+        let message = message.into();
+        let interpreter = Interpreter::new_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::Create2 as u8],
+            0,
+            0,
+            // salt, len, offset, value
+            Stack::new(&[salt, u256::ZERO, u256::ZERO, u256::ZERO]),
+            Memory::new(&[]),
+            Box::default(),
+            None,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap();
+        let result: StepResult = interpreter.run(&mut NoOpObserver());
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.stack.as_slice(), [Uint256::from(u256::ZERO)]);
+        // the gas passed to the creation is consumed
+        let gas_left = 100_000 - 32_000;
+        assert_eq!(result.gas_left, gas_left / 64);
+    }
+
+    #[test]
+    fn message_opcodes_do_not_use_host() {
+        let code = [
+            Opcode::Address as u8,
+            Opcode::Caller as u8,
+            Opcode::CallValue as u8,
+            Opcode::CallDataSize as u8,
+            Opcode::Push1 as u8,
+            4,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::CallDataCopy as u8,
+        ];
+        // no expectations, so every host callback would panic
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            recipient: u256::from(0xaau8).into(),
+            sender: u256::from(0xbbu8).into(),
+            value: u256::from(0xccu8).into(),
+            input: &[1, 2, 3, 4, 5],
+            ..Default::default()
+        };
+        let message = message.into();
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &[],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(
+            result.stack.as_slice(),
+            [0xaau8, 0xbb, 0xcc, 5].map(|value| Uint256::from(u256::from(value)))
+        );
+        assert_eq!(result.memory[..5], [1, 2, 3, 4, 0]);
+    }
+
+    /// Executes EXTCODESIZE of `addr` twice and returns the pushed sizes and the gas used by both
+    /// executions.
+    fn ext_code_size_twice(host: &mut ReferenceHost, addr: u8) -> ([u256; 2], [i64; 2]) {
+        let code = [
+            Opcode::Push1 as u8,
+            addr,
+            Opcode::ExtCodeSize as u8,
+            Opcode::Push1 as u8,
+            addr,
+            Opcode::ExtCodeSize as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 10_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut gas_left = vec![10_000];
+        let mut stack = Vec::new();
+        let mut pc = 0;
+        for _ in 0..2 {
+            let message = ExecutionMessage {
+                gas: *gas_left.last().unwrap(),
+                ..message
+            };
+            let result: StepResult = Interpreter::new_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                host,
+                &code,
+                pc,
+                0,
+                Stack::new(&stack),
+                Memory::new(&[]),
+                Box::default(),
+                Some(2),
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            pc = result.pc as usize;
+            stack = result.stack.into_iter().map(u256::from).collect();
+            gas_left.push(result.gas_left);
+        }
+        (
+            [stack[0], stack[1]],
+            // without the cost of PUSH1
+            [
+                gas_left[0] - gas_left[1] - GAS_VERYLOW as i64,
+                gas_left[1] - gas_left[2] - GAS_VERYLOW as i64,
+            ],
+        )
+    }
+
+    /// Executes an SSTORE which does not change the value of the slot with `gas` gas left.
+    fn sstore_with_gas(gas: i64) -> StepResult {
+        let message = MockExecutionMessage {
+            gas,
+            ..Default::default()
+        };
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_set_storage()
+            .return_const(StorageStatus::EVMC_STORAGE_ASSIGNED);
+        let message = message.into();
+        run_steppable(
+            Revision::EVMC_ISTANBUL,
+            &message,
+            &mut context,
+            &[Opcode::SStore as u8],
+            &[u256::ONE, u256::ONE],
+            &[],
+        )
+    }
+
+    #[test]
+    fn sstore_fails_if_no_more_than_call_stipend_is_left() {
+        let result = sstore_with_gas(GAS_CALL_STIPEND as i64);
+        assert_eq!(result.status_code, StatusCode::EVMC_OUT_OF_GAS);
+
+        // In Istanbul, an SSTORE which does not change the value costs 800.
+        let result = sstore_with_gas(GAS_CALL_STIPEND as i64 + 1);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.gas_left, GAS_CALL_STIPEND as i64 + 1 - 800);
+    }
+
+    #[test]
+    fn ext_code_size_of_contract_charges_cold_then_warm_access() {
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.set_account(
+            Address::from(u256::from(0xaau8)),
+            Account {
+                code: vec![Opcode::Stop as u8; 42],
+                ..Default::default()
+            },
+        );
+        let (sizes, gas) = ext_code_size_twice(&mut host, 0xaa);
+        assert_eq!(sizes, [u256::from(42u8); 2]);
+        assert_eq!(gas, [2_600, 100]);
+    }
+
+    #[test]
+    fn ext_code_size_of_eoa_is_zero() {
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.set_account(Address::from(u256::from(0xbbu8)), Account::default());
+        let (sizes, gas) = ext_code_size_twice(&mut host, 0xbb);
+        assert_eq!(sizes, [u256::ZERO; 2]);
+        assert_eq!(gas, [2_600, 100]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn dump_jumptable_writes_all_entries() {
+        let mut dump = Vec::new();
+        crate::interpreter::dump_jumptable::<false>(&mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(lines.len(), 256);
+
+        let jumptable = crate::interpreter::get_jumptable::<false>();
+        assert_eq!(jumptable.len(), 256);
+        let jump_dest = Opcode::JumpDest as u8 as usize;
+        assert_eq!(
+            lines[jump_dest],
+            format!("0x5b JumpDest {:p}", jumptable[jump_dest] as *const ())
+        );
+        assert!(lines[0x0c].starts_with("0x0c - "));
+
+        #[cfg(feature = "fn-ptr-conversion-dispatch")]
+        assert!(std::ptr::fn_addr_eq(
+            crate::types::OpFnData::<false>::jump_dest()
+                .get_func()
+                .unwrap(),
+            jumptable[jump_dest]
+        ));
+    }
+
+    #[test]
+    fn second_sload_of_same_key_does_not_query_host_access_status() {
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_access_storage()
+            .times(1)
+            .return_const(AccessStatus::EVMC_ACCESS_COLD);
+        context
+            .expect_get_storage()
+            .times(2)
+            .return_const(Uint256::from(u256::ONE));
+        let code = [
+            Opcode::Push0 as u8,
+            Opcode::SLoad as u8,
+            Opcode::Push0 as u8,
+            Opcode::SLoad as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 10_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        // the second access is still charged as warm access
+        assert_eq!(result.gas_left, 10_000 - (2 + 2_100 + 2 + 100));
+    }
+
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    #[test]
+    fn with_jumptable_overrides_individual_handlers() {
+        let mut jumptable = *get_jumptable::<false>();
+        // ADD which always pushes 42
+        jumptable[Opcode::Add as usize] = |i| {
+            i.gas_left.consume(GAS_VERYLOW)?;
+            let (push_location, [_, _]) = i.stack.pop_with_location()?;
+            push_location.push(42u8);
+            i.code_reader.next();
+            i.return_from_op()
+        };
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Add as u8,
+            Opcode::Push1 as u8,
+            3,
+            Opcode::Mul as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let result: StepResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .with_jumptable(&jumptable)
+        .run(&mut NoOpObserver());
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        let expected: Uint256 = u256::from(42u8 * 3).into();
+        assert_eq!(result.stack, [expected]);
+    }
+
+    #[test]
+    fn exp_charges_per_byte_of_exponent_depending_on_revision() {
+        let message = MockExecutionMessage {
+            gas: 10_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        for (exponent, byte_len) in [
+            (u256::ZERO, 0),
+            (u256::from(0xffu8), 1),
+            (u256::from(0x0100u64), 2),
+            (u256::MAX, 32),
+        ] {
+            for (revision, gas_exp_byte) in [
+                (Revision::EVMC_FRONTIER, GAS_EXP_BYTE_FRONTIER),
+                (Revision::EVMC_TANGERINE_WHISTLE, GAS_EXP_BYTE_FRONTIER),
+                (Revision::EVMC_SPURIOUS_DRAGON, GAS_EXP_BYTE),
+                (Revision::EVMC_ISTANBUL, GAS_EXP_BYTE),
+            ] {
+                let mut context = MockExecutionContextTrait::new();
+                let result = run_steppable(
+                    revision,
+                    &message,
+                    &mut context,
+                    &[Opcode::Exp as u8],
+                    &[exponent, 2u8.into()],
+                    &[],
+                );
+                assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+                assert_eq!(
+                    result.gas_left,
+                    10_000 - (GAS_EXP + byte_len * gas_exp_byte) as i64,
+                    "{revision:?} {exponent}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mcopy_charges_base_copy_and_expansion_cost() {
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        let mut memory = [0; 64];
+        memory[0] = 1;
+        memory[63] = 2;
+        // len, src, dest
+        let stack = [u256::from(64u8), u256::ZERO, u256::from(32u8)];
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::MCopy as u8],
+            &stack,
+            &memory,
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        // base + 2 words + expansion from 2 to 3 words
+        assert_eq!(result.gas_left, 1_000 - (3 + 2 * 3 + 3));
+        assert_eq!(result.memory.len(), 96);
+        assert_eq!(result.memory[32], 1);
+        assert_eq!(result.memory[95], 2);
+    }
+
+    #[test]
+    fn mcopy_with_zero_length_only_charges_base_cost() {
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        let stack = [u256::ZERO, u256::from(u64::MAX), u256::from(u64::MAX)];
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::MCopy as u8],
+            &stack,
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.gas_left, 1_000 - 3);
+        assert!(result.memory.is_empty());
+    }
+
+    #[test]
+    fn mcopy_expands_memory_for_destination_beyond_source() {
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        let stack = [u256::from(32u8), u256::ZERO, u256::from(64u8)];
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::MCopy as u8],
+            &stack,
+            &[1; 32],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        // base + 1 word + expansion from 1 to 3 words
+        assert_eq!(result.gas_left, 1_000 - (3 + 3 + 6));
+        assert_eq!(result.memory[..32], [1; 32]);
+        assert_eq!(result.memory[32..64], [0; 32]);
+        assert_eq!(result.memory[64..], [1; 32]);
+    }
+
+    #[test]
+    fn mcopy_handles_overlapping_regions() {
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        let memory: Vec<u8> = (0..64).collect();
+        let mut run_mcopy = |len: u8, src: u8, dest: u8| {
+            run_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                &[Opcode::MCopy as u8],
+                &[len.into(), src.into(), dest.into()],
+                &memory,
+            )
+        };
+
+        // forward: the destination overlaps the end of the source
+        let result = run_mcopy(32, 0, 16);
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.memory[..16], memory[..16]);
+        assert_eq!(result.memory[16..48], memory[..32]);
+        assert_eq!(result.memory[48..], memory[48..]);
+
+        // backward: the destination overlaps the start of the source
+        let result = run_mcopy(32, 16, 0);
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.memory[..32], memory[16..48]);
+        assert_eq!(result.memory[32..], memory[32..]);
+
+        // source and destination are the same
+        let result = run_mcopy(64, 0, 0);
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.memory, memory);
+        assert_eq!(result.gas_left, 1_000 - (3 + 2 * 3));
+    }
+
+    #[test]
+    fn mcopy_with_zero_length_leaves_memory_unchanged() {
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        let memory: Vec<u8> = (0..32).collect();
+        let stack = [u256::ZERO, u256::ZERO, u256::from(16u8)];
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::MCopy as u8],
+            &stack,
+            &memory,
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.memory, memory);
+        assert_eq!(result.gas_left, 1_000 - 3);
+    }
+
+    #[test]
+    fn mcopy_is_undefined_before_cancun() {
+        let message = MockExecutionMessage::default().into();
+        let mut context = MockExecutionContextTrait::new();
+        let result = run_steppable(
+            Revision::EVMC_SHANGHAI,
+            &message,
+            &mut context,
+            &[Opcode::MCopy as u8],
+            &[u256::ZERO; 3],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_FAILED);
+        assert_eq!(result.status_code, StatusCode::EVMC_UNDEFINED_INSTRUCTION);
+    }
+
+    #[test]
+    fn tload_reads_transient_storage_of_recipient() {
+        let message = MockExecutionMessage {
+            gas: 100,
+            recipient: u256::from(0xaau8).into(),
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_transient_storage()
+            .times(1)
+            .with(
+                predicate::eq(Address::from(u256::from(0xaau8))),
+                predicate::eq(Uint256::from(u256::from(1u8))),
+            )
+            .return_const(Uint256::from(u256::from(42u8)));
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::TLoad as u8],
+            &[1u8.into()],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(result.stack.as_slice(), [u256::from(42u8).into()]);
+        assert_eq!(result.gas_left, 100 - GAS_WARM_ACCESS as i64);
+    }
+
+    #[test]
+    fn tstore_writes_transient_storage_of_recipient() {
+        let message = MockExecutionMessage {
+            gas: 100,
+            recipient: u256::from(0xaau8).into(),
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_set_transient_storage()
+            .times(1)
+            .with(
+                predicate::eq(Address::from(u256::from(0xaau8))),
+                predicate::eq(Uint256::from(u256::from(1u8))),
+                predicate::eq(Uint256::from(u256::from(42u8))),
+            )
+            .return_const(());
+        // the key is on top of the stack, the value below it
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::TStore as u8],
+            &[42u8.into(), 1u8.into()],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert!(result.stack.is_empty());
+        assert_eq!(result.gas_left, 100 - GAS_WARM_ACCESS as i64);
+    }
+
+    #[test]
+    fn tstore_fails_in_static_context() {
+        let message = MockExecutionMessage {
+            flags: MessageFlags::EVMC_STATIC as u32,
+            ..Default::default()
+        }
+        .into();
+        let mut context = MockExecutionContextTrait::new();
+        context.expect_set_transient_storage().never();
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::TStore as u8],
+            &[42u8.into(), 1u8.into()],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_FAILED);
+        assert_eq!(result.status_code, StatusCode::EVMC_STATIC_MODE_VIOLATION);
+    }
+
+    #[test]
+    fn transient_storage_ops_are_undefined_before_cancun() {
+        let message = MockExecutionMessage::default().into();
+        for (op, stack) in [
+            (Opcode::TLoad, [u256::ZERO].as_slice()),
+            (Opcode::TStore, [u256::ZERO, u256::ZERO].as_slice()),
+        ] {
+            let mut context = MockExecutionContextTrait::new();
+            let result = run_steppable(
+                Revision::EVMC_SHANGHAI,
+                &message,
+                &mut context,
+                &[op as u8],
+                stack,
+                &[],
+            );
+            assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_FAILED);
+            assert_eq!(result.status_code, StatusCode::EVMC_UNDEFINED_INSTRUCTION);
+        }
+    }
+
+    static BLOB_HASHES: [Uint256; 2] =
+        [Uint256 { bytes: [0x11; 32] }, Uint256 { bytes: [0x22; 32] }];
+
+    /// Returns a context whose transaction has the blob hashes [`BLOB_HASHES`] and a blob base fee
+    /// of 7.
+    fn blob_tx_context() -> MockExecutionContextTrait {
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_tx_context()
+            .return_const(ExecutionTxContext {
+                tx_gas_price: u256::ZERO.into(),
+                tx_origin: u256::ZERO.into(),
+                block_coinbase: u256::ZERO.into(),
+                block_number: 0,
+                block_timestamp: 0,
+                block_gas_limit: 0,
+                block_prev_randao: u256::ZERO.into(),
+                chain_id: u256::ZERO.into(),
+                block_base_fee: u256::ZERO.into(),
+                blob_base_fee: u256::from(7u8).into(),
+                blob_hashes: &BLOB_HASHES,
+                initcodes: &[],
+            });
+        context
+    }
+
+    #[test]
+    fn blob_hash_returns_hash_at_index_or_zero() {
+        let message = MockExecutionMessage::default().into();
+        for (idx, hash) in [
+            (u256::ZERO, BLOB_HASHES[0]),
+            (u256::ONE, BLOB_HASHES[1]),
+            (u256::from(2u8), u256::ZERO.into()),
+            (u256::MAX, u256::ZERO.into()),
+        ] {
+            let result = run_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut blob_tx_context(),
+                &[Opcode::BlobHash as u8],
+                &[idx],
+                &[],
+            );
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.stack.as_slice(), [hash]);
+        }
+    }
+
+    #[test]
+    fn blob_base_fee_pushes_blob_base_fee_of_tx_context() {
+        let message = MockExecutionMessage::default().into();
+        let result = run_steppable(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut blob_tx_context(),
+            &[Opcode::BlobBaseFee as u8],
+            &[],
+            &[],
+        );
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.stack.as_slice(), [u256::from(7u8).into()]);
+    }
+
+    #[test]
+    fn blob_ops_are_undefined_before_cancun() {
+        let message = MockExecutionMessage::default().into();
+        for (op, stack) in [
+            (Opcode::BlobHash, [u256::ZERO].as_slice()),
+            (Opcode::BlobBaseFee, [].as_slice()),
+        ] {
+            let result = run_steppable(
+                Revision::EVMC_SHANGHAI,
+                &message,
+                &mut blob_tx_context(),
+                &[op as u8],
+                stack,
+                &[],
+            );
+            assert_eq!(result.status_code, StatusCode::EVMC_UNDEFINED_INSTRUCTION);
+        }
+    }
+
+    #[test]
+    fn shift_ops_are_undefined_before_constantinople() {
+        let message = MockExecutionMessage::default().into();
+        for op in [Opcode::Shl, Opcode::Shr, Opcode::Sar] {
+            for (revision, status) in [
+                (
+                    Revision::EVMC_BYZANTIUM,
+                    StatusCode::EVMC_UNDEFINED_INSTRUCTION,
+                ),
+                (Revision::EVMC_CONSTANTINOPLE, StatusCode::EVMC_SUCCESS),
+            ] {
+                let result = run_steppable(
+                    revision,
+                    &message,
+                    &mut MockExecutionContextTrait::new(),
+                    &[op as u8],
+                    &[u256::MAX, u256::from(256u64)],
+                    &[],
+                );
+                assert_eq!(result.status_code, status);
+            }
+        }
+    }
+
+    #[test]
+    fn ops_are_undefined_exactly_before_their_revision_even_with_empty_stack() {
+        let revisions = [
+            Revision::EVMC_FRONTIER,
+            Revision::EVMC_HOMESTEAD,
+            Revision::EVMC_TANGERINE_WHISTLE,
+            Revision::EVMC_SPURIOUS_DRAGON,
+            Revision::EVMC_BYZANTIUM,
+            Revision::EVMC_CONSTANTINOPLE,
+            Revision::EVMC_PETERSBURG,
+            Revision::EVMC_ISTANBUL,
+            Revision::EVMC_BERLIN,
+            Revision::EVMC_LONDON,
+            Revision::EVMC_PARIS,
+            Revision::EVMC_SHANGHAI,
+            Revision::EVMC_CANCUN,
+            Revision::EVMC_PRAGUE,
+            Revision::EVMC_OSAKA,
+        ];
+        let message = MockExecutionMessage::default().into();
+        // the handlers must reject exactly the opcodes which opcode_availability reports as not
+        // yet defined
+        for (op, min_revision) in opcode_availability() {
+            assert!(
+                revisions.contains(&min_revision),
+                "{op:?} is introduced in unknown revision {min_revision:?}"
+            );
+            for revision in revisions {
+                let result = run_steppable(
+                    revision,
+                    &message,
+                    &mut ReferenceHost::new(revision),
+                    &[op as u8],
+                    &[],
+                    &[],
+                );
+                assert_eq!(
+                    result.status_code == StatusCode::EVMC_UNDEFINED_INSTRUCTION,
+                    revision < min_revision,
+                    "{op:?} in {revision:?} returned {:?}",
+                    result.status_code
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn code_which_can_not_be_executed_is_rejected_by_constructor() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            kind: MessageKind::EVMC_CREATE,
+            ..Default::default()
+        }
+        .into();
+        let code = vec![Opcode::Stop as u8; 2 * 24576 + 1];
+        let interpreter = Interpreter::new(
+            Revision::EVMC_SHANGHAI,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        );
+        assert_eq!(interpreter.err(), Some(FailStatus::OutOfGas));
+
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_PARIS,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+    }
+
+    #[cfg(feature = "verify-code-hash")]
+    #[test]
+    fn code_which_does_not_match_code_hash_is_rejected_by_constructor() {
+        static WRONG_HASH: Uint256 = Uint256 { bytes: [0xff; 32] };
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            code_hash: Some(&WRONG_HASH),
+            ..Default::default()
+        }
+        .into();
+        let interpreter = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &[Opcode::Push0 as u8],
+            &code_analysis_cache,
+            &hash_cache,
+        );
+        assert_eq!(interpreter.err(), Some(FailStatus::InvalidCode));
+    }
+
+    #[test]
+    fn sha3_gas_and_result() {
+        // (len, expected gas including memory expansion)
+        let cases = [(0u64, 30u64), (1, 30 + 6 + 3), (33, 30 + 2 * 6 + 2 * 3)];
+        for (len, gas) in cases {
+            let mut context = MockExecutionContextTrait::new();
+            let message = MockExecutionMessage::default().into();
+            let result = run_steppable(
+                Revision::EVMC_ISTANBUL,
+                &message,
+                &mut context,
+                &[Opcode::Sha3 as u8],
+                &[len.into(), u256::ZERO],
+                &[],
+            );
+            assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+            assert_eq!(
+                result.gas_left,
+                MockExecutionMessage::DEFAULT_INIT_GAS as i64 - gas as i64
+            );
+            assert_eq!(result.memory.len(), len.div_ceil(32) as usize * 32);
+            let expected: [u8; 32] = Keccak256::digest(vec![0; len as usize]).into();
+            assert_eq!(result.stack.as_slice(), [Uint256 { bytes: expected }]);
+        }
+    }
+
+    #[test]
+    fn sha3_empty_input_is_empty_keccak() {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let result = run_steppable(
+            Revision::EVMC_ISTANBUL,
+            &message,
+            &mut context,
+            &[Opcode::Sha3 as u8],
+            // the offset is ignored for empty input and does not cause memory expansion
+            &[u256::ZERO, u256::MAX],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert!(result.memory.is_empty());
+        assert_eq!(
+            result.stack.as_slice(),
+            [Uint256 {
+                bytes: [
+                    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc,
+                    0xc7, 0x03, 0xc0, 0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa,
+                    0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+                ]
+            }]
+        );
+    }
+
+    #[test]
+    fn sha3_huge_len_is_out_of_gas() {
+        for len in [u256::MAX, u64::MAX.into(), (u64::MAX - 31).into()] {
+            let mut context = MockExecutionContextTrait::new();
+            let message = MockExecutionMessage::default().into();
+            let result = run_steppable(
+                Revision::EVMC_ISTANBUL,
+                &message,
+                &mut context,
+                &[Opcode::Sha3 as u8],
+                &[len, u256::ZERO],
+                &[],
+            );
+            assert_eq!(result.status_code, StatusCode::EVMC_OUT_OF_GAS);
+        }
+    }
+
+    #[test]
+    fn revert_returns_output_and_remaining_gas() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let code = [
+            Opcode::Push4 as u8,
+            0xde,
+            0xad,
+            0xbe,
+            0xef,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            4,
+            Opcode::Push1 as u8,
+            28,
+            Opcode::Revert as u8,
+        ];
+        let interpreter = Interpreter::new(
+            Revision::EVMC_SHANGHAI,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap();
+        let result: ExecutionResult = interpreter.run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_REVERT);
+        assert_eq!(result.output.as_ref(), [0xde, 0xad, 0xbe, 0xef]);
+        // PUSH4 + PUSH0 + MSTORE (incl. memory expansion) + 2 * PUSH1; REVERT itself is free
+        // because the memory is already expanded
+        let gas_used = 3 + 2 + (3 + 3) + 2 * 3;
+        assert!(result.gas_left > 0);
+        assert_eq!(result.gas_left, 100_000 - gas_used);
+    }
+
+    #[test]
+    fn seeded_return_data_is_visible_to_return_data_opcodes() {
+        let return_data: Vec<u8> = (1..=32).collect();
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let code = [
+            // MSTORE(0, RETURNDATASIZE)
+            Opcode::ReturnDataSize as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            // RETURNDATACOPY(32, 0, 32)
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::ReturnDataCopy as u8,
+            // RETURN(0, 64)
+            Opcode::Push1 as u8,
+            64,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .with_return_data(return_data.clone().into_boxed_slice())
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.output[..32], u256::from(32u8).to_be_bytes());
+        assert_eq!(result.output[32..], return_data);
+    }
+
+    #[test]
+    fn output_longer_than_max_output_len_fails() {
+        for (op, success_status) in [
+            (Opcode::Return, StatusCode::EVMC_SUCCESS),
+            (Opcode::Revert, StatusCode::EVMC_REVERT),
+        ] {
+            // return or revert 1 GiB or 1 KiB of memory starting at offset 0
+            for (len, status) in [
+                (1u32 << 30, StatusCode::EVMC_OUT_OF_MEMORY),
+                (1 << 10, success_status),
+            ] {
+                let code_analysis_cache = CodeAnalysisCache::default();
+                let hash_cache = HashCache::default();
+                let mut context = MockExecutionContextTrait::new();
+                let message = MockExecutionMessage::default().into();
+                let mut code = vec![Opcode::Push4 as u8];
+                code.extend_from_slice(&len.to_be_bytes());
+                code.extend([Opcode::Push0 as u8, op as u8]);
+                let result: ExecutionResult = Interpreter::new(
+                    Revision::EVMC_CANCUN,
+                    &message,
+                    &mut context,
+                    &code,
+                    &code_analysis_cache,
+                    &hash_cache,
+                )
+                .unwrap()
+                .with_max_output_len(1 << 10)
+                .run(&mut NoOpObserver());
+                assert_eq!(result.status_code, status);
+            }
+        }
+    }
+
+    /// Runs a chain of three contracts where each one expands its memory to 1024 bytes and then
+    /// calls the next one. The innermost contract returns 1, all others return the output of their
+    /// callee if the call succeeded and 0 otherwise.
+    fn run_nested_memory_expansions(total_memory_limit: u64) -> ExecutionResult {
+        const CONTRACTS: u8 = 3;
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        for i in 0..CONTRACTS {
+            // MSTORE(992, 1) expands memory to 1024 bytes
+            let mut code = vec![
+                Opcode::Push1 as u8,
+                1,
+                Opcode::Push2 as u8,
+                0x03,
+                0xe0,
+                Opcode::MStore as u8,
+            ];
+            if i + 1 < CONTRACTS {
+                // CALL the next contract with 32 bytes of return data written to memory offset 0,
+                // then store MLOAD(0) & success at offset 0
+                code.extend([
+                    Opcode::Push1 as u8,
+                    32,                  // ret len
+                    Opcode::Push0 as u8, // ret offset
+                    Opcode::Push0 as u8, // args len
+                    Opcode::Push0 as u8, // args offset
+                    Opcode::Push0 as u8, // value
+                    Opcode::Push1 as u8,
+                    i + 1, // addr
+                    Opcode::Gas as u8,
+                    Opcode::Call as u8,
+                    Opcode::Push0 as u8,
+                    Opcode::MLoad as u8,
+                    Opcode::And as u8,
+                ]);
+            } else {
+                code.extend([Opcode::Push1 as u8, 1]);
+            }
+            code.extend([
+                Opcode::Push0 as u8,
+                Opcode::MStore as u8,
+                Opcode::Push1 as u8,
+                32,
+                Opcode::Push0 as u8,
+                Opcode::Return as u8,
+            ]);
+            host.set_account(
+                Address::from(u256::from(i)),
+                Account {
+                    code,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let code = host
+            .account(&Address::from(u256::ZERO))
+            .unwrap()
+            .code
+            .clone();
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 1_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+
+        let scope = MemoryBudget::enter(total_memory_limit, 0);
+        let mut interpreter = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap();
+        if let Some(budget) = scope.budget() {
+            interpreter = interpreter.with_memory_budget(budget);
+        }
+        interpreter.run(&mut NoOpObserver())
+    }
+
+    #[test]
+    fn nested_memory_expansions_respect_total_memory_limit() {
+        let mut success = [0; 32];
+        success[31] = 1;
+
+        let result = run_nested_memory_expansions(u64::MAX);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.output.as_ref(), success.as_slice());
+
+        // Each frame uses 1024 bytes, which is below the limit, but all three frames together
+        // exceed it. Therefore the innermost call runs out of gas.
+        let result = run_nested_memory_expansions(2500);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.output.as_ref(), [0; 32].as_slice());
+    }
+
+    /// Returns the gas used by a contract which calls address 0x42 with the given value.
+    fn call_gas_used(value: u8, target_exists: bool) -> i64 {
+        let contract_addr = Address::from(u256::from(0xbbu8));
+        let target_addr = Address::from(u256::from(0x42u8));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.set_account(
+            contract_addr,
+            Account {
+                balance: u256::from(10u8),
+                ..Default::default()
+            },
+        );
+        if target_exists {
+            host.set_account(target_addr, Account::default());
+        }
+
+        let code = [
+            Opcode::Push0 as u8, // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push0 as u8, // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push1 as u8,
+            value,
+            Opcode::Push1 as u8,
+            0x42, // addr
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            recipient: contract_addr,
+            code_address: contract_addr,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        100_000 - result.gas_left
+    }
+
+    #[test]
+    fn call_with_value_to_nonexistent_account_charges_new_account_cost() {
+        assert_eq!(
+            call_gas_used(1, false) - call_gas_used(1, true),
+            GAS_NEW_ACCOUNT as i64
+        );
+    }
+
+    #[test]
+    fn call_with_value_to_existing_account_does_not_charge_new_account_cost() {
+        // the only difference between a call with and without value to an existing account is
+        // the value transfer cost, minus the stipend which the callee without code returns
+        assert_eq!(
+            call_gas_used(1, true) - call_gas_used(0, true),
+            GAS_CALL_VALUE as i64 - GAS_CALL_STIPEND as i64
+        );
+    }
+
+    #[test]
+    fn call_without_value_to_nonexistent_account_does_not_charge_new_account_cost() {
+        assert_eq!(call_gas_used(0, false), call_gas_used(0, true));
+    }
+
+    #[test]
+    fn cancellation_aborts_long_running_execution() {
+        let cancellation = Arc::new(AtomicBool::new(false));
+        let canceller = {
+            let cancellation = Arc::clone(&cancellation);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                cancellation.store(true, Ordering::Relaxed);
+            })
+        };
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        // enough gas to keep the loop running for much longer than the test is supposed to take
+        let message = MockExecutionMessage {
+            gas: 100_000_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        // JUMPDEST PUSH0 JUMP
+        let code = [
+            Opcode::JumpDest as u8,
+            Opcode::Push0 as u8,
+            Opcode::Jump as u8,
+        ];
+        let start = Instant::now();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .with_cancellation(&cancellation)
+        .run(&mut NoOpObserver());
+        canceller.join().unwrap();
+
+        assert_eq!(result.status_code, StatusCode::EVMC_INTERNAL_ERROR);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn deadline_aborts_long_running_execution() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        // enough gas to keep the loop running for much longer than the test is supposed to take
+        let message = MockExecutionMessage {
+            gas: 100_000_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        // JUMPDEST PUSH0 JUMP
+        let code = [
+            Opcode::JumpDest as u8,
+            Opcode::Push0 as u8,
+            Opcode::Jump as u8,
+        ];
+        let start = Instant::now();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .with_deadline(start + Duration::from_millis(10))
+        .run(&mut NoOpObserver());
+
+        assert_eq!(result.status_code, StatusCode::EVMC_INTERNAL_ERROR);
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Returns the gas used by a contract with the given balance which selfdestructs with
+    /// beneficiary 0x42.
+    fn self_destruct_gas_used(revision: Revision, balance: u8, beneficiary_exists: bool) -> i64 {
+        let contract_addr = Address::from(u256::from(0xbbu8));
+        let beneficiary_addr = Address::from(u256::from(0x42u8));
+
+        let mut host = ReferenceHost::new(revision);
+        host.set_account(
+            contract_addr,
+            Account {
+                balance: u256::from(balance),
+                ..Default::default()
+            },
+        );
+        if beneficiary_exists {
+            host.set_account(beneficiary_addr, Account::default());
+        }
+
+        let code = [Opcode::Push1 as u8, 0x42, Opcode::SelfDestruct as u8];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            recipient: contract_addr,
+            code_address: contract_addr,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            revision,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        100_000 - result.gas_left
+    }
+
+    #[test]
+    fn self_destruct_to_new_beneficiary_with_balance_charges_new_account_and_cold_access_cost() {
+        // PUSH1 + SELFDESTRUCT + cold access + new account
+        assert_eq!(
+            self_destruct_gas_used(Revision::EVMC_CANCUN, 1, false),
+            3 + 5_000 + 2_600 + 25_000
+        );
+        // there is no cold access cost before Berlin
+        assert_eq!(
+            self_destruct_gas_used(Revision::EVMC_ISTANBUL, 1, false),
+            3 + 5_000 + 25_000
+        );
+    }
+
+    #[test]
+    fn self_destruct_to_existing_beneficiary_does_not_charge_new_account_cost() {
+        assert_eq!(
+            self_destruct_gas_used(Revision::EVMC_CANCUN, 1, true),
+            3 + 5_000 + 2_600
+        );
+        assert_eq!(
+            self_destruct_gas_used(Revision::EVMC_ISTANBUL, 1, true),
+            3 + 5_000
+        );
+    }
+
+    #[test]
+    fn self_destruct_without_balance_does_not_charge_new_account_cost() {
+        assert_eq!(
+            self_destruct_gas_used(Revision::EVMC_CANCUN, 0, false),
+            3 + 5_000 + 2_600
+        );
+    }
+
+    #[test]
+    fn push0_pushes_zero_and_costs_base_gas() {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result = run_steppable(
+            Revision::EVMC_SHANGHAI,
+            &message,
+            &mut context,
+            &[Opcode::Push0 as u8],
+            &[u256::MAX],
+            &[],
+        );
+        assert_eq!(result.step_status_code, StepStatusCode::EVMC_STEP_STOPPED);
+        assert_eq!(
+            result.stack,
+            [Uint256::from(u256::MAX), Uint256::from(u256::ZERO)]
+        );
+        // G_base and not G_verylow like PUSH1
+        assert_eq!(result.gas_left, 100 - 2);
+    }
+
+    #[test]
+    fn push0_is_undefined_before_shanghai() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_PARIS,
+            &message,
+            &mut context,
+            &[Opcode::Push0 as u8],
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_UNDEFINED_INSTRUCTION);
+    }
+
+    #[test]
+    fn jump_into_push_data_equal_to_jumpdest_fails() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage::default().into();
+        let run = |code: &[u8]| -> ExecutionResult {
+            let mut context = MockExecutionContextTrait::new();
+            Interpreter::new(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                code,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver())
+        };
+
+        // pc 1 is the push data 0x5b, which must not be treated as JUMPDEST
+        let jump = [
+            Opcode::Push1 as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Jump as u8,
+        ];
+        assert_eq!(
+            run(&jump).status_code,
+            StatusCode::EVMC_BAD_JUMP_DESTINATION
+        );
+
+        let jump_i = [
+            Opcode::Push1 as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Push1 as u8,
+            1, // condition
+            Opcode::Push1 as u8,
+            1, // destination
+            Opcode::JumpI as u8,
+        ];
+        assert_eq!(
+            run(&jump_i).status_code,
+            StatusCode::EVMC_BAD_JUMP_DESTINATION
+        );
+
+        // the same code with a real JUMPDEST after the jump succeeds
+        let jump = [
+            Opcode::Push1 as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Push1 as u8,
+            5,
+            Opcode::Jump as u8,
+            Opcode::JumpDest as u8,
+        ];
+        assert_eq!(run(&jump).status_code, StatusCode::EVMC_SUCCESS);
+    }
+
+    #[test]
+    fn opcode_info_matches_stack_requirements_of_handlers() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage::default().into();
+        let run = |op: u8, stack: &[u256]| {
+            let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+            let mut code = vec![op];
+            code.resize(33, 0);
+            let mut interpreter = Interpreter::new(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut host,
+                &code,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap();
+            interpreter.stack = Stack::new(stack);
+            let result: ExecutionResult = interpreter.run(&mut NoOpObserver());
+            result.status_code
+        };
+
+        for op in 0..=u8::MAX {
+            if code_byte_type(op).0 == CodeByteType::DataOrInvalid {
+                continue;
+            }
+            let info = OPCODE_INFO[op as usize];
+            if info.stack_in > 0 {
+                let stack = vec![u256::ZERO; info.stack_in as usize - 1];
+                assert_eq!(
+                    run(op, &stack),
+                    StatusCode::EVMC_STACK_UNDERFLOW,
+                    "opcode {op:#04x}"
+                );
+            }
+            if info.stack_out > info.stack_in {
+                assert_eq!(
+                    run(op, &[u256::ZERO; Stack::CAPACITY]),
+                    StatusCode::EVMC_STACK_OVERFLOW,
+                    "opcode {op:#04x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn opcode_info_matches_stack_effect_of_handlers() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage::default().into();
+        for (op, min_revision) in opcode_availability() {
+            let revision = min_revision.max(Revision::EVMC_CANCUN);
+            let info = OPCODE_INFO[op as usize];
+            // the jump destination lets JUMP and JUMPI with a zero destination succeed
+            let mut code = vec![Opcode::JumpDest as u8, op as u8];
+            code.resize(34, 0);
+            let stack = vec![u256::ZERO; info.stack_in as usize];
+            let mut host = ReferenceHost::new(revision);
+            let result: StepResult = Interpreter::new_steppable(
+                revision,
+                &message,
+                &mut host,
+                &code,
+                0,
+                0,
+                Stack::new(&stack),
+                Memory::new(&[]),
+                Box::default(),
+                Some(2),
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver());
+            if op == Opcode::Invalid {
+                assert_eq!(result.status_code, StatusCode::EVMC_INVALID_INSTRUCTION);
+                continue;
+            }
+            // the handler pops at most stack_in items and pushes exactly stack_out items
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS, "{op:?}");
+            assert_eq!(result.stack.len(), info.stack_out as usize, "{op:?}");
+        }
+    }
 }
@@ -1,7 +1,10 @@
 #![allow(unused_crate_dependencies)]
+pub mod abi;
 mod evmrs;
 mod ffi;
 mod interpreter;
+pub mod precompiles;
+mod transaction;
 mod types;
 mod utils;
 
@@ -16,13 +19,41 @@ compile_error!(
 );
 
 pub use evmc_vm;
+#[cfg(debug_assertions)]
+pub use interpreter::dump_jumptable;
+pub use interpreter::{
+    ExecutionSnapshot, Interpreter, run_with_observer, run_with_opcode_histogram,
+    run_with_trace_format,
+};
 use llvm_profile_wrappers::{
     llvm_profile_enabled, llvm_profile_reset_counters, llvm_profile_set_filename,
     llvm_profile_write_file,
 };
+pub use transaction::{
+    ConstructorError, EmittedLog, Transaction, TransactionError, TransactionResult,
+    calldata_floor_gas, execute_transaction, intrinsic_gas, run_constructor,
+};
+pub use types::hash_cache::{HashCache, HashCachePolicy};
 #[cfg(feature = "mock")]
-pub use types::MockExecutionContextTrait;
-pub use types::{ExecutionContextTrait, MockExecutionMessage, Opcode, u256};
+pub use types::{
+    Account, HostInteraction, HostRecording, HostRecordingError, InstructionStream, Log,
+    MockCallRouter, MockExecutionContextTrait, PlaybackHost, RecordedMessage, RecordedResult,
+    RecordedTxContext, RecordingHost, ReferenceHost, StepInfo,
+};
+pub use types::{
+    CallGraphEdge, CallGraphObserver, CodeAnalysisCache, CodeByteType, CompactBinary,
+    DepthFilterObserver, Eip3155Json, ExecStatus, ExecutionContextTrait, FailStatus, GasBreakdown,
+    GasBreakdownObserver, GasProfileObserver, InterpreterState, InterpreterStateError,
+    MockExecutionMessage, NoOpObserver, Observer, Opcode, OpcodeHistogramObserver, OpcodeStats,
+    ParseError, PendingStorageWrite, RecordedWarning, StateDiffObserver, StorageSlotDiff,
+    StorageWriteHaltObserver, StorageWriteObserver, TraceFormat, TraceResult, TraceStep, Warning,
+    WarningObserver, code_byte_type, opcode_availability, u256,
+};
+
+/// Gas costs of the EVM gas schedule.
+pub mod gas {
+    pub use crate::utils::constants;
+}
 
 /// Dump coverage data when compiled with `RUSTFLAGS="-C instrument-coverage"`.
 /// Otherwise this is a no-op.
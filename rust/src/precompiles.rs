@@ -0,0 +1,333 @@
+//! The addresses of all precompiled contracts and, with feature `precompiles`, the precompiled
+//! contracts ecRecover (0x01), SHA256 (0x02), RIPEMD160 (0x03) and IDENTITY (0x04). Evmrs does not
+//! implement the other precompiles and therefore does not advertise
+//! `EVMC_CAPABILITY_PRECOMPILES`. Messages whose code address is one of the implemented precompiles
+//! are executed by this module, all other messages are executed as EVM1 bytecode.
+use evmc_vm::{Address, Revision};
+#[cfg(feature = "precompiles")]
+use evmc_vm::{ExecutionResult, StatusCode};
+#[cfg(feature = "precompiles")]
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+#[cfg(feature = "precompiles")]
+use ripemd::Ripemd160;
+#[cfg(feature = "precompiles")]
+use sha2::Sha256;
+#[cfg(feature = "precompiles")]
+use sha3::{Digest, Keccak256};
+
+use crate::types::u256;
+#[cfg(feature = "precompiles")]
+use crate::{
+    types::FailStatus,
+    utils::constants::{
+        GAS_ECRECOVER, GAS_IDENTITY, GAS_IDENTITY_WORD, GAS_RIPEMD160, GAS_RIPEMD160_WORD,
+        GAS_SHA256, GAS_SHA256_WORD,
+    },
+};
+
+/// The precompiles implemented by evmrs.
+#[cfg(feature = "precompiles")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precompile {
+    EcRecover = 0x01,
+    Sha256 = 0x02,
+    Ripemd160 = 0x03,
+    Identity = 0x04,
+}
+
+/// Returns the last byte of `address` if all other bytes are zero.
+fn low_address(address: &Address) -> Option<u8> {
+    let (high, low) = address.bytes.split_at(19);
+    high.iter().all(|byte| *byte == 0).then_some(low[0])
+}
+
+/// Returns whether `address` is reserved for a precompile in `revision`, independent of whether
+/// evmrs implements it.
+pub fn is_precompile(revision: Revision, address: &Address) -> bool {
+    match low_address(address) {
+        Some(0x01..=0x04) => true,
+        Some(0x05..=0x08) => revision >= Revision::EVMC_BYZANTIUM,
+        Some(0x09) => revision >= Revision::EVMC_ISTANBUL,
+        // point evaluation (EIP-4844)
+        Some(0x0a) => revision >= Revision::EVMC_CANCUN,
+        // BLS12-381 operations (EIP-2537)
+        Some(0x0b..=0x11) => revision >= Revision::EVMC_PRAGUE,
+        // P256VERIFY (EIP-7951)
+        _ => revision >= Revision::EVMC_OSAKA && u256::from(*address) == u256::from(0x100u64),
+    }
+}
+
+/// Returns the addresses of all precompiles in `revision`, which are warm from the start of each
+/// transaction since Berlin (EIP-2929).
+pub fn precompile_addresses(revision: Revision) -> impl Iterator<Item = Address> {
+    (0x01..=0x11u64)
+        .chain([0x100])
+        .map(|address| Address::from(u256::from(address)))
+        .filter(move |address| is_precompile(revision, address))
+}
+
+#[cfg(feature = "precompiles")]
+impl Precompile {
+    pub fn from_address(address: &Address) -> Option<Self> {
+        match low_address(address)? {
+            0x01 => Some(Self::EcRecover),
+            0x02 => Some(Self::Sha256),
+            0x03 => Some(Self::Ripemd160),
+            0x04 => Some(Self::Identity),
+            _ => None,
+        }
+    }
+
+    /// Returns the gas cost of executing the precompile with an input of `input_len` bytes. The
+    /// prices of these precompiles did not change since Frontier.
+    pub fn gas_cost(self, input_len: usize) -> u64 {
+        let words = (input_len as u64).div_ceil(32);
+        let (base, per_word) = match self {
+            Self::EcRecover => (GAS_ECRECOVER, 0),
+            Self::Sha256 => (GAS_SHA256, GAS_SHA256_WORD),
+            Self::Ripemd160 => (GAS_RIPEMD160, GAS_RIPEMD160_WORD),
+            Self::Identity => (GAS_IDENTITY, GAS_IDENTITY_WORD),
+        };
+        base.saturating_add(words.saturating_mul(per_word))
+    }
+
+    /// Returns the output of the precompile for `input`.
+    pub fn execute(self, input: &[u8]) -> Vec<u8> {
+        match self {
+            Self::EcRecover => ec_recover(input),
+            Self::Sha256 => Sha256::digest(input).to_vec(),
+            Self::Ripemd160 => {
+                let mut output = vec![0; 12];
+                output.extend_from_slice(&Ripemd160::digest(input));
+                output
+            }
+            Self::Identity => input.to_vec(),
+        }
+    }
+}
+
+/// Returns the left padded address which signed the hash in the first word of `input` with the
+/// signature `v`, `r`, `s` in the following three words. Missing input bytes are treated as zero.
+/// If the signature is invalid, the output is empty.
+#[cfg(feature = "precompiles")]
+fn ec_recover(input: &[u8]) -> Vec<u8> {
+    let mut padded = [0; 128];
+    let len = input.len().min(padded.len());
+    padded[..len].copy_from_slice(&input[..len]);
+    let (hash, rest) = padded.split_at(32);
+    let (v, signature) = rest.split_at(32);
+
+    if v[..31].iter().any(|byte| *byte != 0) || !matches!(v[31], 27 | 28) {
+        return Vec::new();
+    }
+    let Some(address) = recover_address(hash, signature, v[31] - 27) else {
+        return Vec::new();
+    };
+    let mut output = vec![0; 12];
+    output.extend_from_slice(&address);
+    output
+}
+
+#[cfg(feature = "precompiles")]
+fn recover_address(hash: &[u8], signature: &[u8], recovery_id: u8) -> Option<[u8; 20]> {
+    let mut signature = Signature::from_slice(signature).ok()?;
+    let mut recovery_id = recovery_id;
+    // k256 only recovers keys from signatures with a low s, but Ethereum accepts both. Negating s
+    // also flips the parity of the recovered point.
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id ^= 1;
+    }
+    let recovery_id = RecoveryId::from_byte(recovery_id)?;
+    let key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id).ok()?;
+    let key_hash = Keccak256::digest(&key.to_encoded_point(false).as_bytes()[1..]);
+    key_hash[12..].try_into().ok()
+}
+
+/// Executes the precompile at `address`. Returns `None` if `address` is not reserved for a
+/// precompile in `revision` or if evmrs does not implement this precompile.
+#[cfg(feature = "precompiles")]
+pub fn execute_precompile(
+    revision: Revision,
+    address: &Address,
+    input: &[u8],
+    gas: i64,
+) -> Option<ExecutionResult> {
+    if !is_precompile(revision, address) {
+        return None;
+    }
+    let precompile = Precompile::from_address(address)?;
+    let gas_left = u64::try_from(gas)
+        .ok()
+        .and_then(|gas| gas.checked_sub(precompile.gas_cost(input.len())));
+    let Some(gas_left) = gas_left else {
+        return Some(FailStatus::OutOfGas.into());
+    };
+    Some(ExecutionResult {
+        status_code: StatusCode::EVMC_SUCCESS,
+        gas_left: gas_left as i64,
+        gas_refund: 0,
+        output: precompile.execute(input).into_boxed_slice(),
+        create_address: None,
+    })
+}
+
+/// Executes the precompile at address `address` directly instead of through a CALL and returns
+/// its output, the gas it used and its status. This is meant for testing precompiles. If evmrs does
+/// not implement the precompile in `revision`, the execution is rejected with `EVMC_REJECTED`.
+#[cfg(feature = "precompiles")]
+pub fn run_precompile(
+    address: u8,
+    input: &[u8],
+    gas: i64,
+    revision: Revision,
+) -> (Box<[u8]>, u64, StatusCode) {
+    let address = Address::from(u256::from(address));
+    match execute_precompile(revision, &address, input, gas) {
+        Some(result) => (
+            result.output,
+            gas.saturating_sub(result.gas_left).max(0) as u64,
+            result.status_code,
+        ),
+        None => (Box::default(), 0, StatusCode::EVMC_REJECTED),
+    }
+}
+
+#[cfg(all(test, feature = "precompiles"))]
+mod tests {
+    use evmc_vm::{Address, Revision, StatusCode};
+    use k256::ecdsa::SigningKey;
+
+    use crate::{
+        precompiles::{
+            Precompile, execute_precompile, is_precompile, precompile_addresses, run_precompile,
+        },
+        types::u256,
+    };
+
+    fn address(byte: u8) -> Address {
+        Address::from(u256::from(byte))
+    }
+
+    #[test]
+    fn precompile_addresses_depend_on_revision() {
+        assert!(!is_precompile(Revision::EVMC_CANCUN, &address(0)));
+        assert!(is_precompile(Revision::EVMC_FRONTIER, &address(4)));
+        assert!(!is_precompile(Revision::EVMC_HOMESTEAD, &address(5)));
+        assert!(is_precompile(Revision::EVMC_BYZANTIUM, &address(8)));
+        assert!(!is_precompile(Revision::EVMC_PETERSBURG, &address(9)));
+        assert!(is_precompile(Revision::EVMC_ISTANBUL, &address(9)));
+        assert!(!is_precompile(Revision::EVMC_SHANGHAI, &address(0x0a)));
+        assert!(is_precompile(Revision::EVMC_CANCUN, &address(0x0a)));
+        assert!(!is_precompile(Revision::EVMC_CANCUN, &address(0x0b)));
+        assert!(is_precompile(Revision::EVMC_PRAGUE, &address(0x0b)));
+        assert!(is_precompile(Revision::EVMC_PRAGUE, &address(0x11)));
+        assert!(!is_precompile(Revision::EVMC_OSAKA, &address(0x12)));
+
+        let p256_verify = Address::from(u256::from(0x100u64));
+        assert!(!is_precompile(Revision::EVMC_PRAGUE, &p256_verify));
+        assert!(is_precompile(Revision::EVMC_OSAKA, &p256_verify));
+
+        assert_eq!(precompile_addresses(Revision::EVMC_FRONTIER).count(), 4);
+        assert_eq!(precompile_addresses(Revision::EVMC_CANCUN).count(), 10);
+        assert_eq!(precompile_addresses(Revision::EVMC_OSAKA).count(), 18);
+
+        let mut high = address(1);
+        high.bytes[0] = 1;
+        assert!(!is_precompile(Revision::EVMC_CANCUN, &high));
+        assert_eq!(Precompile::from_address(&high), None);
+    }
+
+    #[test]
+    fn only_implemented_precompiles_are_routed() {
+        assert!(execute_precompile(Revision::EVMC_CANCUN, &address(5), &[], 100_000).is_none());
+        assert!(execute_precompile(Revision::EVMC_CANCUN, &address(0x0a), &[], 100_000).is_none());
+        assert!(execute_precompile(Revision::EVMC_CANCUN, &address(4), &[], 100_000).is_some());
+        assert!(execute_precompile(Revision::EVMC_CANCUN, &address(0x20), &[], 100_000).is_none());
+
+        let (output, gas_used, status) = run_precompile(5, &[], 100_000, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_REJECTED);
+        assert!(output.is_empty());
+        assert_eq!(gas_used, 0);
+    }
+
+    #[test]
+    fn identity_returns_input_and_charges_per_word() {
+        let input = [1, 2, 3];
+        let (output, gas_used, status) = run_precompile(4, &input, 100, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert_eq!(*output, input);
+        assert_eq!(gas_used, 15 + 3);
+
+        let (output, gas_used, status) = run_precompile(4, &[7; 33], 100, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert_eq!(*output, [7; 33]);
+        assert_eq!(gas_used, 15 + 2 * 3);
+
+        let (output, gas_used, status) = run_precompile(4, &[0; 33], 20, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_OUT_OF_GAS);
+        assert!(output.is_empty());
+        assert_eq!(gas_used, 20);
+    }
+
+    #[test]
+    fn hash_precompiles_match_known_digests() {
+        let (output, gas_used, status) = run_precompile(2, &[], 100, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert_eq!(
+            u256::from_be_bytes((*output).try_into().unwrap()),
+            u256::from_limbs([
+                0xa495_991b_7852_b855,
+                0x27ae_41e4_649b_934c,
+                0x9afb_f4c8_996f_b924,
+                0xe3b0_c442_98fc_1c14,
+            ])
+        );
+        assert_eq!(gas_used, 60);
+
+        let (output, gas_used, status) = run_precompile(3, &[], 1000, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        let mut expected = [0; 32];
+        expected[12..].copy_from_slice(&[
+            0x9c, 0x11, 0x85, 0xa5, 0xc5, 0xe9, 0xfc, 0x54, 0x61, 0x28, 0x08, 0x97, 0x7e, 0xe8,
+            0xf5, 0x48, 0xb2, 0x25, 0x8d, 0x31,
+        ]);
+        assert_eq!(*output, expected);
+        assert_eq!(gas_used, 600);
+    }
+
+    #[test]
+    fn ec_recover_returns_signer() {
+        // the well-known address of the private key 1
+        let signer = [
+            0x7e, 0x5f, 0x45, 0x52, 0x09, 0x1a, 0x69, 0x12, 0x5d, 0x5d, 0xfc, 0xb7, 0xb8, 0xc2,
+            0x65, 0x90, 0x29, 0x39, 0x5b, 0xdf,
+        ];
+        let mut private_key = [0; 32];
+        private_key[31] = 1;
+        let signing_key = SigningKey::from_slice(&private_key).unwrap();
+        let hash = [0xab; 32];
+        let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&hash).unwrap();
+
+        let mut input = hash.to_vec();
+        input.extend_from_slice(&[0; 31]);
+        input.push(27 + recovery_id.to_byte());
+        input.extend_from_slice(&signature.to_bytes());
+        let (output, gas_used, status) = run_precompile(1, &input, 3000, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert_eq!(output[..12], [0; 12]);
+        assert_eq!(output[12..], signer);
+        assert_eq!(gas_used, 3000);
+
+        // invalid v
+        input[63] = 29;
+        let (output, _, status) = run_precompile(1, &input, 3000, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert!(output.is_empty());
+
+        // r = s = 0
+        let (output, _, status) = run_precompile(1, &[], 3000, Revision::EVMC_CANCUN);
+        assert_eq!(status, StatusCode::EVMC_SUCCESS);
+        assert!(output.is_empty());
+    }
+}
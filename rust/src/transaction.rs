@@ -0,0 +1,713 @@
+use std::cmp::{max, min};
+
+use evmc_vm::{
+    AccessStatus, Address, ExecutionMessage, ExecutionResult, ExecutionTxContext, MessageKind,
+    Revision, StatusCode, StorageStatus, Uint256,
+};
+
+use crate::{
+    interpreter::{Interpreter, load_delegated_code},
+    precompiles::precompile_addresses,
+    types::{CodeAnalysisCache, ExecutionContextTrait, NoOpObserver, hash_cache::HashCache},
+    utils::{
+        constants::{GAS_CREATE, GAS_INITCODE_WORD},
+        word_size,
+    },
+};
+
+const GAS_TRANSACTION: u64 = 21_000;
+const GAS_TX_DATA_ZERO: u64 = 4;
+const GAS_TX_DATA_NON_ZERO_FRONTIER: u64 = 68;
+const GAS_TX_DATA_NON_ZERO_ISTANBUL: u64 = 16;
+const GAS_ACCESS_LIST_ADDRESS: u64 = 2_400;
+const GAS_ACCESS_LIST_STORAGE_KEY: u64 = 1_900;
+const GAS_TX_DATA_TOKEN_FLOOR: u64 = 10;
+const MAX_CODE_SIZE: usize = 24576;
+
+/// A transaction which is executed with [`execute_transaction`].
+#[derive(Debug, Clone)]
+pub struct Transaction<'a> {
+    pub sender: Address,
+    /// The called account or, for contract creations, the address of the created contract.
+    pub recipient: Address,
+    /// Whether the transaction is a contract creation. In this case `input` is the init code.
+    pub is_create: bool,
+    pub input: &'a [u8],
+    pub value: Uint256,
+    pub gas_limit: u64,
+    /// The EIP-2930 access list, consisting of addresses and their storage keys.
+    pub access_list: &'a [(Address, &'a [Uint256])],
+}
+
+/// The result of [`execute_transaction`].
+#[derive(Debug)]
+pub struct TransactionResult {
+    /// The result of executing the message of the transaction.
+    pub result: ExecutionResult,
+    /// The intrinsic gas which was deducted before executing the message.
+    pub intrinsic_gas: u64,
+    /// The refund which is applied, capped at a fraction of the used gas.
+    pub gas_refund: u64,
+    /// The gas used by the whole transaction, after applying the refund and, from Prague on, the
+    /// calldata floor.
+    pub gas_used: u64,
+}
+
+/// The reason why [`execute_transaction`] rejected a transaction. Rejected transactions are invalid
+/// and must not be included in a block, so they neither use gas nor change any state.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The gas limit does not cover the intrinsic gas or, from Prague on, the calldata floor.
+    GasLimitTooLow { required: u64, gas_limit: u64 },
+    /// The gas limit exceeds `i64::MAX`, which is the most gas a message can carry.
+    GasLimitTooHigh { gas_limit: u64 },
+}
+
+/// Returns the intrinsic gas of a transaction, which is charged before the message is executed.
+/// This includes the base cost, the cost of the calldata (EIP-2028), the cost of the access list
+/// (EIP-2930) and for contract creations the creation cost and the init code cost (EIP-3860).
+pub fn intrinsic_gas(revision: Revision, tx: &Transaction) -> u64 {
+    let mut gas = GAS_TRANSACTION;
+    if tx.is_create && revision >= Revision::EVMC_HOMESTEAD {
+        gas += GAS_CREATE;
+    }
+
+    let non_zero_cost = if revision >= Revision::EVMC_ISTANBUL {
+        GAS_TX_DATA_NON_ZERO_ISTANBUL
+    } else {
+        GAS_TX_DATA_NON_ZERO_FRONTIER
+    };
+    let non_zero_bytes = tx.input.iter().filter(|byte| **byte != 0).count() as u64;
+    let zero_bytes = tx.input.len() as u64 - non_zero_bytes;
+    gas += zero_bytes * GAS_TX_DATA_ZERO + non_zero_bytes * non_zero_cost;
+
+    if revision >= Revision::EVMC_BERLIN {
+        for (_, keys) in tx.access_list {
+            gas += GAS_ACCESS_LIST_ADDRESS + keys.len() as u64 * GAS_ACCESS_LIST_STORAGE_KEY;
+        }
+    }
+
+    if tx.is_create && revision >= Revision::EVMC_SHANGHAI {
+        // the input length is bounded by the size of the address space, so this can not fail
+        gas += GAS_INITCODE_WORD * word_size(tx.input.len() as u64).unwrap_or_default();
+    }
+
+    gas
+}
+
+/// Returns the minimum gas a transaction uses from Prague on, which depends on its calldata
+/// (EIP-7623). Each zero byte counts as one token and each non-zero byte as four tokens.
+pub fn calldata_floor_gas(tx: &Transaction) -> u64 {
+    let non_zero_bytes = tx.input.iter().filter(|byte| **byte != 0).count() as u64;
+    let zero_bytes = tx.input.len() as u64 - non_zero_bytes;
+    let tokens = zero_bytes + 4 * non_zero_bytes;
+    GAS_TRANSACTION + tokens * GAS_TX_DATA_TOKEN_FLOOR
+}
+
+/// Execute a transaction: deduct the intrinsic gas, warm up the sender, the recipient, the
+/// precompiles, the coinbase and the access list and run the message of the transaction against
+/// `context`. Transactions whose gas limit does not cover the intrinsic gas or does not fit into
+/// the gas of a message are rejected.
+///
+/// Nonces, value transfers and the payment of fees are up to the host.
+pub fn execute_transaction(
+    revision: Revision,
+    tx: &Transaction,
+    context: &mut dyn ExecutionContextTrait,
+) -> Result<TransactionResult, TransactionError> {
+    let intrinsic_gas = intrinsic_gas(revision, tx);
+    let floor_gas = if revision >= Revision::EVMC_PRAGUE {
+        calldata_floor_gas(tx)
+    } else {
+        0
+    };
+    let required = max(intrinsic_gas, floor_gas);
+    if required > tx.gas_limit {
+        return Err(TransactionError::GasLimitTooLow {
+            required,
+            gas_limit: tx.gas_limit,
+        });
+    }
+    let Ok(gas) = i64::try_from(tx.gas_limit - intrinsic_gas) else {
+        return Err(TransactionError::GasLimitTooHigh {
+            gas_limit: tx.gas_limit,
+        });
+    };
+
+    if revision >= Revision::EVMC_BERLIN {
+        context.access_account(&tx.sender);
+        context.access_account(&tx.recipient);
+        for addr in precompile_addresses(revision) {
+            context.access_account(&addr);
+        }
+        for (addr, keys) in tx.access_list {
+            context.access_account(addr);
+            for key in *keys {
+                context.access_storage(addr, key);
+            }
+        }
+    }
+    // EIP-3651
+    if revision >= Revision::EVMC_SHANGHAI {
+        let coinbase = context.get_tx_context().block_coinbase;
+        context.access_account(&coinbase);
+    }
+
+    let code = if tx.is_create {
+        Box::from(tx.input)
+    } else {
+        let mut code = vec![0; context.get_code_size(&tx.recipient)];
+        let len = context.copy_code(&tx.recipient, 0, &mut code);
+        code.truncate(len);
+        code.into_boxed_slice()
+    };
+    let message = ExecutionMessage {
+        kind: if tx.is_create {
+            MessageKind::EVMC_CREATE
+        } else {
+            MessageKind::EVMC_CALL
+        },
+        flags: 0,
+        depth: 0,
+        gas,
+        recipient: tx.recipient,
+        sender: tx.sender,
+        input: if tx.is_create { &[] } else { tx.input },
+        value: tx.value,
+        create2_salt: Uint256 { bytes: [0; 32] },
+        code_address: tx.recipient,
+        code: &[],
+        code_hash: None,
+    };
+
+    let code_analysis_cache = CodeAnalysisCache::default();
+    let hash_cache = HashCache::default();
+    let delegation = load_delegated_code(revision, &code, context);
+    let result: ExecutionResult = if let Some((_, code)) = &delegation {
+        match Interpreter::new_delegated(
+            revision,
+            &message,
+            context,
+            code,
+            &code_analysis_cache,
+            &hash_cache,
+        ) {
+            Ok(interpreter) => interpreter.run(&mut NoOpObserver()),
+            Err(err) => err.into(),
+        }
+    } else {
+        match Interpreter::new(
+            revision,
+            &message,
+            context,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        ) {
+            Ok(interpreter) => interpreter.run(&mut NoOpObserver()),
+            Err(err) => err.into(),
+        }
+    };
+
+    let gas_used = tx.gas_limit - result.gas_left.max(0) as u64;
+    // EIP-3529 reduced the maximum refund from half to a fifth of the used gas.
+    let max_refund_quotient = if revision >= Revision::EVMC_LONDON {
+        5
+    } else {
+        2
+    };
+    let gas_refund = min(
+        result.gas_refund.max(0) as u64,
+        gas_used / max_refund_quotient,
+    );
+    Ok(TransactionResult {
+        result,
+        intrinsic_gas,
+        gas_refund,
+        gas_used: max(gas_used - gas_refund, floor_gas),
+    })
+}
+
+/// A log emitted by a constructor run with [`run_constructor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmittedLog {
+    pub address: Address,
+    pub data: Vec<u8>,
+    pub topics: Vec<Uint256>,
+}
+
+/// The error of [`run_constructor`].
+#[derive(Debug, PartialEq)]
+pub enum ConstructorError {
+    /// The constructor did not succeed. The output contains the revert data, if any.
+    Failed {
+        status_code: StatusCode,
+        output: Box<[u8]>,
+    },
+    /// The returned runtime code is larger than allowed by EIP-170.
+    CodeSizeExceeded(usize),
+    /// The returned runtime code starts with 0xef, which is rejected by EIP-3541.
+    InvalidCodePrefix,
+    /// The creation transaction was rejected before the constructor was run.
+    Rejected(TransactionError),
+}
+
+/// Runs `init_code` with `constructor_args` appended to it, like a contract creation transaction
+/// from `sender` which creates the contract at `address`, and returns the runtime code and the
+/// logs emitted by the constructor. Logs of nested calls are emitted by the host and not included.
+///
+/// The gas limit is not restricted and the runtime code is not stored, that is up to the caller.
+pub fn run_constructor(
+    revision: Revision,
+    sender: Address,
+    address: Address,
+    init_code: &[u8],
+    constructor_args: &[u8],
+    context: &mut dyn ExecutionContextTrait,
+) -> Result<(Box<[u8]>, Vec<EmittedLog>), ConstructorError> {
+    let input = [init_code, constructor_args].concat();
+    let tx = Transaction {
+        sender,
+        recipient: address,
+        is_create: true,
+        input: &input,
+        value: Uint256 { bytes: [0; 32] },
+        gas_limit: i64::MAX as u64,
+        access_list: &[],
+    };
+    let mut log_collector = LogCollector {
+        inner: context,
+        logs: Vec::new(),
+    };
+    let result = execute_transaction(revision, &tx, &mut log_collector)
+        .map_err(ConstructorError::Rejected)?
+        .result;
+    if result.status_code != StatusCode::EVMC_SUCCESS {
+        return Err(ConstructorError::Failed {
+            status_code: result.status_code,
+            output: result.output,
+        });
+    }
+    let code = result.output;
+    if revision >= Revision::EVMC_SPURIOUS_DRAGON && code.len() > MAX_CODE_SIZE {
+        return Err(ConstructorError::CodeSizeExceeded(code.len()));
+    }
+    if revision >= Revision::EVMC_LONDON && code.first() == Some(&0xef) {
+        return Err(ConstructorError::InvalidCodePrefix);
+    }
+
+    Ok((code, log_collector.logs))
+}
+
+/// Forwards all callbacks to the inner host and keeps a copy of the emitted logs.
+struct LogCollector<'a> {
+    inner: &'a mut dyn ExecutionContextTrait,
+    logs: Vec<EmittedLog>,
+}
+
+impl ExecutionContextTrait for LogCollector<'_> {
+    fn get_tx_context(&mut self) -> &ExecutionTxContext<'_> {
+        self.inner.get_tx_context()
+    }
+
+    fn account_exists(&self, address: &Address) -> bool {
+        self.inner.account_exists(address)
+    }
+
+    fn get_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        self.inner.get_storage(address, key)
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) -> StorageStatus {
+        self.inner.set_storage(address, key, value)
+    }
+
+    fn get_balance(&self, address: &Address) -> Uint256 {
+        self.inner.get_balance(address)
+    }
+
+    fn get_code_size(&self, address: &Address) -> usize {
+        self.inner.get_code_size(address)
+    }
+
+    fn get_code_hash(&self, address: &Address) -> Uint256 {
+        self.inner.get_code_hash(address)
+    }
+
+    fn copy_code(&self, address: &Address, code_offset: usize, buffer: &mut [u8]) -> usize {
+        self.inner.copy_code(address, code_offset, buffer)
+    }
+
+    fn selfdestruct(&mut self, address: &Address, beneficiary: &Address) -> bool {
+        self.inner.selfdestruct(address, beneficiary)
+    }
+
+    fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        self.inner.call(message)
+    }
+
+    fn get_block_hash(&self, num: i64) -> Uint256 {
+        self.inner.get_block_hash(num)
+    }
+
+    fn emit_log(&mut self, address: &Address, data: &[u8], topics: &[Uint256]) {
+        self.logs.push(EmittedLog {
+            address: *address,
+            data: data.to_vec(),
+            topics: topics.to_vec(),
+        });
+        self.inner.emit_log(address, data, topics);
+    }
+
+    fn access_account(&mut self, address: &Address) -> AccessStatus {
+        self.inner.access_account(address)
+    }
+
+    fn access_storage(&mut self, address: &Address, key: &Uint256) -> AccessStatus {
+        self.inner.access_storage(address, key)
+    }
+
+    fn get_transient_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        self.inner.get_transient_storage(address, key)
+    }
+
+    fn set_transient_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) {
+        self.inner.set_transient_storage(address, key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::{Address, Revision, StatusCode, Uint256};
+
+    use crate::{
+        transaction::{
+            ConstructorError, EmittedLog, Transaction, TransactionError, calldata_floor_gas,
+            execute_transaction, intrinsic_gas, run_constructor,
+        },
+        types::{Account, Opcode, ReferenceHost, u256},
+    };
+
+    fn transfer() -> Transaction<'static> {
+        Transaction {
+            sender: Address::from(u256::from(0xaau8)),
+            recipient: Address::from(u256::from(0xbbu8)),
+            is_create: false,
+            input: &[],
+            value: u256::ONE.into(),
+            gas_limit: 100_000,
+            access_list: &[],
+        }
+    }
+
+    #[test]
+    fn intrinsic_gas_of_simple_transfer() {
+        assert_eq!(intrinsic_gas(Revision::EVMC_CANCUN, &transfer()), 21_000);
+    }
+
+    #[test]
+    fn intrinsic_gas_of_data_heavy_call() {
+        let mut input = [0xff; 100];
+        input[..40].fill(0);
+        let tx = Transaction {
+            input: &input,
+            ..transfer()
+        };
+        assert_eq!(
+            intrinsic_gas(Revision::EVMC_CANCUN, &tx),
+            21_000 + 40 * 4 + 60 * 16
+        );
+        // before EIP-2028 non-zero bytes were more expensive
+        assert_eq!(
+            intrinsic_gas(Revision::EVMC_PETERSBURG, &tx),
+            21_000 + 40 * 4 + 60 * 68
+        );
+    }
+
+    #[test]
+    fn intrinsic_gas_of_access_list() {
+        let keys = [Uint256::from(u256::ONE), Uint256::from(u256::MAX)];
+        let access_list = [
+            (Address::from(u256::ONE), keys.as_slice()),
+            (Address::from(u256::MAX), [].as_slice()),
+        ];
+        let tx = Transaction {
+            access_list: &access_list,
+            ..transfer()
+        };
+        assert_eq!(
+            intrinsic_gas(Revision::EVMC_CANCUN, &tx),
+            21_000 + 2 * 2_400 + 2 * 1_900
+        );
+        assert_eq!(intrinsic_gas(Revision::EVMC_ISTANBUL, &tx), 21_000);
+    }
+
+    #[test]
+    fn intrinsic_gas_of_create() {
+        let init_code = [0xff; 33];
+        let tx = Transaction {
+            is_create: true,
+            input: &init_code,
+            ..transfer()
+        };
+        // base + create + calldata + 2 words of init code
+        assert_eq!(
+            intrinsic_gas(Revision::EVMC_SHANGHAI, &tx),
+            21_000 + 32_000 + 33 * 16 + 2 * 2
+        );
+        assert_eq!(
+            intrinsic_gas(Revision::EVMC_PARIS, &tx),
+            21_000 + 32_000 + 33 * 16
+        );
+    }
+
+    #[test]
+    fn execute_transaction_deducts_intrinsic_gas() {
+        let tx = transfer();
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        // PUSH1 1 PUSH0 SSTORE
+        host.set_account(
+            tx.recipient,
+            Account {
+                code: vec![
+                    Opcode::Push1 as u8,
+                    1,
+                    Opcode::Push0 as u8,
+                    Opcode::SStore as u8,
+                ],
+                ..Default::default()
+            },
+        );
+
+        let result = execute_transaction(Revision::EVMC_CANCUN, &tx, &mut host).unwrap();
+        assert_eq!(result.result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.intrinsic_gas, 21_000);
+        assert_eq!(result.gas_refund, 0);
+        // PUSH1 + PUSH0 + SSTORE of a cold slot from zero to non-zero
+        assert_eq!(result.gas_used, 21_000 + 3 + 2 + 2_100 + 20_000);
+    }
+
+    #[test]
+    fn execute_transaction_rejects_gas_limit_below_intrinsic_gas() {
+        let tx = Transaction {
+            gas_limit: 20_999,
+            ..transfer()
+        };
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+
+        let result = execute_transaction(Revision::EVMC_CANCUN, &tx, &mut host);
+        assert_eq!(
+            result.unwrap_err(),
+            TransactionError::GasLimitTooLow {
+                required: 21_000,
+                gas_limit: 20_999
+            }
+        );
+    }
+
+    #[test]
+    fn execute_transaction_rejects_gas_limit_above_message_gas() {
+        let tx = Transaction {
+            gas_limit: u64::MAX,
+            ..transfer()
+        };
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+
+        let result = execute_transaction(Revision::EVMC_CANCUN, &tx, &mut host);
+        assert_eq!(
+            result.unwrap_err(),
+            TransactionError::GasLimitTooHigh {
+                gas_limit: u64::MAX
+            }
+        );
+
+        let tx = Transaction {
+            gas_limit: i64::MAX as u64 + 21_000,
+            ..transfer()
+        };
+        let result = execute_transaction(Revision::EVMC_CANCUN, &tx, &mut host).unwrap();
+        assert_eq!(result.result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(result.result.gas_left, i64::MAX);
+    }
+
+    #[test]
+    fn execute_transaction_warms_precompiles_and_coinbase() {
+        // BALANCE of the precompile 0x01 and of the coinbase, which is the zero address
+        let code = vec![
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Balance as u8,
+            Opcode::Push1 as u8,
+            0,
+            Opcode::Balance as u8,
+        ];
+        for (revision, balance_cost) in [
+            // neither is warm before Berlin, but accounts were cheaper to access
+            (Revision::EVMC_ISTANBUL, [700, 700]),
+            (Revision::EVMC_BERLIN, [100, 2_600]),
+            (Revision::EVMC_SHANGHAI, [100, 100]),
+        ] {
+            let tx = transfer();
+            let mut host = ReferenceHost::new(revision);
+            host.set_account(
+                tx.recipient,
+                Account {
+                    code: code.clone(),
+                    ..Default::default()
+                },
+            );
+            let result = execute_transaction(revision, &tx, &mut host).unwrap();
+            assert_eq!(result.result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(
+                result.gas_used,
+                21_000 + 3 + balance_cost[0] + 3 + balance_cost[1],
+                "{revision:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn calldata_floor_dominates_for_data_heavy_transaction() {
+        let input = [0xff; 1_000];
+        let tx = Transaction {
+            input: &input,
+            ..transfer()
+        };
+        assert_eq!(calldata_floor_gas(&tx), 21_000 + 1_000 * 4 * 10);
+
+        // the recipient has no code, so only the intrinsic gas is used before Prague
+        for (revision, gas_used) in [
+            (Revision::EVMC_CANCUN, 21_000 + 1_000 * 16),
+            (Revision::EVMC_PRAGUE, 21_000 + 1_000 * 4 * 10),
+        ] {
+            let mut host = ReferenceHost::new(revision);
+            let result = execute_transaction(revision, &tx, &mut host).unwrap();
+            assert_eq!(result.result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.gas_used, gas_used);
+        }
+    }
+
+    #[test]
+    fn calldata_floor_does_not_apply_to_computation_heavy_transaction() {
+        let tx = Transaction {
+            input: &[0xff],
+            ..transfer()
+        };
+        // 1000 times PUSH0 POP
+        let mut code = Vec::new();
+        for _ in 0..1_000 {
+            code.extend([Opcode::Push0 as u8, Opcode::Pop as u8]);
+        }
+        let gas_used = 21_000 + 16 + 1_000 * (2 + 2);
+        assert!(gas_used > calldata_floor_gas(&tx));
+
+        for revision in [Revision::EVMC_CANCUN, Revision::EVMC_PRAGUE] {
+            let mut host = ReferenceHost::new(revision);
+            host.set_account(
+                tx.recipient,
+                Account {
+                    code: code.clone(),
+                    ..Default::default()
+                },
+            );
+            let result = execute_transaction(revision, &tx, &mut host).unwrap();
+            assert_eq!(result.result.status_code, StatusCode::EVMC_SUCCESS);
+            assert_eq!(result.gas_used, gas_used);
+        }
+    }
+
+    #[test]
+    fn execute_transaction_rejects_gas_limit_below_calldata_floor() {
+        let input = [0xff; 1_000];
+        let tx = Transaction {
+            input: &input,
+            gas_limit: 50_000,
+            ..transfer()
+        };
+        let mut host = ReferenceHost::new(Revision::EVMC_PRAGUE);
+
+        let result = execute_transaction(Revision::EVMC_PRAGUE, &tx, &mut host);
+        assert_eq!(
+            result.unwrap_err(),
+            TransactionError::GasLimitTooLow {
+                required: 21_000 + 1_000 * 4 * 10,
+                gas_limit: 50_000
+            }
+        );
+    }
+
+    #[test]
+    fn run_constructor_returns_runtime_code_and_logs() {
+        // returns 42
+        let runtime_code = [
+            Opcode::Push1 as u8,
+            42,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+        // stores the runtime code in memory, logs it and returns it
+        let mut init_code = vec![Opcode::Push8 as u8];
+        init_code.extend(runtime_code);
+        init_code.extend([
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            8, // len
+            Opcode::Push1 as u8,
+            24, // offset
+            Opcode::Log0 as u8,
+            Opcode::Push1 as u8,
+            8, // len
+            Opcode::Push1 as u8,
+            24, // offset
+            Opcode::Return as u8,
+        ]);
+
+        let sender = Address::from(u256::from(0xaau8));
+        let address = Address::from(u256::from(0xccu8));
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let (code, logs) = run_constructor(
+            Revision::EVMC_CANCUN,
+            sender,
+            address,
+            &init_code,
+            &[1, 2, 3],
+            &mut host,
+        )
+        .unwrap();
+        assert_eq!(code.as_ref(), runtime_code);
+        assert_eq!(
+            logs,
+            [EmittedLog {
+                address,
+                data: runtime_code.to_vec(),
+                topics: Vec::new(),
+            }]
+        );
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let revert = [
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Revert as u8,
+        ];
+        assert_eq!(
+            run_constructor(
+                Revision::EVMC_CANCUN,
+                sender,
+                address,
+                &revert,
+                &[],
+                &mut host
+            ),
+            Err(ConstructorError::Failed {
+                status_code: StatusCode::EVMC_REVERT,
+                output: Box::default(),
+            })
+        );
+    }
+}
@@ -123,11 +123,19 @@ impl TryFrom<u256> for u64 {
     }
 }
 
+/// The error returned by [`u256::from_dec_str`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    InvalidDigit,
+    Overflow,
+}
+
 impl Add for u256 {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0.wrapping_add(rhs.0))
+        self.overflowing_add(rhs).0
     }
 }
 
@@ -141,6 +149,12 @@ impl Sub for u256 {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
+        #[cfg(feature = "small-u256")]
+        if let (Some(lhs), Some(rhs)) = (self.as_small(), rhs.as_small())
+            && lhs >= rhs
+        {
+            return Self(U256::from(lhs - rhs));
+        }
         Self(self.0.wrapping_sub(rhs.0))
     }
 }
@@ -155,7 +169,7 @@ impl Mul for u256 {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        Self(self.0.wrapping_mul(rhs.0))
+        self.overflowing_mul(rhs).0
     }
 }
 
@@ -251,6 +265,7 @@ impl Not for u256 {
     }
 }
 
+/// Shifts of 256 or more bits yield zero (EIP-145).
 impl Shl for u256 {
     type Output = Self;
 
@@ -273,6 +288,7 @@ impl Shl<usize> for u256 {
     }
 }
 
+/// Shifts of 256 or more bits yield zero (EIP-145).
 impl Shr for u256 {
     type Output = Self;
 
@@ -292,12 +308,24 @@ impl u256 {
     pub const ONE: Self = Self(U256::ONE);
     pub const MAX: Self = Self(U256::MAX);
 
+    /// Returns the value as u64 if it fits, so that arithmetic can skip the full 256 bit
+    /// operations.
+    #[cfg(feature = "small-u256")]
+    #[inline(always)]
+    fn as_small(self) -> Option<u64> {
+        match self.into_u64_with_overflow() {
+            (value, false) => Some(value),
+            (_, true) => None,
+        }
+    }
+
     pub fn into_u64_with_overflow(self) -> (u64, bool) {
         let digits: [u64; 4] = transmute!(self.0.0);
         let overflow = digits[1..] != [0; 3];
         (digits[0], overflow)
     }
 
+    /// Returns the value as u64, or `u64::MAX` if it does not fit.
     pub fn into_u64_saturating(self) -> u64 {
         let digits: [u64; 4] = transmute!(self.0.0);
         if digits[1..] != [0; 3] {
@@ -307,6 +335,17 @@ impl u256 {
         }
     }
 
+    /// Returns the value as usize, or `usize::MAX` if it does not fit.
+    pub fn into_usize_saturating(self) -> usize {
+        usize::try_from(self.into_u64_saturating()).unwrap_or(usize::MAX)
+    }
+
+    /// Returns the value as i64, or `i64::MAX` if it does not fit. The value is interpreted as
+    /// unsigned, so values with the sign bit set saturate as well.
+    pub fn into_i64_saturating(self) -> i64 {
+        i64::try_from(self.into_u64_saturating()).unwrap_or(i64::MAX)
+    }
+
     pub fn sdiv(self, rhs: Self) -> Self {
         if rhs == u256::ZERO {
             return u256::ZERO;
@@ -376,20 +415,21 @@ impl u256 {
         Self(acc)
     }
 
-    pub fn signextend(self, rhs: Self) -> Self {
-        let (lhs, lhs_overflow) = self.into_u64_with_overflow();
-        let lhs = lhs as usize;
-        if lhs_overflow || lhs > 31 {
-            return rhs;
+    /// Extends the sign bit of the byte at `byte_index`, counted from the least significant byte,
+    /// to all more significant bytes of `value`. If `byte_index >= 31`, `value` is returned as is.
+    pub fn sign_extend(byte_index: Self, value: Self) -> Self {
+        let (byte_index, overflow) = byte_index.into_u64_with_overflow();
+        let byte_index = byte_index as usize;
+        if overflow || byte_index >= 31 {
+            return value;
         }
 
-        let byte = 31 - lhs; // lhs <= 31 so this does not underflow
-        let negative = (rhs.to_le_bytes()[lhs] & 0x80) > 0;
-
+        let negative = (value.to_le_bytes()[byte_index] & 0x80) > 0;
+        let bits = (byte_index + 1) * 8; // byte_index < 31 so this is at most 248
         let res = if negative {
-            rhs.0 | (U256::MAX << ((32 - byte) * 8))
+            value.0 | (U256::MAX << bits)
         } else {
-            rhs.0 & (U256::MAX >> (byte * 8))
+            value.0 & (U256::MAX >> (256 - bits))
         };
 
         Self(res)
@@ -415,7 +455,10 @@ impl u256 {
         self.to_le_bytes()[31 - idx as usize].into()
     }
 
-    pub fn sar(self, rhs: Self) -> Self {
+    /// Shifts the two's complement value right by `rhs` bits, filling in the sign bit. Shifts of
+    /// 256 or more bits yield zero for non-negative values and [`u256::MAX`] (-1) for negative
+    /// values (EIP-145).
+    pub fn arithmetic_shr(self, rhs: Self) -> Self {
         let lhs = self.0.as_i256();
         let rhs = rhs.to_le_bytes();
         // rhs > 255
@@ -434,32 +477,136 @@ impl u256 {
         Self(shr)
     }
 
+    /// Compares the values in constant time, i.e. without branching on where they differ. Use this
+    /// instead of `==` when comparing secrets, like signatures in precompiles.
+    pub fn ct_eq(&self, other: &Self) -> bool {
+        let diff = self
+            .to_le_bytes()
+            .iter()
+            .zip(other.to_le_bytes())
+            .fold(0, |acc, (lhs, rhs)| acc | (lhs ^ rhs));
+        // Keep the compiler from turning the accumulation back into an early-exit comparison.
+        std::hint::black_box(diff) == 0
+    }
+
     pub fn bits(&self) -> u32 {
         256 - self.0.leading_zeros()
     }
 
+    /// Returns the number of bytes of the minimal big-endian representation, which is 0 for zero.
+    pub fn byte_len(&self) -> u32 {
+        self.bits().div_ceil(8)
+    }
+
+    /// Creates a value from its little-endian representation, i.e. `bytes[0]` is the least
+    /// significant byte.
     pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
         Self(U256::from_le_bytes(bytes))
     }
 
+    /// Creates a value from its big-endian representation, i.e. `bytes[0]` is the most
+    /// significant byte. This is the representation used by the EVM and by [`Uint256`].
     pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
         Self(U256::from_be_bytes(bytes))
     }
 
-    pub fn least_significant_byte(&self) -> u8 {
+    /// Returns the least significant byte, i.e. the value truncated to 8 bits like by MSTORE8.
+    pub fn as_u8(&self) -> u8 {
         self.0.0[0] as u8
     }
 
+    /// Returns the little-endian representation, the inverse of [`u256::from_le_bytes`].
     pub fn to_le_bytes(self) -> [u8; 32] {
         self.0.to_le_bytes()
     }
+
+    /// Returns the big-endian representation, the inverse of [`u256::from_be_bytes`].
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        self.0.to_be_bytes()
+    }
+
+    /// Creates a value from its 64 bit limbs in little-endian order, i.e. `limbs[0]` is the least
+    /// significant limb.
+    pub fn from_limbs(limbs: [u64; 4]) -> Self {
+        Self(U256(transmute!(limbs)))
+    }
+
+    /// Returns the 64 bit limbs in little-endian order, the inverse of [`u256::from_limbs`].
+    pub fn to_limbs(self) -> [u64; 4] {
+        transmute!(self.0.0)
+    }
+
+    /// Adds `rhs` modulo 2^256, like the `+` operator and the ADD opcode, and returns whether the
+    /// addition overflowed.
+    #[inline(always)]
+    pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        #[cfg(feature = "small-u256")]
+        if let (Some(lhs), Some(rhs)) = (self.as_small(), rhs.as_small()) {
+            // the sum of two 64 bit values always fits into 128 bits
+            return (Self(U256::from(lhs as u128 + rhs as u128)), false);
+        }
+        let (sum, overflow) = self.0.overflowing_add(rhs.0);
+        (Self(sum), overflow)
+    }
+
+    /// Multiplies with `rhs` modulo 2^256, like the `*` operator and the MUL opcode, and returns
+    /// whether the multiplication overflowed.
+    #[inline(always)]
+    pub fn overflowing_mul(self, rhs: Self) -> (Self, bool) {
+        #[cfg(feature = "small-u256")]
+        if let (Some(lhs), Some(rhs)) = (self.as_small(), rhs.as_small()) {
+            // the product of two 64 bit values always fits into 128 bits
+            return (Self(U256::from(lhs as u128 * rhs as u128)), false);
+        }
+        let (product, overflow) = self.0.overflowing_mul(rhs.0);
+        (Self(product), overflow)
+    }
+
+    /// Returns `None` if the sum does not fit into 256 bits.
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    /// Returns `None` if `rhs` is greater than `self`.
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    /// Returns `None` if the product does not fit into 256 bits.
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        self.0.checked_mul(rhs.0).map(Self)
+    }
+
+    /// Parses a decimal number consisting only of the digits `0` to `9`. Leading zeros are
+    /// allowed, signs and whitespace are not.
+    pub fn from_dec_str(s: &str) -> Result<Self, ParseError> {
+        if s.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        let ten = Self::from(10u8);
+        s.bytes().try_fold(Self::ZERO, |value, digit| {
+            if !digit.is_ascii_digit() {
+                return Err(ParseError::InvalidDigit);
+            }
+            value
+                .checked_mul(ten)
+                .and_then(|value| value.checked_add(Self::from(digit - b'0')))
+                .ok_or(ParseError::Overflow)
+        })
+    }
+
+    pub fn to_dec_string(&self) -> String {
+        self.to_string()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use ethnum::U256;
     use evmc_vm::Address;
+    use proptest::prelude::*;
 
-    use crate::types::amount::{U64Overflow, u256};
+    use crate::types::amount::{ParseError, U64Overflow, u256};
 
     #[test]
     fn display() {
@@ -496,6 +643,159 @@ mod tests {
         }
     }
 
+    #[test]
+    fn byte_len() {
+        assert_eq!(u256::ZERO.byte_len(), 0);
+        assert_eq!(u256::ONE.byte_len(), 1);
+        assert_eq!(u256::from(0xffu8).byte_len(), 1);
+        assert_eq!(u256::from(0x0100u64).byte_len(), 2);
+        assert_eq!(u256::MAX.byte_len(), 32);
+    }
+
+    #[test]
+    fn sign_extend() {
+        let zero = u256::ZERO;
+        let ff = u256::from(0xffu8);
+        let x7f = u256::from(0x7fu8);
+        assert_eq!(u256::sign_extend(zero, ff), u256::MAX);
+        assert_eq!(u256::sign_extend(zero, x7f), x7f);
+        // only the lowest byte is kept if it is positive
+        assert_eq!(u256::sign_extend(zero, u256::from(0x127fu64)), x7f);
+        assert_eq!(
+            u256::sign_extend(u256::ONE, u256::from(0x80ffu64)),
+            u256::MAX - u256::from(0x7f00u64)
+        );
+        assert_eq!(u256::sign_extend(u256::ONE, ff), ff);
+        let negative = u256::MAX - u256::ONE;
+        let with_high_bits = u256::from(0x80u8) << u256::from(8 * 30u8);
+        assert_eq!(
+            u256::sign_extend(u256::from(30u8), with_high_bits),
+            with_high_bits | (u256::from(0xffu8) << u256::from(8 * 31u8))
+        );
+        for byte_index in [u256::from(31u8), u256::from(32u8), u256::MAX] {
+            assert_eq!(u256::sign_extend(byte_index, ff), ff);
+            assert_eq!(u256::sign_extend(byte_index, negative), negative);
+        }
+    }
+
+    #[test]
+    fn decimal_strings_round_trip() {
+        let values = [
+            u256::ZERO,
+            u256::ONE,
+            u256::MAX,
+            u256::from_limbs([0x9e37_79b9_7f4a_7c15, 0, 0, 0]),
+            u256::from_limbs([
+                0x243f_6a88_85a3_08d3,
+                0x1319_8a2e_0370_7344,
+                0xa409_3822_299f_31d0,
+                0x082e_fa98_ec4e_6c89,
+            ]),
+            u256::from_limbs([
+                0xb7e1_5162_8aed_2a6a,
+                0xbf71_5880_9cf4_f3c7,
+                0x62e7_160f_38b4_da56,
+                0xa784_d904_5190_cfef,
+            ]),
+        ];
+        for value in values {
+            assert_eq!(u256::from_dec_str(&value.to_dec_string()), Ok(value));
+        }
+        assert_eq!(
+            u256::MAX.to_dec_string(),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+    }
+
+    #[test]
+    fn from_dec_str_handles_invalid_input() {
+        assert_eq!(u256::from_dec_str("0000"), Ok(u256::ZERO));
+        assert_eq!(u256::from_dec_str("007"), Ok(u256::from(7u8)));
+        assert_eq!(u256::from_dec_str(""), Err(ParseError::Empty));
+        for invalid in ["12a", "-1", "+1", " 1", "0x10", "1_000"] {
+            assert_eq!(u256::from_dec_str(invalid), Err(ParseError::InvalidDigit));
+        }
+        assert_eq!(
+            u256::from_dec_str(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+            ),
+            Err(ParseError::Overflow)
+        );
+        assert_eq!(
+            u256::from_dec_str(&"9".repeat(100)),
+            Err(ParseError::Overflow)
+        );
+    }
+
+    #[test]
+    fn overflowing_and_checked_arithmetic() {
+        let two = u256::from(2u8);
+        let pow128 = u256::ONE << u256::from(128u8);
+        let pow128_minus_one = pow128 - u256::ONE;
+
+        assert_eq!(u256::MAX.overflowing_add(u256::ONE), (u256::ZERO, true));
+        assert_eq!(u256::MAX.checked_add(u256::ONE), None);
+        assert_eq!(pow128.overflowing_add(pow128), (pow128 * two, false));
+        assert_eq!(pow128.checked_add(pow128), Some(pow128 * two));
+
+        assert_eq!(
+            u256::MAX.overflowing_mul(two),
+            (u256::MAX - u256::ONE, true)
+        );
+        assert_eq!(u256::MAX.checked_mul(two), None);
+        assert_eq!(pow128.overflowing_mul(pow128), (u256::ZERO, true));
+        assert_eq!(pow128.checked_mul(pow128), None);
+        // (2^128 - 1) * (2^128 + 1) = 2^256 - 1
+        assert_eq!(
+            pow128_minus_one.overflowing_mul(pow128 + u256::ONE),
+            (u256::MAX, false)
+        );
+        assert!(pow128_minus_one.checked_mul(pow128_minus_one).is_some());
+
+        assert_eq!(u256::ZERO.checked_sub(u256::ONE), None);
+        assert_eq!(pow128.checked_sub(u256::ONE), Some(pow128_minus_one));
+
+        // the wrapped results match the operators used by the opcodes
+        for (lhs, rhs) in [(u256::MAX, u256::MAX), (pow128, pow128_minus_one)] {
+            assert_eq!(lhs.overflowing_add(rhs).0, lhs + rhs);
+            assert_eq!(lhs.overflowing_mul(rhs).0, lhs * rhs);
+        }
+    }
+
+    #[test]
+    fn limb_conversions_round_trip() {
+        let limbs = [1, 2, 3, u64::MAX];
+        assert_eq!(u256::from_limbs(limbs).to_limbs(), limbs);
+        assert_eq!(u256::from_limbs([1, 0, 0, 0]), u256::from(1u8));
+        assert_eq!(
+            u256::from_limbs([0, 1, 0, 0]),
+            u256::from(u64::MAX) + u256::ONE
+        );
+        assert_eq!(u256::MAX.to_limbs(), [u64::MAX; 4]);
+    }
+
+    #[test]
+    fn byte_conversions_round_trip() {
+        let mut be = [0; 32];
+        for (i, byte) in be.iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+        let mut le = be;
+        le.reverse();
+
+        assert_eq!(u256::from_be_bytes(be).to_be_bytes(), be);
+        assert_eq!(u256::from_le_bytes(le).to_le_bytes(), le);
+        assert_eq!(u256::from_le_bytes(le), u256::from_be_bytes(be));
+        assert_eq!(u256::from_be_bytes(be).to_le_bytes(), le);
+
+        assert_eq!(u256::ONE.to_be_bytes()[31], 1);
+        assert_eq!(u256::ONE.to_le_bytes()[0], 1);
+        for value in [u256::ZERO, u256::ONE, u256::MAX, u256::from(u64::MAX)] {
+            assert_eq!(u256::from_be_bytes(value.to_be_bytes()), value);
+            assert_eq!(u256::from_le_bytes(value.to_le_bytes()), value);
+        }
+    }
+
     #[test]
     fn conversions() {
         assert_eq!(u256::from(false), u256::ZERO);
@@ -519,6 +819,37 @@ mod tests {
         assert_eq!(u256::MAX.into_u64_with_overflow(), (u64::MAX, true));
         assert_eq!(u256::MAX.into_u64_saturating(), u64::MAX);
 
+        for (value, expected) in [
+            (u256::from(usize::MAX - 1), usize::MAX - 1),
+            (u256::from(usize::MAX as u64), usize::MAX),
+            (u256::from(usize::MAX as u64) + u256::ONE, usize::MAX),
+            (u256::MAX, usize::MAX),
+        ] {
+            assert_eq!(value.into_usize_saturating(), expected);
+        }
+        for (value, expected) in [
+            (u256::from(i64::MAX as u64 - 1), i64::MAX - 1),
+            (u256::from(i64::MAX as u64), i64::MAX),
+            (u256::from(i64::MAX as u64 + 1), i64::MAX),
+            (u256::from(u64::MAX) + u256::ONE, i64::MAX),
+            (u256::MAX, i64::MAX),
+        ] {
+            assert_eq!(value.into_i64_saturating(), expected);
+        }
+        assert_eq!(
+            (u256::from(u64::MAX) + u256::ONE).into_u64_saturating(),
+            u64::MAX
+        );
+        for (value, expected) in [
+            (u256::from(0xfeu8), 0xfe),
+            (u256::from(0xffu8), 0xff),
+            (u256::from(0x100u64), 0),
+            (u256::from(0x1abu64), 0xab),
+            (u256::MAX, 0xff),
+        ] {
+            assert_eq!(value.as_u8(), expected);
+        }
+
         assert_eq!(
             Address::from(u256::ONE),
             Address {
@@ -532,4 +863,80 @@ mod tests {
             u256::ONE
         );
     }
+
+    #[test]
+    fn shifts_saturate_at_256_bits() {
+        let negative = u256::ONE << u256::from(255u8);
+        let positive = u256::MAX >> u256::ONE;
+        let max_shift = u256::from(255u8);
+        for shift in [u256::from(256u64), u256::MAX] {
+            assert_eq!(u256::MAX << shift, u256::ZERO);
+            assert_eq!(u256::MAX >> shift, u256::ZERO);
+            assert_eq!(positive.arithmetic_shr(shift), u256::ZERO);
+            assert_eq!(negative.arithmetic_shr(shift), u256::MAX);
+        }
+        assert_eq!(u256::ONE << max_shift, negative);
+        assert_eq!(u256::MAX >> max_shift, u256::ONE);
+        assert_eq!(positive.arithmetic_shr(max_shift), u256::ZERO);
+        assert_eq!(negative.arithmetic_shr(max_shift), u256::MAX);
+        assert_eq!(
+            negative.arithmetic_shr(u256::from(254u8)),
+            u256::MAX - u256::ONE
+        );
+    }
+
+    #[test]
+    fn ct_eq_of_boundary_values() {
+        assert!(u256::ZERO.ct_eq(&u256::ZERO));
+        assert!(u256::MAX.ct_eq(&u256::MAX));
+        assert!(!u256::ZERO.ct_eq(&u256::MAX));
+        assert!(!u256::ZERO.ct_eq(&u256::ONE));
+    }
+
+    #[test]
+    fn arithmetic_matches_full_width_arithmetic() {
+        // With feature "small-u256" values which fit into 64 bits take a fast path. This makes sure
+        // the results are the same as with the full 256 bit operations, including the boundaries.
+        let values = [
+            u256::ZERO,
+            u256::ONE,
+            u256::from(u64::MAX - 1),
+            u256::from(u64::MAX),
+            u256::from(u64::MAX) + u256::ONE,
+            u256::MAX,
+        ];
+        for lhs in values {
+            for rhs in values {
+                assert_eq!(lhs + rhs, u256(lhs.0.wrapping_add(rhs.0)));
+                assert_eq!(lhs - rhs, u256(lhs.0.wrapping_sub(rhs.0)));
+                assert_eq!(lhs * rhs, u256(lhs.0.wrapping_mul(rhs.0)));
+            }
+        }
+        assert_eq!(
+            u256::from(u64::MAX) + u256::from(u64::MAX),
+            u256(U256::from(u64::MAX as u128 * 2))
+        );
+        assert_eq!(u256::ZERO - u256::ONE, u256::MAX);
+    }
+
+    proptest! {
+        #[test]
+        fn ct_eq_agrees_with_eq(
+            lhs in any::<[u64; 4]>(),
+            rhs in any::<[u64; 4]>(),
+            bit in 0..256usize,
+        ) {
+            let lhs = u256::from_limbs(lhs);
+            let rhs = u256::from_limbs(rhs);
+            // random values almost never collide, so also compare with equal values and values
+            // which only differ in a single bit
+            let mut limbs = lhs.to_limbs();
+            limbs[bit / 64] ^= 1 << (bit % 64);
+            let flipped = u256::from_limbs(limbs);
+            for other in [lhs, rhs, flipped] {
+                prop_assert_eq!(lhs.ct_eq(&other), lhs == other);
+                prop_assert_eq!(other.ct_eq(&lhs), other == lhs);
+            }
+        }
+    }
 }
@@ -1,17 +1,57 @@
 use std::{
+    fmt::Display,
     hash::{BuildHasher, Hash},
     num::NonZeroUsize,
-    sync::Mutex,
+    ops::Add,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 use lru::{DefaultHasher, LruCache};
 
-pub struct Cache<K, V, H = DefaultHasher>(
-    // Mutex<LruCache<...>> is faster that quick_cache::Cache<...>
-    Mutex<LruCache<K, V, H>>,
-)
+/// Snapshot of the usage counters of a [`Cache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub capacity: usize,
+    pub len: usize,
+}
+
+impl Add for CacheStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            hits: self.hits + rhs.hits,
+            misses: self.misses + rhs.misses,
+            capacity: self.capacity + rhs.capacity,
+            len: self.len + rhs.len,
+        }
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "hits: {}, misses: {}, len: {}, capacity: {}",
+            self.hits, self.misses, self.len, self.capacity
+        )
+    }
+}
+
+pub struct Cache<K, V, H = DefaultHasher>
 where
-    K: Hash + Eq;
+    K: Hash + Eq,
+{
+    // Mutex<LruCache<...>> is faster that quick_cache::Cache<...>
+    cache: Mutex<LruCache<K, V, H>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
 
 impl<K, V, H> Cache<K, V, H>
 where
@@ -19,18 +59,35 @@ where
     H: BuildHasher + Default,
 {
     pub fn new(size: usize) -> Self {
-        Self(Mutex::new(LruCache::with_hasher(
-            NonZeroUsize::new(size).unwrap(),
-            H::default(),
-        )))
+        Self {
+            cache: Mutex::new(LruCache::with_hasher(
+                NonZeroUsize::new(size).unwrap(),
+                H::default(),
+            )),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
     }
 
+    /// Returns the cached value of `key` or inserts the value returned by `f`. If `f` fails,
+    /// nothing is inserted and the error is returned.
     #[cfg(feature = "code-analysis-cache")]
-    pub fn get_or_insert(&self, key: K, f: impl FnOnce() -> V) -> V
+    pub fn try_get_or_insert<E>(&self, key: K, f: impl FnOnce() -> Result<V, E>) -> Result<V, E>
     where
         V: Clone,
     {
-        self.0.lock().unwrap().get_or_insert(key, f).clone()
+        let mut missed = false;
+        let value = self
+            .cache
+            .lock()
+            .unwrap()
+            .try_get_or_insert(key, || {
+                missed = true;
+                f()
+            })
+            .cloned();
+        self.record_lookup(missed);
+        value
     }
 
     #[cfg(feature = "hash-cache")]
@@ -40,11 +97,71 @@ where
         Q: ToOwned<Owned = K> + Hash + Eq,
         V: Clone,
     {
-        self.0.lock().unwrap().get_or_insert_ref(key, f).clone()
+        let mut missed = false;
+        let value = self
+            .cache
+            .lock()
+            .unwrap()
+            .get_or_insert_ref(key, || {
+                missed = true;
+                f()
+            })
+            .clone();
+        self.record_lookup(missed);
+        value
+    }
+
+    /// Like [`Self::get_or_insert_ref`] for each of `keys`, but only locks the cache once.
+    #[cfg(feature = "hash-cache")]
+    pub fn get_or_insert_ref_many<'q, Q>(
+        &self,
+        keys: impl IntoIterator<Item = &'q Q>,
+        mut f: impl FnMut(&Q) -> V,
+    ) -> Vec<V>
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: ToOwned<Owned = K> + Hash + Eq + 'q,
+        V: Clone,
+    {
+        let mut cache = self.cache.lock().unwrap();
+        keys.into_iter()
+            .map(|key| {
+                let mut missed = false;
+                let value = cache
+                    .get_or_insert_ref(key, || {
+                        missed = true;
+                        f(key)
+                    })
+                    .clone();
+                self.record_lookup(missed);
+                value
+            })
+            .collect()
+    }
+
+    fn record_lookup(&self, missed: bool) {
+        let counter = if missed { &self.misses } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the current hit and miss counters together with the size of the cache.
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            capacity: cache.cap().into(),
+            len: cache.len(),
+        }
     }
 
     #[cfg(test)]
     pub fn capacity(&self) -> usize {
-        self.0.lock().unwrap().cap().into()
+        self.cache.lock().unwrap().cap().into()
+    }
+
+    #[cfg(all(test, any(feature = "hash-cache", feature = "code-analysis-cache")))]
+    pub fn entries(&self) -> usize {
+        self.cache.lock().unwrap().len()
     }
 }
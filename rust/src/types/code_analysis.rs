@@ -1,16 +1,22 @@
-#[cfg(feature = "fn-ptr-conversion-dispatch")]
+#[cfg(any(feature = "fn-ptr-conversion-dispatch", feature = "lazy-analysis"))]
 use std::cmp::min;
 #[cfg(feature = "code-analysis-cache")]
 use std::sync::Arc;
 
+use evmc_vm::{MessageKind, Revision};
 #[cfg(feature = "code-analysis-cache")]
 use nohash_hasher::BuildNoHashHasher;
 
+#[cfg(feature = "eof")]
+use crate::types::eof::{EofContainer, EofError, is_eof};
 #[cfg(feature = "code-analysis-cache")]
-use crate::types::Cache;
+use crate::types::{Cache, CacheStats};
 use crate::types::{CodeByteType, code_byte_type, u256};
 #[cfg(feature = "fn-ptr-conversion-dispatch")]
-use crate::types::{OpFnData, PcMap};
+use crate::{
+    interpreter::OpFn,
+    types::{OpFnData, PcMap},
+};
 
 /// This type represents a hash value in form of a u256.
 /// Because it is already a hash value there is no need to hash it again when implementing Hash.
@@ -64,6 +70,12 @@ impl<const STEPPABLE: bool> CodeAnalysisCache<STEPPABLE> {
         return Self();
     }
 
+    /// Returns the hit and miss counters of the cache.
+    #[cfg(feature = "code-analysis-cache")]
+    pub fn stats(&self) -> CacheStats {
+        self.0.stats()
+    }
+
     #[cfg(test)]
     #[allow(clippy::unused_self)]
     pub fn capacity(&self) -> usize {
@@ -74,7 +86,64 @@ impl<const STEPPABLE: bool> CodeAnalysisCache<STEPPABLE> {
     }
 }
 
+/// The maximum size of deployed code (EIP-170).
+const MAX_CODE_SIZE: usize = 24576;
+/// The maximum size of initcode (EIP-3860).
+const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+
+/// The reasons why code can not be executed at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// Deployed code is larger than the limit of EIP-170, checked from Spurious Dragon on, or
+    /// initcode is larger than the limit of EIP-3860, checked from Shanghai on.
+    CodeTooLarge,
+    /// The code starts with the EOF magic but its header is malformed.
+    #[cfg(feature = "eof")]
+    InvalidEofHeader,
+    /// An instruction in a code section of an EOF container, like a PUSH, is missing some of its
+    /// immediate data.
+    #[cfg(feature = "eof")]
+    UnterminatedPush,
+    /// The code sections of an EOF container contain undefined instructions or invalid jumps, or
+    /// fail the stack height validation.
+    #[cfg(feature = "eof")]
+    InvalidEofCode,
+}
+
+#[cfg(feature = "eof")]
+impl From<EofError> for AnalysisError {
+    fn from(err: EofError) -> Self {
+        match err {
+            EofError::InvalidMagic
+            | EofError::UnsupportedVersion
+            | EofError::TruncatedHeader
+            | EofError::InvalidSectionOrder
+            | EofError::ZeroCodeSections
+            | EofError::TooManyCodeSections
+            | EofError::TooManyContainerSections
+            | EofError::ZeroSectionSize
+            | EofError::InvalidTypeSectionSize
+            | EofError::BodySizeMismatch
+            | EofError::InvalidTypes => Self::InvalidEofHeader,
+            EofError::TruncatedImmediate => Self::UnterminatedPush,
+            EofError::UndefinedInstruction
+            | EofError::InvalidJumpDestination
+            | EofError::InvalidSectionIndex
+            | EofError::UnreachableCode
+            | EofError::NoTerminatingInstruction
+            | EofError::StackUnderflow
+            | EofError::StackOverflow
+            | EofError::StackHeightMismatch
+            | EofError::InvalidMaxStackHeight => Self::InvalidEofCode,
+        }
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(
+    all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")),
+    allow(dead_code)
+)]
 pub struct CodeAnalysis<const STEPPABLE: bool> {
     pub analysis: Vec<AnalysisItem<STEPPABLE>>,
     #[cfg(feature = "fn-ptr-conversion-dispatch")]
@@ -82,23 +151,75 @@ pub struct CodeAnalysis<const STEPPABLE: bool> {
 }
 
 impl<const STEPPABLE: bool> CodeAnalysis<STEPPABLE> {
+    // lazy analysis does not use the eager analysis and its cache
     #[allow(unused_variables)]
+    #[cfg_attr(
+        all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")),
+        allow(dead_code)
+    )]
     pub fn new(
         code: &[u8],
         code_hash: Option<u256>,
+        revision: Revision,
+        kind: MessageKind,
         cache: &CodeAnalysisCache<STEPPABLE>,
-    ) -> AnalysisContainer<Self> {
+    ) -> Result<AnalysisContainer<Self>, AnalysisError> {
+        Self::check_code_size(code, revision, kind)?;
+        // The cache is keyed by the code hash only, so the code is validated on every lookup
+        // because the validation depends on the revision.
+        Self::validate_eof(code, revision)?;
         #[cfg(feature = "code-analysis-cache")]
         match code_hash {
             Some(code_hash) if code_hash != u256::ZERO => {
-                cache.0.get_or_insert(u256Hash(code_hash), || {
-                    AnalysisContainer::new(CodeAnalysis::analyze_code(code))
+                cache.0.try_get_or_insert(u256Hash(code_hash), || {
+                    Ok(AnalysisContainer::new(CodeAnalysis::analyze_code(code)))
                 })
             }
-            _ => AnalysisContainer::new(Self::analyze_code(code)),
+            _ => Ok(AnalysisContainer::new(Self::analyze_code(code))),
         }
         #[cfg(not(feature = "code-analysis-cache"))]
-        Self::analyze_code(code)
+        Ok(Self::analyze_code(code))
+    }
+
+    /// Checks whether `code` can be executed by a message of `kind` in `revision` at all. This is
+    /// used by the lazy analysis, which does not use [`Self::new`] and its cache.
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    pub fn check_code(
+        code: &[u8],
+        revision: Revision,
+        kind: MessageKind,
+    ) -> Result<(), AnalysisError> {
+        Self::check_code_size(code, revision, kind)?;
+        Self::validate_eof(code, revision)
+    }
+
+    fn check_code_size(
+        code: &[u8],
+        revision: Revision,
+        kind: MessageKind,
+    ) -> Result<(), AnalysisError> {
+        let max_size = match kind {
+            MessageKind::EVMC_CREATE | MessageKind::EVMC_CREATE2 | MessageKind::EVMC_EOFCREATE => {
+                (revision >= Revision::EVMC_SHANGHAI).then_some(MAX_INITCODE_SIZE)
+            }
+            _ => (revision >= Revision::EVMC_SPURIOUS_DRAGON).then_some(MAX_CODE_SIZE),
+        };
+        if max_size.is_some_and(|max_size| code.len() > max_size) {
+            return Err(AnalysisError::CodeTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Rejects code starting with the EOF magic which is not a valid EOF container from Osaka on.
+    /// EOF was dropped from Osaka and is not scheduled for any revision yet, so this is only done
+    /// with the feature `eof`. In earlier revisions, such code is executed as legacy code.
+    #[allow(unused_variables)]
+    fn validate_eof(code: &[u8], revision: Revision) -> Result<(), AnalysisError> {
+        #[cfg(feature = "eof")]
+        if revision >= Revision::EVMC_OSAKA && is_eof(code) {
+            EofContainer::validate(code)?;
+        }
+        Ok(())
     }
 
     #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
@@ -118,6 +239,25 @@ impl<const STEPPABLE: bool> CodeAnalysis<STEPPABLE> {
     }
     #[cfg(feature = "fn-ptr-conversion-dispatch")]
     fn analyze_code(code: &[u8]) -> Self {
+        Self::analyze_code_with(code, crate::interpreter::get_jumptable())
+    }
+
+    /// Analyzes `code` without using the cache, taking the functions of all opcodes from
+    /// `jumptable`. JUMPDEST and the internal no-op opcodes always use the default functions,
+    /// because jump destinations are detected by their function.
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    pub fn with_jumptable(
+        code: &[u8],
+        jumptable: &[OpFn<STEPPABLE>; 256],
+    ) -> AnalysisContainer<Self> {
+        let analysis = Self::analyze_code_with(code, jumptable);
+        #[cfg(feature = "code-analysis-cache")]
+        let analysis = AnalysisContainer::new(analysis);
+        analysis
+    }
+
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    fn analyze_code_with(code: &[u8], jumptable: &[OpFn<STEPPABLE>; 256]) -> Self {
         let mut analysis = Vec::with_capacity(code.len());
         // +32+1 because if last op is push32 we need mapping from after converted to after code+32
         let mut pc_map = PcMap::new(code.len() + 32 + 1);
@@ -144,14 +284,14 @@ impl<const STEPPABLE: bool> CodeAnalysis<STEPPABLE> {
                     data[32 - data_len..32 - data_len + avail]
                         .copy_from_slice(&code[pc..pc + avail]);
                     let data = u256::from_be_bytes(data);
-                    analysis.push(OpFnData::func(op, data));
+                    analysis.push(OpFnData::func_from(jumptable, op, data));
                     pc_map.add_mapping(pc - 1, analysis.len() - 1);
 
                     no_ops += data_len;
                     pc += data_len;
                 }
                 CodeByteType::Opcode => {
-                    analysis.push(OpFnData::func(op, u256::ZERO));
+                    analysis.push(OpFnData::func_from(jumptable, op, u256::ZERO));
                     pc_map.add_mapping(pc - 1, analysis.len() - 1);
                 }
                 CodeByteType::DataOrInvalid => {
@@ -169,13 +309,73 @@ impl<const STEPPABLE: bool> CodeAnalysis<STEPPABLE> {
     }
 }
 
+/// Analyzes the code only as far as needed, so that code which halts before jumping far is never
+/// analyzed completely. Each byte is analyzed at most once.
+#[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+#[derive(Debug, Default)]
+pub struct LazyCodeAnalysis {
+    /// The analysis of a prefix of the code, which always ends at an instruction boundary or at
+    /// the end of the code.
+    analysis: Vec<CodeByteType>,
+}
+
+#[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+impl LazyCodeAnalysis {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Extends the analysis so that it covers `pc`, unless `pc` is past the end of `code`. `code`
+    /// must be the same in all calls.
+    pub fn analyze_to(&mut self, code: &[u8], pc: usize) {
+        while self.analysis.len() <= pc {
+            let start = self.analysis.len();
+            let Some(op) = code.get(start).copied() else {
+                return;
+            };
+            let (code_byte_type, data) = code_byte_type(op);
+            self.analysis.resize(
+                min(start + 1 + data, code.len()),
+                CodeByteType::DataOrInvalid,
+            );
+            self.analysis[start] = code_byte_type;
+        }
+    }
+
+    /// Returns the type of the byte at `pc` if it was already analyzed.
+    pub fn get(&self, pc: usize) -> Option<CodeByteType> {
+        self.analysis.get(pc).copied()
+    }
+
+    pub fn is_jump_dest(&mut self, code: &[u8], dest: usize) -> bool {
+        self.analyze_to(code, dest);
+        self.get(dest) == Some(CodeByteType::JumpDest)
+    }
+
+    /// The number of bytes of the code which were analyzed so far.
+    #[cfg(test)]
+    pub fn analyzed_len(&self) -> usize {
+        self.analysis.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use evmc_vm::{MessageKind, Revision};
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    use proptest::prelude::*;
+
     #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
     use crate::types::CodeByteType;
-    use crate::types::{CodeAnalysis, Opcode};
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    use crate::types::LazyCodeAnalysis;
     #[cfg(feature = "fn-ptr-conversion-dispatch")]
-    use crate::types::{OpFnData, u256};
+    use crate::types::OpFnData;
+    use crate::types::{
+        CodeAnalysis, CodeAnalysisCache, Opcode,
+        code_analysis::{AnalysisError, MAX_CODE_SIZE, MAX_INITCODE_SIZE},
+        u256,
+    };
 
     #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
     #[test]
@@ -409,4 +609,136 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn analysis_rejects_code_which_can_not_be_executed() {
+        let too_large_code = vec![Opcode::Stop as u8; MAX_CODE_SIZE + 1];
+        let too_large_initcode = vec![Opcode::Stop as u8; MAX_INITCODE_SIZE + 1];
+        // the code is executed before the revision in which it is rejected
+        let cases = [
+            (
+                &too_large_code,
+                MessageKind::EVMC_CALL,
+                Revision::EVMC_TANGERINE_WHISTLE,
+                Revision::EVMC_SPURIOUS_DRAGON,
+            ),
+            (
+                &too_large_initcode,
+                MessageKind::EVMC_CREATE,
+                Revision::EVMC_PARIS,
+                Revision::EVMC_SHANGHAI,
+            ),
+        ];
+        let cache = CodeAnalysisCache::<false>::default();
+        for (code, kind, legacy_revision, revision) in cases {
+            assert!(CodeAnalysis::new(code, None, legacy_revision, kind, &cache).is_ok());
+            assert_eq!(
+                CodeAnalysis::new(code, Some(u256::ONE), revision, kind, &cache).err(),
+                Some(AnalysisError::CodeTooLarge)
+            );
+        }
+        // failed analyses are not cached
+        #[cfg(feature = "code-analysis-cache")]
+        assert_eq!(cache.0.entries(), 0);
+
+        // initcode may be larger than deployed code
+        let max_size = vec![Opcode::Stop as u8; MAX_INITCODE_SIZE];
+        assert!(
+            CodeAnalysis::new(
+                &max_size,
+                None,
+                Revision::EVMC_SHANGHAI,
+                MessageKind::EVMC_CREATE2,
+                &cache
+            )
+            .is_ok()
+        );
+    }
+
+    /// Builds an EOF container with a single code section with a maximum stack height of 1 and
+    /// no data.
+    #[cfg(feature = "eof")]
+    fn eof_container(code: &[u8]) -> Vec<u8> {
+        let mut container = vec![0xef, 0x00, 0x01, 0x01, 0x00, 0x04, 0x02, 0x00, 0x01];
+        container.extend_from_slice(&(code.len() as u16).to_be_bytes());
+        container.extend_from_slice(&[0xff, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x01]);
+        container.extend_from_slice(code);
+        container
+    }
+
+    #[cfg(feature = "eof")]
+    #[test]
+    fn analysis_rejects_invalid_eof_code() {
+        let invalid_header = [0xef, 0x00, 0x01, 0x01];
+        let unterminated_push = eof_container(&[Opcode::Push2 as u8, 0x01]);
+        let undefined_instruction = eof_container(&[Opcode::Jump as u8]);
+        let cases: [(&[u8], AnalysisError); 3] = [
+            (&invalid_header, AnalysisError::InvalidEofHeader),
+            (&unterminated_push, AnalysisError::UnterminatedPush),
+            (&undefined_instruction, AnalysisError::InvalidEofCode),
+        ];
+        let cache = CodeAnalysisCache::<false>::default();
+        let analyze = |code, revision| {
+            CodeAnalysis::new(
+                code,
+                Some(u256::ONE),
+                revision,
+                MessageKind::EVMC_CALL,
+                &cache,
+            )
+        };
+        for (code, error) in cases {
+            // before Osaka, EOF code is executed as legacy code
+            assert!(analyze(code, Revision::EVMC_PRAGUE).is_ok());
+            assert_eq!(analyze(code, Revision::EVMC_OSAKA).err(), Some(error));
+        }
+
+        // the code is validated on cache hits as well
+        let valid = eof_container(&[Opcode::Push2 as u8, 0x01, 0x02, Opcode::Stop as u8]);
+        assert!(analyze(&valid, Revision::EVMC_OSAKA).is_ok());
+        assert_eq!(
+            analyze(&invalid_header, Revision::EVMC_OSAKA).err(),
+            Some(AnalysisError::InvalidEofHeader)
+        );
+    }
+
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    #[test]
+    fn lazy_analysis_only_analyzes_up_to_requested_pc() {
+        let code = [
+            Opcode::JumpDest as u8,
+            Opcode::Push2 as u8,
+            Opcode::JumpDest as u8,
+            Opcode::JumpDest as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Add as u8,
+        ];
+        let mut analysis = LazyCodeAnalysis::new();
+        assert!(analysis.is_jump_dest(&code, 0));
+        assert_eq!(analysis.analyzed_len(), 1);
+        // the push data is analyzed together with the push
+        assert!(!analysis.is_jump_dest(&code, 2));
+        assert_eq!(analysis.analyzed_len(), 4);
+        assert!(analysis.is_jump_dest(&code, 4));
+        assert_eq!(analysis.analyzed_len(), 5);
+        assert!(!analysis.is_jump_dest(&code, 7));
+        assert_eq!(analysis.analyzed_len(), code.len());
+    }
+
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    proptest! {
+        #[test]
+        fn lazy_and_eager_analysis_agree_on_jump_destinations(
+            code in proptest::collection::vec(any::<u8>(), 0..256),
+            dests in proptest::collection::vec(0..260usize, 0..16),
+        ) {
+            let eager = CodeAnalysis::<false>::analyze_code(&code).analysis;
+            let mut lazy = LazyCodeAnalysis::new();
+            // query in random order first, so that later queries hit the analyzed prefix
+            for dest in dests.into_iter().chain((0..code.len() + 1).rev()) {
+                let expected = eager.get(dest) == Some(&CodeByteType::JumpDest);
+                prop_assert_eq!(lazy.is_jump_dest(&code, dest), expected, "dest {}", dest);
+            }
+        }
+    }
 }
@@ -2,11 +2,19 @@
 use std::cmp::min;
 use std::{self, ops::Deref};
 
+use evmc_vm::{MessageKind, Revision};
+
 #[cfg(feature = "fn-ptr-conversion-dispatch")]
 use crate::interpreter::OpFn;
+#[cfg(not(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch"))))]
+use crate::types::AnalysisContainer;
+#[cfg(feature = "verify-code-hash")]
+use crate::types::hash_cache::HashCache;
 use crate::types::{
-    AnalysisContainer, CodeAnalysis, CodeAnalysisCache, CodeByteType, FailStatus, u256,
+    AnalysisError, CodeAnalysis, CodeAnalysisCache, CodeByteType, FailStatus, u256,
 };
+#[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+use crate::types::{LazyCodeAnalysis, code_byte_type};
 
 #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
 struct PushDataLen<const N: usize>;
@@ -19,7 +27,10 @@ impl<const N: usize> PushDataLen<N> {
 #[derive(Debug)]
 pub struct CodeReader<'a, const STEPPABLE: bool> {
     code: &'a [u8],
+    #[cfg(not(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch"))))]
     code_analysis: AnalysisContainer<CodeAnalysis<STEPPABLE>>,
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    code_analysis: LazyCodeAnalysis,
     pc: usize,
 }
 
@@ -38,23 +49,56 @@ pub enum GetOpcodeError {
 }
 
 impl<'a, const STEPPABLE: bool> CodeReader<'a, STEPPABLE> {
+    #[cfg_attr(
+        all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")),
+        allow(unused_variables)
+    )]
     pub fn new(
         code: &'a [u8],
         code_hash: Option<u256>,
         pc: usize,
+        revision: Revision,
+        kind: MessageKind,
         cache: &CodeAnalysisCache<STEPPABLE>,
-    ) -> Self {
-        let code_analysis = CodeAnalysis::new(code, code_hash, cache);
+    ) -> Result<Self, FailStatus> {
+        #[cfg(not(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch"))))]
+        let code_analysis = CodeAnalysis::new(code, code_hash, revision, kind, cache);
+        // analyze up to the initial pc so that it is known whether it points into push data
+        #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+        let code_analysis = CodeAnalysis::<STEPPABLE>::check_code(code, revision, kind).map(|()| {
+            let mut code_analysis = LazyCodeAnalysis::new();
+            code_analysis.analyze_to(code, pc);
+            code_analysis
+        });
+        let code_analysis = code_analysis.map_err(|err| match err {
+            // EIP-3860: CREATE and CREATE2 with initcode over the limit fail exceptionally, like
+            // running out of gas, and all gas is consumed. Deployed code over the EIP-170 limit
+            // can not exist, so the same status is used for it.
+            AnalysisError::CodeTooLarge => FailStatus::OutOfGas,
+            #[cfg(feature = "eof")]
+            AnalysisError::InvalidEofHeader
+            | AnalysisError::UnterminatedPush
+            | AnalysisError::InvalidEofCode => FailStatus::ContractValidationFailure,
+        })?;
         #[cfg(feature = "fn-ptr-conversion-dispatch")]
         let pc = code_analysis.pc_map.to_converted(pc);
-        Self {
+        Ok(Self {
             code,
             code_analysis,
             pc,
-        }
+        })
     }
 
-    #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
+    /// Replaces the analysis with one whose functions are taken from `jumptable`, see
+    /// [`CodeAnalysis::with_jumptable`]. The position in the original code is kept.
+    #[cfg(feature = "fn-ptr-conversion-dispatch")]
+    pub fn set_jumptable(&mut self, jumptable: &[OpFn<STEPPABLE>; 256]) {
+        let pc = self.pc();
+        self.code_analysis = CodeAnalysis::with_jumptable(self.code, jumptable);
+        self.pc = self.code_analysis.pc_map.to_converted(pc);
+    }
+
+    #[cfg(not(any(feature = "lazy-analysis", feature = "fn-ptr-conversion-dispatch")))]
     pub fn get(&self) -> Result<u8, GetOpcodeError> {
         if let Some(op) = self.code.get(self.pc) {
             let analysis = self.code_analysis.analysis[self.pc];
@@ -67,6 +111,21 @@ impl<'a, const STEPPABLE: bool> CodeReader<'a, STEPPABLE> {
             Err(GetOpcodeError::OutOfRange)
         }
     }
+    // Past the analyzed prefix, the opcode itself determines whether it is valid. This is correct
+    // because the pc is only ever moved past push data or to an analyzed JUMPDEST.
+    #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+    pub fn get(&self) -> Result<u8, GetOpcodeError> {
+        let op = *self.code.get(self.pc).ok_or(GetOpcodeError::OutOfRange)?;
+        let code_byte_type = self
+            .code_analysis
+            .get(self.pc)
+            .unwrap_or_else(|| code_byte_type(op).0);
+        if code_byte_type == CodeByteType::DataOrInvalid {
+            Err(GetOpcodeError::Invalid)
+        } else {
+            Ok(op)
+        }
+    }
     #[cfg(feature = "fn-ptr-conversion-dispatch")]
     pub fn get(&self) -> Result<OpFn<STEPPABLE>, GetOpcodeError> {
         self.code_analysis
@@ -76,12 +135,27 @@ impl<'a, const STEPPABLE: bool> CodeReader<'a, STEPPABLE> {
             .and_then(|analysis| analysis.get_func().ok_or(GetOpcodeError::Invalid))
     }
 
+    /// Returns the opcode at the current position. This must only be called after [`Self::get`]
+    /// returned an opcode.
+    #[cfg(feature = "stack-precheck")]
+    pub fn opcode(&self) -> u8 {
+        #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
+        return self.code[self.pc];
+        #[cfg(feature = "fn-ptr-conversion-dispatch")]
+        return self.code_analysis.analysis[self.pc].opcode();
+    }
+
     pub fn next(&mut self) {
         self.pc += 1;
     }
 
     pub fn try_jump(&mut self, dest: u256) -> Result<(), FailStatus> {
         let dest = u64::try_from(dest).map_err(|_| FailStatus::BadJumpDestination)? as usize;
+        #[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+        if !self.code_analysis.is_jump_dest(self.code, dest) {
+            return Err(FailStatus::BadJumpDestination);
+        }
+        #[cfg(not(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch"))))]
         if !self.code_analysis.analysis.get(dest).is_some_and(|c| {
             #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
             return *c == CodeByteType::JumpDest;
@@ -138,6 +212,24 @@ impl<'a, const STEPPABLE: bool> CodeReader<'a, STEPPABLE> {
         self.pc += offset as usize;
     }
 
+    /// Checks that `code_hash`, if given, is the hash of `code`. The hash is computed with
+    /// `hash_cache`, so it uses the same hash function as SHA3. Like in [`CodeAnalysis::new`], a
+    /// zero hash means that no hash is known. On a mismatch the code analysis cache could return
+    /// the analysis of different code, so the code is rejected with [`FailStatus::InvalidCode`].
+    #[cfg(feature = "verify-code-hash")]
+    pub fn verify_code_hash(
+        code: &[u8],
+        code_hash: Option<u256>,
+        hash_cache: &HashCache,
+    ) -> Result<(), FailStatus> {
+        match code_hash {
+            Some(code_hash) if code_hash != u256::ZERO && hash_cache.hash(code) != code_hash => {
+                Err(FailStatus::InvalidCode)
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn pc(&self) -> usize {
         #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
         return self.pc;
@@ -148,18 +240,92 @@ impl<'a, const STEPPABLE: bool> CodeReader<'a, STEPPABLE> {
 
 #[cfg(test)]
 mod tests {
+    use evmc_vm::{MessageKind, Revision};
+    use proptest::prelude::*;
+
+    #[cfg(feature = "verify-code-hash")]
+    use crate::types::hash_cache::{HashCache, Hasher, KeccakHasher};
     use crate::types::{
         CodeAnalysisCache, FailStatus, Opcode,
         code_reader::{CodeReader, GetOpcodeError},
         u256,
     };
 
+    /// Creates a reader for `code` at `pc` for a call in Cancun.
+    fn code_reader_at<'a, const STEPPABLE: bool>(
+        code: &'a [u8],
+        pc: usize,
+        cache: &CodeAnalysisCache<STEPPABLE>,
+    ) -> CodeReader<'a, STEPPABLE> {
+        CodeReader::new(
+            code,
+            None,
+            pc,
+            Revision::EVMC_CANCUN,
+            MessageKind::EVMC_CALL,
+            cache,
+        )
+        .unwrap()
+    }
+
+    /// Computes the valid jump destinations of `code` independently of the code analysis. A byte
+    /// is a valid destination if it is a JUMPDEST which is not part of the data of a PUSH.
+    fn reference_jump_destinations(code: &[u8]) -> Vec<bool> {
+        let mut valid = vec![false; code.len()];
+        let mut pc = 0;
+        while let Some(op) = code.get(pc).copied() {
+            if op == Opcode::JumpDest as u8 {
+                valid[pc] = true;
+            }
+            if (Opcode::Push1 as u8..=Opcode::Push32 as u8).contains(&op) {
+                pc += (op - Opcode::Push1 as u8 + 1) as usize;
+            }
+            pc += 1;
+        }
+        valid
+    }
+
+    /// Checks `try_jump` against [`reference_jump_destinations`] for every destination in and
+    /// right after `code`. Because the reference does not depend on the dispatch mode, this
+    /// guarantees that all dispatch modes agree on the valid jump destinations.
+    fn assert_jump_destinations_match_reference(code: &[u8]) {
+        let expected = reference_jump_destinations(code);
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let code_analysis_cache_steppable = CodeAnalysisCache::default();
+        for dest in 0..code.len() + 2 {
+            let expected = expected.get(dest).copied().unwrap_or(false);
+            let mut code_reader = code_reader_at::<false>(code, 0, &code_analysis_cache);
+            assert_eq!(
+                code_reader.try_jump(dest.into()).is_ok(),
+                expected,
+                "dest {dest} in {code:02x?}"
+            );
+            let mut code_reader = code_reader_at::<true>(code, 0, &code_analysis_cache_steppable);
+            assert_eq!(
+                code_reader.try_jump(dest.into()).is_ok(),
+                expected,
+                "dest {dest} in {code:02x?} (steppable)"
+            );
+        }
+    }
+
+    /// Generates code bytes which are biased towards JUMPDESTs and PUSHes, because these determine
+    /// the jump destinations.
+    fn any_code() -> impl Strategy<Value = Vec<u8>> {
+        let code_byte = prop_oneof![
+            Just(Opcode::JumpDest as u8),
+            Opcode::Push1 as u8..=Opcode::Push32 as u8,
+            any::<u8>(),
+        ];
+        proptest::collection::vec(code_byte, 0..256)
+    }
+
     #[test]
     fn code_reader_internals() {
         let code_analysis_cache = CodeAnalysisCache::default();
         let code = [Opcode::Add as u8, Opcode::Add as u8, 0xc0];
         let pc = 1;
-        let code_reader = CodeReader::<false>::new(&code, None, pc, &code_analysis_cache);
+        let code_reader = code_reader_at::<false>(&code, pc, &code_analysis_cache);
         assert_eq!(*code_reader, code);
         assert_eq!(code_reader.len(), code.len());
         assert_eq!(code_reader.pc(), pc);
@@ -172,34 +338,34 @@ mod tests {
 
         let code = [Opcode::Push1 as u8, Opcode::Add as u8, Opcode::Add as u8];
 
-        let code_reader = CodeReader::<false>::new(&code, None, 0, &code_analysis_cache);
+        let code_reader = code_reader_at::<false>(&code, 0, &code_analysis_cache);
         assert_eq!(code_reader.pc, 0);
         assert_eq!(code_reader.pc(), 0);
 
-        let mut code_reader = CodeReader::<false>::new(&code, None, 0, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&code, 0, &code_analysis_cache);
         assert_eq!(code_reader.pc, 0);
         code_reader.get_push_data();
         assert_eq!(code_reader.pc, 1);
         assert_eq!(code_reader.pc(), 2);
 
-        let code_reader = CodeReader::<false>::new(&code, None, 2, &code_analysis_cache);
+        let code_reader = code_reader_at::<false>(&code, 2, &code_analysis_cache);
         assert_eq!(code_reader.pc, 1);
         assert_eq!(code_reader.pc(), 2);
 
         let mut code = [Opcode::Add as u8; 23];
         code[0] = Opcode::Push21 as u8;
 
-        let code_reader = CodeReader::<false>::new(&code, None, 0, &code_analysis_cache);
+        let code_reader = code_reader_at::<false>(&code, 0, &code_analysis_cache);
         assert_eq!(code_reader.pc, 0);
         assert_eq!(code_reader.pc(), 0);
 
-        let mut code_reader = CodeReader::<false>::new(&code, None, 0, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&code, 0, &code_analysis_cache);
         assert_eq!(code_reader.pc, 0);
         code_reader.get_push_data();
         assert_eq!(code_reader.pc, 1);
         assert_eq!(code_reader.pc(), 22);
 
-        let code_reader = CodeReader::<false>::new(&code, None, 22, &code_analysis_cache);
+        let code_reader = code_reader_at::<false>(&code, 22, &code_analysis_cache);
         assert_eq!(code_reader.pc, 1);
         assert_eq!(code_reader.pc(), 22);
     }
@@ -207,9 +373,8 @@ mod tests {
     #[test]
     fn code_reader_get() {
         let code_analysis_cache = CodeAnalysisCache::default();
-        let mut code_reader = CodeReader::<false>::new(
+        let mut code_reader = code_reader_at::<false>(
             &[Opcode::Add as u8, Opcode::Add as u8, 0xc0],
-            None,
             0,
             &code_analysis_cache,
         );
@@ -231,13 +396,12 @@ mod tests {
     #[test]
     fn code_reader_try_jump() {
         let code_analysis_cache = CodeAnalysisCache::default();
-        let mut code_reader = CodeReader::<false>::new(
+        let mut code_reader = code_reader_at::<false>(
             &[
                 Opcode::Push1 as u8,
                 Opcode::JumpDest as u8,
                 Opcode::JumpDest as u8,
             ],
-            None,
             0,
             &code_analysis_cache,
         );
@@ -256,23 +420,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn code_reader_maps_analysis_errors_to_fail_status() {
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let too_large = vec![Opcode::Stop as u8; 2 * 24576 + 1];
+        assert_eq!(
+            CodeReader::<false>::new(
+                &too_large,
+                None,
+                0,
+                Revision::EVMC_SHANGHAI,
+                MessageKind::EVMC_CALL,
+                &code_analysis_cache
+            )
+            .err(),
+            Some(FailStatus::OutOfGas)
+        );
+        #[cfg(feature = "eof")]
+        assert_eq!(
+            CodeReader::<false>::new(
+                &[0xef, 0x00, 0x01],
+                None,
+                0,
+                Revision::EVMC_OSAKA,
+                MessageKind::EVMC_CALL,
+                &code_analysis_cache
+            )
+            .err(),
+            Some(FailStatus::ContractValidationFailure)
+        );
+    }
+
     #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
     #[test]
     fn code_reader_get_push_data() {
         let code_analysis_cache = CodeAnalysisCache::default();
-        let mut code_reader = CodeReader::<false>::new(&[0xff; 32], None, 0, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&[0xff; 32], 0, &code_analysis_cache);
         assert_eq!(code_reader.get_push_data::<1>(), 0xffu8.into());
 
-        let mut code_reader = CodeReader::<false>::new(&[0xff; 32], None, 0, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&[0xff; 32], 0, &code_analysis_cache);
         assert_eq!(code_reader.get_push_data::<32>(), u256::MAX);
 
-        let mut code_reader = CodeReader::<false>::new(&[0xff; 32], None, 31, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&[0xff; 32], 31, &code_analysis_cache);
         assert_eq!(
             code_reader.get_push_data::<32>(),
             u256::from(0xffu8) << u256::from(248u8)
         );
 
-        let mut code_reader = CodeReader::<false>::new(&[0xff; 32], None, 32, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&[0xff; 32], 32, &code_analysis_cache);
         assert_eq!(code_reader.get_push_data::<32>(), u256::ZERO);
     }
     #[cfg(feature = "fn-ptr-conversion-dispatch")]
@@ -282,7 +477,65 @@ mod tests {
         // pc on data is non longer possible because there are not data items anymore
         let mut code = [0xff; 33];
         code[0] = Opcode::Push32 as u8;
-        let mut code_reader = CodeReader::<false>::new(&code, None, 0, &code_analysis_cache);
+        let mut code_reader = code_reader_at::<false>(&code, 0, &code_analysis_cache);
         assert_eq!(code_reader.get_push_data(), u256::MAX);
     }
+
+    #[test]
+    fn jump_destinations_match_reference_for_handcrafted_code() {
+        const JUMPDEST: u8 = Opcode::JumpDest as u8;
+        const PUSH1: u8 = Opcode::Push1 as u8;
+        const PUSH2: u8 = Opcode::Push2 as u8;
+        const PUSH32: u8 = Opcode::Push32 as u8;
+        const ADD: u8 = Opcode::Add as u8;
+
+        let mut push32_with_jumpdests = vec![PUSH32];
+        push32_with_jumpdests.extend([JUMPDEST; 32]);
+        push32_with_jumpdests.push(JUMPDEST);
+
+        let codes: [&[u8]; 10] = [
+            &[],
+            &[JUMPDEST],
+            &[JUMPDEST, JUMPDEST, ADD, JUMPDEST],
+            // JUMPDEST in push data
+            &[PUSH1, JUMPDEST, JUMPDEST],
+            &[PUSH2, JUMPDEST, JUMPDEST, JUMPDEST],
+            // multiple pushes before a JUMPDEST
+            &[PUSH1, 0, PUSH2, 0, 0, ADD, JUMPDEST, PUSH1, JUMPDEST],
+            // truncated push at the end of the code
+            &[JUMPDEST, PUSH2, JUMPDEST],
+            // invalid opcodes before a JUMPDEST
+            &[0xc0, 0xc0, JUMPDEST, 0xfe, JUMPDEST],
+            &push32_with_jumpdests,
+            &[PUSH1, PUSH1, JUMPDEST, PUSH32],
+        ];
+        for code in codes {
+            assert_jump_destinations_match_reference(code);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn jump_destinations_match_reference_for_random_code(code in any_code()) {
+            assert_jump_destinations_match_reference(&code);
+        }
+    }
+
+    #[cfg(feature = "verify-code-hash")]
+    #[test]
+    fn verify_code_hash_rejects_mismatched_hash() {
+        let code = [Opcode::Push0 as u8, Opcode::Stop as u8];
+        let code_hash = KeccakHasher::hash(&code);
+        let hash_cache = HashCache::default();
+        let verify =
+            |code, code_hash| CodeReader::<false>::verify_code_hash(code, code_hash, &hash_cache);
+        assert_eq!(verify(&code, Some(code_hash)), Ok(()));
+        assert_eq!(verify(&code, None), Ok(()));
+        // a zero hash is treated as no hash
+        assert_eq!(verify(&code, Some(u256::ZERO)), Ok(()));
+        assert_eq!(
+            verify(&code[..1], Some(code_hash)),
+            Err(FailStatus::InvalidCode)
+        );
+    }
 }
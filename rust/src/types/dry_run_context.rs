@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use evmc_vm::{
+    AccessStatus, Address, ExecutionMessage, ExecutionResult, ExecutionTxContext, StorageStatus,
+    Uint256,
+};
+
+use crate::types::{ExecutionContextTrait, u256};
+
+/// Wraps the context of the host for dry runs like gas estimation. Storage and transient storage
+/// writes, selfdestructs and logs are recorded here instead of being forwarded, so they have no
+/// effect on the host. All other callbacks are forwarded.
+///
+/// Storage writes are tracked per slot so that later reads and the returned [`StorageStatus`] are
+/// the same as in a real run, which keeps gas costs and refunds exact. This does not hold in the
+/// following cases, which are not supported:
+/// - EVMC does not expose the value of a slot at the start of the transaction, so the value of
+///   the host on the first write is used as the original value. If the slot was already changed
+///   earlier in the transaction, the status and therefore the gas and the refund differ from a
+///   real run.
+/// - Nested calls are executed by the host. They do not see the writes recorded here and apply
+///   their own effects to the host.
+/// - Accesses of accounts and storage slots are forwarded, so they warm them in the host.
+///
+/// Therefore the host must provide a context which can be snapshotted before and reverted after
+/// the dry run.
+pub struct DryRunContext<'a> {
+    context: &'a mut dyn ExecutionContextTrait,
+    /// The original and the current value of all written storage slots.
+    storage: BTreeMap<(u256, u256), (u256, u256)>,
+    /// The values of all written transient storage slots.
+    transient_storage: BTreeMap<(u256, u256), u256>,
+    destructed_accounts: BTreeSet<u256>,
+}
+
+impl<'a> DryRunContext<'a> {
+    pub fn new(context: &'a mut dyn ExecutionContextTrait) -> Self {
+        Self {
+            context,
+            storage: BTreeMap::new(),
+            transient_storage: BTreeMap::new(),
+            destructed_accounts: BTreeSet::new(),
+        }
+    }
+}
+
+/// Returns the status of a storage write as defined by EIP-2200, where `original` is the value at
+/// the start of the transaction and `current` the value before the write.
+pub(crate) fn storage_status(original: u256, current: u256, new: u256) -> StorageStatus {
+    if current == new {
+        StorageStatus::EVMC_STORAGE_ASSIGNED
+    } else if original == current {
+        if original == u256::ZERO {
+            StorageStatus::EVMC_STORAGE_ADDED
+        } else if new == u256::ZERO {
+            StorageStatus::EVMC_STORAGE_DELETED
+        } else {
+            StorageStatus::EVMC_STORAGE_MODIFIED
+        }
+    } else if original != u256::ZERO && current == u256::ZERO {
+        if new == original {
+            StorageStatus::EVMC_STORAGE_DELETED_RESTORED
+        } else {
+            StorageStatus::EVMC_STORAGE_DELETED_ADDED
+        }
+    } else if original != u256::ZERO && new == u256::ZERO {
+        StorageStatus::EVMC_STORAGE_MODIFIED_DELETED
+    } else if original != u256::ZERO && new == original {
+        StorageStatus::EVMC_STORAGE_MODIFIED_RESTORED
+    } else if original == u256::ZERO && new == u256::ZERO {
+        StorageStatus::EVMC_STORAGE_ADDED_DELETED
+    } else {
+        StorageStatus::EVMC_STORAGE_ASSIGNED
+    }
+}
+
+impl ExecutionContextTrait for DryRunContext<'_> {
+    fn get_tx_context(&mut self) -> &ExecutionTxContext<'_> {
+        self.context.get_tx_context()
+    }
+
+    fn account_exists(&self, address: &Address) -> bool {
+        self.context.account_exists(address)
+    }
+
+    fn get_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        match self.storage.get(&(address.into(), u256::from(*key))) {
+            Some((_, current)) => (*current).into(),
+            None => self.context.get_storage(address, key),
+        }
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) -> StorageStatus {
+        let slot = self
+            .storage
+            .entry((address.into(), u256::from(*key)))
+            .or_insert_with(|| {
+                let original = u256::from(self.context.get_storage(address, key));
+                (original, original)
+            });
+        let (original, current) = *slot;
+        let new = u256::from(*value);
+        slot.1 = new;
+        storage_status(original, current, new)
+    }
+
+    fn get_balance(&self, address: &Address) -> Uint256 {
+        self.context.get_balance(address)
+    }
+
+    fn get_code_size(&self, address: &Address) -> usize {
+        self.context.get_code_size(address)
+    }
+
+    fn get_code_hash(&self, address: &Address) -> Uint256 {
+        self.context.get_code_hash(address)
+    }
+
+    fn copy_code(&self, address: &Address, code_offset: usize, buffer: &mut [u8]) -> usize {
+        self.context.copy_code(address, code_offset, buffer)
+    }
+
+    fn selfdestruct(&mut self, address: &Address, _beneficiary: &Address) -> bool {
+        self.destructed_accounts.insert(address.into())
+    }
+
+    fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        self.context.call(message)
+    }
+
+    fn get_block_hash(&self, num: i64) -> Uint256 {
+        self.context.get_block_hash(num)
+    }
+
+    fn emit_log(&mut self, _address: &Address, _data: &[u8], _topics: &[Uint256]) {}
+
+    fn access_account(&mut self, address: &Address) -> AccessStatus {
+        self.context.access_account(address)
+    }
+
+    fn access_storage(&mut self, address: &Address, key: &Uint256) -> AccessStatus {
+        self.context.access_storage(address, key)
+    }
+
+    fn get_transient_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        match self
+            .transient_storage
+            .get(&(address.into(), u256::from(*key)))
+        {
+            Some(value) => (*value).into(),
+            None => self.context.get_transient_storage(address, key),
+        }
+    }
+
+    fn set_transient_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) {
+        self.transient_storage
+            .insert((address.into(), u256::from(*key)), u256::from(*value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::{AccessStatus, Address, ExecutionResult, StatusCode, StorageStatus, Uint256};
+    use mockall::predicate;
+
+    use crate::types::{
+        ExecutionContextTrait, MockExecutionContextTrait, MockExecutionMessage,
+        dry_run_context::DryRunContext, u256,
+    };
+
+    #[test]
+    fn set_storage_is_not_forwarded_and_reports_status_of_real_write() {
+        let address = Address::from(u256::from(0xaau8));
+        let key = Uint256::from(u256::ONE);
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_storage()
+            .times(1)
+            .with(predicate::eq(address), predicate::eq(key))
+            .return_const(Uint256::from(u256::from(5u8)));
+        context.expect_set_storage().never();
+        let mut dry_run = DryRunContext::new(&mut context);
+
+        let status = dry_run.set_storage(&address, &key, &u256::ZERO.into());
+        assert_eq!(status, StorageStatus::EVMC_STORAGE_DELETED);
+        assert_eq!(dry_run.get_storage(&address, &key), u256::ZERO.into());
+        let status = dry_run.set_storage(&address, &key, &u256::from(5u8).into());
+        assert_eq!(status, StorageStatus::EVMC_STORAGE_DELETED_RESTORED);
+        assert_eq!(dry_run.get_storage(&address, &key), u256::from(5u8).into());
+    }
+
+    #[test]
+    fn set_storage_takes_original_value_from_host() {
+        let address = Address::from(u256::from(0xaau8));
+        let key = Uint256::from(u256::ONE);
+        let mut context = MockExecutionContextTrait::new();
+        // the slot was set from 0 to 5 earlier in the transaction
+        context
+            .expect_get_storage()
+            .return_const(Uint256::from(u256::from(5u8)));
+        let mut dry_run = DryRunContext::new(&mut context);
+
+        // a real run reports EVMC_STORAGE_ADDED_DELETED, which is not supported
+        let status = dry_run.set_storage(&address, &key, &u256::ZERO.into());
+        assert_eq!(status, StorageStatus::EVMC_STORAGE_DELETED);
+    }
+
+    #[test]
+    fn set_transient_storage_is_not_forwarded() {
+        let address = Address::from(u256::from(0xaau8));
+        let key = Uint256::from(u256::ONE);
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_transient_storage()
+            .times(1)
+            .return_const(Uint256::from(u256::from(5u8)));
+        context.expect_set_transient_storage().never();
+        let mut dry_run = DryRunContext::new(&mut context);
+
+        assert_eq!(
+            dry_run.get_transient_storage(&address, &key),
+            u256::from(5u8).into()
+        );
+        dry_run.set_transient_storage(&address, &key, &u256::from(7u8).into());
+        assert_eq!(
+            dry_run.get_transient_storage(&address, &key),
+            u256::from(7u8).into()
+        );
+    }
+
+    #[test]
+    fn calls_and_accesses_are_forwarded() {
+        let address = Address::from(u256::from(0xaau8));
+        let key = Uint256::from(u256::ONE);
+        let mut context = MockExecutionContextTrait::new();
+        context
+            .expect_get_storage()
+            .return_const(Uint256::from(u256::ZERO));
+        context.expect_set_storage().never();
+        // the nested call is executed by the host and does not see the recorded write
+        context
+            .expect_call()
+            .times(1)
+            .returning(|_| ExecutionResult {
+                status_code: StatusCode::EVMC_SUCCESS,
+                gas_left: 0,
+                gas_refund: 0,
+                output: Box::default(),
+                create_address: None,
+            });
+        context
+            .expect_access_account()
+            .times(1)
+            .return_const(AccessStatus::EVMC_ACCESS_COLD);
+        context
+            .expect_access_storage()
+            .times(1)
+            .return_const(AccessStatus::EVMC_ACCESS_COLD);
+        let mut dry_run = DryRunContext::new(&mut context);
+
+        dry_run.set_storage(&address, &key, &u256::ONE.into());
+        let message = MockExecutionMessage::default().into();
+        dry_run.call(&message);
+        dry_run.access_account(&address);
+        dry_run.access_storage(&address, &key);
+    }
+
+    #[test]
+    fn selfdestruct_and_emit_log_are_not_forwarded() {
+        let address = Address::from(u256::from(0xaau8));
+        let beneficiary = Address::from(u256::from(0xbbu8));
+        let mut context = MockExecutionContextTrait::new();
+        context.expect_selfdestruct().never();
+        context.expect_emit_log().never();
+        let mut dry_run = DryRunContext::new(&mut context);
+
+        assert!(dry_run.selfdestruct(&address, &beneficiary));
+        assert!(!dry_run.selfdestruct(&address, &beneficiary));
+        dry_run.emit_log(&address, &[1, 2, 3], &[u256::ONE.into()]);
+    }
+}
@@ -0,0 +1,647 @@
+use crate::types::{CodeByteType, OPCODE_INFO, Opcode, code_byte_type};
+
+/// The prefix of all EOF containers (EIP-3540).
+pub const EOF_MAGIC: [u8; 2] = [0xef, 0x00];
+const EOF_VERSION: u8 = 1;
+
+const KIND_TYPES: u8 = 0x01;
+const KIND_CODE: u8 = 0x02;
+const KIND_CONTAINER: u8 = 0x03;
+const KIND_DATA: u8 = 0xff;
+const TERMINATOR: u8 = 0x00;
+
+const MAX_CODE_SECTIONS: usize = 1024;
+const MAX_CONTAINER_SECTIONS: usize = 256;
+const MAX_STACK_HEIGHT: u16 = 1024;
+const MAX_TYPE_IO: u8 = 0x7f;
+/// The `outputs` of a code section which never returns to its caller.
+pub const NON_RETURNING: u8 = 0x80;
+
+const STOP: u8 = Opcode::Stop as u8;
+const JUMP: u8 = Opcode::Jump as u8;
+const JUMPI: u8 = Opcode::JumpI as u8;
+const PC: u8 = Opcode::Pc as u8;
+const GAS: u8 = Opcode::Gas as u8;
+const CODESIZE: u8 = Opcode::CodeSize as u8;
+const CODECOPY: u8 = Opcode::CodeCopy as u8;
+const EXTCODESIZE: u8 = Opcode::ExtCodeSize as u8;
+const EXTCODECOPY: u8 = Opcode::ExtCodeCopy as u8;
+const EXTCODEHASH: u8 = Opcode::ExtCodeHash as u8;
+const PUSH1: u8 = Opcode::Push1 as u8;
+const PUSH32: u8 = Opcode::Push32 as u8;
+const CREATE: u8 = Opcode::Create as u8;
+const CALL: u8 = Opcode::Call as u8;
+const CALLCODE: u8 = Opcode::CallCode as u8;
+const RETURN: u8 = Opcode::Return as u8;
+const DELEGATECALL: u8 = Opcode::DelegateCall as u8;
+const CREATE2: u8 = Opcode::Create2 as u8;
+const STATICCALL: u8 = Opcode::StaticCall as u8;
+const REVERT: u8 = Opcode::Revert as u8;
+const INVALID: u8 = Opcode::Invalid as u8;
+const SELFDESTRUCT: u8 = Opcode::SelfDestruct as u8;
+
+// Opcodes which only exist in EOF code.
+const DATALOAD: u8 = 0xd0;
+const DATALOADN: u8 = 0xd1;
+const DATASIZE: u8 = 0xd2;
+const DATACOPY: u8 = 0xd3;
+const RJUMP: u8 = 0xe0;
+const RJUMPI: u8 = 0xe1;
+const RJUMPV: u8 = 0xe2;
+const CALLF: u8 = 0xe3;
+const RETF: u8 = 0xe4;
+const JUMPF: u8 = 0xe5;
+const DUPN: u8 = 0xe6;
+const SWAPN: u8 = 0xe7;
+const EXCHANGE: u8 = 0xe8;
+const EOFCREATE: u8 = 0xec;
+const RETURNCONTRACT: u8 = 0xee;
+const RETURNDATALOAD: u8 = 0xf7;
+const EXTCALL: u8 = 0xf8;
+const EXTDELEGATECALL: u8 = 0xf9;
+const EXTSTATICCALL: u8 = 0xfb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EofError {
+    InvalidMagic,
+    UnsupportedVersion,
+    TruncatedHeader,
+    /// A section kind was encountered where a different one was expected.
+    InvalidSectionOrder,
+    ZeroCodeSections,
+    TooManyCodeSections,
+    TooManyContainerSections,
+    ZeroSectionSize,
+    InvalidTypeSectionSize,
+    /// The sizes in the header do not add up to the size of the body.
+    BodySizeMismatch,
+    InvalidTypes,
+    UndefinedInstruction,
+    TruncatedImmediate,
+    InvalidJumpDestination,
+    InvalidSectionIndex,
+    UnreachableCode,
+    NoTerminatingInstruction,
+    StackUnderflow,
+    StackOverflow,
+    /// The stack height differs between two paths reaching the same instruction, or does not
+    /// match the outputs of the code section when returning.
+    StackHeightMismatch,
+    /// The maximum stack height declared in the type section is not the computed one.
+    InvalidMaxStackHeight,
+}
+
+/// The entry of a code section in the type section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeSection {
+    pub inputs: u8,
+    /// The number of outputs, or [`NON_RETURNING`].
+    pub outputs: u8,
+    /// The maximum stack height of the code section, including its inputs.
+    pub max_stack_height: u16,
+}
+
+/// A parsed EOF container (EIP-3540). All sections borrow from the code the container was parsed
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EofContainer<'a> {
+    pub version: u8,
+    pub types: Vec<TypeSection>,
+    pub code_sections: Vec<&'a [u8]>,
+    pub container_sections: Vec<&'a [u8]>,
+    pub data: &'a [u8],
+}
+
+struct HeaderReader<'a>(&'a [u8]);
+
+impl<'a> HeaderReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EofError> {
+        if self.0.len() < len {
+            return Err(EofError::TruncatedHeader);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn take_u8(&mut self) -> Result<u8, EofError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn take_u16(&mut self) -> Result<u16, EofError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn take_u32(&mut self) -> Result<u32, EofError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn expect_kind(&mut self, kind: u8) -> Result<(), EofError> {
+        if self.take_u8()? != kind {
+            return Err(EofError::InvalidSectionOrder);
+        }
+        Ok(())
+    }
+
+    /// Reads the number of sections followed by the size of each section.
+    fn take_section_sizes(
+        &mut self,
+        max_sections: usize,
+        zero_sections: EofError,
+        too_many: EofError,
+        mut take_size: impl FnMut(&mut Self) -> Result<usize, EofError>,
+    ) -> Result<Vec<usize>, EofError> {
+        let count = self.take_u16()? as usize;
+        if count == 0 {
+            return Err(zero_sections);
+        }
+        if count > max_sections {
+            return Err(too_many);
+        }
+        let sizes = (0..count)
+            .map(|_| take_size(self))
+            .collect::<Result<Vec<_>, _>>()?;
+        if sizes.contains(&0) {
+            return Err(EofError::ZeroSectionSize);
+        }
+        Ok(sizes)
+    }
+}
+
+/// Returns whether `code` starts with the EOF magic.
+pub fn is_eof(code: &[u8]) -> bool {
+    code.starts_with(&EOF_MAGIC)
+}
+
+impl<'a> EofContainer<'a> {
+    /// Parses the header of `code` and splits the body into its sections. The code sections
+    /// themselves are not validated, see [`Self::validate`].
+    pub fn parse(code: &'a [u8]) -> Result<Self, EofError> {
+        let header = code
+            .strip_prefix(&EOF_MAGIC)
+            .ok_or(EofError::InvalidMagic)?;
+        let mut reader = HeaderReader(header);
+        let version = reader.take_u8()?;
+        if version != EOF_VERSION {
+            return Err(EofError::UnsupportedVersion);
+        }
+
+        reader.expect_kind(KIND_TYPES)?;
+        let types_size = reader.take_u16()? as usize;
+
+        reader.expect_kind(KIND_CODE)?;
+        let code_sizes = reader.take_section_sizes(
+            MAX_CODE_SECTIONS,
+            EofError::ZeroCodeSections,
+            EofError::TooManyCodeSections,
+            |r| r.take_u16().map(usize::from),
+        )?;
+
+        let mut kind = reader.take_u8()?;
+        let mut container_sizes = Vec::new();
+        if kind == KIND_CONTAINER {
+            container_sizes = reader.take_section_sizes(
+                MAX_CONTAINER_SECTIONS,
+                EofError::ZeroSectionSize,
+                EofError::TooManyContainerSections,
+                |r| r.take_u32().map(|size| size as usize),
+            )?;
+            kind = reader.take_u8()?;
+        }
+        if kind != KIND_DATA {
+            return Err(EofError::InvalidSectionOrder);
+        }
+        let data_size = reader.take_u16()? as usize;
+        reader.expect_kind(TERMINATOR)?;
+
+        if types_size != code_sizes.len() * 4 {
+            return Err(EofError::InvalidTypeSectionSize);
+        }
+        let body_size = types_size
+            + code_sizes.iter().sum::<usize>()
+            + container_sizes.iter().sum::<usize>()
+            + data_size;
+        if reader.0.len() != body_size {
+            return Err(EofError::BodySizeMismatch);
+        }
+
+        let mut body = HeaderReader(reader.0);
+        let types = body
+            .take(types_size)?
+            .chunks_exact(4)
+            .map(|entry| TypeSection {
+                inputs: entry[0],
+                outputs: entry[1],
+                max_stack_height: u16::from_be_bytes([entry[2], entry[3]]),
+            })
+            .collect::<Vec<_>>();
+        Self::validate_types(&types)?;
+        let code_sections = code_sizes
+            .iter()
+            .map(|size| body.take(*size))
+            .collect::<Result<_, _>>()?;
+        let container_sections = container_sizes
+            .iter()
+            .map(|size| body.take(*size))
+            .collect::<Result<_, _>>()?;
+        let data = body.take(data_size)?;
+
+        Ok(Self {
+            version,
+            types,
+            code_sections,
+            container_sections,
+            data,
+        })
+    }
+
+    /// Parses `code` and validates the instructions and stack heights of all code sections.
+    pub fn validate(code: &'a [u8]) -> Result<Self, EofError> {
+        let container = Self::parse(code)?;
+        for (section, code) in container.code_sections.iter().enumerate() {
+            validate_stack_heights(code, section, &container.types)?;
+        }
+        Ok(container)
+    }
+
+    fn validate_types(types: &[TypeSection]) -> Result<(), EofError> {
+        if types[0].inputs != 0 || types[0].outputs != NON_RETURNING {
+            return Err(EofError::InvalidTypes);
+        }
+        for ty in types {
+            if ty.inputs > MAX_TYPE_IO
+                || (ty.outputs > MAX_TYPE_IO && ty.outputs != NON_RETURNING)
+                || ty.max_stack_height > MAX_STACK_HEIGHT - 1
+                || ty.max_stack_height < u16::from(ty.inputs)
+            {
+                return Err(EofError::InvalidTypes);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the length of the immediate data of the instruction at `pos`.
+fn immediate_len(code: &[u8], pos: usize) -> Result<usize, EofError> {
+    Ok(match code[pos] {
+        op @ PUSH1..=PUSH32 => (op - PUSH1 + 1) as usize,
+        RJUMP | RJUMPI | CALLF | JUMPF | DATALOADN => 2,
+        RJUMPV => {
+            let max_index = *code.get(pos + 1).ok_or(EofError::TruncatedImmediate)?;
+            1 + 2 * (max_index as usize + 1)
+        }
+        DUPN | SWAPN | EXCHANGE | EOFCREATE | RETURNCONTRACT => 1,
+        _ => 0,
+    })
+}
+
+fn read_u16(code: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([code[pos], code[pos + 1]])
+}
+
+/// Checks that calling a code section of type `target` with a stack height of `height` does not
+/// overflow the stack.
+fn check_callee_stack(height: u16, target: TypeSection) -> Result<(), EofError> {
+    if u32::from(height) + u32::from(target.max_stack_height)
+        > u32::from(MAX_STACK_HEIGHT) + u32::from(target.inputs)
+    {
+        return Err(EofError::StackOverflow);
+    }
+    Ok(())
+}
+
+/// Returns the absolute target of a relative jump whose offset is stored at `offset_pos` and
+/// which is relative to `next`.
+fn jump_target(code: &[u8], offset_pos: usize, next: usize) -> Option<usize> {
+    let offset = read_u16(code, offset_pos) as i16;
+    next.checked_add_signed(offset as isize)
+}
+
+/// Validates the instructions of the code section with index `section` and checks that the stack
+/// height at each instruction is independent of the path which reaches it (EIP-5450).
+///
+/// The code is traversed in a single forward pass. Forward jumps widen the range of possible
+/// stack heights of their target, backward jumps must reach their target with exactly the stack
+/// height it was first reached with.
+pub fn validate_stack_heights(
+    code: &[u8],
+    section: usize,
+    types: &[TypeSection],
+) -> Result<(), EofError> {
+    let ty = types.get(section).ok_or(EofError::InvalidSectionIndex)?;
+    let target_type = |pos: usize| {
+        types
+            .get(read_u16(code, pos + 1) as usize)
+            .copied()
+            .ok_or(EofError::InvalidSectionIndex)
+    };
+
+    let mut is_instruction = vec![false; code.len()];
+    let mut pos = 0;
+    while pos < code.len() {
+        is_instruction[pos] = true;
+        pos += 1 + immediate_len(code, pos)?;
+    }
+    if pos > code.len() {
+        return Err(EofError::TruncatedImmediate);
+    }
+
+    // The minimum and maximum stack height before each instruction.
+    let mut heights: Vec<Option<(u16, u16)>> = vec![None; code.len()];
+    if let Some(first) = heights.first_mut() {
+        *first = Some((ty.inputs.into(), ty.inputs.into()));
+    }
+    let mut max_height = u16::from(ty.inputs);
+    let mut pos = 0;
+    while pos < code.len() {
+        let op = code[pos];
+        let next = pos + 1 + immediate_len(code, pos)?;
+        let (min, max) = heights[pos].ok_or(EofError::UnreachableCode)?;
+
+        let (stack_in, stack_out): (u16, u16) = match op {
+            JUMP | JUMPI | PC | GAS | CODESIZE | CODECOPY | EXTCODESIZE | EXTCODECOPY
+            | EXTCODEHASH | CREATE | CALL | CALLCODE | DELEGATECALL | CREATE2 | STATICCALL
+            | SELFDESTRUCT => return Err(EofError::UndefinedInstruction),
+            DATALOAD | RETURNDATALOAD => (1, 1),
+            DATALOADN | DATASIZE => (0, 1),
+            DATACOPY => (3, 0),
+            RJUMP => (0, 0),
+            RJUMPI | RJUMPV => (1, 0),
+            DUPN => {
+                let n = u16::from(code[pos + 1]) + 1;
+                (n, n + 1)
+            }
+            SWAPN => {
+                let n = u16::from(code[pos + 1]) + 2;
+                (n, n)
+            }
+            EXCHANGE => {
+                let n = u16::from(code[pos + 1] >> 4) + u16::from(code[pos + 1] & 0x0f) + 3;
+                (n, n)
+            }
+            EOFCREATE | EXTCALL => (4, 1),
+            RETURNCONTRACT => (2, 0),
+            EXTDELEGATECALL | EXTSTATICCALL => (3, 1),
+            CALLF => {
+                let target = target_type(pos)?;
+                if target.outputs == NON_RETURNING {
+                    return Err(EofError::InvalidSectionIndex);
+                }
+                check_callee_stack(max, target)?;
+                (target.inputs.into(), target.outputs.into())
+            }
+            RETF => {
+                if ty.outputs == NON_RETURNING || min != max || max != u16::from(ty.outputs) {
+                    return Err(EofError::StackHeightMismatch);
+                }
+                (0, 0)
+            }
+            JUMPF => {
+                let target = target_type(pos)?;
+                check_callee_stack(max, target)?;
+                if target.outputs == NON_RETURNING {
+                    (target.inputs.into(), 0)
+                } else {
+                    if ty.outputs == NON_RETURNING || target.outputs > ty.outputs {
+                        return Err(EofError::InvalidSectionIndex);
+                    }
+                    let expected = u16::from(ty.outputs) + u16::from(target.inputs)
+                        - u16::from(target.outputs);
+                    if min != max || max != expected {
+                        return Err(EofError::StackHeightMismatch);
+                    }
+                    (0, 0)
+                }
+            }
+            op if code_byte_type(op).0 == CodeByteType::DataOrInvalid => {
+                return Err(EofError::UndefinedInstruction);
+            }
+            op => {
+                let info = OPCODE_INFO[op as usize];
+                (info.stack_in.into(), info.stack_out.into())
+            }
+        };
+        if min < stack_in {
+            return Err(EofError::StackUnderflow);
+        }
+        let (min, max) = (min - stack_in + stack_out, max - stack_in + stack_out);
+        if max > MAX_STACK_HEIGHT {
+            return Err(EofError::StackOverflow);
+        }
+        max_height = max_height.max(max);
+
+        let mut successors = Vec::new();
+        match op {
+            STOP | RETURN | REVERT | INVALID | RETF | JUMPF | RETURNCONTRACT => (),
+            RJUMP => successors.push(jump_target(code, pos + 1, next)),
+            RJUMPI => {
+                successors.push(Some(next));
+                successors.push(jump_target(code, pos + 1, next));
+            }
+            RJUMPV => {
+                successors.push(Some(next));
+                for index in 0..=code[pos + 1] as usize {
+                    successors.push(jump_target(code, pos + 2 + 2 * index, next));
+                }
+            }
+            _ => successors.push(Some(next)),
+        }
+        for successor in successors {
+            let successor = successor.ok_or(EofError::InvalidJumpDestination)?;
+            if successor == code.len() && successor == next {
+                return Err(EofError::NoTerminatingInstruction);
+            }
+            if !is_instruction.get(successor).copied().unwrap_or_default() {
+                return Err(EofError::InvalidJumpDestination);
+            }
+            let height = &mut heights[successor];
+            if successor > pos {
+                *height = Some(match *height {
+                    Some((succ_min, succ_max)) => (succ_min.min(min), succ_max.max(max)),
+                    None => (min, max),
+                });
+            } else if *height != Some((min, max)) || min != max {
+                return Err(EofError::StackHeightMismatch);
+            }
+        }
+        pos = next;
+    }
+
+    if max_height != ty.max_stack_height {
+        return Err(EofError::InvalidMaxStackHeight);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{
+        Opcode,
+        eof::{
+            EofContainer, EofError, KIND_CODE, NON_RETURNING, RJUMP, RJUMPI, TypeSection,
+            validate_stack_heights,
+        },
+    };
+
+    /// Builds a container with a single code section and the given data.
+    fn container(max_stack_height: u16, code: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut container = vec![0xef, 0x00, 0x01, 0x01, 0x00, 0x04, 0x02, 0x00, 0x01];
+        container.extend_from_slice(&(code.len() as u16).to_be_bytes());
+        container.push(0xff);
+        container.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        container.push(0x00);
+        container.extend_from_slice(&[0x00, NON_RETURNING]);
+        container.extend_from_slice(&max_stack_height.to_be_bytes());
+        container.extend_from_slice(code);
+        container.extend_from_slice(data);
+        container
+    }
+
+    fn validate_single(max_stack_height: u16, code: &[u8]) -> Result<(), EofError> {
+        let types = [TypeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height,
+        }];
+        validate_stack_heights(code, 0, &types)
+    }
+
+    #[test]
+    fn parse_splits_sections() {
+        let code = container(1, &[Opcode::Push0 as u8, Opcode::Stop as u8], &[1, 2, 3]);
+        let container = EofContainer::validate(&code).unwrap();
+        assert_eq!(container.version, 1);
+        assert_eq!(
+            container.types,
+            [TypeSection {
+                inputs: 0,
+                outputs: NON_RETURNING,
+                max_stack_height: 1
+            }]
+        );
+        assert_eq!(
+            container.code_sections,
+            [[Opcode::Push0 as u8, Opcode::Stop as u8].as_slice()]
+        );
+        assert!(container.container_sections.is_empty());
+        assert_eq!(container.data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        let valid = container(0, &[Opcode::Stop as u8], &[]);
+        assert_eq!(
+            EofContainer::parse(&[0xef, 0x01, 0x01]),
+            Err(EofError::InvalidMagic)
+        );
+        for len in 2..15 {
+            assert_eq!(
+                EofContainer::parse(&valid[..len]),
+                Err(EofError::TruncatedHeader)
+            );
+        }
+
+        let mut code = valid.clone();
+        code[2] = 0x02;
+        assert_eq!(
+            EofContainer::parse(&code),
+            Err(EofError::UnsupportedVersion)
+        );
+
+        let mut code = valid.clone();
+        code[3] = KIND_CODE;
+        assert_eq!(
+            EofContainer::parse(&code),
+            Err(EofError::InvalidSectionOrder)
+        );
+
+        let mut code = valid.clone();
+        code[7..9].copy_from_slice(&[0, 0]);
+        assert_eq!(EofContainer::parse(&code), Err(EofError::ZeroCodeSections));
+
+        let mut code = valid.clone();
+        code.push(0);
+        assert_eq!(EofContainer::parse(&code), Err(EofError::BodySizeMismatch));
+
+        let mut code = valid;
+        code[16] = 0x01;
+        assert_eq!(EofContainer::parse(&code), Err(EofError::InvalidTypes));
+    }
+
+    #[test]
+    fn stack_heights_of_straight_code() {
+        let add = [
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Add as u8,
+            Opcode::Stop as u8,
+        ];
+        assert_eq!(validate_single(2, &add), Ok(()));
+        assert_eq!(
+            validate_single(1, &add),
+            Err(EofError::InvalidMaxStackHeight)
+        );
+        assert_eq!(
+            validate_single(0, &[Opcode::Add as u8, Opcode::Stop as u8]),
+            Err(EofError::StackUnderflow)
+        );
+        assert_eq!(
+            validate_single(1, &[Opcode::Push0 as u8]),
+            Err(EofError::NoTerminatingInstruction)
+        );
+        assert_eq!(
+            validate_single(0, &[Opcode::Stop as u8, Opcode::Stop as u8]),
+            Err(EofError::UnreachableCode)
+        );
+        assert_eq!(
+            validate_single(0, &[Opcode::Push1 as u8]),
+            Err(EofError::TruncatedImmediate)
+        );
+        assert_eq!(
+            validate_single(1, &[Opcode::Push0 as u8, Opcode::Jump as u8]),
+            Err(EofError::UndefinedInstruction)
+        );
+    }
+
+    #[test]
+    fn stack_heights_of_relative_jumps() {
+        // loop which keeps the stack height constant
+        let looping = [Opcode::Push0 as u8, Opcode::Pop as u8, RJUMP, 0xff, 0xfb];
+        assert_eq!(validate_single(1, &looping), Ok(()));
+
+        // loop which grows the stack
+        let growing = [Opcode::Push0 as u8, RJUMP, 0xff, 0xfc];
+        assert_eq!(
+            validate_single(1, &growing),
+            Err(EofError::StackHeightMismatch)
+        );
+
+        // forward branch which merges two different stack heights
+        let branching = [
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            RJUMPI,
+            0x00,
+            0x01,
+            Opcode::Push0 as u8,
+            Opcode::Stop as u8,
+        ];
+        assert_eq!(validate_single(2, &branching), Ok(()));
+
+        // jump into push data
+        let into_immediate = [
+            RJUMP,
+            0x00,
+            0x01,
+            Opcode::Push1 as u8,
+            0,
+            Opcode::Stop as u8,
+        ];
+        assert_eq!(
+            validate_single(0, &into_immediate),
+            Err(EofError::InvalidJumpDestination)
+        );
+    }
+}
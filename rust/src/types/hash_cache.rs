@@ -1,19 +1,85 @@
+#[cfg(feature = "blake2b")]
+use blake2::{Blake2b, digest::consts::U32};
 use sha3::{Digest, Keccak256};
 
-#[cfg(feature = "hash-cache")]
-use crate::types::Cache;
 use crate::types::u256;
+#[cfg(feature = "hash-cache")]
+use crate::types::{Cache, CacheStats};
 
 #[cfg(feature = "hash-cache")]
 type HashCache32 = Cache<[u8; 32], u256>;
 #[cfg(feature = "hash-cache")]
 type HashCache64 = Cache<[u8; 64], u256>;
 
+/// The hash function used by the SHA3 opcode.
+pub trait Hasher {
+    fn hash(data: &[u8]) -> u256;
+}
+
+/// Keccak-256, the hash function used by Ethereum.
+pub struct KeccakHasher;
+
+impl Hasher for KeccakHasher {
+    fn hash(data: &[u8]) -> u256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(data);
+        let mut bytes = [0; 32];
+        hasher.finalize_into((&mut bytes).into());
+
+        u256::from_be_bytes(bytes)
+    }
+}
+
+/// BLAKE2b with a 256 bit digest.
+#[cfg(feature = "blake2b")]
+pub struct Blake2bHasher;
+
+#[cfg(feature = "blake2b")]
+impl Hasher for Blake2bHasher {
+    fn hash(data: &[u8]) -> u256 {
+        let mut hasher = Blake2b::<U32>::new();
+        hasher.update(data);
+        let mut bytes = [0; 32];
+        hasher.finalize_into((&mut bytes).into());
+
+        u256::from_be_bytes(bytes)
+    }
+}
+
+/// Decides which inputs are looked up in a [`HashCache`]. The policy is passed with each lookup,
+/// so that users of a shared cache can each have their own policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashCachePolicy {
+    /// Inputs shorter than this are always hashed directly.
+    pub min_cacheable_len: usize,
+    /// If false, all inputs are hashed directly.
+    pub enabled: bool,
+}
+
+impl Default for HashCachePolicy {
+    fn default() -> Self {
+        Self {
+            min_cacheable_len: HashCache::DEFAULT_MIN_CACHEABLE_LEN,
+            enabled: true,
+        }
+    }
+}
+
+impl HashCachePolicy {
+    #[cfg(feature = "hash-cache")]
+    fn use_cache(self, len: usize) -> bool {
+        self.enabled && len >= self.min_cacheable_len
+    }
+}
+
 pub struct HashCache {
+    hash_fn: fn(&[u8]) -> u256,
     #[cfg(feature = "hash-cache")]
     hash_cache_32: HashCache32,
     #[cfg(feature = "hash-cache")]
     hash_cache_64: HashCache64,
+    /// The policy used by [`Self::hash`] and [`Self::hash_many`].
+    policy: HashCachePolicy,
 }
 
 impl Default for HashCache {
@@ -23,47 +89,132 @@ impl Default for HashCache {
 }
 
 impl HashCache {
-    const DEFAULT_CACHE_SIZE: usize = 1024; // value taken from evmzero
+    pub const DEFAULT_CACHE_SIZE: usize = 1024; // value taken from evmzero
+    pub const DEFAULT_MIN_CACHEABLE_LEN: usize = 0;
 
-    #[allow(unused_variables)]
     pub fn new(size: usize) -> Self {
+        Self::new_with_policy(size, Self::DEFAULT_MIN_CACHEABLE_LEN)
+    }
+
+    /// Creates a cache with `size` entries per input length which by default only caches inputs
+    /// of at least `min_cacheable_len` bytes. Only inputs of 32 or 64 bytes are cached at all.
+    #[allow(unused_variables)]
+    pub fn new_with_policy(size: usize, min_cacheable_len: usize) -> Self {
         Self {
+            hash_fn: KeccakHasher::hash,
             #[cfg(feature = "hash-cache")]
             hash_cache_32: HashCache32::new(size),
             #[cfg(feature = "hash-cache")]
             hash_cache_64: HashCache64::new(size),
+            policy: HashCachePolicy {
+                min_cacheable_len,
+                enabled: true,
+            },
         }
     }
 
-    fn sha3(data: &[u8]) -> u256 {
-        let mut hasher = Keccak256::new();
-        hasher.update(data);
-        let mut bytes = [0; 32];
-        hasher.finalize_into((&mut bytes).into());
+    /// The policy used by [`Self::hash`] and [`Self::hash_many`].
+    pub fn policy(&self) -> HashCachePolicy {
+        self.policy
+    }
 
-        u256::from_be_bytes(bytes)
+    /// Uses `H` instead of Keccak-256 to hash inputs. This must be called before any input is
+    /// hashed, otherwise the cache contains hashes of the previous hash function.
+    pub fn with_hasher<H: Hasher>(self) -> Self {
+        self.with_hash_fn(H::hash)
+    }
+
+    /// Like [`Self::with_hasher`], but takes the hash function of an existing cache, see
+    /// [`Self::hash_fn`].
+    pub fn with_hash_fn(mut self, hash_fn: fn(&[u8]) -> u256) -> Self {
+        self.hash_fn = hash_fn;
+        self
+    }
+
+    /// The hash function used by this cache.
+    pub fn hash_fn(&self) -> fn(&[u8]) -> u256 {
+        self.hash_fn
     }
 
-    #[allow(clippy::unused_self)]
     pub fn hash(&self, data: &[u8]) -> u256 {
+        self.hash_with_policy(data, self.policy)
+    }
+
+    /// Like [`Self::hash`], but only looks up `data` in the cache if `policy` allows it.
+    #[allow(unused_variables)]
+    pub fn hash_with_policy(&self, data: &[u8], policy: HashCachePolicy) -> u256 {
         #[cfg(feature = "hash-cache")]
-        if data.len() == 32 {
+        if data.len() == 32 && policy.use_cache(32) {
             // SAFETY:
             // data has length 32 so it is safe to cast it to &[u8; 32].
             let data = unsafe { &*(data.as_ptr() as *const [u8; 32]) };
             self.hash_cache_32
-                .get_or_insert_ref(data, || Self::sha3(data))
-        } else if data.len() == 64 {
+                .get_or_insert_ref(data, || (self.hash_fn)(data))
+        } else if data.len() == 64 && policy.use_cache(64) {
             // SAFETY:
             // data has length 64 so it is safe to cast it to &[u8; 64].
             let data = unsafe { &*(data.as_ptr() as *const [u8; 64]) };
             self.hash_cache_64
-                .get_or_insert_ref(data, || Self::sha3(data))
+                .get_or_insert_ref(data, || (self.hash_fn)(data))
         } else {
-            Self::sha3(data)
+            (self.hash_fn)(data)
         }
         #[cfg(not(feature = "hash-cache"))]
-        Self::sha3(data)
+        (self.hash_fn)(data)
+    }
+
+    /// Hashes all `inputs` with the same semantics as [`Self::hash`], but locks each cache only
+    /// once for the whole batch.
+    pub fn hash_many(&self, inputs: &[&[u8]]) -> Vec<u256> {
+        self.hash_many_with_policy(inputs, self.policy)
+    }
+
+    /// Like [`Self::hash_many`], but only looks up inputs in the cache if `policy` allows it.
+    #[allow(unused_variables)]
+    pub fn hash_many_with_policy(&self, inputs: &[&[u8]], policy: HashCachePolicy) -> Vec<u256> {
+        #[cfg(feature = "hash-cache")]
+        {
+            let use_cache_32 = policy.use_cache(32);
+            let use_cache_64 = policy.use_cache(64);
+            let mut hashes = Vec::with_capacity(inputs.len());
+            let mut batch_32 = Vec::new();
+            let mut batch_64 = Vec::new();
+            for (idx, input) in inputs.iter().enumerate() {
+                if let Ok(data) = <&[u8; 32]>::try_from(*input)
+                    && use_cache_32
+                {
+                    batch_32.push((idx, data));
+                    hashes.push(u256::ZERO);
+                } else if let Ok(data) = <&[u8; 64]>::try_from(*input)
+                    && use_cache_64
+                {
+                    batch_64.push((idx, data));
+                    hashes.push(u256::ZERO);
+                } else {
+                    hashes.push((self.hash_fn)(input));
+                }
+            }
+
+            let cached_32 = self
+                .hash_cache_32
+                .get_or_insert_ref_many(batch_32.iter().map(|(_, data)| *data), |data| {
+                    (self.hash_fn)(data)
+                });
+            for ((idx, _), hash) in batch_32.iter().zip(cached_32) {
+                hashes[*idx] = hash;
+            }
+            let cached_64 = self
+                .hash_cache_64
+                .get_or_insert_ref_many(batch_64.iter().map(|(_, data)| *data), |data| {
+                    (self.hash_fn)(data)
+                });
+            for ((idx, _), hash) in batch_64.iter().zip(cached_64) {
+                hashes[*idx] = hash;
+            }
+            hashes
+        }
+        #[cfg(not(feature = "hash-cache"))]
+        inputs.iter().map(|input| (self.hash_fn)(input)).collect()
     }
 
     #[cfg(test)]
@@ -77,4 +228,113 @@ impl HashCache {
         #[cfg(not(feature = "hash-cache"))]
         0
     }
+
+    /// Returns the combined hit and miss counters of the caches for 32 and 64 byte inputs.
+    #[cfg(feature = "hash-cache")]
+    pub fn stats(&self) -> CacheStats {
+        self.hash_cache_32.stats() + self.hash_cache_64.stats()
+    }
+
+    /// Returns the number of cached hashes.
+    #[cfg(all(test, feature = "hash-cache"))]
+    pub fn entries(&self) -> usize {
+        self.hash_cache_32.entries() + self.hash_cache_64.entries()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::hash_cache::HashCache;
+    #[cfg(feature = "hash-cache")]
+    use crate::types::hash_cache::HashCachePolicy;
+    #[cfg(feature = "blake2b")]
+    use crate::types::hash_cache::{Blake2bHasher, Hasher, KeccakHasher};
+
+    #[cfg(feature = "blake2b")]
+    #[test]
+    fn selected_hasher_is_used_for_all_input_lengths() {
+        let hash_cache = HashCache::default().with_hasher::<Blake2bHasher>();
+        for len in [0, 32, 64, 100] {
+            let input = vec![1; len];
+            assert_eq!(hash_cache.hash(&input), Blake2bHasher::hash(&input));
+            assert_eq!(hash_cache.hash(&input), Blake2bHasher::hash(&input));
+            assert_ne!(hash_cache.hash(&input), KeccakHasher::hash(&input));
+        }
+        assert_eq!(
+            hash_cache.hash_many(&[&[1; 32], &[1; 64]]),
+            [Blake2bHasher::hash(&[1; 32]), Blake2bHasher::hash(&[1; 64])]
+        );
+    }
+
+    #[test]
+    fn hash_many_matches_individual_hashes() {
+        let hash_cache = HashCache::new_with_policy(16, 64);
+        let inputs: [&[u8]; 6] = [&[1; 64], &[], &[2; 32], &[1; 64], &[3; 10], &[4; 64]];
+        let hashes = hash_cache.hash_many(&inputs);
+
+        let expected: Vec<_> = inputs
+            .iter()
+            .map(|input| HashCache::new(16).hash(input))
+            .collect();
+        assert_eq!(hashes, expected);
+        #[cfg(feature = "hash-cache")]
+        {
+            assert_eq!(hash_cache.entries(), 2);
+            assert_eq!(hash_cache.stats().hits, 1);
+        }
+    }
+
+    #[cfg(feature = "hash-cache")]
+    #[test]
+    fn inputs_below_min_cacheable_len_bypass_cache() {
+        let hash_cache = HashCache::new_with_policy(16, 64);
+        hash_cache.hash(&[1; 32]);
+        assert_eq!(hash_cache.entries(), 0);
+        hash_cache.hash(&[1; 64]);
+        assert_eq!(hash_cache.entries(), 1);
+
+        let policy = HashCachePolicy {
+            min_cacheable_len: 32,
+            ..hash_cache.policy()
+        };
+        hash_cache.hash_with_policy(&[1; 32], policy);
+        assert_eq!(hash_cache.entries(), 2);
+    }
+
+    #[cfg(feature = "hash-cache")]
+    #[test]
+    fn disabled_cache_is_bypassed() {
+        let hash_cache = HashCache::default();
+        let disabled = HashCachePolicy {
+            enabled: false,
+            ..HashCachePolicy::default()
+        };
+        let hash = hash_cache.hash_with_policy(&[1; 32], disabled);
+        assert_eq!(
+            hash_cache
+                .hash_many_with_policy(&[&[1; 64]], disabled)
+                .len(),
+            1
+        );
+        assert_eq!(hash_cache.entries(), 0);
+
+        assert_eq!(hash_cache.hash(&[1; 32]), hash);
+        assert_eq!(hash_cache.entries(), 1);
+    }
+
+    #[cfg(feature = "hash-cache")]
+    #[test]
+    fn stats_count_hits_and_misses() {
+        let hash_cache = HashCache::new(16);
+        hash_cache.hash(&[1; 32]);
+        hash_cache.hash(&[1; 32]);
+        hash_cache.hash(&[1; 64]);
+        hash_cache.hash(&[1; 10]);
+
+        let stats = hash_cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.len, 2);
+        assert_eq!(stats.capacity, 32);
+    }
 }
@@ -0,0 +1,1245 @@
+use std::{
+    cell::{Cell, RefCell},
+    ops::{Deref, DerefMut},
+};
+
+use evmc_vm::{
+    AccessStatus, Address, ExecutionMessage, ExecutionResult, ExecutionTxContext, MessageKind,
+    StatusCode, StorageStatus, Uint256,
+};
+
+use crate::{
+    types::ExecutionContextTrait,
+    utils::{ByteReader, UnexpectedEnd},
+};
+
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HostRecordingError {
+    UnsupportedVersion,
+    UnexpectedEnd,
+    TrailingBytes,
+    InvalidValue,
+}
+
+impl From<UnexpectedEnd> for HostRecordingError {
+    fn from(_: UnexpectedEnd) -> Self {
+        Self::UnexpectedEnd
+    }
+}
+
+/// The transaction context returned by the host. The initcodes are not recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedTxContext {
+    /// The recorded context without blob hashes and initcodes.
+    pub tx_context: ExecutionTxContext<'static>,
+    pub blob_hashes: Vec<Uint256>,
+}
+
+impl From<&ExecutionTxContext<'_>> for RecordedTxContext {
+    fn from(tx_context: &ExecutionTxContext) -> Self {
+        Self {
+            tx_context: ExecutionTxContext {
+                tx_gas_price: tx_context.tx_gas_price,
+                tx_origin: tx_context.tx_origin,
+                block_coinbase: tx_context.block_coinbase,
+                block_number: tx_context.block_number,
+                block_timestamp: tx_context.block_timestamp,
+                block_gas_limit: tx_context.block_gas_limit,
+                block_prev_randao: tx_context.block_prev_randao,
+                chain_id: tx_context.chain_id,
+                block_base_fee: tx_context.block_base_fee,
+                blob_base_fee: tx_context.blob_base_fee,
+                blob_hashes: &[],
+                initcodes: &[],
+            },
+            blob_hashes: tx_context.blob_hashes.to_vec(),
+        }
+    }
+}
+
+/// A message passed to the host by a call or create. The code and code hash are not recorded,
+/// because they are never set by the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMessage {
+    pub kind: MessageKind,
+    pub flags: u32,
+    pub depth: i32,
+    pub gas: i64,
+    pub recipient: Address,
+    pub sender: Address,
+    pub input: Vec<u8>,
+    pub value: Uint256,
+    pub create2_salt: Uint256,
+    pub code_address: Address,
+}
+
+impl From<&ExecutionMessage<'_>> for RecordedMessage {
+    fn from(message: &ExecutionMessage) -> Self {
+        Self {
+            kind: message.kind,
+            flags: message.flags,
+            depth: message.depth,
+            gas: message.gas,
+            recipient: message.recipient,
+            sender: message.sender,
+            input: message.input.to_vec(),
+            value: message.value,
+            create2_salt: message.create2_salt,
+            code_address: message.code_address,
+        }
+    }
+}
+
+/// The result of a call or create returned by the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedResult {
+    pub status_code: StatusCode,
+    pub gas_left: i64,
+    pub gas_refund: i64,
+    pub output: Vec<u8>,
+    pub create_address: Option<Address>,
+}
+
+impl From<&ExecutionResult> for RecordedResult {
+    fn from(result: &ExecutionResult) -> Self {
+        Self {
+            status_code: result.status_code,
+            gas_left: result.gas_left,
+            gas_refund: result.gas_refund,
+            output: result.output.to_vec(),
+            create_address: result.create_address,
+        }
+    }
+}
+
+impl From<&RecordedResult> for ExecutionResult {
+    fn from(result: &RecordedResult) -> Self {
+        Self {
+            status_code: result.status_code,
+            gas_left: result.gas_left,
+            gas_refund: result.gas_refund,
+            output: Box::from(result.output.as_slice()),
+            create_address: result.create_address,
+        }
+    }
+}
+
+/// A single host callback with its arguments and its result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostInteraction {
+    GetTxContext(RecordedTxContext),
+    AccountExists {
+        address: Address,
+        exists: bool,
+    },
+    GetStorage {
+        address: Address,
+        key: Uint256,
+        value: Uint256,
+    },
+    SetStorage {
+        address: Address,
+        key: Uint256,
+        value: Uint256,
+        status: StorageStatus,
+    },
+    GetBalance {
+        address: Address,
+        balance: Uint256,
+    },
+    GetCodeSize {
+        address: Address,
+        size: usize,
+    },
+    GetCodeHash {
+        address: Address,
+        hash: Uint256,
+    },
+    CopyCode {
+        address: Address,
+        code_offset: usize,
+        buffer_len: usize,
+        /// The bytes which were copied into the buffer.
+        code: Vec<u8>,
+    },
+    SelfDestruct {
+        address: Address,
+        beneficiary: Address,
+        destructed: bool,
+    },
+    Call {
+        message: RecordedMessage,
+        result: RecordedResult,
+    },
+    GetBlockHash {
+        number: i64,
+        hash: Uint256,
+    },
+    EmitLog {
+        address: Address,
+        data: Vec<u8>,
+        topics: Vec<Uint256>,
+    },
+    AccessAccount {
+        address: Address,
+        status: AccessStatus,
+    },
+    AccessStorage {
+        address: Address,
+        key: Uint256,
+        status: AccessStatus,
+    },
+    GetTransientStorage {
+        address: Address,
+        key: Uint256,
+        value: Uint256,
+    },
+    SetTransientStorage {
+        address: Address,
+        key: Uint256,
+        value: Uint256,
+    },
+}
+
+/// All host callbacks of an execution in the order in which they were issued.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostRecording {
+    pub interactions: Vec<HostInteraction>,
+}
+
+impl HostRecording {
+    /// Serializes the recording into a versioned binary format. All integers are encoded little
+    /// endian, enums by their EVMC value and variable length fields are prefixed with their length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(vec![FORMAT_VERSION]);
+        writer.u64(self.interactions.len() as u64);
+        for interaction in &self.interactions {
+            writer.interaction(interaction);
+        }
+        writer.0
+    }
+
+    /// Deserializes a recording which was serialized with [`HostRecording::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HostRecordingError> {
+        let mut reader = Reader(ByteReader::new(bytes));
+        if reader.u8()? != FORMAT_VERSION {
+            return Err(HostRecordingError::UnsupportedVersion);
+        }
+        let len = reader.len()?;
+        let interactions = (0..len)
+            .map(|_| reader.interaction())
+            .collect::<Result<_, _>>()?;
+        if !reader.is_empty() {
+            return Err(HostRecordingError::TrailingBytes);
+        }
+        Ok(Self { interactions })
+    }
+}
+
+/// Forwards all callbacks to the inner host and records them together with their results.
+pub struct RecordingHost<'a> {
+    inner: &'a mut dyn ExecutionContextTrait,
+    // Some callbacks only take `&self`.
+    interactions: RefCell<Vec<HostInteraction>>,
+}
+
+impl<'a> RecordingHost<'a> {
+    pub fn new(inner: &'a mut dyn ExecutionContextTrait) -> Self {
+        Self {
+            inner,
+            interactions: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn into_recording(self) -> HostRecording {
+        HostRecording {
+            interactions: self.interactions.into_inner(),
+        }
+    }
+
+    fn record(&self, interaction: HostInteraction) {
+        self.interactions.borrow_mut().push(interaction);
+    }
+}
+
+impl ExecutionContextTrait for RecordingHost<'_> {
+    fn get_tx_context(&mut self) -> &ExecutionTxContext<'_> {
+        let tx_context = self.inner.get_tx_context();
+        self.interactions
+            .get_mut()
+            .push(HostInteraction::GetTxContext(tx_context.into()));
+        tx_context
+    }
+
+    fn account_exists(&self, address: &Address) -> bool {
+        let exists = self.inner.account_exists(address);
+        self.record(HostInteraction::AccountExists {
+            address: *address,
+            exists,
+        });
+        exists
+    }
+
+    fn get_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        let value = self.inner.get_storage(address, key);
+        self.record(HostInteraction::GetStorage {
+            address: *address,
+            key: *key,
+            value,
+        });
+        value
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) -> StorageStatus {
+        let status = self.inner.set_storage(address, key, value);
+        self.record(HostInteraction::SetStorage {
+            address: *address,
+            key: *key,
+            value: *value,
+            status,
+        });
+        status
+    }
+
+    fn get_balance(&self, address: &Address) -> Uint256 {
+        let balance = self.inner.get_balance(address);
+        self.record(HostInteraction::GetBalance {
+            address: *address,
+            balance,
+        });
+        balance
+    }
+
+    fn get_code_size(&self, address: &Address) -> usize {
+        let size = self.inner.get_code_size(address);
+        self.record(HostInteraction::GetCodeSize {
+            address: *address,
+            size,
+        });
+        size
+    }
+
+    fn get_code_hash(&self, address: &Address) -> Uint256 {
+        let hash = self.inner.get_code_hash(address);
+        self.record(HostInteraction::GetCodeHash {
+            address: *address,
+            hash,
+        });
+        hash
+    }
+
+    fn copy_code(&self, address: &Address, code_offset: usize, buffer: &mut [u8]) -> usize {
+        let len = self.inner.copy_code(address, code_offset, buffer);
+        self.record(HostInteraction::CopyCode {
+            address: *address,
+            code_offset,
+            buffer_len: buffer.len(),
+            code: buffer[..len].to_vec(),
+        });
+        len
+    }
+
+    fn selfdestruct(&mut self, address: &Address, beneficiary: &Address) -> bool {
+        let destructed = self.inner.selfdestruct(address, beneficiary);
+        self.record(HostInteraction::SelfDestruct {
+            address: *address,
+            beneficiary: *beneficiary,
+            destructed,
+        });
+        destructed
+    }
+
+    fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        let result = self.inner.call(message);
+        self.record(HostInteraction::Call {
+            message: message.into(),
+            result: (&result).into(),
+        });
+        result
+    }
+
+    fn get_block_hash(&self, num: i64) -> Uint256 {
+        let hash = self.inner.get_block_hash(num);
+        self.record(HostInteraction::GetBlockHash { number: num, hash });
+        hash
+    }
+
+    fn emit_log(&mut self, address: &Address, data: &[u8], topics: &[Uint256]) {
+        self.inner.emit_log(address, data, topics);
+        self.record(HostInteraction::EmitLog {
+            address: *address,
+            data: data.to_vec(),
+            topics: topics.to_vec(),
+        });
+    }
+
+    fn access_account(&mut self, address: &Address) -> AccessStatus {
+        let status = self.inner.access_account(address);
+        self.record(HostInteraction::AccessAccount {
+            address: *address,
+            status,
+        });
+        status
+    }
+
+    fn access_storage(&mut self, address: &Address, key: &Uint256) -> AccessStatus {
+        let status = self.inner.access_storage(address, key);
+        self.record(HostInteraction::AccessStorage {
+            address: *address,
+            key: *key,
+            status,
+        });
+        status
+    }
+
+    fn get_transient_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        let value = self.inner.get_transient_storage(address, key);
+        self.record(HostInteraction::GetTransientStorage {
+            address: *address,
+            key: *key,
+            value,
+        });
+        value
+    }
+
+    fn set_transient_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) {
+        self.inner.set_transient_storage(address, key, value);
+        self.record(HostInteraction::SetTransientStorage {
+            address: *address,
+            key: *key,
+            value: *value,
+        });
+    }
+}
+
+/// Replays a [`HostRecording`] without a real host. Each callback returns the recorded result of
+/// the next recorded interaction. Recordings which could not have been produced by a host are
+/// rejected when the playback host is created.
+///
+/// # Panics
+/// All callbacks panic if they do not match the next recorded interaction, including its
+/// arguments, or if all interactions were already replayed.
+pub struct PlaybackHost<'a> {
+    interactions: &'a [HostInteraction],
+    next: Cell<usize>,
+    tx_context: Option<ExecutionTxContext<'a>>,
+}
+
+impl<'a> PlaybackHost<'a> {
+    pub fn new(recording: &'a HostRecording) -> Result<Self, HostRecordingError> {
+        if !recording.interactions.iter().all(is_consistent) {
+            return Err(HostRecordingError::InvalidValue);
+        }
+        Ok(Self {
+            interactions: &recording.interactions,
+            next: Cell::new(0),
+            tx_context: None,
+        })
+    }
+
+    /// Returns true if all recorded interactions were replayed.
+    pub fn is_finished(&self) -> bool {
+        self.next.get() == self.interactions.len()
+    }
+
+    fn next(&self) -> &'a HostInteraction {
+        let next = self.next.get();
+        let Some(interaction) = self.interactions.get(next) else {
+            panic!("execution issued more host calls than recorded");
+        };
+        self.next.set(next + 1);
+        interaction
+    }
+}
+
+/// Returns false if the host can not have returned the recorded result for the recorded arguments.
+fn is_consistent(interaction: &HostInteraction) -> bool {
+    match interaction {
+        HostInteraction::CopyCode {
+            buffer_len, code, ..
+        } => code.len() <= *buffer_len,
+        _ => true,
+    }
+}
+
+fn diverged(callback: &str, expected: &HostInteraction) -> ! {
+    panic!("execution diverged from recording: called {callback} but expected {expected:?}")
+}
+
+impl ExecutionContextTrait for PlaybackHost<'_> {
+    fn get_tx_context(&mut self) -> &ExecutionTxContext<'_> {
+        match self.next() {
+            HostInteraction::GetTxContext(recorded) => self.tx_context.insert(ExecutionTxContext {
+                blob_hashes: &recorded.blob_hashes,
+                ..recorded.tx_context
+            }),
+            other => diverged("get_tx_context", other),
+        }
+    }
+
+    fn account_exists(&self, address: &Address) -> bool {
+        match self.next() {
+            HostInteraction::AccountExists { address: a, exists } if a == address => *exists,
+            other => diverged("account_exists", other),
+        }
+    }
+
+    fn get_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        match self.next() {
+            HostInteraction::GetStorage {
+                address: a,
+                key: k,
+                value,
+            } if a == address && k == key => *value,
+            other => diverged("get_storage", other),
+        }
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) -> StorageStatus {
+        match self.next() {
+            HostInteraction::SetStorage {
+                address: a,
+                key: k,
+                value: v,
+                status,
+            } if a == address && k == key && v == value => *status,
+            other => diverged("set_storage", other),
+        }
+    }
+
+    fn get_balance(&self, address: &Address) -> Uint256 {
+        match self.next() {
+            HostInteraction::GetBalance {
+                address: a,
+                balance,
+            } if a == address => *balance,
+            other => diverged("get_balance", other),
+        }
+    }
+
+    fn get_code_size(&self, address: &Address) -> usize {
+        match self.next() {
+            HostInteraction::GetCodeSize { address: a, size } if a == address => *size,
+            other => diverged("get_code_size", other),
+        }
+    }
+
+    fn get_code_hash(&self, address: &Address) -> Uint256 {
+        match self.next() {
+            HostInteraction::GetCodeHash { address: a, hash } if a == address => *hash,
+            other => diverged("get_code_hash", other),
+        }
+    }
+
+    fn copy_code(&self, address: &Address, code_offset: usize, buffer: &mut [u8]) -> usize {
+        match self.next() {
+            HostInteraction::CopyCode {
+                address: a,
+                code_offset: o,
+                buffer_len,
+                code,
+            } if a == address && *o == code_offset && *buffer_len == buffer.len() => {
+                buffer[..code.len()].copy_from_slice(code);
+                code.len()
+            }
+            other => diverged("copy_code", other),
+        }
+    }
+
+    fn selfdestruct(&mut self, address: &Address, beneficiary: &Address) -> bool {
+        match self.next() {
+            HostInteraction::SelfDestruct {
+                address: a,
+                beneficiary: b,
+                destructed,
+            } if a == address && b == beneficiary => *destructed,
+            other => diverged("selfdestruct", other),
+        }
+    }
+
+    fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        match self.next() {
+            HostInteraction::Call { message: m, result }
+                if *m == RecordedMessage::from(message) =>
+            {
+                result.into()
+            }
+            other => diverged("call", other),
+        }
+    }
+
+    fn get_block_hash(&self, num: i64) -> Uint256 {
+        match self.next() {
+            HostInteraction::GetBlockHash { number, hash } if *number == num => *hash,
+            other => diverged("get_block_hash", other),
+        }
+    }
+
+    fn emit_log(&mut self, address: &Address, data: &[u8], topics: &[Uint256]) {
+        match self.next() {
+            HostInteraction::EmitLog {
+                address: a,
+                data: d,
+                topics: t,
+            } if a == address && d == data && t == topics => {}
+            other => diverged("emit_log", other),
+        }
+    }
+
+    fn access_account(&mut self, address: &Address) -> AccessStatus {
+        match self.next() {
+            HostInteraction::AccessAccount { address: a, status } if a == address => *status,
+            other => diverged("access_account", other),
+        }
+    }
+
+    fn access_storage(&mut self, address: &Address, key: &Uint256) -> AccessStatus {
+        match self.next() {
+            HostInteraction::AccessStorage {
+                address: a,
+                key: k,
+                status,
+            } if a == address && k == key => *status,
+            other => diverged("access_storage", other),
+        }
+    }
+
+    fn get_transient_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        match self.next() {
+            HostInteraction::GetTransientStorage {
+                address: a,
+                key: k,
+                value,
+            } if a == address && k == key => *value,
+            other => diverged("get_transient_storage", other),
+        }
+    }
+
+    fn set_transient_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) {
+        match self.next() {
+            HostInteraction::SetTransientStorage {
+                address: a,
+                key: k,
+                value: v,
+            } if a == address && k == key && v == value => {}
+            other => diverged("set_transient_storage", other),
+        }
+    }
+}
+
+const STATUS_CODES: [StatusCode; 21] = [
+    StatusCode::EVMC_SUCCESS,
+    StatusCode::EVMC_FAILURE,
+    StatusCode::EVMC_REVERT,
+    StatusCode::EVMC_OUT_OF_GAS,
+    StatusCode::EVMC_INVALID_INSTRUCTION,
+    StatusCode::EVMC_UNDEFINED_INSTRUCTION,
+    StatusCode::EVMC_STACK_OVERFLOW,
+    StatusCode::EVMC_STACK_UNDERFLOW,
+    StatusCode::EVMC_BAD_JUMP_DESTINATION,
+    StatusCode::EVMC_INVALID_MEMORY_ACCESS,
+    StatusCode::EVMC_CALL_DEPTH_EXCEEDED,
+    StatusCode::EVMC_STATIC_MODE_VIOLATION,
+    StatusCode::EVMC_PRECOMPILE_FAILURE,
+    StatusCode::EVMC_CONTRACT_VALIDATION_FAILURE,
+    StatusCode::EVMC_ARGUMENT_OUT_OF_RANGE,
+    StatusCode::EVMC_WASM_UNREACHABLE_INSTRUCTION,
+    StatusCode::EVMC_WASM_TRAP,
+    StatusCode::EVMC_INSUFFICIENT_BALANCE,
+    StatusCode::EVMC_INTERNAL_ERROR,
+    StatusCode::EVMC_REJECTED,
+    StatusCode::EVMC_OUT_OF_MEMORY,
+];
+
+const STORAGE_STATUSES: [StorageStatus; 9] = [
+    StorageStatus::EVMC_STORAGE_ASSIGNED,
+    StorageStatus::EVMC_STORAGE_ADDED,
+    StorageStatus::EVMC_STORAGE_DELETED,
+    StorageStatus::EVMC_STORAGE_MODIFIED,
+    StorageStatus::EVMC_STORAGE_DELETED_ADDED,
+    StorageStatus::EVMC_STORAGE_MODIFIED_DELETED,
+    StorageStatus::EVMC_STORAGE_DELETED_RESTORED,
+    StorageStatus::EVMC_STORAGE_ADDED_DELETED,
+    StorageStatus::EVMC_STORAGE_MODIFIED_RESTORED,
+];
+
+const ACCESS_STATUSES: [AccessStatus; 2] = [
+    AccessStatus::EVMC_ACCESS_COLD,
+    AccessStatus::EVMC_ACCESS_WARM,
+];
+
+const MESSAGE_KINDS: [MessageKind; 6] = [
+    MessageKind::EVMC_CALL,
+    MessageKind::EVMC_DELEGATECALL,
+    MessageKind::EVMC_CALLCODE,
+    MessageKind::EVMC_CREATE,
+    MessageKind::EVMC_CREATE2,
+    MessageKind::EVMC_EOFCREATE,
+];
+
+/// Returns the variant whose EVMC value is `value`.
+fn find_variant<T: Copy>(
+    variants: &[T],
+    value: i32,
+    evmc_value: impl Fn(T) -> i32,
+) -> Result<T, HostRecordingError> {
+    variants
+        .iter()
+        .copied()
+        .find(|variant| evmc_value(*variant) == value)
+        .ok_or(HostRecordingError::InvalidValue)
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn address(&mut self, address: &Address) {
+        self.0.extend_from_slice(&address.bytes);
+    }
+
+    fn uint256(&mut self, value: &Uint256) {
+        self.0.extend_from_slice(&value.bytes);
+    }
+
+    fn uint256s(&mut self, values: &[Uint256]) {
+        self.u64(values.len() as u64);
+        for value in values {
+            self.uint256(value);
+        }
+    }
+
+    fn tx_context(&mut self, recorded: &RecordedTxContext) {
+        let tx_context = &recorded.tx_context;
+        self.uint256(&tx_context.tx_gas_price);
+        self.address(&tx_context.tx_origin);
+        self.address(&tx_context.block_coinbase);
+        self.i64(tx_context.block_number);
+        self.i64(tx_context.block_timestamp);
+        self.i64(tx_context.block_gas_limit);
+        self.uint256(&tx_context.block_prev_randao);
+        self.uint256(&tx_context.chain_id);
+        self.uint256(&tx_context.block_base_fee);
+        self.uint256(&tx_context.blob_base_fee);
+        self.uint256s(&recorded.blob_hashes);
+    }
+
+    fn message(&mut self, message: &RecordedMessage) {
+        self.i32(message.kind as i32);
+        self.u64(u64::from(message.flags));
+        self.i32(message.depth);
+        self.i64(message.gas);
+        self.address(&message.recipient);
+        self.address(&message.sender);
+        self.bytes(&message.input);
+        self.uint256(&message.value);
+        self.uint256(&message.create2_salt);
+        self.address(&message.code_address);
+    }
+
+    fn result(&mut self, result: &RecordedResult) {
+        self.i32(result.status_code as i32);
+        self.i64(result.gas_left);
+        self.i64(result.gas_refund);
+        self.bytes(&result.output);
+        match &result.create_address {
+            Some(address) => {
+                self.u8(1);
+                self.address(address);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn interaction(&mut self, interaction: &HostInteraction) {
+        match interaction {
+            HostInteraction::GetTxContext(recorded) => {
+                self.u8(0);
+                self.tx_context(recorded);
+            }
+            HostInteraction::AccountExists { address, exists } => {
+                self.u8(1);
+                self.address(address);
+                self.u8(*exists as u8);
+            }
+            HostInteraction::GetStorage {
+                address,
+                key,
+                value,
+            } => {
+                self.u8(2);
+                self.address(address);
+                self.uint256(key);
+                self.uint256(value);
+            }
+            HostInteraction::SetStorage {
+                address,
+                key,
+                value,
+                status,
+            } => {
+                self.u8(3);
+                self.address(address);
+                self.uint256(key);
+                self.uint256(value);
+                self.i32(*status as i32);
+            }
+            HostInteraction::GetBalance { address, balance } => {
+                self.u8(4);
+                self.address(address);
+                self.uint256(balance);
+            }
+            HostInteraction::GetCodeSize { address, size } => {
+                self.u8(5);
+                self.address(address);
+                self.u64(*size as u64);
+            }
+            HostInteraction::GetCodeHash { address, hash } => {
+                self.u8(6);
+                self.address(address);
+                self.uint256(hash);
+            }
+            HostInteraction::CopyCode {
+                address,
+                code_offset,
+                buffer_len,
+                code,
+            } => {
+                self.u8(7);
+                self.address(address);
+                self.u64(*code_offset as u64);
+                self.u64(*buffer_len as u64);
+                self.bytes(code);
+            }
+            HostInteraction::SelfDestruct {
+                address,
+                beneficiary,
+                destructed,
+            } => {
+                self.u8(8);
+                self.address(address);
+                self.address(beneficiary);
+                self.u8(*destructed as u8);
+            }
+            HostInteraction::Call { message, result } => {
+                self.u8(9);
+                self.message(message);
+                self.result(result);
+            }
+            HostInteraction::GetBlockHash { number, hash } => {
+                self.u8(10);
+                self.i64(*number);
+                self.uint256(hash);
+            }
+            HostInteraction::EmitLog {
+                address,
+                data,
+                topics,
+            } => {
+                self.u8(11);
+                self.address(address);
+                self.bytes(data);
+                self.uint256s(topics);
+            }
+            HostInteraction::AccessAccount { address, status } => {
+                self.u8(12);
+                self.address(address);
+                self.i32(*status as i32);
+            }
+            HostInteraction::AccessStorage {
+                address,
+                key,
+                status,
+            } => {
+                self.u8(13);
+                self.address(address);
+                self.uint256(key);
+                self.i32(*status as i32);
+            }
+            HostInteraction::GetTransientStorage {
+                address,
+                key,
+                value,
+            } => {
+                self.u8(14);
+                self.address(address);
+                self.uint256(key);
+                self.uint256(value);
+            }
+            HostInteraction::SetTransientStorage {
+                address,
+                key,
+                value,
+            } => {
+                self.u8(15);
+                self.address(address);
+                self.uint256(key);
+                self.uint256(value);
+            }
+        }
+    }
+}
+
+/// Reads the recording specific values on top of the primitives of [`ByteReader`].
+struct Reader<'a>(ByteReader<'a>);
+
+impl<'a> Deref for Reader<'a> {
+    type Target = ByteReader<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Reader<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Reader<'_> {
+    fn bool(&mut self) -> Result<bool, HostRecordingError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(HostRecordingError::InvalidValue),
+        }
+    }
+
+    fn address(&mut self) -> Result<Address, HostRecordingError> {
+        Ok(Address {
+            bytes: self.array()?,
+        })
+    }
+
+    fn uint256(&mut self) -> Result<Uint256, HostRecordingError> {
+        Ok(Uint256 {
+            bytes: self.array()?,
+        })
+    }
+
+    fn uint256s(&mut self) -> Result<Vec<Uint256>, HostRecordingError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.uint256()).collect()
+    }
+
+    fn storage_status(&mut self) -> Result<StorageStatus, HostRecordingError> {
+        find_variant(&STORAGE_STATUSES, self.i32()?, |status| status as i32)
+    }
+
+    fn access_status(&mut self) -> Result<AccessStatus, HostRecordingError> {
+        find_variant(&ACCESS_STATUSES, self.i32()?, |status| status as i32)
+    }
+
+    fn tx_context(&mut self) -> Result<RecordedTxContext, HostRecordingError> {
+        Ok(RecordedTxContext {
+            tx_context: ExecutionTxContext {
+                tx_gas_price: self.uint256()?,
+                tx_origin: self.address()?,
+                block_coinbase: self.address()?,
+                block_number: self.i64()?,
+                block_timestamp: self.i64()?,
+                block_gas_limit: self.i64()?,
+                block_prev_randao: self.uint256()?,
+                chain_id: self.uint256()?,
+                block_base_fee: self.uint256()?,
+                blob_base_fee: self.uint256()?,
+                blob_hashes: &[],
+                initcodes: &[],
+            },
+            blob_hashes: self.uint256s()?,
+        })
+    }
+
+    fn message(&mut self) -> Result<RecordedMessage, HostRecordingError> {
+        Ok(RecordedMessage {
+            kind: find_variant(&MESSAGE_KINDS, self.i32()?, |kind| kind as i32)?,
+            flags: u32::try_from(self.u64()?).map_err(|_| HostRecordingError::InvalidValue)?,
+            depth: self.i32()?,
+            gas: self.i64()?,
+            recipient: self.address()?,
+            sender: self.address()?,
+            input: self.bytes()?,
+            value: self.uint256()?,
+            create2_salt: self.uint256()?,
+            code_address: self.address()?,
+        })
+    }
+
+    fn result(&mut self) -> Result<RecordedResult, HostRecordingError> {
+        Ok(RecordedResult {
+            status_code: find_variant(&STATUS_CODES, self.i32()?, |status| status as i32)?,
+            gas_left: self.i64()?,
+            gas_refund: self.i64()?,
+            output: self.bytes()?,
+            create_address: if self.bool()? {
+                Some(self.address()?)
+            } else {
+                None
+            },
+        })
+    }
+
+    fn interaction(&mut self) -> Result<HostInteraction, HostRecordingError> {
+        let interaction = match self.u8()? {
+            0 => HostInteraction::GetTxContext(self.tx_context()?),
+            1 => HostInteraction::AccountExists {
+                address: self.address()?,
+                exists: self.bool()?,
+            },
+            2 => HostInteraction::GetStorage {
+                address: self.address()?,
+                key: self.uint256()?,
+                value: self.uint256()?,
+            },
+            3 => HostInteraction::SetStorage {
+                address: self.address()?,
+                key: self.uint256()?,
+                value: self.uint256()?,
+                status: self.storage_status()?,
+            },
+            4 => HostInteraction::GetBalance {
+                address: self.address()?,
+                balance: self.uint256()?,
+            },
+            5 => HostInteraction::GetCodeSize {
+                address: self.address()?,
+                size: self.len()?,
+            },
+            6 => HostInteraction::GetCodeHash {
+                address: self.address()?,
+                hash: self.uint256()?,
+            },
+            7 => HostInteraction::CopyCode {
+                address: self.address()?,
+                code_offset: self.len()?,
+                buffer_len: self.len()?,
+                code: self.bytes()?,
+            },
+            8 => HostInteraction::SelfDestruct {
+                address: self.address()?,
+                beneficiary: self.address()?,
+                destructed: self.bool()?,
+            },
+            9 => HostInteraction::Call {
+                message: self.message()?,
+                result: self.result()?,
+            },
+            10 => HostInteraction::GetBlockHash {
+                number: self.i64()?,
+                hash: self.uint256()?,
+            },
+            11 => HostInteraction::EmitLog {
+                address: self.address()?,
+                data: self.bytes()?,
+                topics: self.uint256s()?,
+            },
+            12 => HostInteraction::AccessAccount {
+                address: self.address()?,
+                status: self.access_status()?,
+            },
+            13 => HostInteraction::AccessStorage {
+                address: self.address()?,
+                key: self.uint256()?,
+                status: self.access_status()?,
+            },
+            14 => HostInteraction::GetTransientStorage {
+                address: self.address()?,
+                key: self.uint256()?,
+                value: self.uint256()?,
+            },
+            15 => HostInteraction::SetTransientStorage {
+                address: self.address()?,
+                key: self.uint256()?,
+                value: self.uint256()?,
+            },
+            _ => return Err(HostRecordingError::InvalidValue),
+        };
+        Ok(interaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::{Address, ExecutionResult, Revision, StatusCode};
+
+    use crate::{
+        interpreter::Interpreter,
+        types::{
+            CodeAnalysisCache, ExecutionContextTrait, HostInteraction, HostRecording,
+            HostRecordingError, MockExecutionMessage, NoOpObserver, Opcode, PlaybackHost,
+            RecordingHost, ReferenceHost, hash_cache::HashCache, u256,
+        },
+    };
+
+    fn run(context: &mut dyn ExecutionContextTrait, code: &[u8]) -> ExecutionResult {
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            recipient: u256::from(0xbbu8).into(),
+            ..Default::default()
+        };
+        Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message.into(),
+            context,
+            code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut NoOpObserver())
+    }
+
+    #[test]
+    fn replaying_recording_yields_identical_result() {
+        // SSTORE(0, 42), MSTORE(0, SLOAD(0)), call 0xaa with the word in memory as input and
+        // return the output of the call
+        let code = [
+            Opcode::Push1 as u8,
+            42,
+            Opcode::Push0 as u8,
+            Opcode::SStore as u8,
+            Opcode::Push0 as u8,
+            Opcode::SLoad as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,                  // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push1 as u8,
+            32,                  // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push0 as u8, // value
+            Opcode::Push1 as u8,
+            0xaa, // address
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+            Opcode::Pop as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.call_router_mut()
+            .route(Address::from(u256::from(0xaau8)), |message| {
+                let mut output = message.input.to_vec();
+                output[31] += 1;
+                ExecutionResult {
+                    status_code: StatusCode::EVMC_SUCCESS,
+                    gas_left: message.gas - 100,
+                    gas_refund: 0,
+                    output: Box::from(output),
+                    create_address: None,
+                }
+            });
+        let mut recording_host = RecordingHost::new(&mut host);
+        let recorded = run(&mut recording_host, &code);
+        assert_eq!(recorded.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(recorded.output[31], 43);
+
+        let recording = recording_host.into_recording();
+        assert!(
+            recording
+                .interactions
+                .iter()
+                .any(|interaction| matches!(interaction, HostInteraction::Call { .. }))
+        );
+        let recording = HostRecording::from_bytes(&recording.to_bytes()).unwrap();
+
+        let mut playback_host = PlaybackHost::new(&recording).unwrap();
+        let replayed = run(&mut playback_host, &code);
+        assert!(playback_host.is_finished());
+        assert_eq!(replayed.status_code, recorded.status_code);
+        assert_eq!(replayed.gas_left, recorded.gas_left);
+        assert_eq!(replayed.gas_refund, recorded.gas_refund);
+        assert_eq!(replayed.output, recorded.output);
+    }
+
+    #[test]
+    #[should_panic(expected = "execution diverged from recording")]
+    fn replaying_different_execution_panics() {
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut recording_host = RecordingHost::new(&mut host);
+        run(
+            &mut recording_host,
+            &[Opcode::Push0 as u8, Opcode::Balance as u8],
+        );
+        let recording = recording_host.into_recording();
+
+        let mut playback_host = PlaybackHost::new(&recording).unwrap();
+        run(
+            &mut playback_host,
+            &[Opcode::Push0 as u8, Opcode::SLoad as u8],
+        );
+    }
+
+    #[test]
+    fn from_bytes_rejects_invalid_input() {
+        let bytes = HostRecording {
+            interactions: vec![HostInteraction::GetBlockHash {
+                number: 1,
+                hash: u256::ONE.into(),
+            }],
+        }
+        .to_bytes();
+
+        let mut invalid_version = bytes.clone();
+        invalid_version[0] = u8::MAX;
+        assert_eq!(
+            HostRecording::from_bytes(&invalid_version),
+            Err(HostRecordingError::UnsupportedVersion)
+        );
+        assert_eq!(
+            HostRecording::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(HostRecordingError::UnexpectedEnd)
+        );
+        let mut trailing_bytes = bytes;
+        trailing_bytes.push(0);
+        assert_eq!(
+            HostRecording::from_bytes(&trailing_bytes),
+            Err(HostRecordingError::TrailingBytes)
+        );
+    }
+
+    #[test]
+    fn playback_host_rejects_inconsistent_recording() {
+        // more code was copied than fits into the buffer
+        let recording = HostRecording {
+            interactions: vec![HostInteraction::CopyCode {
+                address: u256::ONE.into(),
+                code_offset: 0,
+                buffer_len: 1,
+                code: vec![1, 2],
+            }],
+        };
+        let recording = HostRecording::from_bytes(&recording.to_bytes()).unwrap();
+        assert_eq!(
+            PlaybackHost::new(&recording).err(),
+            Some(HostRecordingError::InvalidValue)
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::mem;
+
+use evmc_vm::{ExecutionMessage, Revision, StepResult, StepStatusCode};
+
+use crate::{
+    interpreter::Interpreter,
+    types::{
+        CodeAnalysisCache, ExecutionContextTrait, Memory, NoOpObserver, Stack,
+        hash_cache::HashCache, u256,
+    },
+};
+
+/// Information about a single executed instruction, yielded by [`InstructionStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    /// The pc of the executed instruction.
+    pub pc: u64,
+    /// The executed opcode.
+    pub opcode: u8,
+    /// The gas left after the instruction was executed.
+    pub gas_left: i64,
+    /// The top of the stack after the instruction was executed.
+    pub stack_top: Option<u256>,
+}
+
+/// Drives the steppable interpreter one instruction at a time. Each call to `next` executes exactly
+/// one instruction. The stream ends when the execution stopped, returned, reverted or failed. The
+/// final status can be queried with [`InstructionStream::status`].
+///
+/// ```
+/// use evmrs::{
+///     InstructionStream, MockExecutionContextTrait, MockExecutionMessage, Opcode,
+///     evmc_vm::Revision,
+/// };
+///
+/// let code = [
+///     Opcode::Push1 as u8,
+///     1,
+///     Opcode::Push1 as u8,
+///     2,
+///     Opcode::Add as u8,
+///     Opcode::Stop as u8,
+/// ];
+/// let message = MockExecutionMessage::default().into();
+/// let mut context = MockExecutionContextTrait::new();
+///
+/// let opcodes: Vec<_> =
+///     InstructionStream::new(Revision::EVMC_CANCUN, &message, &mut context, &code)
+///         .map(|step| step.opcode)
+///         .collect();
+/// assert_eq!(
+///     opcodes,
+///     [
+///         Opcode::Push1 as u8,
+///         Opcode::Push1 as u8,
+///         Opcode::Add as u8,
+///         Opcode::Stop as u8,
+///     ]
+/// );
+/// ```
+pub struct InstructionStream<'a> {
+    revision: Revision,
+    message: &'a ExecutionMessage<'a>,
+    context: &'a mut dyn ExecutionContextTrait,
+    code: &'a [u8],
+    code_analysis_cache: CodeAnalysisCache<true>,
+    hash_cache: HashCache,
+    status: StepStatusCode,
+    pc: u64,
+    gas_left: i64,
+    gas_refund: i64,
+    stack: Vec<u256>,
+    memory: Vec<u8>,
+    last_call_return_data: Box<[u8]>,
+    output: Box<[u8]>,
+}
+
+impl<'a> InstructionStream<'a> {
+    pub fn new(
+        revision: Revision,
+        message: &'a ExecutionMessage<'a>,
+        context: &'a mut dyn ExecutionContextTrait,
+        code: &'a [u8],
+    ) -> Self {
+        Self {
+            revision,
+            message,
+            context,
+            code,
+            code_analysis_cache: CodeAnalysisCache::default(),
+            hash_cache: HashCache::default(),
+            status: StepStatusCode::EVMC_STEP_RUNNING,
+            pc: 0,
+            gas_left: message.gas,
+            gas_refund: 0,
+            stack: Vec::new(),
+            memory: Vec::new(),
+            last_call_return_data: Box::default(),
+            output: Box::default(),
+        }
+    }
+
+    pub fn status(&self) -> StepStatusCode {
+        self.status
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl Iterator for InstructionStream<'_> {
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.status != StepStatusCode::EVMC_STEP_RUNNING {
+            return None;
+        }
+        let pc = self.pc;
+        let Some(&opcode) = self.code.get(pc as usize) else {
+            self.status = StepStatusCode::EVMC_STEP_STOPPED;
+            return None;
+        };
+
+        let message = ExecutionMessage {
+            gas: self.gas_left,
+            ..*self.message
+        };
+        let result: StepResult = match Interpreter::new_steppable(
+            self.revision,
+            &message,
+            &mut *self.context,
+            self.code,
+            pc as usize,
+            self.gas_refund,
+            Stack::new(&self.stack),
+            Memory::new(&self.memory),
+            mem::take(&mut self.last_call_return_data),
+            Some(1),
+            &self.code_analysis_cache,
+            &self.hash_cache,
+        ) {
+            Ok(interpreter) => interpreter.run(&mut NoOpObserver()),
+            Err(err) => err.into(),
+        };
+
+        self.status = result.step_status_code;
+        self.pc = result.pc;
+        self.gas_left = result.gas_left;
+        self.gas_refund = result.gas_refund;
+        self.stack = result.stack.into_iter().map(u256::from).collect();
+        self.memory = result.memory;
+        self.last_call_return_data = result.last_call_return_data;
+        self.output = result.output;
+
+        Some(StepInfo {
+            pc,
+            opcode,
+            gas_left: self.gas_left,
+            stack_top: self.stack.last().copied(),
+        })
+    }
+}
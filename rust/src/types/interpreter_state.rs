@@ -0,0 +1,359 @@
+use evmc_vm::{ExecutionMessage, Revision, StepResult, Uint256};
+
+use crate::{
+    interpreter::Interpreter,
+    types::{
+        CodeAnalysisCache, ExecutionContextTrait, Memory, NoOpObserver, Stack,
+        hash_cache::HashCache, u256,
+    },
+    utils::{ByteReader, UnexpectedEnd},
+};
+
+const FORMAT_VERSION: u8 = 1;
+/// The maximum depth of a frame, see `evmc_message::depth`.
+const MAX_DEPTH: i32 = 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InterpreterStateError {
+    UnsupportedVersion,
+    UnexpectedEnd,
+    TrailingBytes,
+    /// The stack is longer than [`Stack::CAPACITY`] or the depth is outside of `0..=1024`.
+    InvalidValue,
+}
+
+impl From<UnexpectedEnd> for InterpreterStateError {
+    fn from(_: UnexpectedEnd) -> Self {
+        Self::UnexpectedEnd
+    }
+}
+
+/// The complete state of an interpreter frame, which can be serialized to resume the execution
+/// later or in another process.
+///
+/// The pc is always stored as an offset into the original code, independent of the features the
+/// interpreter was compiled with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterpreterState {
+    pub pc: u64,
+    pub gas_left: i64,
+    pub gas_refund: i64,
+    pub depth: i32,
+    /// The stack, with the top of the stack as last element.
+    pub stack: Vec<u256>,
+    pub memory: Vec<u8>,
+    pub last_call_return_data: Vec<u8>,
+}
+
+impl InterpreterState {
+    /// Creates the state of a frame at the given depth from the result of a step execution.
+    pub fn from_step_result(result: &StepResult, depth: i32) -> Self {
+        Self {
+            pc: result.pc,
+            gas_left: result.gas_left,
+            gas_refund: result.gas_refund,
+            depth,
+            stack: result.stack.iter().copied().map(u256::from).collect(),
+            memory: result.memory.clone(),
+            last_call_return_data: result.last_call_return_data.to_vec(),
+        }
+    }
+
+    /// Serializes the state into a versioned binary format. All integers are encoded little
+    /// endian, stack elements big endian and variable length fields are prefixed with their length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // version, pc, gas left, gas refund, depth and the three lengths
+        const FIXED_LEN: usize = 1 + 8 + 8 + 8 + 4 + 3 * 8;
+        let mut bytes = Vec::with_capacity(
+            FIXED_LEN
+                + self.stack.len() * 32
+                + self.memory.len()
+                + self.last_call_return_data.len(),
+        );
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.pc.to_le_bytes());
+        bytes.extend_from_slice(&self.gas_left.to_le_bytes());
+        bytes.extend_from_slice(&self.gas_refund.to_le_bytes());
+        bytes.extend_from_slice(&self.depth.to_le_bytes());
+        bytes.extend_from_slice(&(self.stack.len() as u64).to_le_bytes());
+        for value in &self.stack {
+            bytes.extend_from_slice(&Uint256::from(*value).bytes);
+        }
+        bytes.extend_from_slice(&(self.memory.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&(self.last_call_return_data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.last_call_return_data);
+        bytes
+    }
+
+    /// Deserializes a state which was serialized with [`InterpreterState::to_bytes`]. States which
+    /// can not occur in an execution are rejected with [`InterpreterStateError::InvalidValue`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InterpreterStateError> {
+        let mut reader = ByteReader::new(bytes);
+        if reader.u8()? != FORMAT_VERSION {
+            return Err(InterpreterStateError::UnsupportedVersion);
+        }
+        let pc = reader.u64()?;
+        let gas_left = reader.i64()?;
+        let gas_refund = reader.i64()?;
+        let depth = reader.i32()?;
+        if !(0..=MAX_DEPTH).contains(&depth) {
+            return Err(InterpreterStateError::InvalidValue);
+        }
+        let stack_len = reader.len()?;
+        if stack_len > Stack::CAPACITY {
+            return Err(InterpreterStateError::InvalidValue);
+        }
+        let stack = (0..stack_len)
+            .map(|_| reader.array().map(|bytes| u256::from(Uint256 { bytes })))
+            .collect::<Result<_, _>>()?;
+        let memory = reader.bytes()?;
+        let last_call_return_data = reader.bytes()?;
+        if !reader.is_empty() {
+            return Err(InterpreterStateError::TrailingBytes);
+        }
+
+        Ok(Self {
+            pc,
+            gas_left,
+            gas_refund,
+            depth,
+            stack,
+            memory,
+            last_call_return_data,
+        })
+    }
+
+    /// Resumes the execution of `code` from this state. The gas and depth of `message` are replaced
+    /// by the ones stored in the state. If `steps` is `None` the execution runs until completion.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resume(
+        &self,
+        revision: Revision,
+        message: &ExecutionMessage,
+        context: &mut dyn ExecutionContextTrait,
+        code: &[u8],
+        steps: Option<i32>,
+        code_analysis_cache: &CodeAnalysisCache<true>,
+        hash_cache: &HashCache,
+    ) -> StepResult {
+        let message = ExecutionMessage {
+            gas: self.gas_left,
+            depth: self.depth,
+            ..*message
+        };
+        match Interpreter::new_steppable(
+            revision,
+            &message,
+            context,
+            code,
+            self.pc as usize,
+            self.gas_refund,
+            Stack::new(&self.stack),
+            Memory::new(&self.memory),
+            Box::from(self.last_call_return_data.as_slice()),
+            steps,
+            code_analysis_cache,
+            hash_cache,
+        ) {
+            Ok(interpreter) => interpreter.run(&mut NoOpObserver()),
+            Err(err) => err.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::{Revision, StepResult, StepStatusCode};
+
+    use crate::{
+        interpreter::Interpreter,
+        types::{
+            CodeAnalysisCache, InterpreterState, InterpreterStateError, Memory,
+            MockExecutionContextTrait, MockExecutionMessage, NoOpObserver, Opcode, Stack,
+            hash_cache::HashCache, u256,
+        },
+    };
+
+    #[test]
+    fn to_bytes_from_bytes_round_trip() {
+        let state = InterpreterState {
+            pc: 3,
+            gas_left: 1_000,
+            gas_refund: -5,
+            depth: 2,
+            stack: vec![u256::ONE, u256::MAX],
+            memory: vec![1, 2, 3],
+            last_call_return_data: vec![4, 5],
+        };
+        assert_eq!(InterpreterState::from_bytes(&state.to_bytes()), Ok(state));
+    }
+
+    #[test]
+    fn from_bytes_rejects_invalid_input() {
+        let bytes = InterpreterState {
+            pc: 0,
+            gas_left: 0,
+            gas_refund: 0,
+            depth: 0,
+            stack: vec![u256::ONE],
+            memory: Vec::new(),
+            last_call_return_data: Vec::new(),
+        }
+        .to_bytes();
+
+        let mut invalid_version = bytes.clone();
+        invalid_version[0] = u8::MAX;
+        assert_eq!(
+            InterpreterState::from_bytes(&invalid_version),
+            Err(InterpreterStateError::UnsupportedVersion)
+        );
+        assert_eq!(
+            InterpreterState::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(InterpreterStateError::UnexpectedEnd)
+        );
+        let mut trailing_bytes = bytes;
+        trailing_bytes.push(0);
+        assert_eq!(
+            InterpreterState::from_bytes(&trailing_bytes),
+            Err(InterpreterStateError::TrailingBytes)
+        );
+    }
+
+    #[test]
+    fn from_bytes_rejects_states_which_can_not_occur() {
+        let state = InterpreterState {
+            pc: 0,
+            gas_left: 0,
+            gas_refund: 0,
+            depth: 1024,
+            stack: vec![u256::ZERO; Stack::CAPACITY],
+            memory: Vec::new(),
+            last_call_return_data: Vec::new(),
+        };
+        assert_eq!(
+            InterpreterState::from_bytes(&state.to_bytes()),
+            Ok(state.clone())
+        );
+
+        for depth in [-1, 1025] {
+            let state = InterpreterState {
+                depth,
+                ..state.clone()
+            };
+            assert_eq!(
+                InterpreterState::from_bytes(&state.to_bytes()),
+                Err(InterpreterStateError::InvalidValue)
+            );
+        }
+
+        let mut stack = state.stack.clone();
+        stack.push(u256::ZERO);
+        let state = InterpreterState { stack, ..state };
+        assert_eq!(
+            InterpreterState::from_bytes(&state.to_bytes()),
+            Err(InterpreterStateError::InvalidValue)
+        );
+
+        // a huge stack length is rejected before anything is allocated for it
+        let mut bytes = InterpreterState {
+            stack: Vec::new(),
+            ..state
+        }
+        .to_bytes();
+        bytes[29..37].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            InterpreterState::from_bytes(&bytes),
+            Err(InterpreterStateError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn resume_from_checkpoint_matches_uninterrupted_run() {
+        // MSTORE(0, 1 + 2), then return MLOAD(0) * 2 from offset 32
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Add as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push0 as u8,
+            Opcode::MLoad as u8,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Mul as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Return as u8,
+        ];
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            gas: 1_000,
+            depth: 1,
+            ..Default::default()
+        };
+        let message = message.into();
+
+        let run = |steps| -> StepResult {
+            let mut context = MockExecutionContextTrait::new();
+            Interpreter::new_steppable(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut context,
+                &code,
+                0,
+                0,
+                Stack::new(&[]),
+                Memory::new(&[]),
+                Box::default(),
+                steps,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut NoOpObserver())
+        };
+
+        let uninterrupted = run(None);
+        assert_eq!(
+            uninterrupted.step_status_code,
+            StepStatusCode::EVMC_STEP_RETURNED
+        );
+
+        // stop after MLOAD, so that both stack and memory are non-empty
+        let checkpoint = run(Some(7));
+        assert_eq!(
+            checkpoint.step_status_code,
+            StepStatusCode::EVMC_STEP_RUNNING
+        );
+        let bytes = InterpreterState::from_step_result(&checkpoint, 1).to_bytes();
+        let state = InterpreterState::from_bytes(&bytes).unwrap();
+        assert_eq!(state.stack, [u256::from(3u8)]);
+
+        let mut context = MockExecutionContextTrait::new();
+        let resumed = state.resume(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            None,
+            &code_analysis_cache,
+            &hash_cache,
+        );
+        assert_eq!(resumed.step_status_code, uninterrupted.step_status_code);
+        assert_eq!(resumed.status_code, uninterrupted.status_code);
+        assert_eq!(resumed.pc, uninterrupted.pc);
+        assert_eq!(resumed.gas_left, uninterrupted.gas_left);
+        assert_eq!(resumed.gas_refund, uninterrupted.gas_refund);
+        assert_eq!(resumed.output, uninterrupted.output);
+        assert_eq!(resumed.memory, uninterrupted.memory);
+    }
+}
@@ -1,6 +1,14 @@
 #[cfg(feature = "alloc-reuse")]
 use std::sync::Mutex;
-use std::{cmp::max, iter};
+use std::{
+    cell::RefCell,
+    iter,
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use crate::{
     types::{FailStatus, u256},
@@ -10,19 +18,133 @@ use crate::{
 #[cfg(feature = "alloc-reuse")]
 static REUSABLE_MEMORY: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
 
+thread_local! {
+    /// The budget of the outermost frame which is currently executed on this thread, see
+    /// [`MemoryBudget::enter`].
+    static CURRENT_BUDGET: RefCell<Option<Arc<MemoryBudget>>> = const { RefCell::new(None) };
+}
+
+/// A limit for the total length of the memories of all frames of a call tree. Expanding a memory
+/// beyond the limit fails with out of gas. The length of a memory is released when it is dropped.
 #[derive(Debug)]
-pub struct Memory(Vec<u8>);
+pub struct MemoryBudget {
+    used: AtomicU64,
+    limit: u64,
+}
+
+/// Keeps the budget of an outermost frame available to its nested frames, see
+/// [`MemoryBudget::enter`].
+pub struct MemoryBudgetScope {
+    budget: Option<Arc<MemoryBudget>>,
+    /// The budget to restore when the scope ends, if this scope replaced it.
+    previous: Option<Option<Arc<MemoryBudget>>>,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> Arc<Self> {
+        Arc::new(Self {
+            used: AtomicU64::new(0),
+            limit,
+        })
+    }
+
+    /// Returns the budget of a frame at `depth`. The outermost frame creates a new budget with
+    /// `limit`, which stays available to nested frames until the returned scope is dropped.
+    /// Nested calls are executed by the host on the same thread as their caller, so nested frames
+    /// use the budget of the outermost frame instead. If `limit` is `u64::MAX`, there is no
+    /// budget and the thread state is not touched at all.
+    pub fn enter(limit: u64, depth: i32) -> MemoryBudgetScope {
+        if limit == u64::MAX {
+            return MemoryBudgetScope {
+                budget: None,
+                previous: None,
+            };
+        }
+        if depth > 0 {
+            return MemoryBudgetScope {
+                budget: Self::current(),
+                previous: None,
+            };
+        }
+        let budget = Self::new(limit);
+        let previous = CURRENT_BUDGET.replace(Some(budget.clone()));
+        MemoryBudgetScope {
+            budget: Some(budget),
+            previous: Some(previous),
+        }
+    }
+
+    /// Returns the budget of the outermost frame which is currently executed on this thread.
+    pub fn current() -> Option<Arc<Self>> {
+        CURRENT_BUDGET.with_borrow(Option::clone)
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn reserve(&self, len: u64) -> Result<(), FailStatus> {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(len).filter(|used| *used <= self.limit)
+            })
+            .map(|_| ())
+            .map_err(|_| FailStatus::OutOfGas)
+    }
+
+    fn release(&self, len: u64) {
+        self.used.fetch_sub(len, Ordering::Relaxed);
+    }
+}
+
+impl MemoryBudgetScope {
+    pub fn budget(&self) -> Option<Arc<MemoryBudget>> {
+        self.budget.clone()
+    }
+}
+
+impl Drop for MemoryBudgetScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CURRENT_BUDGET.set(previous);
+        }
+    }
+}
+
+/// Returns the total cost of a memory of `size` bytes. The cost of an expansion is the difference
+/// of the costs of the new and the current size.
+pub fn memory_cost(size: u64) -> Result<u64, FailStatus> {
+    let word_size = word_size(size)?;
+    let (pow2, pow2_overflow) = word_size.overflowing_pow(2);
+    let (word_size_3, word_size_3_overflow) = word_size.overflowing_mul(3);
+    let (cost, cost_overflow) = (pow2 / 512).overflowing_add(word_size_3);
+    if pow2_overflow || word_size_3_overflow || cost_overflow {
+        return Err(FailStatus::OutOfGas);
+    };
+    Ok(cost)
+}
+
+#[derive(Debug)]
+pub struct Memory(Vec<u8>, Option<Arc<MemoryBudget>>);
 
-#[cfg(feature = "alloc-reuse")]
 impl Drop for Memory {
     fn drop(&mut self) {
-        let mut memory = Vec::new();
-        std::mem::swap(&mut memory, &mut self.0);
-        REUSABLE_MEMORY.lock().unwrap().push(memory);
+        if let Some(budget) = &self.1 {
+            budget.release(self.0.len() as u64);
+        }
+        #[cfg(feature = "alloc-reuse")]
+        {
+            let mut memory = Vec::new();
+            std::mem::swap(&mut memory, &mut self.0);
+            REUSABLE_MEMORY.lock().unwrap().push(memory);
+        }
     }
 }
 
 impl Memory {
+    /// Creates a memory with the contents of `memory`. The backing allocation may be reused from
+    /// a dropped memory and contain stale bytes beyond the length, but those are never observable:
+    /// every expansion writes zeros to all bytes between the old and the new length.
     pub fn new(memory: &[u8]) -> Self {
         #[cfg(not(feature = "alloc-reuse"))]
         let mut m = Vec::new();
@@ -31,7 +153,17 @@ impl Memory {
         m.clear();
 
         m.extend_from_slice(memory);
-        Self(m)
+        Self(m, None)
+    }
+
+    /// Charges the current and all future lengths of this memory to `budget`. The current length
+    /// is charged even if it exceeds the limit, only expansions fail.
+    pub fn set_budget(&mut self, budget: Arc<MemoryBudget>) {
+        if let Some(previous) = &self.1 {
+            previous.release(self.len());
+        }
+        budget.used.fetch_add(self.len(), Ordering::Relaxed);
+        self.1 = Some(budget);
     }
 
     pub fn as_slice(&self) -> &[u8] {
@@ -42,12 +174,57 @@ impl Memory {
         self.0.len() as u64
     }
 
-    fn expand(&mut self, new_len_bytes: u64, gas_left: &mut Gas) -> Result<(), FailStatus> {
+    /// Expands the memory to cover `len` bytes starting at `offset` and charges the expansion
+    /// cost. Accesses of length zero never expand the memory. Regions which do not fit into `u64`
+    /// or `usize` can never be paid for, so they fail with [`FailStatus::OutOfGas`] like
+    /// expansions whose quadratic cost exceeds `gas_left`.
+    pub fn expand(
+        &mut self,
+        offset: u256,
+        len: u256,
+        gas_left: &mut Gas,
+    ) -> Result<(), FailStatus> {
+        let (len, len_overflow) = len.into_u64_with_overflow();
+        if len_overflow {
+            return Err(FailStatus::OutOfGas);
+        }
+        self.expand_range(offset, len, gas_left)?;
+        Ok(())
+    }
+
+    /// Like [`Self::expand`], but returns the byte range of the region.
+    fn expand_range(
+        &mut self,
+        offset: u256,
+        len: u64,
+        gas_left: &mut Gas,
+    ) -> Result<Range<usize>, FailStatus> {
+        if len == 0 {
+            return Ok(0..0);
+        }
+        let (offset, offset_overflow) = offset.into_u64_with_overflow();
+        let (end, end_overflow) = offset.overflowing_add(len);
+        if offset_overflow || end_overflow {
+            return Err(FailStatus::OutOfGas);
+        }
+        let end_usize = usize::try_from(end).map_err(|_| FailStatus::OutOfGas)?;
+        self.expand_to(end, gas_left)?;
+        // offset <= end
+        Ok(offset as usize..end_usize)
+    }
+
+    fn expand_to(&mut self, new_len_bytes: u64, gas_left: &mut Gas) -> Result<(), FailStatus> {
         #[cold]
         fn expand_raw(m: &mut Memory, new_len: u64, gas_left: &mut Gas) -> Result<(), FailStatus> {
             let current_len = m.0.len() as u64;
+            let additional_len = new_len - current_len;
             m.consume_expansion_cost(new_len, gas_left)?;
-            m.0.extend(iter::repeat_n(0, (new_len - current_len) as usize));
+            if let Some(budget) = &m.1 {
+                budget.reserve(additional_len)?;
+            }
+            // Explicitly write zeros instead of only setting the length, so that stale bytes of
+            // a reused allocation never become visible.
+            m.0.extend(iter::repeat_n(0, additional_len as usize));
             Ok(())
         }
 
@@ -60,17 +237,6 @@ impl Memory {
     }
 
     fn consume_expansion_cost(&self, new_len: u64, gas_left: &mut Gas) -> Result<(), FailStatus> {
-        fn memory_cost(size: u64) -> Result<u64, FailStatus> {
-            let word_size = word_size(size)?;
-            let (pow2, pow2_overflow) = word_size.overflowing_pow(2);
-            let (word_size_3, word_size_3_overflow) = word_size.overflowing_mul(3);
-            let (cost, cost_overflow) = (pow2 / 512).overflowing_add(word_size_3);
-            if pow2_overflow || word_size_3_overflow || cost_overflow {
-                return Err(FailStatus::OutOfGas);
-            };
-            Ok(cost)
-        }
-
         let current_len = self.0.len() as u64;
 
         if new_len > current_len {
@@ -86,27 +252,16 @@ impl Memory {
         len: u64,
         gas_left: &mut Gas,
     ) -> Result<&mut [u8], FailStatus> {
-        if len == 0 {
-            return Ok(&mut []);
-        }
-        let (offset, offset_overflow) = offset.into_u64_with_overflow();
-        let (end, end_overflow) = offset.overflowing_add(len);
-        if offset_overflow || end_overflow {
-            return Err(FailStatus::OutOfGas);
-        }
-        self.expand(end, gas_left)?;
-
-        let offset = offset as usize;
-        let end = end as usize;
+        let Range { start, end } = self.expand_range(offset, len, gas_left)?;
         #[cfg(feature = "unsafe-hints")]
         // SAFETY:
-        // end = offset + len, so offset <= end
-        // end will always be in bounds because expand takes care of expanding the memory
+        // end = start + len, so start <= end
+        // end will always be in bounds because expand_range takes care of expanding the memory
         // accordingly.
         unsafe {
-            std::hint::assert_unchecked(offset <= end && end <= self.0.len());
+            std::hint::assert_unchecked(start <= end && end <= self.0.len());
         }
-        Ok(&mut self.0[offset..end])
+        Ok(&mut self.0[start..end])
     }
 
     pub fn get_word(&mut self, offset: u256, gas_left: &mut Gas) -> Result<u256, FailStatus> {
@@ -138,28 +293,30 @@ impl Memory {
         len: u256,
         gas_left: &mut Gas,
     ) -> Result<(), FailStatus> {
-        let (src_offset, src_overflow) = src_offset.into_u64_with_overflow();
-        let (dest_offset, dest_overflow) = dest_offset.into_u64_with_overflow();
         let (len, len_overflow) = len.into_u64_with_overflow();
-        let (end, end_overflow) = max(src_offset, dest_offset).overflowing_add(len);
-        if src_overflow || dest_overflow || len_overflow || end_overflow {
+        if len_overflow {
             return Err(FailStatus::OutOfGas);
         }
         gas_left.consume_copy_cost(len)?;
-        self.expand(end, gas_left)?;
-        let src_offset = src_offset as usize;
-        let dest_offset = dest_offset as usize;
-        let len = len as usize;
-        self.0
-            .copy_within(src_offset..src_offset + len, dest_offset); // + does not overflow
+        // The expansion cost only depends on the final length, so expanding for the source and
+        // then for the destination costs the same as expanding for both at once.
+        let src = self.expand_range(src_offset, len, gas_left)?;
+        let dest = self.expand_range(dest_offset, len, gas_left)?;
+        self.0.copy_within(src, dest.start);
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use crate::{
-        types::{FailStatus, memory::Memory, u256},
+        types::{
+            FailStatus,
+            memory::{Memory, MemoryBudget},
+            u256,
+        },
         utils::Gas,
     };
 
@@ -173,15 +330,15 @@ mod tests {
     #[test]
     fn expand() {
         let mut memory = Memory::new(&[]);
-        assert_eq!(memory.expand(1, &mut Gas::new(1_000)), Ok(()));
+        assert_eq!(memory.expand_to(1, &mut Gas::new(1_000)), Ok(()));
         assert_eq!(memory.as_slice(), [0; 32]);
 
         let mut memory = Memory::new(&[]);
-        assert_eq!(memory.expand(32, &mut Gas::new(1_000)), Ok(()));
+        assert_eq!(memory.expand_to(32, &mut Gas::new(1_000)), Ok(()));
         assert_eq!(memory.as_slice(), [0; 32]);
 
         let mut memory = Memory::new(&[1; 32]);
-        assert_eq!(memory.expand(64, &mut Gas::new(1_000)), Ok(()));
+        assert_eq!(memory.expand_to(64, &mut Gas::new(1_000)), Ok(()));
         assert_eq!(memory.as_slice(), {
             let mut mem = [1; 64];
             mem[32..].copy_from_slice(&[0; 32]);
@@ -190,11 +347,110 @@ mod tests {
 
         let mut memory = Memory::new(&[]);
         assert_eq!(
-            memory.expand(u64::MAX, &mut Gas::new(1_000)),
+            memory.expand_to(u64::MAX, &mut Gas::new(1_000)),
             Err(FailStatus::OutOfGas)
         );
     }
 
+    #[test]
+    fn expansion_zero_fills_new_region() {
+        // with feature alloc-reuse the allocation of this memory is reused below
+        drop(Memory::new(&[0xff; 64]));
+        let mut memory = Memory::new(&[0xff; 32]);
+        assert_eq!(
+            memory.expand(32u8.into(), 32u8.into(), &mut Gas::new(i64::MAX)),
+            Ok(())
+        );
+        assert_eq!(memory.as_slice()[..32], [0xff; 32]);
+        assert_eq!(memory.as_slice()[32..], [0; 32]);
+    }
+
+    #[test]
+    fn expand_region() {
+        let mut memory = Memory::new(&[]);
+        let mut gas_left = Gas::new(0);
+        assert_eq!(memory.expand(u256::MAX, u256::ZERO, &mut gas_left), Ok(()));
+        assert_eq!(memory.len(), 0);
+
+        for offset in [u256::from_limbs([0, 1, 0, 0]), u256::MAX] {
+            let mut memory = Memory::new(&[]);
+            assert_eq!(
+                memory.expand(offset, u256::ONE, &mut Gas::new(i64::MAX)),
+                Err(FailStatus::OutOfGas)
+            );
+            assert_eq!(memory.len(), 0);
+        }
+        assert_eq!(
+            Memory::new(&[]).expand(u256::ZERO, u256::MAX, &mut Gas::new(i64::MAX)),
+            Err(FailStatus::OutOfGas)
+        );
+
+        // 32 words cost 32 * 3 + 32^2 / 512 = 98
+        let mut memory = Memory::new(&[]);
+        let mut gas_left = Gas::new(98);
+        assert_eq!(
+            memory.expand(u256::from(992u64), 32u8.into(), &mut gas_left),
+            Ok(())
+        );
+        assert_eq!(memory.len(), 1024);
+        assert_eq!(gas_left, 0);
+
+        let mut memory = Memory::new(&[]);
+        assert_eq!(
+            memory.expand(u256::from(992u64), 32u8.into(), &mut Gas::new(97)),
+            Err(FailStatus::OutOfGas)
+        );
+        assert_eq!(memory.len(), 0);
+    }
+
+    #[test]
+    fn expand_respects_budget() {
+        let budget = MemoryBudget::new(96);
+
+        let mut memory1 = Memory::new(&[0; 32]);
+        memory1.set_budget(budget.clone());
+        let mut memory2 = Memory::new(&[]);
+        memory2.set_budget(budget.clone());
+        assert_eq!(memory2.expand_to(32, &mut Gas::new(1_000)), Ok(()));
+        // 64 bytes are live, so one more word fits but two do not
+        assert_eq!(
+            memory1.expand_to(96, &mut Gas::new(1_000)),
+            Err(FailStatus::OutOfGas)
+        );
+        assert_eq!(memory1.expand_to(64, &mut Gas::new(1_000)), Ok(()));
+        assert_eq!(
+            memory2.expand_to(64, &mut Gas::new(1_000)),
+            Err(FailStatus::OutOfGas)
+        );
+
+        // dropping a memory frees its share of the budget, also on another thread
+        std::thread::spawn(move || drop(memory1)).join().unwrap();
+        assert_eq!(budget.used(), 32);
+        assert_eq!(memory2.expand_to(96, &mut Gas::new(1_000)), Ok(()));
+
+        // memories without a budget are not limited
+        let mut memory3 = Memory::new(&[]);
+        assert_eq!(memory3.expand_to(1024, &mut Gas::new(1_000)), Ok(()));
+        assert_eq!(budget.used(), 96);
+    }
+
+    #[test]
+    fn budget_is_shared_with_nested_frames_while_scope_is_alive() {
+        assert!(MemoryBudget::enter(u64::MAX, 0).budget().is_none());
+        assert!(MemoryBudget::current().is_none());
+
+        let scope = MemoryBudget::enter(1024, 0);
+        let budget = scope.budget().unwrap();
+        let nested = MemoryBudget::enter(1024, 1).budget().unwrap();
+        assert!(Arc::ptr_eq(&budget, &nested));
+        // an unlimited nested frame does not use the budget
+        assert!(MemoryBudget::enter(u64::MAX, 1).budget().is_none());
+
+        drop(scope);
+        assert!(MemoryBudget::current().is_none());
+        assert!(MemoryBudget::enter(1024, 1).budget().is_none());
+    }
+
     #[test]
     fn consume_expansion_cost() {
         let memory = Memory::new(&[]);
@@ -378,19 +634,22 @@ mod tests {
             Ok(())
         );
 
+        // copies of length zero never expand the memory, regardless of the offsets
         let mut mem = Memory::new(&[]);
         let mut gas_left = Gas::new(0);
         assert_eq!(
             mem.copy_within(u256::ONE, u256::ZERO, u256::ZERO, &mut gas_left),
-            Err(FailStatus::OutOfGas)
+            Ok(())
         );
+        assert_eq!(mem.len(), 0);
 
         let mut mem = Memory::new(&[]);
         let mut gas_left = Gas::new(0);
         assert_eq!(
             mem.copy_within(u256::ZERO, u256::ONE, u256::ZERO, &mut gas_left),
-            Err(FailStatus::OutOfGas)
+            Ok(())
         );
+        assert_eq!(mem.len(), 0);
 
         let mut mem = Memory::new(&[]);
         let mut gas_left = Gas::new(0);
@@ -403,6 +662,14 @@ mod tests {
         let mut gas_left = Gas::new(1_000_000);
         assert_eq!(
             mem.copy_within(u256::MAX, u256::ZERO, u256::ZERO, &mut gas_left),
+            Ok(())
+        );
+        assert_eq!(mem.len(), 0);
+
+        let mut mem = Memory::new(&[]);
+        let mut gas_left = Gas::new(1_000_000);
+        assert_eq!(
+            mem.copy_within(u256::MAX, u256::ZERO, u256::ONE, &mut gas_left),
             Err(FailStatus::OutOfGas)
         );
 
@@ -3,8 +3,17 @@ mod amount;
 mod cache;
 mod code_analysis;
 mod code_reader;
+mod dry_run_context;
+// EOF code is only validated by the code analysis with the feature `eof`
+#[cfg_attr(not(feature = "eof"), allow(dead_code))]
+pub mod eof;
 mod execution_context;
 pub mod hash_cache;
+#[cfg(feature = "mock")]
+mod host_recording;
+#[cfg(feature = "mock")]
+mod instruction_stream;
+mod interpreter_state;
 mod memory;
 mod mock_execution_message;
 mod observer;
@@ -13,16 +22,28 @@ mod op_fn_data;
 mod opcode;
 #[cfg(feature = "fn-ptr-conversion-dispatch")]
 mod pc_map;
+#[cfg(feature = "mock")]
+mod reference_host;
 mod stack;
 mod status_code;
 
-pub use amount::u256;
+pub use amount::{ParseError, u256};
 #[cfg(feature = "needs-cache")]
-pub use cache::Cache;
-pub use code_analysis::{AnalysisContainer, CodeAnalysis, CodeAnalysisCache};
+pub use cache::{Cache, CacheStats};
+#[cfg(not(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch"))))]
+pub use code_analysis::AnalysisContainer;
+#[cfg(all(feature = "lazy-analysis", not(feature = "fn-ptr-conversion-dispatch")))]
+pub use code_analysis::LazyCodeAnalysis;
+pub use code_analysis::{AnalysisError, CodeAnalysis, CodeAnalysisCache};
 pub use code_reader::{CodeReader, GetOpcodeError};
+pub use dry_run_context::DryRunContext;
 pub use execution_context::*;
-pub use memory::Memory;
+#[cfg(feature = "mock")]
+pub use host_recording::*;
+#[cfg(feature = "mock")]
+pub use instruction_stream::{InstructionStream, StepInfo};
+pub use interpreter_state::{InterpreterState, InterpreterStateError};
+pub use memory::{Memory, MemoryBudget, memory_cost};
 pub use mock_execution_message::MockExecutionMessage;
 pub use observer::*;
 #[cfg(feature = "fn-ptr-conversion-dispatch")]
@@ -30,5 +51,7 @@ pub use op_fn_data::OpFnData;
 pub use opcode::*;
 #[cfg(feature = "fn-ptr-conversion-dispatch")]
 pub use pc_map::PcMap;
+#[cfg(feature = "mock")]
+pub use reference_host::{Account, Log, MockCallRouter, ReferenceHost};
 pub use stack::Stack;
 pub use status_code::{ExecStatus, FailStatus};
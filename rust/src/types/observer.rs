@@ -1,13 +1,42 @@
-use std::{borrow::Cow, io::Write};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display, Write as _},
+    io::{self, Write},
+    ops::RangeInclusive,
+};
 
-#[cfg(feature = "fn-ptr-conversion-dispatch")]
-use crate::Opcode;
-use crate::interpreter::Interpreter;
+use evmc_vm::{Address, ExecutionResult, Revision, StatusCode};
 
+use crate::{
+    interpreter::Interpreter,
+    types::{Opcode, memory_cost, u256},
+};
+
+/// Hooks into the execution of each opcode.
+///
+/// All program counters passed to or read by observers are offsets into the original code, never
+/// indices into the converted code used by the `fn-ptr-conversion-dispatch` feature.
+/// [`CodeReader::pc`](crate::types::CodeReader::pc) always returns the original program counter.
 pub trait Observer<const STEPPABLE: bool> {
-    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>);
+    /// Called before each opcode, before [`Observer::pre_op`]. If this returns false, the
+    /// execution halts before the opcode as if the step limit of a steppable execution was
+    /// reached, i.e. the interpreter is still running and the result reports success.
+    fn should_continue(&mut self, _interpreter: &Interpreter<STEPPABLE>) -> bool {
+        true
+    }
+
+    /// Called before each opcode with the gas left and the gas refund counter before the opcode
+    /// is executed, which corresponds to the `gas` field of geth's traces.
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: u64, gas_refund: i64);
 
-    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>);
+    /// Called after an opcode was executed successfully. `gas_left` is the gas left after the
+    /// opcode and `gas_cost` the gas it consumed, including the gas used by nested calls.
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: i64, gas_cost: u64);
+
+    /// Called right after the opcode at `pc` deducted its gas and before [`Observer::post_op`].
+    /// `cost` includes the gas for memory expansion and the gas used by nested calls.
+    fn gas_consumed(&mut self, _opcode: Opcode, _cost: u64, _pc: usize) {}
 
     fn log(&mut self, message: Cow<str>);
 }
@@ -15,13 +44,34 @@ pub trait Observer<const STEPPABLE: bool> {
 pub struct NoOpObserver();
 
 impl<const STEPPABLE: bool> Observer<STEPPABLE> for NoOpObserver {
-    fn pre_op(&mut self, _interpreter: &Interpreter<STEPPABLE>) {}
+    fn pre_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {}
 
-    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>) {}
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {}
 
     fn log(&mut self, _message: Cow<str>) {}
 }
 
+/// Returns the opcode at the current pc. Must only be called from [`Observer::pre_op`].
+fn current_opcode<const STEPPABLE: bool>(interpreter: &Interpreter<STEPPABLE>) -> Opcode {
+    opcode_at(interpreter, interpreter.code_reader.pc())
+}
+
+/// Returns the opcode at `pc`, which must be the position of an opcode that was already fetched.
+pub(crate) fn opcode_at<const STEPPABLE: bool>(
+    interpreter: &Interpreter<STEPPABLE>,
+    pc: usize,
+) -> Opcode {
+    let op = interpreter.code_reader[pc];
+    // SAFETY:
+    // The op at pc was already fetched, which means that code_reader.get() returned Ok(..) which
+    // in turn means that the code analysis determined that this byte is a valid Opcode.
+    unsafe { std::mem::transmute::<u8, Opcode>(op) }
+}
+
+/// Writes a line for each opcode with its name, the gas left and the top of the stack, all taken
+/// before the opcode is executed. The format is the same as the one of the lfvm instruction logger,
+/// so that both logs can be compared line by line. The gas refund counter is reported by
+/// [`Eip3155Json`] instead.
 pub struct LoggingObserver<W: Write> {
     writer: W,
 }
@@ -33,30 +83,18 @@ impl<W: Write> LoggingObserver<W> {
 }
 
 impl<W: Write, const STEPPABLE: bool> Observer<STEPPABLE> for LoggingObserver<W> {
-    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>) {
-        // pre_op is called after the op is fetched so this will always be Ok(..)
-        #[cfg(not(feature = "fn-ptr-conversion-dispatch"))]
-        let op = interpreter.code_reader.get().unwrap();
-        #[cfg(feature = "fn-ptr-conversion-dispatch")]
-        let op = {
-            let op = interpreter.code_reader[interpreter.code_reader.pc()];
-            // SAFETY:
-            // pre_op is called after the op is fetched, which means that code_reader.get() returned
-            // Some(..) which in turn means that the code analysis determined that this byte is a
-            // valid Opcode.
-            unsafe { std::mem::transmute::<u8, Opcode>(op) }
-        };
-        let gas = interpreter.gas_left.as_u64();
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: u64, _gas_refund: i64) {
+        let op = current_opcode(interpreter);
         let top = interpreter
             .stack
             .peek()
             .map(ToString::to_string)
             .unwrap_or("-empty-".to_owned());
-        writeln!(self.writer, "{op:?}, {gas}, {top}").unwrap();
+        writeln!(self.writer, "{op:?}, {gas_left}, {top}").unwrap();
         self.writer.flush().unwrap();
     }
 
-    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>) {}
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {}
 
     fn log(&mut self, message: Cow<str>) {
         writeln!(self.writer, "{message}").unwrap();
@@ -64,8 +102,1798 @@ impl<W: Write, const STEPPABLE: bool> Observer<STEPPABLE> for LoggingObserver<W>
     }
 }
 
+/// A call or create which was executed by an observed frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallGraphEdge {
+    pub caller: u256,
+    /// The called address or, for creates, the address of the created contract (zero if the
+    /// creation failed).
+    pub callee: u256,
+    pub kind: Opcode,
+    /// The depth of the calling frame.
+    pub depth: i32,
+    /// The gas used by the call instruction, including the gas used by the callee.
+    pub gas: u64,
+}
+
+/// Records the calls and creates of all frames it observes. The interpreter does not pass its
+/// observer to the nested frames executed by the host, so only the calls made directly by the
+/// observed frame are recorded. To build a graph over several depths, each frame has to be
+/// executed with the same observer.
+#[derive(Debug, Default)]
+pub struct CallGraphObserver {
+    edges: Vec<CallGraphEdge>,
+    /// The call which is currently executed.
+    pending: Option<CallGraphEdge>,
+}
+
+impl CallGraphObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All recorded calls in the order in which they completed.
+    pub fn edges(&self) -> &[CallGraphEdge] {
+        &self.edges
+    }
+
+    /// Returns the call graph in the Graphviz DOT format. Each address is a node and each call is
+    /// an edge labeled with the call kind and the used gas.
+    pub fn to_dot(&self) -> String {
+        let nodes: BTreeSet<_> = self
+            .edges
+            .iter()
+            .flat_map(|edge| [edge.caller, edge.callee])
+            .collect();
+
+        let mut dot = "digraph calls {\n".to_owned();
+        for node in nodes {
+            writeln!(dot, "    \"{}\";", Self::node_name(node)).unwrap();
+        }
+        for edge in &self.edges {
+            let kind = format!("{:?}", edge.kind).to_uppercase();
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{kind}\\ngas: {}\"];",
+                Self::node_name(edge.caller),
+                Self::node_name(edge.callee),
+                edge.gas
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn node_name(addr: u256) -> String {
+        let mut name = "0x".to_owned();
+        for byte in Address::from(addr).bytes {
+            write!(name, "{byte:02x}").unwrap();
+        }
+        name
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for CallGraphObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        let op = interpreter.code_reader[interpreter.code_reader.pc()];
+        let stack = interpreter.stack.as_slice();
+        let (kind, callee) = match op {
+            op if op == Opcode::Call as u8 => (Opcode::Call, stack.len().checked_sub(2)),
+            op if op == Opcode::CallCode as u8 => (Opcode::CallCode, stack.len().checked_sub(2)),
+            op if op == Opcode::DelegateCall as u8 => {
+                (Opcode::DelegateCall, stack.len().checked_sub(2))
+            }
+            op if op == Opcode::StaticCall as u8 => {
+                (Opcode::StaticCall, stack.len().checked_sub(2))
+            }
+            // the address of the created contract is only known after the execution
+            op if op == Opcode::Create as u8 => (Opcode::Create, None),
+            op if op == Opcode::Create2 as u8 => (Opcode::Create2, None),
+            _ => return,
+        };
+        let edge = CallGraphEdge {
+            caller: interpreter.message.recipient.into(),
+            callee: callee.map(|idx| stack[idx]).unwrap_or(u256::ZERO),
+            kind,
+            depth: interpreter.message.depth,
+            gas: 0,
+        };
+        self.pending = Some(edge);
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, gas_cost: u64) {
+        let Some(mut edge) = self.pending.take() else {
+            return;
+        };
+        if matches!(edge.kind, Opcode::Create | Opcode::Create2) {
+            edge.callee = interpreter.stack.peek().copied().unwrap_or(u256::ZERO);
+        }
+        edge.gas = gas_cost;
+        self.edges.push(edge);
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// Forwards the step events of all frames whose depth is within `depths` to the inner observer and
+/// drops the events of all other frames. Log messages are always forwarded.
+pub struct DepthFilterObserver<O> {
+    inner: O,
+    depths: RangeInclusive<i32>,
+    /// Whether the last op passed to [`Observer::pre_op`] was within `depths`.
+    last_op_observed: bool,
+}
+
+impl<O> DepthFilterObserver<O> {
+    pub fn new(inner: O, depths: RangeInclusive<i32>) -> Self {
+        Self {
+            inner,
+            depths,
+            last_op_observed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.inner
+    }
+}
+
+impl<O: Observer<STEPPABLE>, const STEPPABLE: bool> Observer<STEPPABLE> for DepthFilterObserver<O> {
+    fn should_continue(&mut self, interpreter: &Interpreter<STEPPABLE>) -> bool {
+        !self.depths.contains(&interpreter.message.depth) || self.inner.should_continue(interpreter)
+    }
+
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: u64, gas_refund: i64) {
+        self.last_op_observed = self.depths.contains(&interpreter.message.depth);
+        if self.last_op_observed {
+            self.inner.pre_op(interpreter, gas_left, gas_refund);
+        }
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: i64, gas_cost: u64) {
+        if self.depths.contains(&interpreter.message.depth) {
+            self.inner.post_op(interpreter, gas_left, gas_cost);
+        }
+    }
+
+    fn gas_consumed(&mut self, opcode: Opcode, cost: u64, pc: usize) {
+        if self.last_op_observed {
+            self.inner.gas_consumed(opcode, cost, pc);
+        }
+    }
+
+    fn log(&mut self, message: Cow<str>) {
+        self.inner.log(message);
+    }
+}
+
+/// The gas used by a frame, split into categories.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GasBreakdown {
+    /// Gas used by all opcodes which do not belong to any other category.
+    pub computation: u64,
+    /// Gas used for memory expansions, independent of the opcode which expanded the memory.
+    pub memory: u64,
+    /// Gas used by SLOAD, SSTORE, TLOAD and TSTORE.
+    pub storage: u64,
+    /// Gas used by calls, creates and SELFDESTRUCT, including the gas used by nested frames.
+    pub calls: u64,
+    /// Gas used by LOG0 to LOG4.
+    pub logs: u64,
+}
+
+impl GasBreakdown {
+    pub fn total(&self) -> u64 {
+        self.computation + self.memory + self.storage + self.calls + self.logs
+    }
+}
+
+impl Display for GasBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "computation: {}, memory: {}, storage: {}, calls: {}, logs: {}",
+            self.computation, self.memory, self.storage, self.calls, self.logs
+        )
+    }
+}
+
+/// Accumulates the gas used by the opcodes of all frames it observes into a [`GasBreakdown`]. The
+/// gas of opcodes which fail is not included.
+#[derive(Debug, Default)]
+pub struct GasBreakdownObserver {
+    breakdown: GasBreakdown,
+    /// The opcode which is currently executed and the memory length before its execution.
+    pending: Option<(u8, u64)>,
+}
+
+impl GasBreakdownObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn breakdown(&self) -> GasBreakdown {
+        self.breakdown
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for GasBreakdownObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        let op = interpreter.code_reader[interpreter.code_reader.pc()];
+        self.pending = Some((op, interpreter.memory.len()));
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, gas_cost: u64) {
+        let Some((op, memory_len)) = self.pending.take() else {
+            return;
+        };
+        // The memory was successfully expanded, so its cost did not overflow.
+        let memory = memory_cost(interpreter.memory.len()).unwrap_or_default()
+            - memory_cost(memory_len).unwrap_or_default();
+        let bucket = match op {
+            op if op == Opcode::SLoad as u8
+                || op == Opcode::SStore as u8
+                || op == Opcode::TLoad as u8
+                || op == Opcode::TStore as u8 =>
+            {
+                &mut self.breakdown.storage
+            }
+            op if op == Opcode::Call as u8
+                || op == Opcode::CallCode as u8
+                || op == Opcode::DelegateCall as u8
+                || op == Opcode::StaticCall as u8
+                || op == Opcode::Create as u8
+                || op == Opcode::Create2 as u8
+                || op == Opcode::SelfDestruct as u8 =>
+            {
+                &mut self.breakdown.calls
+            }
+            op if (Opcode::Log0 as u8..=Opcode::Log4 as u8).contains(&op) => {
+                &mut self.breakdown.logs
+            }
+            _ => &mut self.breakdown.computation,
+        };
+        *bucket += gas_cost.saturating_sub(memory);
+        self.breakdown.memory += memory.min(gas_cost);
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// A storage write which was about to be executed when [`StorageWriteHaltObserver`] halted the
+/// execution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PendingStorageWrite {
+    /// Either [`Opcode::SStore`] or [`Opcode::TStore`].
+    pub opcode: Opcode,
+    pub address: Address,
+    pub key: u256,
+    pub value: u256,
+}
+
+/// Halts the execution right before the first SSTORE or TSTORE, so that the write is neither
+/// charged nor passed to the host. The write can be retrieved with
+/// [`StorageWriteHaltObserver::pending_write`]. Writes which would fail with a stack underflow do
+/// not halt the execution.
+#[derive(Debug, Default)]
+pub struct StorageWriteHaltObserver {
+    pending_write: Option<PendingStorageWrite>,
+}
+
+impl StorageWriteHaltObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pending_write(&self) -> Option<PendingStorageWrite> {
+        self.pending_write
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for StorageWriteHaltObserver {
+    fn should_continue(&mut self, interpreter: &Interpreter<STEPPABLE>) -> bool {
+        let op = interpreter.code_reader[interpreter.code_reader.pc()];
+        let opcode = if op == Opcode::SStore as u8 {
+            Opcode::SStore
+        } else if op == Opcode::TStore as u8 {
+            Opcode::TStore
+        } else {
+            return true;
+        };
+        let [.., value, key] = interpreter.stack.as_slice() else {
+            return true;
+        };
+        self.pending_write = Some(PendingStorageWrite {
+            opcode,
+            address: interpreter.message.recipient,
+            key: *key,
+            value: *value,
+        });
+        false
+    }
+
+    fn pre_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {}
+
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {}
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// Records the final value of each storage slot written by SSTORE in the observed frames. Writes of
+/// frames which later revert or fail are included as well.
+#[derive(Debug, Default)]
+pub struct StorageWriteObserver {
+    writes: BTreeMap<(u256, u256), u256>,
+    /// The depth, address, key and value of the SSTORE which is currently executed.
+    pending: Option<(i32, u256, u256, u256)>,
+}
+
+impl StorageWriteObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The written slots, keyed by address and key, with the last value written to them.
+    pub fn writes(&self) -> &BTreeMap<(u256, u256), u256> {
+        &self.writes
+    }
+
+    pub fn into_writes(self) -> BTreeMap<(u256, u256), u256> {
+        self.writes
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for StorageWriteObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        self.pending = None;
+        if interpreter.code_reader[interpreter.code_reader.pc()] != Opcode::SStore as u8 {
+            return;
+        }
+        if let [.., value, key] = interpreter.stack.as_slice() {
+            self.pending = Some((
+                interpreter.message.depth,
+                interpreter.message.recipient.into(),
+                *key,
+                *value,
+            ));
+        }
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {
+        // If the SSTORE failed, the next post_op belongs to the call in the parent frame.
+        let Some((depth, address, key, value)) = self.pending.take() else {
+            return;
+        };
+        if depth == interpreter.message.depth {
+            self.writes.insert((address, key), value);
+        }
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// A storage slot touched by SLOAD or SSTORE, with its value before and after the execution, see
+/// [`StateDiffObserver`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StorageSlotDiff {
+    pub address: Address,
+    pub key: u256,
+    pub pre: u256,
+    pub post: u256,
+}
+
+/// Records the value before the execution and the final value of each storage slot touched by
+/// SLOAD or SSTORE in the observed frames. The value before the execution is read from the host
+/// when a slot is touched for the first time. Like with [`StorageWriteObserver`], writes of frames
+/// which later revert or fail are included.
+#[derive(Debug, Default)]
+pub struct StateDiffObserver {
+    /// The pre and post values of all touched slots, keyed by address and key.
+    slots: BTreeMap<(u256, u256), (u256, u256)>,
+    /// The depth, address, key and value of the SSTORE which is currently executed.
+    pending: Option<(i32, u256, u256, u256)>,
+}
+
+impl StateDiffObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All touched slots, ordered by address and key. Slots which were only read or which were
+    /// restored to their previous value have equal pre and post values.
+    pub fn diff(&self) -> Vec<StorageSlotDiff> {
+        self.slots
+            .iter()
+            .map(|(&(address, key), &(pre, post))| StorageSlotDiff {
+                address: address.into(),
+                key,
+                pre,
+                post,
+            })
+            .collect()
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for StateDiffObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        self.pending = None;
+        let op = interpreter.code_reader[interpreter.code_reader.pc()];
+        if op != Opcode::SLoad as u8 && op != Opcode::SStore as u8 {
+            return;
+        }
+        let recipient = interpreter.message.recipient;
+        let address = u256::from(recipient);
+        let (key, value) = match interpreter.stack.as_slice() {
+            [.., value, key] if op == Opcode::SStore as u8 => (*key, Some(*value)),
+            [.., key] if op == Opcode::SLoad as u8 => (*key, None),
+            _ => return,
+        };
+        self.slots.entry((address, key)).or_insert_with(|| {
+            let pre = interpreter
+                .context
+                .get_storage(&recipient, &key.into())
+                .into();
+            (pre, pre)
+        });
+        if let Some(value) = value {
+            self.pending = Some((interpreter.message.depth, address, key, value));
+        }
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {
+        // If the SSTORE failed, the next post_op belongs to the call in the parent frame.
+        let Some((depth, address, key, value)) = self.pending.take() else {
+            return;
+        };
+        if depth == interpreter.message.depth
+            && let Some((_, post)) = self.slots.get_mut(&(address, key))
+        {
+            *post = value;
+        }
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// The number of executions and the total gas of an opcode, see [`OpcodeHistogramObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeStats {
+    pub opcode: Opcode,
+    pub count: u64,
+    /// The gas used by all executions of the opcode, including memory expansion and the gas used
+    /// by nested calls.
+    pub gas: u64,
+}
+
+/// Counts how often each opcode is executed and how much gas it uses in total. Opcodes which fail
+/// are not included.
+#[derive(Debug, Default)]
+pub struct OpcodeHistogramObserver {
+    /// The statistics of all executed opcodes, keyed by their byte value.
+    stats: BTreeMap<u8, OpcodeStats>,
+    pending: Option<Opcode>,
+}
+
+impl OpcodeHistogramObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, opcode: Opcode) -> Option<&OpcodeStats> {
+        self.stats.get(&(opcode as u8))
+    }
+
+    /// The statistics of all executed opcodes, ordered by their byte value.
+    pub fn stats(&self) -> impl Iterator<Item = &OpcodeStats> {
+        self.stats.values()
+    }
+
+    /// Returns the `n` most frequently executed opcodes, in descending order.
+    pub fn top_by_count(&self, n: usize) -> Vec<OpcodeStats> {
+        self.top_by(n, |stats| stats.count)
+    }
+
+    /// Returns the `n` opcodes which used the most gas, in descending order.
+    pub fn top_by_gas(&self, n: usize) -> Vec<OpcodeStats> {
+        self.top_by(n, |stats| stats.gas)
+    }
+
+    fn top_by(&self, n: usize, key: impl Fn(&OpcodeStats) -> u64) -> Vec<OpcodeStats> {
+        let mut stats: Vec<_> = self.stats.values().copied().collect();
+        stats.sort_by_key(|stats| std::cmp::Reverse(key(stats)));
+        stats.truncate(n);
+        stats
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for OpcodeHistogramObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        self.pending = Some(current_opcode(interpreter));
+    }
+
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, gas_cost: u64) {
+        let Some(opcode) = self.pending.take() else {
+            return;
+        };
+        let stats = self.stats.entry(opcode as u8).or_insert(OpcodeStats {
+            opcode,
+            count: 0,
+            gas: 0,
+        });
+        stats.count += 1;
+        stats.gas += gas_cost;
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// Accumulates the gas used per opcode, see [`Observer::gas_consumed`]. Memory expansion is
+/// attributed to the opcode which expanded the memory. Opcodes which fail are not included.
+#[derive(Debug, Default)]
+pub struct GasProfileObserver {
+    gas: HashMap<Opcode, u64>,
+}
+
+impl GasProfileObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total gas used by all executions of `opcode`.
+    pub fn gas(&self, opcode: Opcode) -> u64 {
+        self.gas.get(&opcode).copied().unwrap_or_default()
+    }
+
+    pub fn profile(&self) -> &HashMap<Opcode, u64> {
+        &self.gas
+    }
+
+    /// Returns all executed opcodes with their total gas, sorted by descending gas. Opcodes with
+    /// the same gas are ordered by their byte value.
+    pub fn report(&self) -> Vec<(Opcode, u64)> {
+        let mut report: Vec<_> = self.gas.iter().map(|(op, gas)| (*op, *gas)).collect();
+        report.sort_by_key(|(op, gas)| (std::cmp::Reverse(*gas), *op as u8));
+        report
+    }
+
+    /// Writes one line per executed opcode in the order of [`Self::report`].
+    pub fn write_report(&self, writer: &mut impl Write) -> io::Result<()> {
+        for (op, gas) in self.report() {
+            writeln!(writer, "{op:?}: {gas}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for GasProfileObserver {
+    fn pre_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {}
+
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {}
+
+    fn gas_consumed(&mut self, opcode: Opcode, cost: u64, _pc: usize) {
+        *self.gas.entry(opcode).or_default() += cost;
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// A non-fatal anomaly encountered during the execution. Warnings never affect the execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    /// CALLCODE, which is superseded by DELEGATECALL, or SELFDESTRUCT, which is deprecated since
+    /// Shanghai (EIP-6049).
+    DeprecatedOpcode,
+    /// A JUMP or JUMPI to the JUMPDEST directly following it, which has no effect.
+    SuspiciousJump { destination: u256 },
+    /// An opcode which expanded the memory by more than the threshold of the [`WarningObserver`].
+    LargeMemoryExpansion { from: u64, to: u64 },
+}
+
+/// A [`Warning`] together with the opcode which caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedWarning {
+    pub pc: usize,
+    pub opcode: Opcode,
+    pub depth: i32,
+    pub warning: Warning,
+}
+
+/// Collects the [`Warning`]s of all frames it observes.
+#[derive(Debug)]
+pub struct WarningObserver {
+    warnings: Vec<RecordedWarning>,
+    /// Memory expansions by more bytes than this are reported as
+    /// [`Warning::LargeMemoryExpansion`].
+    memory_threshold: u64,
+    /// The pc and opcode which are currently executed and the memory length before the execution.
+    pending: Option<(usize, Opcode, u64)>,
+}
+
+impl Default for WarningObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WarningObserver {
+    /// The default threshold for [`Warning::LargeMemoryExpansion`] is 1 MiB.
+    pub const DEFAULT_MEMORY_THRESHOLD: u64 = 1 << 20;
+
+    pub fn new() -> Self {
+        Self::with_memory_threshold(Self::DEFAULT_MEMORY_THRESHOLD)
+    }
+
+    pub fn with_memory_threshold(memory_threshold: u64) -> Self {
+        Self {
+            warnings: Vec::new(),
+            memory_threshold,
+            pending: None,
+        }
+    }
+
+    /// All recorded warnings in the order in which they occurred.
+    pub fn warnings(&self) -> &[RecordedWarning] {
+        &self.warnings
+    }
+
+    pub fn into_warnings(self) -> Vec<RecordedWarning> {
+        self.warnings
+    }
+}
+
+impl<const STEPPABLE: bool> Observer<STEPPABLE> for WarningObserver {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: u64, _gas_refund: i64) {
+        let pc = interpreter.code_reader.pc();
+        let opcode = current_opcode(interpreter);
+        let warning = match opcode {
+            Opcode::CallCode => Some(Warning::DeprecatedOpcode),
+            Opcode::SelfDestruct => (interpreter.revision >= Revision::EVMC_SHANGHAI)
+                .then_some(Warning::DeprecatedOpcode),
+            Opcode::Jump | Opcode::JumpI => interpreter
+                .stack
+                .peek()
+                .filter(|destination| **destination == u256::from(pc as u64 + 1))
+                .map(|destination| Warning::SuspiciousJump {
+                    destination: *destination,
+                }),
+            _ => None,
+        };
+        if let Some(warning) = warning {
+            self.warnings.push(RecordedWarning {
+                pc,
+                opcode,
+                depth: interpreter.message.depth,
+                warning,
+            });
+        }
+        self.pending = Some((pc, opcode, interpreter.memory.len()));
+    }
+
+    fn post_op(&mut self, interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, _gas_cost: u64) {
+        let Some((pc, opcode, from)) = self.pending.take() else {
+            return;
+        };
+        let to = interpreter.memory.len();
+        if to.saturating_sub(from) > self.memory_threshold {
+            self.warnings.push(RecordedWarning {
+                pc,
+                opcode,
+                depth: interpreter.message.depth,
+                warning: Warning::LargeMemoryExpansion { from, to },
+            });
+        }
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// The state of a frame before an opcode was executed, together with the gas used by the opcode.
+/// See [`TraceFormat::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceStep<'a> {
+    /// The offset of the opcode in the original code.
+    pub pc: u64,
+    pub opcode: Opcode,
+    /// The gas left before the opcode.
+    pub gas: u64,
+    /// The gas used by the opcode, including memory expansion and the gas used by nested calls.
+    pub gas_cost: u64,
+    pub depth: i32,
+    pub refund: i64,
+    /// The stack before the opcode, with the top element last.
+    pub stack: &'a [u256],
+    pub memory_size: u64,
+}
+
+/// The outcome of a traced frame, see [`TraceFormat::end`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceResult<'a> {
+    pub status_code: StatusCode,
+    pub gas_used: u64,
+    pub output: &'a [u8],
+}
+
+/// A format in which a [`FormattingObserver`] writes the trace of an execution.
+pub trait TraceFormat {
+    /// Called before the first step with the revision and the gas limit of the traced frame.
+    fn begin(&mut self, _revision: Revision, _gas_limit: u64) {}
+
+    /// Called once for each opcode after it completed. If the execution fails, the failing opcode
+    /// is reported right before [`TraceFormat::end`] and is charged all remaining gas.
+    fn step(&mut self, step: &TraceStep);
+
+    fn end(&mut self, result: &TraceResult);
+}
+
+/// The state captured in [`Observer::pre_op`] which is reported once the gas cost is known.
+#[derive(Debug)]
+struct PendingTraceStep {
+    pc: u64,
+    opcode: Opcode,
+    gas: u64,
+    depth: i32,
+    refund: i64,
+    stack: Vec<u256>,
+    memory_size: u64,
+}
+
+impl PendingTraceStep {
+    fn with_gas_cost(&self, gas_cost: u64) -> TraceStep {
+        TraceStep {
+            pc: self.pc,
+            opcode: self.opcode,
+            gas: self.gas,
+            gas_cost,
+            depth: self.depth,
+            refund: self.refund,
+            stack: &self.stack,
+            memory_size: self.memory_size,
+        }
+    }
+}
+
+/// Drives a [`TraceFormat`] with the steps of a single frame. Because observers do not see the
+/// result of an execution, [`FormattingObserver::finish`] must be called with it afterwards.
+#[derive(Debug)]
+pub struct FormattingObserver<F> {
+    format: F,
+    /// The gas limit of the traced frame, set once [`TraceFormat::begin`] was called.
+    gas_limit: Option<u64>,
+    pending: Option<PendingTraceStep>,
+}
+
+impl<F: TraceFormat> FormattingObserver<F> {
+    pub fn new(format: F) -> Self {
+        Self {
+            format,
+            gas_limit: None,
+            pending: None,
+        }
+    }
+
+    /// Reports a failed opcode, if any, and the result of the execution to the format and returns
+    /// it.
+    pub fn finish(mut self, result: &ExecutionResult) -> F {
+        let gas_left = result.gas_left.max(0) as u64;
+        if let Some(pending) = self.pending.take() {
+            let gas_cost = pending.gas.saturating_sub(gas_left);
+            self.format.step(&pending.with_gas_cost(gas_cost));
+        }
+        let gas_limit = self.gas_limit.unwrap_or(gas_left);
+        self.format.end(&TraceResult {
+            status_code: result.status_code,
+            gas_used: gas_limit.saturating_sub(gas_left),
+            output: &result.output,
+        });
+        self.format
+    }
+}
+
+impl<F: TraceFormat, const STEPPABLE: bool> Observer<STEPPABLE> for FormattingObserver<F> {
+    fn pre_op(&mut self, interpreter: &Interpreter<STEPPABLE>, gas_left: u64, gas_refund: i64) {
+        if self.gas_limit.is_none() {
+            let gas_limit = interpreter.message.gas.max(0) as u64;
+            self.gas_limit = Some(gas_limit);
+            self.format.begin(interpreter.revision, gas_limit);
+        }
+        self.pending = Some(PendingTraceStep {
+            pc: interpreter.code_reader.pc() as u64,
+            opcode: current_opcode(interpreter),
+            gas: gas_left,
+            depth: interpreter.message.depth,
+            refund: gas_refund,
+            stack: interpreter.stack.as_slice().to_vec(),
+            memory_size: interpreter.memory.len(),
+        });
+    }
+
+    fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, _gas_left: i64, gas_cost: u64) {
+        if let Some(pending) = self.pending.take() {
+            self.format.step(&pending.with_gas_cost(gas_cost));
+        }
+    }
+
+    fn log(&mut self, _message: Cow<str>) {}
+}
+
+/// Writes one JSON object per step in the format of EIP-3155, see [`Eip3155Json`].
+pub type JsonTraceObserver<W> = FormattingObserver<Eip3155Json<W>>;
+
+/// Formats `value` as a hex string without leading zeros, e.g. `0x0` or `0x1f`.
+fn compact_hex(value: &u256) -> String {
+    let bytes = value.to_be_bytes();
+    let first = bytes.iter().position(|byte| *byte != 0).unwrap_or(31);
+    let mut hex = format!("0x{:x}", bytes[first]);
+    for byte in &bytes[first + 1..] {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    hex
+}
+
+/// Writes one JSON object per line in the format of EIP-3155. Depths are reported 1-based as
+/// required by the EIP.
+pub struct Eip3155Json<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Eip3155Json<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> TraceFormat for Eip3155Json<W> {
+    fn step(&mut self, step: &TraceStep) {
+        let stack = step
+            .stack
+            .iter()
+            .map(|value| format!("\"{}\"", compact_hex(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let op_name = format!("{:?}", step.opcode).to_uppercase();
+        writeln!(
+            self.writer,
+            "{{\"pc\":{},\"op\":{},\"gas\":\"{:#x}\",\"gasCost\":\"{:#x}\",\"memSize\":{},\"stack\":[{stack}],\"depth\":{},\"refund\":{},\"opName\":\"{op_name}\"}}",
+            step.pc,
+            step.opcode as u8,
+            step.gas,
+            step.gas_cost,
+            step.memory_size,
+            step.depth + 1,
+            step.refund,
+        )
+        .unwrap();
+    }
+
+    fn end(&mut self, result: &TraceResult) {
+        let output: String = result
+            .output
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        write!(
+            self.writer,
+            "{{\"output\":\"0x{output}\",\"gasUsed\":\"{:#x}\"",
+            result.gas_used
+        )
+        .unwrap();
+        if result.status_code != StatusCode::EVMC_SUCCESS {
+            write!(self.writer, ",\"error\":\"{:?}\"", result.status_code).unwrap();
+        }
+        writeln!(self.writer, "}}").unwrap();
+        self.writer.flush().unwrap();
+    }
+}
+
+/// Writes fixed-size little-endian records, which are cheaper to produce and parse than JSON.
+///
+/// Each step is written as the tag `0x01` followed by the pc (u64), the opcode (u8), the gas
+/// (u64), the gas cost (u64), the depth (i32) and the stack size (u32). The end is written as the
+/// tag `0x02` followed by the status code (i32), the gas used (u64), the output length (u32) and
+/// the output.
+pub struct CompactBinary<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CompactBinary<W> {
+    pub const STEP_TAG: u8 = 0x01;
+    pub const END_TAG: u8 = 0x02;
+
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> TraceFormat for CompactBinary<W> {
+    fn step(&mut self, step: &TraceStep) {
+        let mut record = [0; 34];
+        record[0] = Self::STEP_TAG;
+        record[1..9].copy_from_slice(&step.pc.to_le_bytes());
+        record[9] = step.opcode as u8;
+        record[10..18].copy_from_slice(&step.gas.to_le_bytes());
+        record[18..26].copy_from_slice(&step.gas_cost.to_le_bytes());
+        record[26..30].copy_from_slice(&step.depth.to_le_bytes());
+        record[30..34].copy_from_slice(&(step.stack.len() as u32).to_le_bytes());
+        self.writer.write_all(&record).unwrap();
+    }
+
+    fn end(&mut self, result: &TraceResult) {
+        let mut record = [0; 17];
+        record[0] = Self::END_TAG;
+        record[1..5].copy_from_slice(&(result.status_code as i32).to_le_bytes());
+        record[5..13].copy_from_slice(&result.gas_used.to_le_bytes());
+        record[13..17].copy_from_slice(&(result.output.len() as u32).to_le_bytes());
+        self.writer.write_all(&record).unwrap();
+        self.writer.write_all(result.output).unwrap();
+        self.writer.flush().unwrap();
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ObserverType {
     NoOp,
     Logging,
+    GasBreakdown,
+    Json,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, cell::RefCell, ops::RangeInclusive, rc::Rc};
+
+    use evmc_vm::{Address, ExecutionResult, Revision, StatusCode};
+
+    use crate::{
+        interpreter::Interpreter,
+        types::{
+            Account, CallGraphObserver, CodeAnalysisCache, CompactBinary, DepthFilterObserver,
+            Eip3155Json, FormattingObserver, GasBreakdownObserver, GasProfileObserver,
+            LoggingObserver, MockExecutionContextTrait, MockExecutionMessage, Observer, Opcode,
+            OpcodeHistogramObserver, OpcodeStats, PendingStorageWrite, RecordedWarning,
+            ReferenceHost, StateDiffObserver, StorageSlotDiff, StorageWriteHaltObserver,
+            StorageWriteObserver, TraceFormat, TraceResult, TraceStep, Warning, WarningObserver,
+            hash_cache::HashCache, u256,
+        },
+    };
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn logging_observer_writes_op_gas_and_top_of_stack() {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push0 as u8,
+            Opcode::Add as u8,
+        ];
+        let mut observer = LoggingObserver::new(Vec::new());
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        let log = String::from_utf8(observer.writer).unwrap();
+        assert_eq!(log, "Push1, 100, -empty-\nPush0, 97, 1\nAdd, 95, 0\n");
+    }
+
+    /// Records the gas left and the gas cost of every executed opcode.
+    #[derive(Default)]
+    struct GasRecordingObserver(Vec<(i64, u64)>);
+
+    impl<const STEPPABLE: bool> Observer<STEPPABLE> for GasRecordingObserver {
+        fn pre_op(
+            &mut self,
+            _interpreter: &Interpreter<STEPPABLE>,
+            _gas_left: u64,
+            _gas_refund: i64,
+        ) {
+        }
+
+        fn post_op(&mut self, _interpreter: &Interpreter<STEPPABLE>, gas_left: i64, gas_cost: u64) {
+            self.0.push((gas_left, gas_cost));
+        }
+
+        fn log(&mut self, _message: Cow<str>) {}
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn post_op_receives_gas_left_and_gas_cost() {
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Add as u8,
+        ];
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut context = MockExecutionContextTrait::new();
+        let mut observer = GasRecordingObserver::default();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(observer.0, [(97, 3), (94, 3), (91, 3)]);
+    }
+
+    /// Records the gas left and gas refund passed to [`Observer::pre_op`].
+    #[derive(Default)]
+    struct PreOpGasObserver(Vec<(u64, i64)>);
+
+    impl<const STEPPABLE: bool> Observer<STEPPABLE> for PreOpGasObserver {
+        fn pre_op(
+            &mut self,
+            _interpreter: &Interpreter<STEPPABLE>,
+            gas_left: u64,
+            gas_refund: i64,
+        ) {
+            self.0.push((gas_left, gas_refund));
+        }
+
+        fn post_op(
+            &mut self,
+            _interpreter: &Interpreter<STEPPABLE>,
+            _gas_left: i64,
+            _gas_cost: u64,
+        ) {
+        }
+
+        fn log(&mut self, _message: Cow<str>) {}
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn pre_op_receives_gas_before_opcode() {
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Add as u8,
+            Opcode::Stop as u8,
+        ];
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut context = MockExecutionContextTrait::new();
+        let mut observer = PreOpGasObserver::default();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(observer.0, [(100, 0), (97, 0), (94, 0), (91, 0)]);
+        assert!(observer.0.windows(2).all(|w| w[0].0 > w[1].0));
+    }
+
+    /// Returns code which calls `addr` with the given call opcode and without any arguments.
+    fn call_code(call: Opcode, addr: u8) -> Vec<u8> {
+        let mut code = vec![
+            Opcode::Push0 as u8, // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push0 as u8, // args len
+            Opcode::Push0 as u8, // args offset
+        ];
+        if matches!(call, Opcode::Call | Opcode::CallCode) {
+            code.push(Opcode::Push0 as u8); // value
+        }
+        code.extend([Opcode::Push1 as u8, addr, Opcode::Gas as u8, call as u8]);
+        code
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn call_graph_to_dot_contains_nodes_and_labeled_edges() {
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        for (addr, gas_used) in [(0xaau8, 100), (0xccu8, 50)] {
+            host.call_router_mut()
+                .route(Address::from(u256::from(addr)), move |message| {
+                    ExecutionResult {
+                        status_code: StatusCode::EVMC_SUCCESS,
+                        gas_left: message.gas - gas_used,
+                        gas_refund: 0,
+                        output: Box::default(),
+                        create_address: None,
+                    }
+                });
+        }
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut observer = CallGraphObserver::new();
+
+        // 0xbb calls 0xaa, which in turn performs a static call to 0xcc. The host does not pass the
+        // observer to the nested frame, so the frame of 0xaa is executed with it explicitly.
+        for (addr, depth, code) in [
+            (0xbbu8, 0, call_code(Opcode::Call, 0xaa)),
+            (0xaau8, 1, call_code(Opcode::StaticCall, 0xcc)),
+        ] {
+            let message = MockExecutionMessage {
+                depth,
+                gas: 100_000,
+                recipient: Address::from(u256::from(addr)),
+                ..Default::default()
+            };
+            let message = message.into();
+            let result: ExecutionResult = Interpreter::new(
+                Revision::EVMC_CANCUN,
+                &message,
+                &mut host,
+                &code,
+                &code_analysis_cache,
+                &hash_cache,
+            )
+            .unwrap()
+            .run(&mut observer);
+            assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        }
+
+        assert_eq!(observer.edges().len(), 2);
+        let dot = observer.to_dot();
+        assert!(dot.starts_with("digraph calls {"));
+        let [aa, bb, cc] = ["aa", "bb", "cc"].map(|addr| format!("\"0x{addr:0>40}\""));
+        for node in [&aa, &bb, &cc] {
+            assert!(dot.contains(&format!("    {node};\n")), "{dot}");
+        }
+        // cold access + gas used by the callee
+        assert!(
+            dot.contains(&format!("{bb} -> {aa} [label=\"CALL\\ngas: 2700\"];")),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(&format!("{aa} -> {cc} [label=\"STATICCALL\\ngas: 2650\"];")),
+            "{dot}"
+        );
+    }
+
+    /// Records the depth of every executed opcode. Clones share the same records.
+    #[derive(Clone, Default)]
+    struct DepthRecordingObserver(Rc<RefCell<Vec<i32>>>);
+
+    impl<const STEPPABLE: bool> Observer<STEPPABLE> for DepthRecordingObserver {
+        fn pre_op(
+            &mut self,
+            interpreter: &Interpreter<STEPPABLE>,
+            _gas_left: u64,
+            _gas_refund: i64,
+        ) {
+            self.0.borrow_mut().push(interpreter.message.depth);
+        }
+
+        fn post_op(
+            &mut self,
+            _interpreter: &Interpreter<STEPPABLE>,
+            _gas_left: i64,
+            _gas_cost: u64,
+        ) {
+        }
+
+        fn log(&mut self, _message: Cow<str>) {}
+    }
+
+    /// Executes a frame at depth 0 which calls 0xaa, which is executed at depth 1 by the host. Both
+    /// frames are observed through a [`DepthFilterObserver`] with the given depths. Returns the
+    /// depths of all steps which reached the inner observer.
+    fn observed_depths_of_two_level_call(depths: RangeInclusive<i32>) -> Vec<i32> {
+        let recorder = DepthRecordingObserver::default();
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let callee_recorder = recorder.clone();
+        let callee_depths = depths.clone();
+        host.call_router_mut()
+            .route(Address::from(u256::from(0xaau8)), move |message| {
+                let code = [Opcode::Push1 as u8, 1, Opcode::Pop as u8];
+                let mut context = MockExecutionContextTrait::new();
+                let mut observer =
+                    DepthFilterObserver::new(callee_recorder.clone(), callee_depths.clone());
+                Interpreter::new(
+                    Revision::EVMC_CANCUN,
+                    message,
+                    &mut context,
+                    &code,
+                    &CodeAnalysisCache::default(),
+                    &HashCache::default(),
+                )
+                .unwrap()
+                .run(&mut observer)
+            });
+
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut observer = DepthFilterObserver::new(recorder.clone(), depths);
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &call_code(Opcode::Call, 0xaa),
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        recorder.0.take()
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn depth_filter_observer_forwards_only_steps_of_matching_depths() {
+        // 5 x PUSH0, PUSH1, GAS, CALL in the caller and PUSH1, POP in the callee
+        assert_eq!(
+            observed_depths_of_two_level_call(0..=1),
+            [0, 0, 0, 0, 0, 0, 0, 0, 1, 1]
+        );
+        assert_eq!(observed_depths_of_two_level_call(0..=0), [0; 8]);
+        assert_eq!(observed_depths_of_two_level_call(1..=1), [1, 1]);
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn gas_breakdown_buckets_sum_to_total_gas() {
+        let code = [
+            Opcode::Push1 as u8,
+            2,
+            Opcode::Push1 as u8,
+            3,
+            Opcode::Add as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push0 as u8,
+            Opcode::SStore as u8,
+        ];
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut observer = GasBreakdownObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        let breakdown = observer.breakdown();
+        // expansion to one word
+        assert_eq!(breakdown.memory, 3);
+        assert!(breakdown.computation > 0);
+        assert!(breakdown.storage > 0);
+        assert_eq!(breakdown.calls, 0);
+        assert_eq!(breakdown.logs, 0);
+        assert_eq!(breakdown.total(), (100_000 - result.gas_left) as u64);
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn storage_write_halt_observer_halts_before_first_storage_write() {
+        let code = [
+            Opcode::Push1 as u8,
+            42, // value
+            Opcode::Push1 as u8,
+            1, // key
+            Opcode::SStore as u8,
+            Opcode::Push1 as u8,
+            43, // value
+            Opcode::Push1 as u8,
+            1, // key
+            Opcode::SStore as u8,
+        ];
+        let message = MockExecutionMessage {
+            recipient: u256::from(0xaau8).into(),
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut context = MockExecutionContextTrait::new();
+        context.expect_set_storage().times(0);
+        let mut observer = StorageWriteHaltObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        // only the two pushes were executed
+        assert_eq!(
+            result.gas_left,
+            MockExecutionMessage::DEFAULT_INIT_GAS as i64 - 2 * 3
+        );
+        assert_eq!(
+            observer.pending_write(),
+            Some(PendingStorageWrite {
+                opcode: Opcode::SStore,
+                address: u256::from(0xaau8).into(),
+                key: u256::ONE,
+                value: u256::from(42u8),
+            })
+        );
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn storage_write_observer_reports_final_value_of_each_slot() {
+        // SSTORE(1, 42), SSTORE(2, 7), SSTORE(1, 43)
+        let mut code = Vec::new();
+        for (key, value) in [(1, 42), (2, 7), (1, 43)] {
+            code.extend([
+                Opcode::Push1 as u8,
+                value,
+                Opcode::Push1 as u8,
+                key,
+                Opcode::SStore as u8,
+            ]);
+        }
+        let message = MockExecutionMessage {
+            recipient: u256::from(0xaau8).into(),
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut observer = StorageWriteObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        let address = u256::from(0xaau8);
+        assert_eq!(
+            observer.into_writes().into_iter().collect::<Vec<_>>(),
+            [
+                ((address, u256::ONE), u256::from(43u8)),
+                ((address, u256::from(2u8)), u256::from(7u8)),
+            ]
+        );
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn state_diff_observer_reports_pre_and_post_value_of_touched_slots() {
+        let address = u256::from(0xaau8);
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.set_account(
+            address.into(),
+            Account {
+                storage: [(u256::ONE, u256::from(42u8))].into(),
+                ..Default::default()
+            },
+        );
+        // SLOAD(2), SSTORE(1, 43)
+        let code = [
+            Opcode::Push1 as u8,
+            2,
+            Opcode::SLoad as u8,
+            Opcode::Push1 as u8,
+            43,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::SStore as u8,
+        ];
+        let message = MockExecutionMessage {
+            recipient: address.into(),
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut observer = StateDiffObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        assert_eq!(
+            observer.diff(),
+            [
+                StorageSlotDiff {
+                    address: address.into(),
+                    key: u256::ONE,
+                    pre: u256::from(42u8),
+                    post: u256::from(43u8),
+                },
+                StorageSlotDiff {
+                    address: address.into(),
+                    key: u256::from(2u8),
+                    pre: u256::ZERO,
+                    post: u256::ZERO,
+                },
+            ]
+        );
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn opcode_histogram_observer_counts_executions_and_gas_per_opcode() {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let code = [
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Add as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::MStore as u8,
+        ];
+        let mut observer = OpcodeHistogramObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        let push0 = OpcodeStats {
+            opcode: Opcode::Push0,
+            count: 2,
+            gas: 4,
+        };
+        // 3 for the opcode and 6 for the memory expansion to 64 bytes
+        let mstore = OpcodeStats {
+            opcode: Opcode::MStore,
+            count: 1,
+            gas: 9,
+        };
+        assert_eq!(observer.get(Opcode::Push0), Some(&push0));
+        assert_eq!(observer.get(Opcode::Sub), None);
+        assert_eq!(observer.stats().count(), 4);
+        assert_eq!(observer.top_by_count(1), [push0]);
+        assert_eq!(observer.top_by_gas(2), [mstore, push0]);
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn gas_profile_observer_attributes_memory_expansion_to_opcode() {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage::default().into();
+        let code = [
+            Opcode::Push0 as u8,
+            Opcode::MLoad as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::MLoad as u8,
+            Opcode::Pop as u8,
+        ];
+        let mut observer = GasProfileObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        // 2 * (3 for the opcode) + 3 for the expansion to 32 bytes + 3 for the expansion to 64
+        // bytes
+        assert_eq!(observer.gas(Opcode::MLoad), 12);
+        assert_eq!(observer.gas(Opcode::MStore), 3);
+        assert_eq!(observer.gas(Opcode::Add), 0);
+        assert_eq!(
+            observer.report(),
+            [
+                (Opcode::MLoad, 12),
+                (Opcode::Push0, 4),
+                (Opcode::MStore, 3),
+                (Opcode::Push1, 3),
+                (Opcode::Pop, 2),
+            ]
+        );
+
+        let mut report = Vec::new();
+        observer.write_report(&mut report).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap().lines().next(),
+            Some("MLoad: 12")
+        );
+    }
+
+    /// Counts the steps and records the gas used reported to it.
+    #[derive(Default)]
+    struct CountingFormat {
+        began: bool,
+        steps: usize,
+        gas_used: Option<u64>,
+    }
+
+    impl TraceFormat for CountingFormat {
+        fn begin(&mut self, _revision: Revision, _gas_limit: u64) {
+            self.began = true;
+        }
+
+        fn step(&mut self, _step: &TraceStep) {
+            self.steps += 1;
+        }
+
+        fn end(&mut self, result: &TraceResult) {
+            self.gas_used = Some(result.gas_used);
+        }
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn warning_observer_reports_deprecated_callcode_without_affecting_execution() {
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.call_router_mut()
+            .route(Address::from(u256::from(0xaau8)), |message| {
+                ExecutionResult {
+                    status_code: StatusCode::EVMC_SUCCESS,
+                    gas_left: message.gas,
+                    gas_refund: 0,
+                    output: Box::default(),
+                    create_address: None,
+                }
+            });
+        let message = MockExecutionMessage {
+            gas: 100_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let code = call_code(Opcode::CallCode, 0xaa);
+        let mut observer = WarningObserver::new();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        assert_eq!(
+            observer.warnings(),
+            [RecordedWarning {
+                pc: code.len() - 1,
+                opcode: Opcode::CallCode,
+                depth: 0,
+                warning: Warning::DeprecatedOpcode,
+            }]
+        );
+    }
+
+    fn run_traced<F: TraceFormat>(code: &[u8], format: F) -> F {
+        let mut context = MockExecutionContextTrait::new();
+        let message = MockExecutionMessage {
+            gas: 100,
+            ..Default::default()
+        };
+        let message = message.into();
+        let mut observer = FormattingObserver::new(format);
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut context,
+            code,
+            &CodeAnalysisCache::default(),
+            &HashCache::default(),
+        )
+        .unwrap()
+        .run(&mut observer);
+        observer.finish(&result)
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn formatting_observer_drives_custom_trace_format() {
+        let code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push0 as u8,
+            Opcode::Add as u8,
+        ];
+        let format = run_traced(&code, CountingFormat::default());
+        assert!(format.began);
+        assert_eq!(format.steps, 3);
+        assert_eq!(format.gas_used, Some(8));
+
+        // the failing opcode is reported as well and charged all remaining gas
+        let code = [Opcode::Push0 as u8, Opcode::Add as u8];
+        let format = run_traced(&code, CountingFormat::default());
+        assert_eq!(format.steps, 2);
+        assert_eq!(format.gas_used, Some(100));
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn eip3155_json_writes_one_line_per_step_and_summary() {
+        let code = [Opcode::Push1 as u8, 0x1f, Opcode::Push0 as u8];
+        let trace = run_traced(&code, Eip3155Json::new(Vec::new())).into_inner();
+        let trace = String::from_utf8(trace).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"pc":0,"op":96,"gas":"0x64","gasCost":"0x3","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"PUSH1"}"#,
+                r#"{"pc":2,"op":95,"gas":"0x61","gasCost":"0x2","memSize":0,"stack":["0x1f"],"depth":1,"refund":0,"opName":"PUSH0"}"#,
+                r#"{"output":"0x","gasUsed":"0x5"}"#,
+            ]
+        );
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn compact_binary_writes_fixed_size_records() {
+        let code = [Opcode::Push0 as u8];
+        let trace = run_traced(&code, CompactBinary::new(Vec::new())).into_inner();
+        assert_eq!(trace.len(), 34 + 17);
+        assert_eq!(trace[0], CompactBinary::<Vec<u8>>::STEP_TAG);
+        assert_eq!(trace[9], Opcode::Push0 as u8);
+        assert_eq!(trace[18..26], 2u64.to_le_bytes());
+        assert_eq!(trace[34], CompactBinary::<Vec<u8>>::END_TAG);
+        assert_eq!(trace[39..47], 2u64.to_le_bytes());
+    }
+
+    /// Records the opcode, gas and stack of every step reported to it.
+    #[derive(Default)]
+    struct RecordingFormat(Vec<(Opcode, u64, u64, Vec<u256>)>);
+
+    impl TraceFormat for RecordingFormat {
+        fn step(&mut self, step: &TraceStep) {
+            self.0
+                .push((step.opcode, step.gas, step.gas_cost, step.stack.to_vec()));
+        }
+
+        fn end(&mut self, _result: &TraceResult) {}
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(all(feature = "fn-ptr-conversion-dispatch", not(feature = "tail-call")))]
+    #[test]
+    fn traced_pcs_are_offsets_in_original_code() {
+        struct PcFormat(Vec<u64>);
+
+        impl TraceFormat for PcFormat {
+            fn step(&mut self, step: &TraceStep) {
+                self.0.push(step.pc);
+            }
+
+            fn end(&mut self, _result: &TraceResult) {}
+        }
+
+        let mut code = vec![Opcode::Push32 as u8];
+        code.extend_from_slice(&[0xff; 32]);
+        code.extend_from_slice(&[
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Add as u8,
+            Opcode::Push1 as u8,
+            39,
+            Opcode::Jump as u8,
+            Opcode::JumpDest as u8,
+            Opcode::Pop as u8,
+        ]);
+        let PcFormat(pcs) = run_traced(&code, PcFormat(Vec::new()));
+        // JUMP continues after the JUMPDEST it jumps to, so the JUMPDEST is not traced
+        assert_eq!(pcs, [0, 33, 35, 36, 38, 40]);
+    }
+
+    // observers are not called if feature tail-call is enabled
+    #[cfg(not(feature = "tail-call"))]
+    #[test]
+    fn traced_gas_matches_gas_pushed_by_gas_opcode() {
+        let code = [
+            Opcode::Gas as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::MStore as u8, // memory expansion
+            Opcode::Gas as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Add as u8,
+            Opcode::Gas as u8,
+            Opcode::Pop as u8,
+        ];
+        let steps = run_traced(&code, RecordingFormat::default()).0;
+        assert_eq!(steps.len(), 9);
+
+        let mut gas_ops = 0;
+        for (step, next) in steps.iter().zip(&steps[1..]) {
+            let (op, gas, gas_cost, _) = step;
+            if *op != Opcode::Gas {
+                assert_eq!(next.1, gas - gas_cost);
+                continue;
+            }
+            gas_ops += 1;
+            // GAS pushes the gas left after charging its own cost, which is the traced gas of the
+            // next step
+            let pushed = *next.3.last().unwrap();
+            assert_eq!(pushed, u256::from(gas - gas_cost));
+            assert_eq!(pushed, u256::from(next.1));
+        }
+        assert_eq!(gas_ops, 3);
+    }
 }
@@ -11,11 +11,18 @@ use crate::{
 pub struct OpFnData<const STEPPABLE: bool> {
     func: Option<OpFn<STEPPABLE>>,
     data: u256,
+    #[cfg(feature = "stack-precheck")]
+    op: u8,
 }
 
 impl<const STEPPABLE: bool> OpFnData<STEPPABLE> {
     pub fn data(data: u256) -> Self {
-        Self { func: None, data }
+        Self {
+            func: None,
+            data,
+            #[cfg(feature = "stack-precheck")]
+            op: 0,
+        }
     }
 
     pub fn skip_no_ops_iter(count: usize) -> impl Iterator<Item = Self> {
@@ -25,9 +32,16 @@ impl<const STEPPABLE: bool> OpFnData<STEPPABLE> {
     }
 
     pub fn func(op: u8, data: u256) -> Self {
+        Self::func_from(interpreter::get_jumptable(), op, data)
+    }
+
+    /// Like [`Self::func`], but takes the function of `op` from `jumptable`.
+    pub fn func_from(jumptable: &[OpFn<STEPPABLE>; 256], op: u8, data: u256) -> Self {
         Self {
-            func: Some(interpreter::get_jumptable()[op as usize]),
+            func: Some(jumptable[op as usize]),
             data,
+            #[cfg(feature = "stack-precheck")]
+            op,
         }
     }
 
@@ -58,6 +72,11 @@ impl<const STEPPABLE: bool> OpFnData<STEPPABLE> {
     pub fn get_data(&self) -> u256 {
         self.data
     }
+
+    #[cfg(feature = "stack-precheck")]
+    pub fn opcode(&self) -> u8 {
+        self.op
+    }
 }
 
 impl<const STEPPABLE: bool> Debug for OpFnData<STEPPABLE> {
@@ -1,3 +1,5 @@
+use evmc_vm::Revision;
+
 const STOP: u8 = 0x00;
 const ADD: u8 = 0x01;
 const MUL: u8 = 0x02;
@@ -148,7 +150,7 @@ const REVERT: u8 = 0xFD;
 const INVALID: u8 = 0xFE;
 const SELFDESTRUCT: u8 = 0xFF;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(u8)]
 pub enum Opcode {
     Stop = STOP,
@@ -341,3 +343,160 @@ pub fn code_byte_type(code_byte: u8) -> (CodeByteType, usize) {
         _ => (CodeByteType::DataOrInvalid, 0),
     }
 }
+
+/// The stack requirements of an opcode and the first revision in which it is defined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpcodeInfo {
+    /// Number of items popped from the stack.
+    pub stack_in: u8,
+    /// Number of items pushed to the stack.
+    pub stack_out: u8,
+    /// Opcodes which are defined in all supported revisions use [`Revision::EVMC_FRONTIER`].
+    pub min_revision: Revision,
+}
+
+impl OpcodeInfo {
+    const fn new(stack_in: u8, stack_out: u8) -> Self {
+        Self {
+            stack_in,
+            stack_out,
+            min_revision: Revision::EVMC_FRONTIER,
+        }
+    }
+
+    const fn since(self, min_revision: Revision) -> Self {
+        Self {
+            min_revision,
+            ..self
+        }
+    }
+}
+
+/// The [`OpcodeInfo`] of all 256 code bytes, indexed by the code byte. Undefined opcodes neither
+/// pop nor push.
+pub static OPCODE_INFO: [OpcodeInfo; 256] = {
+    let mut table = [OpcodeInfo::new(0, 0); 256];
+    let mut code_byte = 0;
+    while code_byte < 256 {
+        table[code_byte] = opcode_info(code_byte as u8);
+        code_byte += 1;
+    }
+    table
+};
+
+/// Returns all opcodes together with the first revision in which they are defined, ordered by
+/// their code byte. Opcodes which are defined in all supported revisions are reported with
+/// [`Revision::EVMC_FRONTIER`]. The revisions are taken from [`OPCODE_INFO`], which must match
+/// the revision checks of the opcode handlers, so this can be used to generate documentation.
+pub fn opcode_availability() -> Vec<(Opcode, Revision)> {
+    (0..=u8::MAX)
+        .filter(|code_byte| code_byte_type(*code_byte).0 != CodeByteType::DataOrInvalid)
+        .map(|code_byte| {
+            // SAFETY:
+            // All bytes which are not data or invalid according to the code analysis are variants
+            // of Opcode.
+            let opcode = unsafe { std::mem::transmute::<u8, Opcode>(code_byte) };
+            (opcode, OPCODE_INFO[code_byte as usize].min_revision)
+        })
+        .collect()
+}
+
+const fn opcode_info(code_byte: u8) -> OpcodeInfo {
+    match code_byte {
+        ADDRESS
+        | ORIGIN
+        | CALLER
+        | CALLVALUE
+        | CALLDATASIZE
+        | CODESIZE
+        | GASPRICE
+        | COINBASE
+        | TIMESTAMP
+        | NUMBER
+        | PREVRANDAO
+        | GASLIMIT
+        | PC
+        | MSIZE
+        | GAS
+        | PUSH1..=PUSH32 => OpcodeInfo::new(0, 1),
+        RETURNDATASIZE => OpcodeInfo::new(0, 1).since(Revision::EVMC_BYZANTIUM),
+        CHAINID | SELFBALANCE => OpcodeInfo::new(0, 1).since(Revision::EVMC_ISTANBUL),
+        BASEFEE => OpcodeInfo::new(0, 1).since(Revision::EVMC_LONDON),
+        BLOBBASEFEE => OpcodeInfo::new(0, 1).since(Revision::EVMC_CANCUN),
+        PUSH0 => OpcodeInfo::new(0, 1).since(Revision::EVMC_SHANGHAI),
+        ISZERO | NOT | BALANCE | CALLDATALOAD | EXTCODESIZE | BLOCKHASH | MLOAD | SLOAD => {
+            OpcodeInfo::new(1, 1)
+        }
+        EXTCODEHASH => OpcodeInfo::new(1, 1).since(Revision::EVMC_CONSTANTINOPLE),
+        BLOBHASH | TLOAD => OpcodeInfo::new(1, 1).since(Revision::EVMC_CANCUN),
+        POP | JUMP | SELFDESTRUCT => OpcodeInfo::new(1, 0),
+        ADD | MUL | SUB | DIV | SDIV | MOD | SMOD | EXP | SIGNEXTEND | LT | GT | SLT | SGT | EQ
+        | AND | OR | XOR | BYTE | SHA3 => OpcodeInfo::new(2, 1),
+        SHL | SHR | SAR => OpcodeInfo::new(2, 1).since(Revision::EVMC_CONSTANTINOPLE),
+        MSTORE | MSTORE8 | SSTORE | JUMPI | RETURN => OpcodeInfo::new(2, 0),
+        REVERT => OpcodeInfo::new(2, 0).since(Revision::EVMC_BYZANTIUM),
+        TSTORE => OpcodeInfo::new(2, 0).since(Revision::EVMC_CANCUN),
+        ADDMOD | MULMOD | CREATE => OpcodeInfo::new(3, 1),
+        CALLDATACOPY | CODECOPY => OpcodeInfo::new(3, 0),
+        RETURNDATACOPY => OpcodeInfo::new(3, 0).since(Revision::EVMC_BYZANTIUM),
+        MCOPY => OpcodeInfo::new(3, 0).since(Revision::EVMC_CANCUN),
+        EXTCODECOPY => OpcodeInfo::new(4, 0),
+        CREATE2 => OpcodeInfo::new(4, 1).since(Revision::EVMC_CONSTANTINOPLE),
+        DELEGATECALL => OpcodeInfo::new(6, 1).since(Revision::EVMC_HOMESTEAD),
+        STATICCALL => OpcodeInfo::new(6, 1).since(Revision::EVMC_BYZANTIUM),
+        CALL | CALLCODE => OpcodeInfo::new(7, 1),
+        DUP1..=DUP16 => {
+            let n = code_byte - DUP1 + 1;
+            OpcodeInfo::new(n, n + 1)
+        }
+        SWAP1..=SWAP16 => {
+            let n = code_byte - SWAP1 + 1;
+            OpcodeInfo::new(n + 1, n + 1)
+        }
+        LOG0..=LOG4 => OpcodeInfo::new(code_byte - LOG0 + 2, 0),
+        _ => OpcodeInfo::new(0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::Revision;
+
+    use crate::types::{CodeByteType, Opcode, code_byte_type, opcode_availability};
+
+    #[test]
+    fn opcode_availability_defines_introduction_revision_of_every_opcode() {
+        let availability = opcode_availability();
+        let opcodes: Vec<u8> = availability
+            .iter()
+            .map(|(opcode, _)| *opcode as u8)
+            .collect();
+        let code_bytes: Vec<u8> = (0..=u8::MAX)
+            .filter(|code_byte| code_byte_type(*code_byte).0 != CodeByteType::DataOrInvalid)
+            .collect();
+        assert_eq!(opcodes, code_bytes);
+
+        for (opcode, revision) in availability {
+            let expected = match opcode {
+                Opcode::DelegateCall => Revision::EVMC_HOMESTEAD,
+                Opcode::ReturnDataSize
+                | Opcode::ReturnDataCopy
+                | Opcode::StaticCall
+                | Opcode::Revert => Revision::EVMC_BYZANTIUM,
+                Opcode::Shl | Opcode::Shr | Opcode::Sar | Opcode::ExtCodeHash | Opcode::Create2 => {
+                    Revision::EVMC_CONSTANTINOPLE
+                }
+                Opcode::ChainId | Opcode::SelfBalance => Revision::EVMC_ISTANBUL,
+                Opcode::BaseFee => Revision::EVMC_LONDON,
+                Opcode::Push0 => Revision::EVMC_SHANGHAI,
+                Opcode::BlobHash
+                | Opcode::BlobBaseFee
+                | Opcode::TLoad
+                | Opcode::TStore
+                | Opcode::MCopy => Revision::EVMC_CANCUN,
+                _ => Revision::EVMC_FRONTIER,
+            };
+            assert_eq!(revision, expected, "{opcode:?}");
+        }
+    }
+}
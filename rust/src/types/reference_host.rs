@@ -0,0 +1,970 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use evmc_vm::{
+    AccessStatus, Address, ExecutionContext, ExecutionMessage, ExecutionResult, ExecutionTxContext,
+    MessageKind, Revision, StatusCode, StorageStatus, Uint256, ffi::evmc_host_interface,
+};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    interpreter::Interpreter,
+    types::{
+        CodeAnalysisCache, ExecutionContextTrait, MemoryBudget, NoOpObserver,
+        dry_run_context::storage_status, hash_cache::HashCache, u256,
+    },
+};
+
+type CallHandler = Box<dyn FnMut(&ExecutionMessage) -> ExecutionResult>;
+
+/// Maps call targets to closures which produce the result of a call to that target. This allows
+/// stubbing out dependencies of a contract under test without deploying their code.
+#[derive(Default)]
+pub struct MockCallRouter {
+    routes: BTreeMap<u256, CallHandler>,
+}
+
+impl MockCallRouter {
+    /// Route all calls to `addr` to `handler`. A previously registered handler for the same
+    /// address is replaced.
+    pub fn route<F>(&mut self, addr: Address, handler: F)
+    where
+        F: FnMut(&ExecutionMessage) -> ExecutionResult + 'static,
+    {
+        self.routes.insert(addr.into(), Box::new(handler));
+    }
+
+    /// Returns the result of the registered handler for the code address of `message` or `None`
+    /// if no handler is registered for it.
+    pub fn dispatch(&mut self, message: &ExecutionMessage) -> Option<ExecutionResult> {
+        self.routes
+            .get_mut(&u256::from(message.code_address))
+            .map(|handler| handler(message))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub balance: u256,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<u256, u256>,
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Self {
+            balance: u256::ZERO,
+            code: Vec::new(),
+            storage: BTreeMap::new(),
+        }
+    }
+}
+
+/// A log emitted via one of the LOG opcodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub address: Address,
+    pub data: Vec<u8>,
+    pub topics: Vec<Uint256>,
+}
+
+/// A simple in-memory host for tests.
+///
+/// Calls are first dispatched through the [`MockCallRouter`]. Calls to addresses without a
+/// registered handler execute the code of the target account with a nested interpreter. If a
+/// call fails, all state changes made during it are reverted. CREATE and CREATE2 are not
+/// supported and fail.
+pub struct ReferenceHost {
+    pub revision: Revision,
+    pub tx_context: ExecutionTxContext<'static>,
+    accounts: BTreeMap<u256, Account>,
+    original_storage: BTreeMap<(u256, u256), u256>,
+    transient_storage: BTreeMap<(u256, u256), u256>,
+    accessed_accounts: BTreeSet<u256>,
+    accessed_storage: BTreeSet<(u256, u256)>,
+    destructed_accounts: BTreeSet<u256>,
+    touched_accounts: BTreeSet<u256>,
+    logs: Vec<Log>,
+    call_router: MockCallRouter,
+}
+
+/// The state of a [`ReferenceHost`] before a call, which is restored if the call fails.
+struct Snapshot {
+    accounts: BTreeMap<u256, Account>,
+    transient_storage: BTreeMap<(u256, u256), u256>,
+    accessed_accounts: BTreeSet<u256>,
+    accessed_storage: BTreeSet<(u256, u256)>,
+    destructed_accounts: BTreeSet<u256>,
+    touched_accounts: BTreeSet<u256>,
+    logs_len: usize,
+}
+
+impl ReferenceHost {
+    pub fn new(revision: Revision) -> Self {
+        Self {
+            revision,
+            tx_context: ExecutionTxContext {
+                tx_gas_price: u256::ZERO.into(),
+                tx_origin: u256::ZERO.into(),
+                block_coinbase: u256::ZERO.into(),
+                block_number: 0,
+                block_timestamp: 0,
+                block_gas_limit: 0,
+                block_prev_randao: u256::ZERO.into(),
+                chain_id: u256::ZERO.into(),
+                block_base_fee: u256::ZERO.into(),
+                blob_base_fee: u256::ZERO.into(),
+                blob_hashes: &[],
+                initcodes: &[],
+            },
+            accounts: BTreeMap::new(),
+            original_storage: BTreeMap::new(),
+            transient_storage: BTreeMap::new(),
+            accessed_accounts: BTreeSet::new(),
+            accessed_storage: BTreeSet::new(),
+            destructed_accounts: BTreeSet::new(),
+            touched_accounts: BTreeSet::new(),
+            logs: Vec::new(),
+            call_router: MockCallRouter::default(),
+        }
+    }
+
+    pub fn set_account(&mut self, addr: Address, account: Account) {
+        self.accounts.insert(addr.into(), account);
+    }
+
+    pub fn account(&self, addr: &Address) -> Option<&Account> {
+        self.accounts.get(&u256::from(addr))
+    }
+
+    /// All logs emitted so far, in emission order.
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    /// Executes `message` without restoring the state if it fails. See [`Self::call`].
+    fn execute_call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        self.touched_accounts.insert(message.recipient.into());
+        if let Some(result) = self.call_router.dispatch(message) {
+            return result;
+        }
+        if matches!(
+            message.kind,
+            MessageKind::EVMC_CREATE | MessageKind::EVMC_CREATE2
+        ) {
+            return ExecutionResult {
+                status_code: StatusCode::EVMC_FAILURE,
+                gas_left: 0,
+                gas_refund: 0,
+                output: Box::default(),
+                create_address: None,
+            };
+        }
+
+        let value = u256::from(message.value);
+        if message.kind == MessageKind::EVMC_CALL && value != u256::ZERO {
+            self.touched_accounts.insert(message.sender.into());
+            self.accounts
+                .entry(message.sender.into())
+                .or_default()
+                .balance -= value;
+            self.accounts
+                .entry(message.recipient.into())
+                .or_default()
+                .balance += value;
+        }
+
+        let code = self
+            .accounts
+            .get(&u256::from(message.code_address))
+            .map(|account| account.code.clone())
+            .unwrap_or_default();
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let mut interpreter = match Interpreter::new(
+            self.revision,
+            message,
+            self,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        ) {
+            Ok(interpreter) => interpreter,
+            Err(err) => return err.into(),
+        };
+        // nested frames share the memory budget of the outermost frame, like in EvmRs
+        if let Some(budget) = MemoryBudget::current() {
+            interpreter = interpreter.with_memory_budget(budget);
+        }
+        interpreter.run(&mut NoOpObserver())
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            accounts: self.accounts.clone(),
+            transient_storage: self.transient_storage.clone(),
+            accessed_accounts: self.accessed_accounts.clone(),
+            accessed_storage: self.accessed_storage.clone(),
+            destructed_accounts: self.destructed_accounts.clone(),
+            touched_accounts: self.touched_accounts.clone(),
+            logs_len: self.logs.len(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.accounts = snapshot.accounts;
+        self.transient_storage = snapshot.transient_storage;
+        self.accessed_accounts = snapshot.accessed_accounts;
+        self.accessed_storage = snapshot.accessed_storage;
+        self.destructed_accounts = snapshot.destructed_accounts;
+        self.touched_accounts = snapshot.touched_accounts;
+        self.logs.truncate(snapshot.logs_len);
+    }
+
+    /// All accounts which were the target of a call, received or sent value, had their storage
+    /// written or were involved in a selfdestruct, sorted in ascending order by address.
+    pub fn touched_accounts(&self) -> Vec<Address> {
+        self.touched_accounts
+            .iter()
+            .map(|addr| Address::from(*addr))
+            .collect()
+    }
+
+    pub fn call_router_mut(&mut self) -> &mut MockCallRouter {
+        &mut self.call_router
+    }
+
+    /// Returns an [`ExecutionContext`] whose host interface forwards all callbacks to this host.
+    /// This allows driving code which goes through the EVMC host interface, like
+    /// [`evmc_vm::EvmcVm::execute`], with this host. The host stays mutably borrowed for as long
+    /// as the context is alive, so the host context pointer cannot dangle.
+    pub fn as_execution_context(&mut self) -> ExecutionContext<'_> {
+        ExecutionContext::new(&REFERENCE_HOST_INTERFACE, (self as *mut Self).cast())
+    }
+}
+
+impl ExecutionContextTrait for ReferenceHost {
+    fn get_tx_context(&mut self) -> &ExecutionTxContext<'_> {
+        &self.tx_context
+    }
+
+    fn account_exists(&self, address: &Address) -> bool {
+        self.accounts.contains_key(&u256::from(address))
+    }
+
+    fn get_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        self.accounts
+            .get(&u256::from(address))
+            .and_then(|account| account.storage.get(&u256::from(*key)))
+            .copied()
+            .unwrap_or(u256::ZERO)
+            .into()
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) -> StorageStatus {
+        let addr = u256::from(address);
+        let key = u256::from(*key);
+        let new = u256::from(*value);
+        self.touched_accounts.insert(addr);
+        let storage = &mut self.accounts.entry(addr).or_default().storage;
+        let current = storage.get(&key).copied().unwrap_or(u256::ZERO);
+        let original = *self.original_storage.entry((addr, key)).or_insert(current);
+        if new == u256::ZERO {
+            storage.remove(&key);
+        } else {
+            storage.insert(key, new);
+        }
+        storage_status(original, current, new)
+    }
+
+    fn get_balance(&self, address: &Address) -> Uint256 {
+        self.accounts
+            .get(&u256::from(address))
+            .map(|account| account.balance)
+            .unwrap_or(u256::ZERO)
+            .into()
+    }
+
+    fn get_code_size(&self, address: &Address) -> usize {
+        self.accounts
+            .get(&u256::from(address))
+            .map(|account| account.code.len())
+            .unwrap_or_default()
+    }
+
+    fn get_code_hash(&self, address: &Address) -> Uint256 {
+        match self.accounts.get(&u256::from(address)) {
+            Some(account) => Uint256 {
+                bytes: Keccak256::digest(&account.code).into(),
+            },
+            None => u256::ZERO.into(),
+        }
+    }
+
+    fn copy_code(&self, address: &Address, code_offset: usize, buffer: &mut [u8]) -> usize {
+        let Some(account) = self.accounts.get(&u256::from(address)) else {
+            return 0;
+        };
+        let code = account.code.get(code_offset..).unwrap_or_default();
+        let len = buffer.len().min(code.len());
+        buffer[..len].copy_from_slice(&code[..len]);
+        len
+    }
+
+    fn selfdestruct(&mut self, address: &Address, beneficiary: &Address) -> bool {
+        let addr = u256::from(address);
+        let balance = self
+            .accounts
+            .get_mut(&addr)
+            .map(|account| std::mem::replace(&mut account.balance, u256::ZERO))
+            .unwrap_or(u256::ZERO);
+        self.accounts.entry(beneficiary.into()).or_default().balance += balance;
+        self.touched_accounts.insert(addr);
+        self.touched_accounts.insert(beneficiary.into());
+        self.destructed_accounts.insert(addr)
+    }
+
+    fn call(&mut self, message: &ExecutionMessage) -> ExecutionResult {
+        let snapshot = self.snapshot();
+        let result = self.execute_call(message);
+        if result.status_code != StatusCode::EVMC_SUCCESS {
+            self.restore(snapshot);
+        }
+        result
+    }
+
+    fn get_block_hash(&self, _num: i64) -> Uint256 {
+        u256::ZERO.into()
+    }
+
+    fn emit_log(&mut self, address: &Address, data: &[u8], topics: &[Uint256]) {
+        self.logs.push(Log {
+            address: *address,
+            data: data.to_vec(),
+            topics: topics.to_vec(),
+        });
+    }
+
+    fn access_account(&mut self, address: &Address) -> AccessStatus {
+        if self.accessed_accounts.insert(address.into()) {
+            AccessStatus::EVMC_ACCESS_COLD
+        } else {
+            AccessStatus::EVMC_ACCESS_WARM
+        }
+    }
+
+    fn access_storage(&mut self, address: &Address, key: &Uint256) -> AccessStatus {
+        if self
+            .accessed_storage
+            .insert((address.into(), (*key).into()))
+        {
+            AccessStatus::EVMC_ACCESS_COLD
+        } else {
+            AccessStatus::EVMC_ACCESS_WARM
+        }
+    }
+
+    fn get_transient_storage(&self, address: &Address, key: &Uint256) -> Uint256 {
+        self.transient_storage
+            .get(&(u256::from(address), u256::from(*key)))
+            .copied()
+            .unwrap_or(u256::ZERO)
+            .into()
+    }
+
+    fn set_transient_storage(&mut self, address: &Address, key: &Uint256, value: &Uint256) {
+        self.transient_storage
+            .insert((address.into(), (*key).into()), (*value).into());
+    }
+}
+
+/// The host interface of [`ReferenceHost::as_execution_context`].
+static REFERENCE_HOST_INTERFACE: evmc_host_interface = evmc_host_interface {
+    account_exists: Some(callbacks::account_exists),
+    get_storage: Some(callbacks::get_storage),
+    set_storage: Some(callbacks::set_storage),
+    get_balance: Some(callbacks::get_balance),
+    get_code_size: Some(callbacks::get_code_size),
+    get_code_hash: Some(callbacks::get_code_hash),
+    copy_code: Some(callbacks::copy_code),
+    selfdestruct: Some(callbacks::selfdestruct),
+    call: Some(callbacks::call),
+    get_tx_context: Some(callbacks::get_tx_context),
+    get_block_hash: Some(callbacks::get_block_hash),
+    emit_log: Some(callbacks::emit_log),
+    access_account: Some(callbacks::access_account),
+    access_storage: Some(callbacks::access_storage),
+    get_transient_storage: Some(callbacks::get_transient_storage),
+    set_transient_storage: Some(callbacks::set_transient_storage),
+};
+
+/// The callbacks of [`REFERENCE_HOST_INTERFACE`]. They are only called through an
+/// [`ExecutionContext`] created by [`ReferenceHost::as_execution_context`], so the host context
+/// always points to a [`ReferenceHost`] which is exclusively borrowed by that context. All other
+/// pointers are valid for the duration of the callback according to the EVMC specification.
+mod callbacks {
+    use std::slice;
+
+    use evmc_vm::{
+        AccessStatus, Address, ExecutionMessage, StorageStatus, Uint256,
+        ffi::{evmc_host_context, evmc_message, evmc_result, evmc_tx_context},
+    };
+
+    use crate::types::{ExecutionContextTrait, ReferenceHost};
+
+    /// # Safety
+    /// `context` must point to a [`ReferenceHost`] which is not accessed otherwise while the
+    /// returned reference is alive.
+    unsafe fn host<'a>(context: *mut evmc_host_context) -> &'a mut ReferenceHost {
+        // SAFETY:
+        // Guaranteed by the caller.
+        unsafe { &mut *context.cast::<ReferenceHost>() }
+    }
+
+    /// # Safety
+    /// If `len` is not zero, `data` must be valid for reads of `len` elements.
+    unsafe fn slice_or_empty<'a, T>(data: *const T, len: usize) -> &'a [T] {
+        if len == 0 {
+            return &[];
+        }
+        // SAFETY:
+        // Guaranteed by the caller.
+        unsafe { slice::from_raw_parts(data, len) }
+    }
+
+    pub(super) unsafe extern "C" fn account_exists(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+    ) -> bool {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        host.account_exists(addr)
+    }
+
+    pub(super) unsafe extern "C" fn get_storage(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        key: *const Uint256,
+    ) -> Uint256 {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let key = unsafe { &*key };
+        host.get_storage(addr, key)
+    }
+
+    pub(super) unsafe extern "C" fn set_storage(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        key: *const Uint256,
+        value: *const Uint256,
+    ) -> StorageStatus {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let key = unsafe { &*key };
+        // SAFETY:
+        // See module documentation.
+        let value = unsafe { &*value };
+        host.set_storage(addr, key, value)
+    }
+
+    pub(super) unsafe extern "C" fn get_balance(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+    ) -> Uint256 {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        host.get_balance(addr)
+    }
+
+    pub(super) unsafe extern "C" fn get_code_size(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+    ) -> usize {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        host.get_code_size(addr)
+    }
+
+    pub(super) unsafe extern "C" fn get_code_hash(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+    ) -> Uint256 {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        host.get_code_hash(addr)
+    }
+
+    pub(super) unsafe extern "C" fn copy_code(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        code_offset: usize,
+        buffer: *mut u8,
+        buffer_len: usize,
+    ) -> usize {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        if buffer_len == 0 {
+            return 0;
+        }
+        // SAFETY:
+        // See module documentation. The buffer is not empty, so it is not a null pointer.
+        let buffer = unsafe { slice::from_raw_parts_mut(buffer, buffer_len) };
+        host.copy_code(addr, code_offset, buffer)
+    }
+
+    pub(super) unsafe extern "C" fn selfdestruct(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        beneficiary: *const Address,
+    ) -> bool {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let beneficiary = unsafe { &*beneficiary };
+        host.selfdestruct(addr, beneficiary)
+    }
+
+    pub(super) unsafe extern "C" fn call(
+        context: *mut evmc_host_context,
+        message: *const evmc_message,
+    ) -> evmc_result {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let message = unsafe { &*message };
+        host.call(&ExecutionMessage::from(message)).into()
+    }
+
+    pub(super) unsafe extern "C" fn get_tx_context(
+        context: *mut evmc_host_context,
+    ) -> evmc_tx_context {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        (*host.get_tx_context()).into()
+    }
+
+    pub(super) unsafe extern "C" fn get_block_hash(
+        context: *mut evmc_host_context,
+        num: i64,
+    ) -> Uint256 {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        host.get_block_hash(num)
+    }
+
+    pub(super) unsafe extern "C" fn emit_log(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        data: *const u8,
+        data_len: usize,
+        topics: *const Uint256,
+        topics_len: usize,
+    ) {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let data = unsafe { slice_or_empty(data, data_len) };
+        // SAFETY:
+        // See module documentation.
+        let topics = unsafe { slice_or_empty(topics, topics_len) };
+        host.emit_log(addr, data, topics);
+    }
+
+    pub(super) unsafe extern "C" fn access_account(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+    ) -> AccessStatus {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        host.access_account(addr)
+    }
+
+    pub(super) unsafe extern "C" fn access_storage(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        key: *const Uint256,
+    ) -> AccessStatus {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let key = unsafe { &*key };
+        host.access_storage(addr, key)
+    }
+
+    pub(super) unsafe extern "C" fn get_transient_storage(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        key: *const Uint256,
+    ) -> Uint256 {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let key = unsafe { &*key };
+        host.get_transient_storage(addr, key)
+    }
+
+    pub(super) unsafe extern "C" fn set_transient_storage(
+        context: *mut evmc_host_context,
+        addr: *const Address,
+        key: *const Uint256,
+        value: *const Uint256,
+    ) {
+        // SAFETY:
+        // See module documentation.
+        let host = unsafe { host(context) };
+        // SAFETY:
+        // See module documentation.
+        let addr = unsafe { &*addr };
+        // SAFETY:
+        // See module documentation.
+        let key = unsafe { &*key };
+        // SAFETY:
+        // See module documentation.
+        let value = unsafe { &*value };
+        host.set_transient_storage(addr, key, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evmc_vm::{Address, ExecutionResult, Revision, StatusCode, StorageStatus, Uint256};
+
+    use crate::{
+        interpreter::Interpreter,
+        types::{
+            Account, CodeAnalysisCache, ExecutionContextTrait, Log, MockExecutionMessage,
+            NoOpObserver, Opcode, ReferenceHost, hash_cache::HashCache, u256,
+        },
+    };
+
+    #[test]
+    fn call_to_routed_address_returns_stubbed_result() {
+        let stub_addr = Address::from(u256::from(0xaau8));
+        let contract_addr = Address::from(u256::from(0xbbu8));
+
+        let mut output = [0; 32];
+        output[31] = 41;
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        host.call_router_mut()
+            .route(stub_addr, move |message| ExecutionResult {
+                status_code: StatusCode::EVMC_SUCCESS,
+                gas_left: message.gas,
+                gas_refund: 0,
+                output: Box::from(output.as_slice()),
+                create_address: None,
+            });
+
+        // CALL 0xaa with 32 bytes of return data written to memory offset 0,
+        // then return MLOAD(0) + 1
+        let code = [
+            Opcode::Push1 as u8,
+            32,                  // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push0 as u8, // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push0 as u8, // value
+            Opcode::Push1 as u8,
+            0xaa, // addr
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+            Opcode::Pop as u8,
+            Opcode::Push0 as u8,
+            Opcode::MLoad as u8,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Add as u8,
+            Opcode::Push0 as u8,
+            Opcode::MStore as u8,
+            Opcode::Push1 as u8,
+            32,
+            Opcode::Push0 as u8,
+            Opcode::Return as u8,
+        ];
+        host.set_account(
+            contract_addr,
+            Account {
+                code: code.to_vec(),
+                ..Default::default()
+            },
+        );
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            recipient: contract_addr,
+            code_address: contract_addr,
+            gas: 1_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+        let mut expected = [0; 32];
+        expected[31] = 42;
+        assert_eq!(result.output.as_ref(), expected.as_slice());
+    }
+
+    #[test]
+    fn logs_and_touched_accounts_are_ordered_deterministically() {
+        let contract_addr = Address::from(u256::from(0xbbu8));
+        let callee_addrs = [0x30u8, 0x10, 0x20].map(|addr| Address::from(u256::from(addr)));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        for addr in callee_addrs {
+            host.call_router_mut()
+                .route(addr, |message| ExecutionResult {
+                    status_code: StatusCode::EVMC_SUCCESS,
+                    gas_left: message.gas,
+                    gas_refund: 0,
+                    output: Box::default(),
+                    create_address: None,
+                });
+        }
+
+        // emit LOG1 with topic 3, LOG0 with 1 byte of data and LOG1 with topic 1, then call
+        // 0x30, 0x10 and 0x20
+        let mut code = vec![
+            Opcode::Push1 as u8,
+            3,                   // topic
+            Opcode::Push0 as u8, // len
+            Opcode::Push0 as u8, // offset
+            Opcode::Log1 as u8,
+            Opcode::Push1 as u8,
+            1,                   // len
+            Opcode::Push0 as u8, // offset
+            Opcode::Log0 as u8,
+            Opcode::Push1 as u8,
+            1,                   // topic
+            Opcode::Push0 as u8, // len
+            Opcode::Push0 as u8, // offset
+            Opcode::Log1 as u8,
+        ];
+        for addr in [0x30, 0x10, 0x20] {
+            code.extend([
+                Opcode::Push0 as u8, // ret len
+                Opcode::Push0 as u8, // ret offset
+                Opcode::Push0 as u8, // args len
+                Opcode::Push0 as u8, // args offset
+                Opcode::Push0 as u8, // value
+                Opcode::Push1 as u8,
+                addr,
+                Opcode::Gas as u8,
+                Opcode::Call as u8,
+                Opcode::Pop as u8,
+            ]);
+        }
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            recipient: contract_addr,
+            code_address: contract_addr,
+            gas: 1_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        assert_eq!(
+            host.logs(),
+            [
+                Log {
+                    address: contract_addr,
+                    data: Vec::new(),
+                    topics: vec![Uint256::from(u256::from(3u8))],
+                },
+                Log {
+                    address: contract_addr,
+                    data: vec![0],
+                    topics: Vec::new(),
+                },
+                Log {
+                    address: contract_addr,
+                    data: Vec::new(),
+                    topics: vec![Uint256::from(u256::from(1u8))],
+                },
+            ]
+        );
+        assert_eq!(
+            host.touched_accounts(),
+            [0x10u8, 0x20, 0x30].map(|addr| Address::from(u256::from(addr)))
+        );
+    }
+
+    #[test]
+    fn state_changes_of_failed_call_are_reverted() {
+        let contract_addr = Address::from(u256::from(0xbbu8));
+        let callee_addr = Address::from(u256::from(0xccu8));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        // SSTORE(0, 1), TSTORE(0, 1) and LOG0, then revert
+        let callee_code = [
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push0 as u8,
+            Opcode::SStore as u8,
+            Opcode::Push1 as u8,
+            1,
+            Opcode::Push0 as u8,
+            Opcode::TStore as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Log0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Push0 as u8,
+            Opcode::Revert as u8,
+        ];
+        host.set_account(
+            callee_addr,
+            Account {
+                code: callee_code.to_vec(),
+                ..Default::default()
+            },
+        );
+        let code = [
+            Opcode::Push0 as u8, // ret len
+            Opcode::Push0 as u8, // ret offset
+            Opcode::Push0 as u8, // args len
+            Opcode::Push0 as u8, // args offset
+            Opcode::Push0 as u8, // value
+            Opcode::Push1 as u8,
+            0xcc, // addr
+            Opcode::Gas as u8,
+            Opcode::Call as u8,
+        ];
+
+        let code_analysis_cache = CodeAnalysisCache::default();
+        let hash_cache = HashCache::default();
+        let message = MockExecutionMessage {
+            recipient: contract_addr,
+            code_address: contract_addr,
+            gas: 1_000_000,
+            ..Default::default()
+        };
+        let message = message.into();
+        let result: ExecutionResult = Interpreter::new(
+            Revision::EVMC_CANCUN,
+            &message,
+            &mut host,
+            &code,
+            &code_analysis_cache,
+            &hash_cache,
+        )
+        .unwrap()
+        .run(&mut NoOpObserver());
+        assert_eq!(result.status_code, StatusCode::EVMC_SUCCESS);
+
+        assert!(host.account(&callee_addr).unwrap().storage.is_empty());
+        assert_eq!(
+            u256::from(host.get_transient_storage(&callee_addr, &u256::ZERO.into())),
+            u256::ZERO
+        );
+        assert!(host.logs().is_empty());
+        assert!(host.touched_accounts().is_empty());
+    }
+
+    #[test]
+    fn execution_context_forwards_to_reference_host() {
+        let addr = Address::from(u256::from(0xaau8));
+        let key = Uint256::from(u256::ONE);
+        let value = Uint256::from(u256::from(42u8));
+
+        let mut host = ReferenceHost::new(Revision::EVMC_CANCUN);
+        let mut context = host.as_execution_context();
+        assert_eq!(u256::from(context.get_storage(&addr, &key)), u256::ZERO);
+        assert_eq!(
+            context.set_storage(&addr, &key, &value),
+            StorageStatus::EVMC_STORAGE_ADDED
+        );
+        assert_eq!(u256::from(context.get_storage(&addr, &key)), 42u8.into());
+        assert_eq!(
+            context.set_storage(&addr, &key, &value),
+            StorageStatus::EVMC_STORAGE_ASSIGNED
+        );
+
+        assert_eq!(
+            host.account(&addr).unwrap().storage.get(&u256::ONE),
+            Some(&u256::from(42u8))
+        );
+    }
+}
@@ -32,64 +32,87 @@ impl PushLocation<'_> {
 static REUSABLE_STACK: Mutex<Vec<Vec<u256>>> = Mutex::new(Vec::new());
 
 #[derive(Debug)]
-pub struct Stack(Vec<u256>);
+pub struct Stack {
+    values: Vec<u256>,
+    /// The maximum number of values. Pushing beyond it fails with a stack overflow.
+    limit: usize,
+}
 
 #[cfg(feature = "alloc-reuse")]
 impl Drop for Stack {
     fn drop(&mut self) {
         let mut stack = Vec::new();
-        std::mem::swap(&mut stack, &mut self.0);
+        std::mem::swap(&mut stack, &mut self.values);
         REUSABLE_STACK.lock().unwrap().push(stack);
     }
 }
 
 impl Stack {
-    const CAPACITY: usize = 1024;
+    pub const CAPACITY: usize = 1024;
 
+    /// Creates a stack with the default limit of [`Self::CAPACITY`] values.
     #[inline(never)]
     pub fn new(inner: &[u256]) -> Self {
-        let len = min(inner.len(), Self::CAPACITY);
+        Self::with_limit(inner, Self::CAPACITY)
+    }
+
+    /// Creates a stack which holds at most `limit` values. Values of `inner` beyond the limit are
+    /// dropped.
+    #[inline(always)]
+    pub fn with_limit(inner: &[u256], limit: usize) -> Self {
+        let len = min(inner.len(), limit);
         let inner = &inner[..len];
         #[cfg(not(feature = "alloc-reuse"))]
-        let mut v = Vec::with_capacity(Self::CAPACITY);
+        let mut v = Vec::with_capacity(limit);
         #[cfg(feature = "alloc-reuse")]
         let mut v = REUSABLE_STACK
             .lock()
             .unwrap()
             .pop()
-            .unwrap_or_else(|| Vec::with_capacity(Self::CAPACITY));
+            .unwrap_or_else(|| Vec::with_capacity(limit));
         v.clear();
+        // Stacks from REUSABLE_STACK may have been created with a smaller limit.
+        v.reserve_exact(limit);
         #[cfg(feature = "unsafe-stack")]
         // SAFETY:
-        // inner was shorted to the minimum of its original length and Self::CAPACITY.
-        // v was taken from REUSABLE_STACK which was put there by Stack::drop or was created with
-        // capacity Self::CAPACITY. Therefore it always has capacity Self::CAPACITY.
+        // inner was shorted to the minimum of its original length and limit.
+        // v has at least capacity limit, because it was either created with this capacity or
+        // reserved enough space above.
         unsafe {
             std::hint::assert_unchecked(inner.len() <= v.capacity());
         }
         v.extend_from_slice(inner);
-        Self(v)
+        Self { values: v, limit }
+    }
+
+    /// The maximum number of values on this stack.
+    pub fn limit(&self) -> usize {
+        self.limit
     }
 
     pub fn as_slice(&self) -> &[u256] {
-        self.0.as_slice()
+        self.values.as_slice()
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.values.len()
     }
 
     pub fn push(&mut self, value: impl Into<u256>) -> Result<(), FailStatus> {
-        if self.0.len() >= Self::CAPACITY {
+        // With feature stack-precheck the interpreter checks for overflows before dispatching.
+        #[cfg(not(feature = "stack-precheck"))]
+        if self.values.len() >= self.limit {
             return Err(FailStatus::StackOverflow);
         }
+        #[cfg(feature = "stack-precheck")]
+        debug_assert!(self.values.len() < self.limit);
         #[cfg(feature = "unsafe-stack")]
         // SAFETY:
-        // self.0 is initialized with capacity Self::CAPACITY and never shrunk.
+        // self.values is initialized with a capacity of at least self.limit and never shrunk.
         unsafe {
-            std::hint::assert_unchecked(self.0.capacity() == Self::CAPACITY);
+            std::hint::assert_unchecked(self.values.capacity() >= self.limit);
         }
-        self.0.push(value.into());
+        self.values.push(value.into());
         Ok(())
     }
 
@@ -100,12 +123,12 @@ impl Stack {
 
         #[cfg(not(feature = "unsafe-stack"))]
         {
-            let len = self.0.len();
-            self.0.swap(len - 1, len - 1 - N);
+            let len = self.values.len();
+            self.values.swap(len - 1, len - 1 - N);
         }
         #[cfg(feature = "unsafe-stack")]
         {
-            let start = self.0.as_mut_ptr();
+            let start = self.values.as_mut_ptr();
             // SAFETY:
             // This does not wrap and the whole range is valid.
             let top = unsafe { start.add(self.len() - 1) };
@@ -125,10 +148,10 @@ impl Stack {
     pub fn pop<const N: usize>(&mut self) -> Result<[u256; N], FailStatus> {
         self.check_underflow(N)?;
 
-        let new_len = self.0.len() - N;
+        let new_len = self.values.len() - N;
         let mut array = [u256::ZERO; N];
-        array.copy_from_slice(&self.0[new_len..]);
-        self.0.truncate(new_len);
+        array.copy_from_slice(&self.values[new_len..]);
+        self.values.truncate(new_len);
         Ok(array)
     }
 
@@ -139,10 +162,10 @@ impl Stack {
 
         self.check_underflow(N)?;
 
-        self.0.truncate(self.len() - (N - 1));
+        self.values.truncate(self.len() - (N - 1));
         // SAFETY:
         // This does not wrap and the whole range from start to start + self.len is valid.
-        let pop_start = unsafe { self.0.as_ptr().add(self.len() - 1) };
+        let pop_start = unsafe { self.values.as_ptr().add(self.len() - 1) };
         // SAFETY:
         // The the first self.len elements are initialized (invariant).
         // `self.len` just got decremented by N - 1, which means now that the first `self.len  +
@@ -150,12 +173,12 @@ impl Stack {
         // starting at index `self.len - 1` as an array of length N and type u256.
         let pop_data = unsafe { *(pop_start as *const [u256; N]) };
         let len = self.len();
-        let push_location = PushLocation(&mut self.0[len - 1]);
+        let push_location = PushLocation(&mut self.values[len - 1]);
         Ok((push_location, pop_data))
     }
 
     pub fn peek(&self) -> Option<&u256> {
-        self.0.last()
+        self.values.last()
     }
 
     pub fn dup<const N: usize>(&mut self) -> Result<(), FailStatus> {
@@ -164,27 +187,33 @@ impl Stack {
 
         self.check_underflow(N)?;
         #[cfg(not(feature = "unsafe-stack"))]
-        let element = self.0[self.0.len() - N];
+        let element = self.values[self.values.len() - N];
         #[cfg(feature = "unsafe-stack")]
         // SAFETY:
-        // self.0.len() >= nth + 1 was checked in check_underflow.
-        // Therefore self.0.len() - 1 - nth is in bounds.
-        let element = *unsafe { self.0.get_unchecked(self.0.len() - N) };
+        // self.values.len() >= nth + 1 was checked in check_underflow.
+        // Therefore self.values.len() - 1 - nth is in bounds.
+        let element = *unsafe { self.values.get_unchecked(self.values.len() - N) };
         self.push(element)
     }
 
     #[inline(always)]
     fn check_underflow(&self, min_len: usize) -> Result<(), FailStatus> {
-        if self.0.len() < min_len {
+        // With feature stack-precheck the interpreter checks for underflows before dispatching.
+        #[cfg(not(feature = "stack-precheck"))]
+        if self.values.len() < min_len {
             return Err(FailStatus::StackUnderflow);
         }
+        #[cfg(feature = "stack-precheck")]
+        debug_assert!(self.values.len() >= min_len);
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::types::{FailStatus, stack::Stack, u256};
+    #[cfg(not(feature = "stack-precheck"))]
+    use crate::types::FailStatus;
+    use crate::types::{stack::Stack, u256};
 
     #[test]
     fn internals() {
@@ -199,8 +228,30 @@ mod tests {
         assert_eq!(stack.push(u256::MAX), Ok(()));
         assert_eq!(stack.as_slice(), [u256::MAX]);
 
-        let mut stack = Stack::new(&[u256::ZERO; Stack::CAPACITY]);
-        assert_eq!(stack.push(u256::ZERO), Err(FailStatus::StackOverflow));
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::new(&[u256::ZERO; Stack::CAPACITY]);
+            assert_eq!(stack.push(u256::ZERO), Err(FailStatus::StackOverflow));
+        }
+    }
+
+    #[test]
+    fn push_respects_custom_limit() {
+        let mut stack = Stack::with_limit(&[u256::ZERO; Stack::CAPACITY], 2048);
+        assert_eq!(stack.limit(), 2048);
+        assert_eq!(stack.push(u256::ZERO), Ok(()));
+        assert_eq!(stack.len(), Stack::CAPACITY + 1);
+
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::with_limit(&[u256::ZERO; 2048], 2048);
+            assert_eq!(stack.push(u256::ZERO), Err(FailStatus::StackOverflow));
+        }
+
+        let stack = Stack::with_limit(&[u256::ZERO; 3], 2);
+        assert_eq!(stack.as_slice(), [u256::ZERO; 2]);
     }
 
     #[test]
@@ -208,14 +259,18 @@ mod tests {
         let mut stack = Stack::new(&[u256::MAX]);
         assert_eq!(stack.pop::<1>(), Ok([u256::MAX]));
 
-        let mut stack = Stack::new(&[]);
-        assert_eq!(stack.pop::<1>(), Err(FailStatus::StackUnderflow));
-
         let mut stack = Stack::new(&[u256::ONE, u256::MAX]);
         assert_eq!(stack.pop::<2>(), Ok([u256::ONE, u256::MAX]));
 
-        let mut stack = Stack::new(&[u256::MAX]);
-        assert_eq!(stack.pop::<2>(), Err(FailStatus::StackUnderflow));
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::new(&[]);
+            assert_eq!(stack.pop::<1>(), Err(FailStatus::StackUnderflow));
+
+            let mut stack = Stack::new(&[u256::MAX]);
+            assert_eq!(stack.pop::<2>(), Err(FailStatus::StackUnderflow));
+        }
     }
 
     #[test]
@@ -226,23 +281,27 @@ mod tests {
         push_location.push(u256::ONE);
         assert_eq!(stack.as_slice(), [u256::ONE]);
 
-        let mut stack = Stack::new(&[]);
-        assert_eq!(
-            stack.pop_with_location::<1>().unwrap_err(),
-            FailStatus::StackUnderflow
-        );
-
         let mut stack = Stack::new(&[u256::ONE, u256::MAX]);
         let (push_location, data) = stack.pop_with_location::<2>().unwrap();
         assert_eq!(data, [u256::ONE, u256::MAX]);
         push_location.push(u256::ZERO);
         assert_eq!(stack.as_slice(), [u256::ZERO]);
 
-        let mut stack = Stack::new(&[u256::MAX]);
-        assert_eq!(
-            stack.pop_with_location::<2>().unwrap_err(),
-            FailStatus::StackUnderflow
-        );
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::new(&[]);
+            assert_eq!(
+                stack.pop_with_location::<1>().unwrap_err(),
+                FailStatus::StackUnderflow
+            );
+
+            let mut stack = Stack::new(&[u256::MAX]);
+            assert_eq!(
+                stack.pop_with_location::<2>().unwrap_err(),
+                FailStatus::StackUnderflow
+            );
+        }
     }
 
     #[test]
@@ -255,11 +314,15 @@ mod tests {
         stack.dup::<2>().unwrap();
         assert_eq!(stack.as_slice(), [u256::MAX, u256::ZERO, u256::MAX]);
 
-        let mut stack = Stack::new(&[u256::MAX, u256::ZERO]);
-        assert_eq!(stack.dup::<3>(), Err(FailStatus::StackUnderflow));
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::new(&[u256::MAX, u256::ZERO]);
+            assert_eq!(stack.dup::<3>(), Err(FailStatus::StackUnderflow));
 
-        let mut stack = Stack::new(&[u256::ZERO; 1024]);
-        assert_eq!(stack.dup::<1>(), Err(FailStatus::StackOverflow));
+            let mut stack = Stack::new(&[u256::ZERO; 1024]);
+            assert_eq!(stack.dup::<1>(), Err(FailStatus::StackOverflow));
+        }
     }
 
     #[test]
@@ -268,8 +331,12 @@ mod tests {
         assert_eq!(stack.swap_with_top::<1>(), Ok(()));
         assert_eq!(stack.as_slice(), [u256::ONE, u256::MAX]);
 
-        let mut stack = Stack::new(&[u256::MAX, u256::ONE]);
-        assert_eq!(stack.swap_with_top::<2>(), Err(FailStatus::StackUnderflow));
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
+        {
+            let mut stack = Stack::new(&[u256::MAX, u256::ONE]);
+            assert_eq!(stack.swap_with_top::<2>(), Err(FailStatus::StackUnderflow));
+        }
     }
 
     #[test]
@@ -278,6 +345,8 @@ mod tests {
         assert_eq!(stack.check_underflow(0), Ok(()));
         let stack = Stack::new(&[u256::ZERO]);
         assert_eq!(stack.check_underflow(1), Ok(()));
+        // the stack does not check its bounds if feature stack-precheck is enabled
+        #[cfg(not(feature = "stack-precheck"))]
         assert_eq!(stack.check_underflow(2), Err(FailStatus::StackUnderflow));
     }
 }
@@ -15,25 +15,28 @@ pub enum ExecStatus {
 /// This type combines the failure variants of [`EvmcStatusCode`] and [`EvmcStepStatusCode`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FailStatus {
-    Failure = EvmcStatusCode::EVMC_FAILURE as isize,
-    OutOfGas = EvmcStatusCode::EVMC_OUT_OF_GAS as isize,
-    InvalidInstruction = EvmcStatusCode::EVMC_INVALID_INSTRUCTION as isize,
-    UndefinedInstruction = EvmcStatusCode::EVMC_UNDEFINED_INSTRUCTION as isize,
-    StackOverflow = EvmcStatusCode::EVMC_STACK_OVERFLOW as isize,
-    StackUnderflow = EvmcStatusCode::EVMC_STACK_UNDERFLOW as isize,
-    BadJumpDestination = EvmcStatusCode::EVMC_BAD_JUMP_DESTINATION as isize,
-    InvalidMemoryAccess = EvmcStatusCode::EVMC_INVALID_MEMORY_ACCESS as isize,
-    CallDepthExceeded = EvmcStatusCode::EVMC_CALL_DEPTH_EXCEEDED as isize,
-    StaticModeViolation = EvmcStatusCode::EVMC_STATIC_MODE_VIOLATION as isize,
-    PrecompileFailure = EvmcStatusCode::EVMC_PRECOMPILE_FAILURE as isize,
-    ContractValidationFailure = EvmcStatusCode::EVMC_CONTRACT_VALIDATION_FAILURE as isize,
-    ArgumentOutOfRange = EvmcStatusCode::EVMC_ARGUMENT_OUT_OF_RANGE as isize,
-    WasmUnreachableInstruction = EvmcStatusCode::EVMC_WASM_UNREACHABLE_INSTRUCTION as isize,
-    WasmTrap = EvmcStatusCode::EVMC_WASM_TRAP as isize,
-    InsufficientBalance = EvmcStatusCode::EVMC_INSUFFICIENT_BALANCE as isize,
-    InternalError = EvmcStatusCode::EVMC_INTERNAL_ERROR as isize,
-    Rejected = EvmcStatusCode::EVMC_REJECTED as isize,
-    OutOfMemory = EvmcStatusCode::EVMC_OUT_OF_MEMORY as isize,
+    Failure,
+    OutOfGas,
+    InvalidInstruction,
+    UndefinedInstruction,
+    StackOverflow,
+    StackUnderflow,
+    BadJumpDestination,
+    InvalidMemoryAccess,
+    CallDepthExceeded,
+    StaticModeViolation,
+    PrecompileFailure,
+    ContractValidationFailure,
+    ArgumentOutOfRange,
+    WasmUnreachableInstruction,
+    WasmTrap,
+    InsufficientBalance,
+    InternalError,
+    Rejected,
+    OutOfMemory,
+    /// The code does not match the code hash of the message, see feature "verify-code-hash". EVMC
+    /// has no dedicated status for this, so it is reported as `EVMC_CONTRACT_VALIDATION_FAILURE`.
+    InvalidCode,
 }
 
 impl From<FailStatus> for EvmcStatusCode {
@@ -50,7 +53,9 @@ impl From<FailStatus> for EvmcStatusCode {
             FailStatus::CallDepthExceeded => Self::EVMC_CALL_DEPTH_EXCEEDED,
             FailStatus::StaticModeViolation => Self::EVMC_STATIC_MODE_VIOLATION,
             FailStatus::PrecompileFailure => Self::EVMC_PRECOMPILE_FAILURE,
-            FailStatus::ContractValidationFailure => Self::EVMC_CONTRACT_VALIDATION_FAILURE,
+            FailStatus::ContractValidationFailure | FailStatus::InvalidCode => {
+                Self::EVMC_CONTRACT_VALIDATION_FAILURE
+            }
             FailStatus::ArgumentOutOfRange => Self::EVMC_ARGUMENT_OUT_OF_RANGE,
             FailStatus::WasmUnreachableInstruction => Self::EVMC_WASM_UNREACHABLE_INSTRUCTION,
             FailStatus::WasmTrap => Self::EVMC_WASM_TRAP,
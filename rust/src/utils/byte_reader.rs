@@ -0,0 +1,82 @@
+/// The input of a [`ByteReader`] ended before the requested value was complete.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnexpectedEnd;
+
+/// Reads values from the front of a byte slice. This is shared by the binary formats of
+/// `InterpreterState` and `HostRecording`, which encode integers little endian and prefix variable
+/// length fields with their length.
+pub struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], UnexpectedEnd> {
+        if self.0.len() < len {
+            return Err(UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], UnexpectedEnd> {
+        // take returns exactly N bytes
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, UnexpectedEnd> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn i32(&mut self) -> Result<i32, UnexpectedEnd> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, UnexpectedEnd> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, UnexpectedEnd> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    /// Reads a length. Lengths which do not fit into `usize` can not be followed by as many bytes,
+    /// so they are reported as [`UnexpectedEnd`], too.
+    pub fn len(&mut self) -> Result<usize, UnexpectedEnd> {
+        usize::try_from(self.u64()?).map_err(|_| UnexpectedEnd)
+    }
+
+    /// Reads a length prefixed byte string.
+    pub fn bytes(&mut self) -> Result<Vec<u8>, UnexpectedEnd> {
+        let len = self.len()?;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{ByteReader, UnexpectedEnd};
+
+    #[test]
+    fn byte_reader_reads_little_endian_values_and_detects_end() {
+        let mut bytes = vec![7];
+        bytes.extend_from_slice(&(-2i32).to_le_bytes());
+        bytes.extend_from_slice(&3u64.to_le_bytes());
+        bytes.extend_from_slice(&[1, 2, 3]);
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.i32(), Ok(-2));
+        assert_eq!(reader.bytes(), Ok(vec![1, 2, 3]));
+        assert!(reader.is_empty());
+        assert_eq!(reader.i64(), Err(UnexpectedEnd));
+
+        let bytes = u64::MAX.to_le_bytes();
+        assert_eq!(ByteReader::new(&bytes).bytes(), Err(UnexpectedEnd));
+    }
+}
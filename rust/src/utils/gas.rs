@@ -2,7 +2,14 @@ use evmc_vm::{AccessStatus, Address, Revision};
 
 use crate::{
     types::{ExecutionContextTrait, FailStatus, u256},
-    utils::word_size,
+    utils::{DELEGATION_DESIGNATOR_LEN, delegation_designator, word_size},
+};
+
+pub mod constants;
+
+use constants::{
+    GAS_CALL_VALUE, GAS_COLD_ACCOUNT_ACCESS, GAS_COPY, GAS_NEW_ACCOUNT, GAS_SHA3_WORD,
+    GAS_WARM_ACCESS,
 };
 
 #[derive(Debug)]
@@ -70,7 +77,7 @@ impl Gas {
     #[inline(always)]
     pub fn consume_positive_value_cost(&mut self, value: &u256) -> Result<(), FailStatus> {
         if *value != u256::ZERO {
-            self.consume(9_000)?;
+            self.consume(GAS_CALL_VALUE)?;
         }
         Ok(())
     }
@@ -83,7 +90,7 @@ impl Gas {
         context: &mut dyn ExecutionContextTrait,
     ) -> Result<(), FailStatus> {
         if *value != u256::ZERO && !context.account_exists(addr) {
-            self.consume(25_000)?;
+            self.consume(GAS_NEW_ACCOUNT)?;
         }
         Ok(())
     }
@@ -99,9 +106,9 @@ impl Gas {
             return Ok(());
         }
         if context.access_account(addr) == AccessStatus::EVMC_ACCESS_COLD {
-            self.consume(2_600)
+            self.consume(GAS_COLD_ACCOUNT_ACCESS)
         } else {
-            self.consume(100)
+            self.consume(GAS_WARM_ACCESS)
         }
     }
 
@@ -115,12 +122,10 @@ impl Gas {
         if revision < Revision::EVMC_PRAGUE {
             return Ok(());
         }
-        if context.get_code_size(addr) == 23 {
-            let mut code = [0; 23];
+        if context.get_code_size(addr) == DELEGATION_DESIGNATOR_LEN {
+            let mut code = [0; DELEGATION_DESIGNATOR_LEN];
             context.copy_code(addr, 0, &mut code);
-            if code.len() == 23 && code[..3] == [0xef, 0x01, 0x00] {
-                let mut delegation_addr = Address::default();
-                delegation_addr.bytes.copy_from_slice(&code[3..]);
+            if let Some(delegation_addr) = delegation_designator(&code) {
                 self.consume_address_access_cost(&delegation_addr, revision, context)?;
             }
         }
@@ -129,7 +134,13 @@ impl Gas {
 
     #[inline(always)]
     pub fn consume_copy_cost(&mut self, len: u64) -> Result<(), FailStatus> {
-        let cost = word_size(len)? * 3; // does not overflow because word_size divides by 32
+        let cost = word_size(len)? * GAS_COPY; // does not overflow because word_size divides by 32
+        self.consume(cost)
+    }
+
+    #[inline(always)]
+    pub fn consume_hash_cost(&mut self, len: u64) -> Result<(), FailStatus> {
+        let cost = word_size(len)? * GAS_SHA3_WORD; // does not overflow because word_size divides by 32
         self.consume(cost)
     }
 }
@@ -293,4 +304,34 @@ mod tests {
         );
         assert_eq!(gas_left, 2);
     }
+
+    #[test]
+    fn consume_hash_cost() {
+        let mut gas_left = Gas::new(1);
+        assert_eq!(gas_left.consume_hash_cost(0), Ok(()));
+        assert_eq!(gas_left, 1);
+
+        let mut gas_left = Gas::new(6);
+        assert_eq!(gas_left.consume_hash_cost(1), Ok(()));
+        assert_eq!(gas_left, 0);
+
+        let mut gas_left = Gas::new(6);
+        assert_eq!(gas_left.consume_hash_cost(32), Ok(()));
+        assert_eq!(gas_left, 0);
+
+        let mut gas_left = Gas::new(12);
+        assert_eq!(gas_left.consume_hash_cost(33), Ok(()));
+        assert_eq!(gas_left, 0);
+
+        let mut gas_left = Gas::new(5);
+        assert_eq!(gas_left.consume_hash_cost(1), Err(FailStatus::OutOfGas));
+        assert_eq!(gas_left, 5);
+
+        let mut gas_left = Gas::new(5);
+        assert_eq!(
+            gas_left.consume_hash_cost(u64::MAX),
+            Err(FailStatus::OutOfGas)
+        );
+        assert_eq!(gas_left, 5);
+    }
 }
@@ -0,0 +1,73 @@
+//! Gas costs of the EVM gas schedule. The names follow the Ethereum yellow paper.
+
+/// Cost of JUMPDEST.
+pub const GAS_JUMPDEST: u64 = 1;
+/// Cost of instructions which only access the execution environment or the stack, e.g. ADDRESS,
+/// POP or PC.
+pub const GAS_BASE: u64 = 2;
+/// Cost of simple arithmetic, comparison and bitwise instructions as well as PUSH, DUP and SWAP.
+pub const GAS_VERYLOW: u64 = 3;
+/// Cost of DIV, MOD, SIGNEXTEND and similar instructions.
+pub const GAS_LOW: u64 = 5;
+/// Cost of ADDMOD, MULMOD and JUMP.
+pub const GAS_MID: u64 = 8;
+/// Cost of JUMPI.
+pub const GAS_HIGH: u64 = 10;
+
+/// Static cost of EXP.
+pub const GAS_EXP: u64 = 10;
+/// Cost of EXP per byte of the exponent since Spurious Dragon (EIP-160).
+pub const GAS_EXP_BYTE: u64 = 50;
+/// Cost of EXP per byte of the exponent before Spurious Dragon.
+pub const GAS_EXP_BYTE_FRONTIER: u64 = 10;
+/// Static cost of SHA3.
+pub const GAS_SHA3: u64 = 30;
+/// Cost of SHA3 and CREATE2 per word of hashed data.
+pub const GAS_SHA3_WORD: u64 = 6;
+/// Cost of copy instructions per word of copied data.
+pub const GAS_COPY: u64 = 3;
+/// Cost of BLOCKHASH.
+pub const GAS_BLOCKHASH: u64 = 20;
+
+/// Cost of accessing an account or storage slot which was already accessed in this transaction
+/// (since Berlin).
+pub const GAS_WARM_ACCESS: u64 = 100;
+/// Cost of accessing an account which was not yet accessed in this transaction (since Berlin).
+pub const GAS_COLD_ACCOUNT_ACCESS: u64 = 2_600;
+/// Cost of loading a storage slot which was not yet accessed in this transaction (since Berlin).
+pub const GAS_COLD_SLOAD: u64 = 2_100;
+
+/// Static cost of LOG0 to LOG4.
+pub const GAS_LOG: u64 = 375;
+/// Cost of LOG0 to LOG4 per byte of data.
+pub const GAS_LOG_DATA: u64 = 8;
+/// Cost of LOG1 to LOG4 per topic.
+pub const GAS_LOG_TOPIC: u64 = 375;
+
+/// Static cost of CREATE and CREATE2.
+pub const GAS_CREATE: u64 = 32_000;
+/// Cost of CREATE and CREATE2 per word of init code (since Shanghai).
+pub const GAS_INITCODE_WORD: u64 = 2;
+/// Static cost of SELFDESTRUCT (since Tangerine Whistle).
+pub const GAS_SELFDESTRUCT: u64 = 5_000;
+/// Additional cost of value transferring calls.
+pub const GAS_CALL_VALUE: u64 = 9_000;
+/// Gas which is passed to the callee in addition to the gas limit of value transferring calls.
+pub const GAS_CALL_STIPEND: u64 = 2_300;
+/// Cost of creating a new account with a value transferring call or SELFDESTRUCT.
+pub const GAS_NEW_ACCOUNT: u64 = 25_000;
+
+/// Cost of the ecRecover precompile.
+pub const GAS_ECRECOVER: u64 = 3_000;
+/// Static cost of the SHA256 precompile.
+pub const GAS_SHA256: u64 = 60;
+/// Cost of the SHA256 precompile per word of input.
+pub const GAS_SHA256_WORD: u64 = 12;
+/// Static cost of the RIPEMD160 precompile.
+pub const GAS_RIPEMD160: u64 = 600;
+/// Cost of the RIPEMD160 precompile per word of input.
+pub const GAS_RIPEMD160_WORD: u64 = 120;
+/// Static cost of the IDENTITY precompile.
+pub const GAS_IDENTITY: u64 = 15;
+/// Cost of the IDENTITY precompile per word of input.
+pub const GAS_IDENTITY_WORD: u64 = 3;
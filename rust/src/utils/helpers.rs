@@ -1,6 +1,6 @@
 use std::cmp::min;
 
-use evmc_vm::{ExecutionMessage, MessageFlags, Revision};
+use evmc_vm::{Address, ExecutionMessage, MessageFlags, Revision};
 
 use crate::{
     types::{FailStatus, u256},
@@ -67,9 +67,26 @@ pub fn check_not_read_only(message: &ExecutionMessage) -> Result<(), FailStatus>
     Ok(())
 }
 
+/// The prefix of an EIP-7702 delegation designator (`0xef0100 || address`).
+pub const DELEGATION_DESIGNATOR_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+/// The length of an EIP-7702 delegation designator in bytes.
+pub const DELEGATION_DESIGNATOR_LEN: usize = 23;
+
+/// Returns the delegation target if `code` is an EIP-7702 delegation designator.
+#[inline(always)]
+pub fn delegation_designator(code: &[u8]) -> Option<Address> {
+    if code.len() != DELEGATION_DESIGNATOR_LEN || code[..3] != DELEGATION_DESIGNATOR_PREFIX {
+        return None;
+    }
+    let mut addr = Address::default();
+    addr.bytes.copy_from_slice(&code[3..]);
+    Some(addr)
+}
+
 #[cfg(test)]
 mod tests {
-    use evmc_vm::{MessageFlags, Revision};
+    use evmc_vm::{Address, MessageFlags, Revision};
 
     use crate::{
         types::{FailStatus, MockExecutionMessage, u256},
@@ -155,4 +172,19 @@ mod tests {
             Err(FailStatus::StaticModeViolation)
         );
     }
+
+    #[test]
+    fn delegation_designator() {
+        let mut code = [0; 23];
+        code[..3].copy_from_slice(&[0xef, 0x01, 0x00]);
+        code[22] = 0xaa;
+        let mut addr = Address::default();
+        addr.bytes[19] = 0xaa;
+        assert_eq!(utils::delegation_designator(&code), Some(addr));
+
+        assert_eq!(utils::delegation_designator(&code[..22]), None);
+        assert_eq!(utils::delegation_designator(&[0xef, 0x01, 0x00]), None);
+        code[1] = 0x00;
+        assert_eq!(utils::delegation_designator(&code), None);
+    }
 }
@@ -1,5 +1,7 @@
+mod byte_reader;
 mod gas;
 mod helpers;
 
+pub use byte_reader::*;
 pub use gas::*;
 pub use helpers::*;
@@ -5,7 +5,7 @@ use driver::{
 };
 use evmrs::{
     MockExecutionContextTrait, MockExecutionMessage, Opcode,
-    evmc_vm::{Revision, StatusCode, StepStatusCode},
+    evmc_vm::{AccessStatus, Revision, StatusCode, StepStatusCode},
 };
 
 #[test]
@@ -56,6 +56,69 @@ fn execute_handles_error_correctly() {
     assert_eq!(result.status_code, StatusCode::EVMC_STACK_UNDERFLOW);
 }
 
+#[test]
+fn execute_batch_returns_results_in_order() {
+    let mut instance = Instance::default();
+    let mut host = null_ptr_host_interface();
+    host.get_tx_context = Some(get_tx_context_zeroed);
+    let revision = Revision::EVMC_CANCUN;
+    let messages: [_; 3] =
+        std::array::from_fn(|_| MockExecutionMessage::default().to_evmc_message());
+    // return a single byte with value 42
+    let return_byte = [
+        Opcode::Push1 as u8,
+        42,
+        Opcode::Push0 as u8,
+        Opcode::MStore8 as u8,
+        Opcode::Push1 as u8,
+        1,
+        Opcode::Push0 as u8,
+        Opcode::Return as u8,
+    ];
+    // this will error because the stack is empty
+    let underflow = [Opcode::Add as u8];
+    let codes: [&[u8]; 3] = [&return_byte, &underflow, &[]];
+
+    let results = instance.run_batch_with_null_context(&host, revision, &messages, &codes);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].status_code, StatusCode::EVMC_SUCCESS);
+    assert_eq!(*results[0].output, [42]);
+    assert_eq!(results[1].status_code, StatusCode::EVMC_STACK_UNDERFLOW);
+    assert_eq!(results[2].status_code, StatusCode::EVMC_SUCCESS);
+    assert!(results[2].output.is_empty());
+}
+
+#[test]
+fn estimate_gas_discards_state_changes_and_keeps_status() {
+    let mut instance = Instance::default();
+    let host = host_interface::mocked_host_interface();
+    let mut context = MockExecutionContextTrait::new();
+    context
+        .expect_access_storage()
+        .return_const(AccessStatus::EVMC_ACCESS_COLD);
+    context.expect_get_storage().return_const(ZERO);
+    context.expect_set_storage().never();
+    context.expect_emit_log().never();
+    let revision = Revision::EVMC_CANCUN;
+    let message = MockExecutionMessage::default().to_evmc_message();
+    let code = &[
+        Opcode::Push1 as u8,
+        1,
+        Opcode::Push0 as u8,
+        Opcode::SStore as u8,
+        Opcode::Push0 as u8,
+        Opcode::Push0 as u8,
+        Opcode::Log0 as u8,
+        Opcode::Push0 as u8,
+        Opcode::Push0 as u8,
+        Opcode::Revert as u8,
+    ];
+    let (result, gas_used) = instance.estimate_gas(&host, &mut context, revision, &message, code);
+    assert_eq!(result.status_code, StatusCode::EVMC_REVERT);
+    assert_eq!(gas_used, (message.gas - result.gas_left) as u64);
+    assert!(gas_used > 20_000);
+}
+
 #[test]
 fn step_n_can_be_called_with_mocked_context() {
     let mut instance = SteppableInstance::default();
@@ -0,0 +1,57 @@
+#![allow(unused_crate_dependencies)]
+//! Executes all fuzzing regression inputs in `tests/regressions`. See the README in that directory
+//! for the file format.
+use std::{
+    fmt::Write,
+    fs, panic,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use driver::fuzzing::fuzz_execute;
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+#[test]
+fn regression_inputs_do_not_panic_and_match_expected_results() {
+    // Panics inside of the interpreter are caught at the FFI boundary and converted into an
+    // internal error. Use a panic hook to detect them anyway.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        PANICKED.store(true, Ordering::Relaxed);
+        default_hook(info);
+    }));
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let mut inputs: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    inputs.sort();
+    assert!(
+        !inputs.is_empty(),
+        "no regression inputs in {}",
+        dir.display()
+    );
+
+    for input in inputs {
+        let name = input.file_stem().unwrap().to_string_lossy().into_owned();
+        PANICKED.store(false, Ordering::Relaxed);
+        let result = fuzz_execute(&fs::read(&input).unwrap());
+        assert!(!PANICKED.load(Ordering::Relaxed), "{name} panicked");
+
+        let Ok(expected) = fs::read_to_string(input.with_extension("expected")) else {
+            continue;
+        };
+        let Some(result) = result else {
+            panic!("{name} could not be decoded but has an expected result");
+        };
+        let mut actual = format!("status: {:?}\noutput: ", result.status_code);
+        for byte in &result.output {
+            write!(actual, "{byte:02x}").unwrap();
+        }
+        actual.push('\n');
+        assert_eq!(actual, expected, "{name}");
+    }
+}
@@ -0,0 +1,126 @@
+#![allow(unused_crate_dependencies)]
+//! Property based tests which compare the arithmetic of `u256` against `num-bigint` reduced
+//! modulo 2^256.
+use evmrs::u256;
+use num_bigint::BigUint;
+use proptest::prelude::*;
+
+fn modulus() -> BigUint {
+    BigUint::from(1u8) << 256
+}
+
+fn to_big(value: u256) -> BigUint {
+    BigUint::from_bytes_le(&value.to_le_bytes())
+}
+
+fn from_big(value: &BigUint) -> u256 {
+    let value = value % modulus();
+    let mut bytes = [0; 32];
+    let le_bytes = value.to_bytes_le();
+    bytes[..le_bytes.len()].copy_from_slice(&le_bytes);
+    u256::from_le_bytes(bytes)
+}
+
+/// Generates values which are biased towards edge cases. All strategies shrink towards zero.
+fn any_u256() -> impl Strategy<Value = u256> {
+    prop_oneof![
+        Just(u256::ZERO),
+        Just(u256::ONE),
+        Just(u256::MAX),
+        any::<u64>().prop_map(u256::from),
+        any::<[u8; 32]>().prop_map(u256::from_be_bytes),
+    ]
+}
+
+/// Generates shift amounts which are mostly in range, but also exceed 255.
+fn any_shift() -> impl Strategy<Value = u256> {
+    prop_oneof![(0u64..300).prop_map(u256::from), any_u256()]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1_000))]
+
+    #[test]
+    fn add(a in any_u256(), b in any_u256()) {
+        prop_assert_eq!(a + b, from_big(&(to_big(a) + to_big(b))));
+    }
+
+    #[test]
+    fn sub(a in any_u256(), b in any_u256()) {
+        prop_assert_eq!(a - b, from_big(&(to_big(a) + modulus() - to_big(b))));
+    }
+
+    #[test]
+    fn mul(a in any_u256(), b in any_u256()) {
+        prop_assert_eq!(a * b, from_big(&(to_big(a) * to_big(b))));
+    }
+
+    #[test]
+    fn div(a in any_u256(), b in any_u256()) {
+        let expected = if b == u256::ZERO {
+            u256::ZERO
+        } else {
+            from_big(&(to_big(a) / to_big(b)))
+        };
+        prop_assert_eq!(a / b, expected);
+    }
+
+    #[test]
+    fn rem(a in any_u256(), b in any_u256()) {
+        let expected = if b == u256::ZERO {
+            u256::ZERO
+        } else {
+            from_big(&(to_big(a) % to_big(b)))
+        };
+        prop_assert_eq!(a % b, expected);
+    }
+
+    #[test]
+    fn pow(a in any_u256(), b in any_u256()) {
+        prop_assert_eq!(a.pow(b), from_big(&to_big(a).modpow(&to_big(b), &modulus())));
+    }
+
+    #[test]
+    fn addmod(a in any_u256(), b in any_u256(), m in any_u256()) {
+        let expected = if m == u256::ZERO {
+            u256::ZERO
+        } else {
+            from_big(&((to_big(a) + to_big(b)) % to_big(m)))
+        };
+        prop_assert_eq!(u256::addmod(a, b, m), expected);
+    }
+
+    #[test]
+    fn mulmod(a in any_u256(), b in any_u256(), m in any_u256()) {
+        let expected = if m == u256::ZERO {
+            u256::ZERO
+        } else {
+            from_big(&((to_big(a) * to_big(b)) % to_big(m)))
+        };
+        prop_assert_eq!(u256::mulmod(a, b, m), expected);
+    }
+
+    #[test]
+    fn shl(value in any_u256(), shift in any_shift()) {
+        let expected = match u64::try_from(shift) {
+            Ok(shift) if shift < 256 => from_big(&(to_big(value) << shift)),
+            _ => u256::ZERO,
+        };
+        prop_assert_eq!(value << shift, expected);
+    }
+
+    #[test]
+    fn shr(value in any_u256(), shift in any_shift()) {
+        let expected = match u64::try_from(shift) {
+            Ok(shift) if shift < 256 => from_big(&(to_big(value) >> shift)),
+            _ => u256::ZERO,
+        };
+        prop_assert_eq!(value >> shift, expected);
+    }
+
+    #[test]
+    fn cmp(a in any_u256(), b in any_u256()) {
+        prop_assert_eq!(a.cmp(&b), to_big(a).cmp(&to_big(b)));
+        prop_assert_eq!(a == b, to_big(a) == to_big(b));
+    }
+}